@@ -2,687 +2,142 @@
 //!
 //! This module implements the expression evaluation engine, corresponding to
 //! the FRMEVL (Formula Evaluation) routine in the original Microsoft BASIC.
-//! It uses the operator precedence parsing method with a stack-based approach.
+//! Expressions are parsed into `Expr` trees by the parser once; the evaluator
+//! walks those trees.
 
 use crate::error::{BasicError, BasicResult};
+use crate::functions::FunctionRegistry;
 use crate::runtime::memory::{MemoryManager, Value};
 use crate::lexer::Token;
+use crate::parser::{BinaryOp, Expr, Parser, UnaryOp};
 
-/// Expression evaluator using operator precedence parsing
+/// Expression evaluator over parsed expression trees
 pub struct ExpressionEvaluator {
-    /// Stack for storing intermediate results during evaluation
-    #[allow(dead_code)]
-    value_stack: Vec<Value>,
-    /// Stack for storing operators and their precedence
-    #[allow(dead_code)]
-    operator_stack: Vec<OperatorFrame>,
-    /// Current position in token stream
-    position: usize,
-}
-
-/// Frame for storing operator information on the stack
-#[derive(Clone, Debug)]
-#[allow(dead_code)]
-struct OperatorFrame {
-    /// The operator token
-    operator: Token,
-    /// Precedence level of the operator
-    precedence: u8,
-    /// Left-hand side value (already evaluated)
-    left_value: Value,
+    /// Functions callable from expressions
+    functions: FunctionRegistry,
 }
 
 impl ExpressionEvaluator {
-    /// Create a new expression evaluator
+    /// Create a new expression evaluator with the standard functions
     pub fn new() -> Self {
-        Self {
-            value_stack: Vec::new(),
-            operator_stack: Vec::new(),
-            position: 0,
-        }
+        Self { functions: FunctionRegistry::new() }
     }
 
-    /// Evaluate an expression from tokens
-    ///
-    /// Simple recursive descent parser implementation.
-    pub fn evaluate(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
-        self.position = 0;
-        let result = self.parse_logical_or(tokens, mem)?;
-
-        // Check that all tokens were consumed
-        if self.position < tokens.len() {
-            // Check if the remaining token is an unmatched parenthesis
-            if tokens[self.position] == Token::RightParen {
-                return Err(BasicError::ExpectedRightParen);
-            } else {
-                return Err(BasicError::ExpectedExpression);
-            }
-        }
-
-        Ok(result)
+    /// Functions callable from expressions
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
     }
 
-    /// Parse logical OR operations
-    fn parse_logical_or(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
-        let mut left = self.parse_logical_and(tokens, mem)?;
-
-        while self.position < tokens.len() {
-            match self.current_token(tokens) {
-                Some(Token::Or) => {
-                    self.position += 1;
-                    let right = self.parse_logical_and(tokens, mem)?;
-                    left = self.execute_or(&left, &right)?;
-                }
-                _ => break,
-            }
-        }
-
-        Ok(left)
+    /// Functions callable from expressions, for registering host functions
+    pub fn functions_mut(&mut self) -> &mut FunctionRegistry {
+        &mut self.functions
     }
 
-    /// Parse logical AND operations
-    fn parse_logical_and(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
-        let mut left = self.parse_expression(tokens, mem)?;
-
-        while self.position < tokens.len() {
-            match self.current_token(tokens) {
-                Some(Token::And) => {
-                    self.position += 1;
-                    let right = self.parse_expression(tokens, mem)?;
-                    left = self.execute_and(&left, &right)?;
+    /// Evaluate an expression from tokens
+    ///
+    /// The tokens are parsed first; prefer `eval` for expressions that are
+    /// already part of a parsed statement.
+    pub fn evaluate(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
+        let expr = Parser::new(tokens).parse_expression()?;
+        self.eval(&expr, mem)
+    }
+
+    /// Evaluate a parsed expression
+    pub fn eval(&mut self, expr: &Expr, mem: &mut MemoryManager) -> BasicResult<Value> {
+        match expr {
+            Expr::Number(n) => Ok(Value::Float(*n)),
+            Expr::String(s) => Ok(Value::String(s.clone())),
+            Expr::Variable(name) => mem.get_variable(name),
+            Expr::ArrayElement { name, indices } => {
+                // Without a dimensioned array this may be a host function call
+                if !mem.arrays().contains_key(name) && self.functions.get(name).is_some() {
+                    return self.call_function(name, indices, mem);
                 }
-                _ => break,
+                let indices = self.eval_indices(indices, mem)?;
+                // array.dimensions already includes the +1 adjustment from DIM
+                mem.get_array_element(name, &indices)
             }
-        }
-
-        Ok(left)
-    }
-
-    /// Parse a full expression (handles comparisons, addition and subtraction)
-    fn parse_expression(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
-        let mut left = self.parse_term(tokens, mem)?;
-
-        while self.position < tokens.len() {
-            match self.current_token(tokens) {
-                Some(Token::Plus) => {
-                    self.position += 1;
-                    let right = self.parse_term(tokens, mem)?;
-                    left = self.execute_addition(&left, &right)?;
-                }
-                Some(Token::Minus) => {
-                    self.position += 1;
-                    let right = self.parse_term(tokens, mem)?;
-                    left = self.execute_subtraction(&left, &right)?;
-                }
-                // Comparison operators (same precedence as addition/subtraction)
-                Some(Token::Equal) => {
-                    self.position += 1;
-                    let right = self.parse_term(tokens, mem)?;
-                    left = self.execute_equal(&left, &right)?;
-                }
-                Some(Token::NotEqual) => {
-                    self.position += 1;
-                    let right = self.parse_term(tokens, mem)?;
-                    left = self.execute_not_equal(&left, &right)?;
-                }
-                Some(Token::Less) => {
-                    self.position += 1;
-                    let right = self.parse_term(tokens, mem)?;
-                    left = self.execute_less(&left, &right)?;
-                }
-                Some(Token::LessEqual) => {
-                    self.position += 1;
-                    let right = self.parse_term(tokens, mem)?;
-                    left = self.execute_less_equal(&left, &right)?;
+            Expr::Function { name, args } => self.call_function(name, args, mem),
+            Expr::Unary { op, operand } => {
+                let value = self.eval(operand, mem)?;
+                match op {
+                    UnaryOp::Negate => Ok(Value::Float(-value.to_float()?)),
+                    UnaryOp::Not => Ok(Value::Float(if value.to_float()? == 0.0 { 1.0 } else { 0.0 })),
                 }
-                Some(Token::Greater) => {
-                    self.position += 1;
-                    let right = self.parse_term(tokens, mem)?;
-                    left = self.execute_greater(&left, &right)?;
-                }
-                Some(Token::GreaterEqual) => {
-                    self.position += 1;
-                    let right = self.parse_term(tokens, mem)?;
-                    left = self.execute_greater_equal(&left, &right)?;
-                }
-                _ => break,
             }
-        }
-
-        Ok(left)
-    }
-
-    /// Parse a term (handles multiplication and division)
-    fn parse_term(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
-        let mut left = self.parse_factor(tokens, mem)?;
-
-        while self.position < tokens.len() {
-            match self.current_token(tokens) {
-                Some(Token::Multiply) => {
-                    self.position += 1;
-                    let right = self.parse_factor(tokens, mem)?;
-                    left = self.execute_multiplication(&left, &right)?;
-                }
-                Some(Token::Divide) => {
-                    self.position += 1;
-                    let right = self.parse_factor(tokens, mem)?;
-                    left = self.execute_division(&left, &right)?;
+            Expr::Binary { op, left, right } => {
+                let left = self.eval(left, mem)?;
+                let right = self.eval(right, mem)?;
+                let result = self.execute_binary_operation(&left, *op, &right)?;
+                // A concatenation creates a new string in string space
+                if let Value::String(s) = &result {
+                    mem.push_temporary(s)?;
                 }
-                _ => break,
+                Ok(result)
             }
         }
-
-        Ok(left)
     }
 
-    /// Parse a factor (handles power, unary operators, and primary values)
-    fn parse_factor(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
-        // Handle power operator (right-associative)
-        let mut base = self.parse_unary(tokens, mem)?;
-
-        if self.position < tokens.len() && self.current_token(tokens) == Some(&Token::Power) {
-            self.position += 1;
-            let exponent = self.parse_factor(tokens, mem)?; // Recursive for right-associativity
-            base = self.execute_power(&base, &exponent)?;
-        }
-
-        Ok(base)
-    }
-
-    /// Parse unary operators and primary values
-    fn parse_unary(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
-        if self.position >= tokens.len() {
-            return Err(BasicError::ExpectedExpression);
-        }
-
-        match self.current_token(tokens) {
-            Some(Token::Minus) => {
-                self.position += 1;
-                let operand = self.parse_unary(tokens, mem)?;
-                Ok(Value::Float(-operand.to_float()?))
-            }
-            Some(Token::Plus) => {
-                self.position += 1;
-                self.parse_unary(tokens, mem)
-            }
-            _ => self.evaluate_primary(tokens, mem),
-        }
-    }
-
-    /// Evaluate a primary value (number, string, variable, function call, or parenthesized expression)
+    /// Call a function from the registry
     ///
-    /// This corresponds to the EVAL routine in the original BASIC.
-    fn evaluate_primary(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
-        if self.position >= tokens.len() {
-            return Err(BasicError::ExpectedExpression);
-        }
-
-        let token = &tokens[self.position];
-        let result = match token {
-            Token::Number(n) => Ok(Value::Float(*n)),
-            Token::String(s) => Ok(Value::String(s.clone())),
-            Token::Identifier(name) => {
-                // Check if this is an array access (identifier followed by left paren)
-                if self.position + 1 < tokens.len() && tokens[self.position + 1] == Token::LeftParen {
-                    // Array access: A(i) or A(i,j)
-                    self.position += 2; // Skip identifier and left paren
-
-                    // Parse indices
-                    let mut indices = Vec::new();
-                    loop {
-                        // Parse the index expression
-                        let index_value = self.parse_logical_or(tokens, mem)?;
-                        let index = index_value.to_float()? as usize;
-                        indices.push(index);
-
-                        // Check for comma (more indices) or right paren (done)
-                        if self.position >= tokens.len() {
-                            return Err(BasicError::ExpectedRightParen);
-                        }
-
-                        match &tokens[self.position] {
-                            Token::Comma => {
-                                self.position += 1; // Skip comma
-                                continue;
-                            }
-                            Token::RightParen => {
-                                self.position += 1; // Skip right paren
-                                break;
-                            }
-                            _ => return Err(BasicError::Syntax),
-                        }
-                    }
-
-                    // Get the array value
-                    let array = mem.arrays().get(name)
-                        .ok_or_else(|| BasicError::VariableNotFound(name.clone()))?;
-
-                    // Convert indices to linear index
-                    if indices.len() != array.dimensions.len() {
-                        return Err(BasicError::Syntax);
-                    }
-
-                    let mut linear_index = 0;
-                    let mut multiplier = 1;
-                    for i in (0..indices.len()).rev() {
-                        // array.dimensions already includes the +1 adjustment from DIM
-                        if indices[i] >= array.dimensions[i] {
-                            return Err(BasicError::BadSubscript);
-                        }
-                        linear_index += indices[i] * multiplier;
-                        multiplier *= array.dimensions[i];
-                    }
-
-                    // Return the array element (don't advance position, already advanced)
-                    return Ok(array.data.get(linear_index)
-                        .ok_or(BasicError::BadSubscript)?
-                        .clone());
-                } else {
-                    // Regular variable lookup
-                    mem.get_variable(name).cloned()
-                }
+    /// FRE is answered here because it reads the interpreter's memory rather
+    /// than its argument.
+    fn call_function(&mut self, name: &str, args: &[Expr], mem: &mut MemoryManager) -> BasicResult<Value> {
+        let args = self.eval_args(args, mem)?;
+        if name == "FRE" {
+            if args.len() != 1 {
+                return Err(BasicError::Syntax);
             }
-            Token::LeftParen => {
-                // Handle parenthesized expression
-                self.position += 1; // Skip '('
-
-                if self.position >= tokens.len() {
-                    return Err(BasicError::ExpectedExpression);
-                }
-
-                // Evaluate the expression inside parentheses
-                let inner_value = self.evaluate_subexpression(tokens, mem)?;
-
-                Ok(inner_value)
-            }
-
-            // Handle functions
-            Token::Sgn => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                Ok(Value::Float(if f > 0.0 { 1.0 } else if f < 0.0 { -1.0 } else { 0.0 }))
-            }),
-
-            Token::Int => self.evaluate_unary_function(tokens, mem, |v| {
-                Ok(Value::Float(v.to_float()?.floor()))
-            }),
-
-            Token::Abs => self.evaluate_unary_function(tokens, mem, |v| {
-                Ok(Value::Float(v.to_float()?.abs()))
-            }),
-
-            Token::Sqr => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                if f < 0.0 {
-                    return Err(BasicError::IllegalQuantity);
-                }
-                Ok(Value::Float(f.sqrt()))
-            }),
-
-            Token::Rnd => self.evaluate_unary_function(tokens, mem, |_v| {
-                // Simple random number implementation - RND(n) returns random between 0 and 1
-                use std::collections::hash_map::DefaultHasher;
-                use std::hash::{Hash, Hasher};
-                let mut hasher = DefaultHasher::new();
-                std::time::SystemTime::now().hash(&mut hasher);
-                let random = (hasher.finish() % 1000000) as f64 / 1000000.0;
-                Ok(Value::Float(random))
-            }),
-
-            Token::Exp => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                Ok(Value::Float(f.exp()))
-            }),
-
-            Token::Log => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                if f <= 0.0 {
-                    return Err(BasicError::IllegalQuantity);
-                }
-                Ok(Value::Float(f.ln()))
-            }),
-
-            Token::Sin => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                Ok(Value::Float(f.sin()))
-            }),
-
-            Token::Cos => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                Ok(Value::Float(f.cos()))
-            }),
-
-            Token::Tan => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                Ok(Value::Float(f.tan()))
-            }),
-
-            Token::Atn => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                Ok(Value::Float(f.atan()))
-            }),
-
-            // String functions
-            Token::Len => self.evaluate_unary_function(tokens, mem, |v| {
-                let s = match v {
-                    Value::String(s) => s,
-                    _ => return Err(BasicError::TypeMismatch),
-                };
-                Ok(Value::Float(s.len() as f64))
-            }),
-            Token::Space => self.evaluate_unary_function(tokens, mem, |v| {
-                let count = v.to_float()? as usize;
-                Ok(Value::String(" ".repeat(count)))
-            }),
-            Token::Tab => self.evaluate_unary_function(tokens, mem, |v| {
-                let position = v.to_float()? as usize;
-                Ok(Value::String(" ".repeat(position)))
-            }),
-
-            Token::Left => self.evaluate_binary_function(tokens, mem, |str_val, len_val| {
-                let s = match str_val {
-                    Value::String(s) => s,
-                    _ => return Err(BasicError::TypeMismatch),
-                };
-                let len = len_val.to_float()? as usize;
-                if len > s.len() {
-                    Ok(Value::String(s))
-                } else {
-                    Ok(Value::String(s[..len].to_string()))
-                }
-            }),
-
-            Token::Right => self.evaluate_binary_function(tokens, mem, |str_val, len_val| {
-                let s = match str_val {
-                    Value::String(s) => s,
-                    _ => return Err(BasicError::TypeMismatch),
-                };
-                let len = len_val.to_float()? as usize;
-                if len > s.len() {
-                    Ok(Value::String(s))
-                } else {
-                    Ok(Value::String(s[s.len() - len..].to_string()))
-                }
-            }),
-
-            Token::Mid => self.evaluate_ternary_function(tokens, mem, |str_val, start_val, len_val| {
-                let s = match str_val {
-                    Value::String(s) => s,
-                    _ => return Err(BasicError::TypeMismatch),
-                };
-                let start = start_val.to_float()? as usize;
-                let len = len_val.to_float()? as usize;
-
-                if start == 0 || start > s.len() {
-                    return Ok(Value::String(String::new()));
-                }
-
-                let start_idx = start - 1; // BASIC is 1-indexed
-                let end_idx = std::cmp::min(start_idx + len, s.len());
-
-                if start_idx >= s.len() {
-                    Ok(Value::String(String::new()))
-                } else {
-                    Ok(Value::String(s[start_idx..end_idx].to_string()))
-                }
-            }),
-
-            Token::Str => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                // Convert to string, removing decimal part if it's .0
-                if f.fract() == 0.0 {
-                    Ok(Value::String(format!("{}", f as i64)))
-                } else {
-                    Ok(Value::String(format!("{}", f)))
-                }
-            }),
-
-            Token::Val => self.evaluate_unary_function(tokens, mem, |v| {
-                let s = match v {
-                    Value::String(s) => s,
-                    _ => return Err(BasicError::TypeMismatch),
-                };
-                // Trim whitespace from the string before parsing
-                let trimmed = s.trim();
-                if trimmed.is_empty() {
-                    return Ok(Value::Float(0.0)); // VAL of empty string is 0 in BASIC
-                }
-                trimmed.parse::<f64>()
-                    .map(Value::Float)
-                    .map_err(|_| BasicError::TypeMismatch)
-            }),
-
-            Token::Asc => self.evaluate_unary_function(tokens, mem, |v| {
-                let s = match v {
-                    Value::String(s) => s,
-                    _ => return Err(BasicError::TypeMismatch),
-                };
-                if s.is_empty() {
-                    return Err(BasicError::IllegalQuantity);
-                }
-                Ok(Value::Float(s.chars().next().unwrap() as u8 as f64))
-            }),
-
-            Token::Chr => self.evaluate_unary_function(tokens, mem, |v| {
-                let f = v.to_float()?;
-                if f < 0.0 || f > 255.0 {
-                    return Err(BasicError::IllegalQuantity);
-                }
-                let ch = f as u8 as char;
-                Ok(Value::String(ch.to_string()))
-            }),
-
-            Token::Instr => self.evaluate_binary_function(tokens, mem, |haystack_val, needle_val| {
-                let haystack = match haystack_val {
-                    Value::String(s) => s,
-                    _ => return Err(BasicError::TypeMismatch),
-                };
-                let needle = match needle_val {
-                    Value::String(s) => s,
-                    _ => return Err(BasicError::TypeMismatch),
-                };
-                
-                // INSTR returns 1-based index, 0 if not found
-                if let Some(pos) = haystack.find(&needle) {
-                    Ok(Value::Float((pos + 1) as f64))
-                } else {
-                    Ok(Value::Float(0.0))
-                }
-            }),
-
-            // Handle unary minus
-            Token::Minus => {
-                self.position += 1;
-                let operand = self.evaluate_primary(tokens, mem)?;
-                match operand {
-                    Value::Float(f) => Ok(Value::Float(-f)),
-                    Value::Integer(i) => Ok(Value::Integer(-i)),
-                    Value::String(_) => Err(BasicError::TypeMismatch),
-                }
-            }
-
-            _ => Err(BasicError::ExpectedExpression),
-        };
-
-        // Advance position if successful
-        if result.is_ok() && !matches!(token, Token::LeftParen) {
-            self.position += 1;
-        }
-
-        result
-    }
-
-    /// Evaluate a unary function with one argument
-    fn evaluate_unary_function<F>(
-        &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-        func: F,
-    ) -> BasicResult<Value>
-    where
-        F: FnOnce(Value) -> BasicResult<Value>,
-    {
-        self.position += 1; // Skip function name
-
-        // Check for opening parenthesis
-        if self.position >= tokens.len() || tokens[self.position] != Token::LeftParen {
-            return Err(BasicError::ExpectedRightParen);
-        }
-
-        self.position += 1; // Skip '('
-
-        // Evaluate the argument as a full expression
-        let arg_value = self.parse_logical_or(tokens, mem)?;
-
-        // Check for closing parenthesis
-        if self.position >= tokens.len() || tokens[self.position] != Token::RightParen {
-            return Err(BasicError::ExpectedRightParen);
-        }
-
-        self.position += 1; // Skip ')'
-
-        // Apply the function
-        func(arg_value)
-    }
-
-    /// Evaluate a binary function with two arguments
-    fn evaluate_binary_function<F>(
-        &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-        func: F,
-    ) -> BasicResult<Value>
-    where
-        F: FnOnce(Value, Value) -> BasicResult<Value>,
-    {
-        self.position += 1; // Skip function name
-
-        // Check for opening parenthesis
-        if self.position >= tokens.len() || tokens[self.position] != Token::LeftParen {
-            return Err(BasicError::ExpectedRightParen);
-        }
-
-        self.position += 1; // Skip '('
-
-        // Evaluate first argument
-        let arg1 = self.parse_logical_or(tokens, mem)?;
-
-        // Check for comma
-        if self.position >= tokens.len() || tokens[self.position] != Token::Comma {
-            return Err(BasicError::Syntax);
+            return Ok(Value::Float(mem.free_memory() as f64));
         }
 
-        self.position += 1; // Skip ','
-
-        // Evaluate second argument
-        let arg2 = self.parse_logical_or(tokens, mem)?;
-
-        // Check for closing parenthesis
-        if self.position >= tokens.len() || tokens[self.position] != Token::RightParen {
-            return Err(BasicError::ExpectedRightParen);
+        let result = self.functions.call(name, &args)?;
+        // String functions create their result in string space
+        if let Value::String(s) = &result {
+            mem.push_temporary(s)?;
         }
-
-        self.position += 1; // Skip ')'
-
-        // Apply the function
-        func(arg1, arg2)
+        Ok(result)
     }
 
-    /// Evaluate a ternary function with three arguments
-    fn evaluate_ternary_function<F>(
-        &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-        func: F,
-    ) -> BasicResult<Value>
-    where
-        F: FnOnce(Value, Value, Value) -> BasicResult<Value>,
-    {
-        self.position += 1; // Skip function name
-
-        // Check for opening parenthesis
-        if self.position >= tokens.len() || tokens[self.position] != Token::LeftParen {
-            return Err(BasicError::ExpectedRightParen);
-        }
-
-        self.position += 1; // Skip '('
-
-        // Evaluate first argument
-        let arg1 = self.parse_logical_or(tokens, mem)?;
-
-        // Check for first comma
-        if self.position >= tokens.len() || tokens[self.position] != Token::Comma {
-            return Err(BasicError::Syntax);
-        }
-
-        self.position += 1; // Skip ','
-
-        // Evaluate second argument
-        let arg2 = self.parse_logical_or(tokens, mem)?;
-
-        // Check for second comma
-        if self.position >= tokens.len() || tokens[self.position] != Token::Comma {
-            return Err(BasicError::Syntax);
+    /// Evaluate function arguments
+    fn eval_args(&mut self, args: &[Expr], mem: &mut MemoryManager) -> BasicResult<Vec<Value>> {
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.eval(arg, mem)?);
         }
-
-        self.position += 1; // Skip ','
-
-        // Evaluate third argument
-        let arg3 = self.parse_logical_or(tokens, mem)?;
-
-        // Check for closing parenthesis
-        if self.position >= tokens.len() || tokens[self.position] != Token::RightParen {
-            return Err(BasicError::ExpectedRightParen);
-        }
-
-        self.position += 1; // Skip ')'
-
-        // Apply the function
-        func(arg1, arg2, arg3)
+        Ok(values)
     }
 
-    /// Execute a pending operation from the operator stack
-    #[allow(dead_code)]
-    fn execute_pending_operation(&mut self) -> BasicResult<()> {
-        if self.operator_stack.is_empty() || self.value_stack.len() < 2 {
-            return Err(BasicError::Syntax);
+    /// Evaluate array subscripts
+    pub fn eval_indices(&mut self, indices: &[Expr], mem: &mut MemoryManager) -> BasicResult<Vec<usize>> {
+        let mut result = Vec::with_capacity(indices.len());
+        for index in indices {
+            result.push(self.eval(index, mem)?.to_float()? as usize);
         }
-
-        let frame = self.operator_stack.pop().unwrap();
-        let right_value = self.value_stack.pop().unwrap();
-
-        // Execute the operation with the stored left value and current right value
-        let result = self.execute_binary_operation(&frame.left_value, &frame.operator, &right_value)?;
-
-        // Push the result back to the value stack
-        self.value_stack.push(result);
-
-        Ok(())
+        Ok(result)
     }
 
     /// Execute a binary operation between two values
-    #[allow(dead_code)]
-    fn execute_binary_operation(&self, left: &Value, op: &Token, right: &Value) -> BasicResult<Value> {
+    fn execute_binary_operation(&self, left: &Value, op: BinaryOp, right: &Value) -> BasicResult<Value> {
         match op {
             // Arithmetic operations
-            Token::Plus => self.execute_addition(left, right),
-            Token::Minus => self.execute_subtraction(left, right),
-            Token::Multiply => self.execute_multiplication(left, right),
-            Token::Divide => self.execute_division(left, right),
-            Token::Power => self.execute_power(left, right),
+            BinaryOp::Add => self.execute_addition(left, right),
+            BinaryOp::Subtract => self.execute_subtraction(left, right),
+            BinaryOp::Multiply => self.execute_multiplication(left, right),
+            BinaryOp::Divide => self.execute_division(left, right),
+            BinaryOp::Power => self.execute_power(left, right),
 
             // Comparison operations
-            Token::Equal => self.execute_equal(left, right),
-            Token::NotEqual => self.execute_not_equal(left, right),
-            Token::Less => self.execute_less(left, right),
-            Token::LessEqual => self.execute_less_equal(left, right),
-            Token::Greater => self.execute_greater(left, right),
-            Token::GreaterEqual => self.execute_greater_equal(left, right),
+            BinaryOp::Equal => self.execute_equal(left, right),
+            BinaryOp::NotEqual => self.execute_not_equal(left, right),
+            BinaryOp::Less => self.execute_less(left, right),
+            BinaryOp::LessEqual => self.execute_less_equal(left, right),
+            BinaryOp::Greater => self.execute_greater(left, right),
+            BinaryOp::GreaterEqual => self.execute_greater_equal(left, right),
 
             // Logical operations
-            Token::And => self.execute_and(left, right),
-            Token::Or => self.execute_or(left, right),
-
-            _ => Err(BasicError::Syntax),
+            BinaryOp::And => self.execute_and(left, right),
+            BinaryOp::Or => self.execute_or(left, right),
         }
     }
 
@@ -820,50 +275,6 @@ impl ExpressionEvaluator {
         let result = (left_f != 0.0) || (right_f != 0.0);
         Ok(Value::Float(if result { 1.0 } else { 0.0 }))
     }
-
-    /// Get the current token
-    fn current_token<'a>(&self, tokens: &'a [Token]) -> Option<&'a Token> {
-        tokens.get(self.position)
-    }
-
-    /// Evaluate a subexpression (used for parentheses)
-    /// Creates a temporary evaluator to avoid interfering with current state
-    fn evaluate_subexpression(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<Value> {
-        let mut sub_evaluator = ExpressionEvaluator::new();
-        let start_pos = self.position;
-
-        // Find the matching closing parenthesis
-        let mut paren_depth = 1;
-        let mut end_pos = start_pos;
-
-        while end_pos < tokens.len() && paren_depth > 0 {
-            match &tokens[end_pos] {
-                Token::LeftParen => paren_depth += 1,
-                Token::RightParen => paren_depth -= 1,
-                _ => {}
-            }
-            end_pos += 1;
-        }
-
-        if paren_depth != 0 {
-            return Err(BasicError::ExpectedRightParen);
-        }
-
-        // Evaluate the subexpression
-        let sub_tokens = &tokens[start_pos..end_pos - 1]; // Exclude closing paren
-        let result = sub_evaluator.evaluate(sub_tokens, mem)?;
-
-        // Update position past the closing parenthesis
-        self.position = end_pos;
-
-        Ok(result)
-    }
-
-    /// Peek at the next token without advancing
-    #[allow(dead_code)]
-    fn peek_token<'a>(&self, tokens: &'a [Token]) -> Option<&'a Token> {
-        tokens.get(self.position + 1)
-    }
 }
 
 impl Default for ExpressionEvaluator {
@@ -944,6 +355,16 @@ mod tests {
         assert_eq!(evaluate_expression("SQR(9)").unwrap(), Value::Float(3.0));
     }
 
+    #[test]
+    fn test_relational_precedence_and_not() {
+        // Comparisons bind looser than arithmetic
+        assert_eq!(evaluate_expression("3 > 1 + 1").unwrap(), Value::Float(1.0));
+        assert_eq!(evaluate_expression("1 + 1 = 2").unwrap(), Value::Float(1.0));
+        assert_eq!(evaluate_expression("NOT 0").unwrap(), Value::Float(1.0));
+        assert_eq!(evaluate_expression("NOT 1 = 1").unwrap(), Value::Float(0.0));
+        assert_eq!(evaluate_expression("-2 ^ 2").unwrap(), Value::Float(-4.0));
+    }
+
     #[test]
     fn test_string_concatenation() {
         assert_eq!(evaluate_expression("\"HELLO\" + \" \" + \"WORLD\"").unwrap(),
@@ -979,6 +400,61 @@ mod tests {
         assert!(matches!(evaluate_expression("5 / 0"), Err(BasicError::DivisionByZero)));
     }
 
+    #[test]
+    fn test_unimplemented_function() {
+        assert!(matches!(evaluate_expression("PEEK(0)"), Err(BasicError::UndefinedFunction)));
+    }
+
+    #[test]
+    fn test_string_temporaries_and_fre() {
+        let mut evaluator = ExpressionEvaluator::new();
+        let mut mem = MemoryManager::with_string_space(100);
+        let mut lexer = Lexer::new();
+        let mut eval = |expr: &str, mem: &mut MemoryManager| {
+            let tokens = lexer.tokenize(expr).unwrap();
+            evaluator.evaluate(&tokens, mem)
+        };
+
+        assert_eq!(eval("FRE(0)", &mut mem), Ok(Value::Float(100.0)));
+        // The concatenation and LEFT$ results are temporaries in string space
+        assert_eq!(eval("LEFT$(\"ABC\" + \"DEF\", 4)", &mut mem), Ok(Value::String("ABCD".to_string())));
+        assert_eq!(mem.strings().available_memory(), 90);
+        mem.release_temporaries();
+        assert_eq!(mem.strings().available_memory(), 100);
+        assert_eq!(eval("FRE(1, 2)", &mut mem), Err(BasicError::Syntax));
+    }
+
+    #[test]
+    fn test_host_functions() {
+        use crate::functions::ArgType;
+        use std::collections::HashMap;
+
+        let (mut evaluator, mut mem, mut lexer) = create_test_evaluator();
+        let db: HashMap<String, String> = [("NAME".to_string(), "ADA".to_string())].into();
+        evaluator.functions_mut().register("DB$", &[ArgType::String], ArgType::String, move |args| {
+            let Value::String(key) = &args[0] else { unreachable!() };
+            Ok(Value::String(db.get(key).cloned().unwrap_or_default()))
+        });
+        evaluator.functions_mut().register("HTTPGET$", &[ArgType::String], ArgType::String, |args| {
+            let Value::String(url) = &args[0] else { unreachable!() };
+            Ok(Value::String(format!("200 {}", url)))
+        });
+
+        let mut eval = |expr: &str, mem: &mut MemoryManager| {
+            let tokens = lexer.tokenize(expr).unwrap();
+            evaluator.evaluate(&tokens, mem)
+        };
+        assert_eq!(eval("DB$(\"NAME\") + \"!\"", &mut mem).unwrap(), Value::String("ADA!".to_string()));
+        assert_eq!(eval("LEN(HTTPGET$(\"/\"))", &mut mem).unwrap(), Value::Float(5.0));
+        // Host functions get the same argument checking as the built-ins
+        assert_eq!(eval("DB$(1)", &mut mem), Err(BasicError::TypeMismatch));
+        assert_eq!(eval("DB$(\"A\", \"B\")", &mut mem), Err(BasicError::Syntax));
+
+        // A dimensioned array of the same name takes precedence
+        mem.arrays_mut().insert("DB$".to_string(), crate::runtime::memory::Array::new("DB$".to_string(), vec![2]));
+        assert_eq!(eval("DB$(1)", &mut mem), Ok(Value::String(String::new())));
+    }
+
     #[test]
     fn test_sqrt_negative() {
         assert!(matches!(evaluate_expression("SQR(-4)"), Err(BasicError::IllegalQuantity)));
@@ -995,7 +471,7 @@ mod tests {
         // Just test that RND returns a valid float
         let result = evaluate_expression("RND(1)").unwrap();
         match result {
-            Value::Float(f) => assert!(f >= 0.0 && f < 1.0),
+            Value::Float(f) => assert!((0.0..1.0).contains(&f)),
             _ => panic!("RND should return a float"),
         }
     }
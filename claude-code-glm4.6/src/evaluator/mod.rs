@@ -4,4 +4,5 @@
 
 pub use evaluator::ExpressionEvaluator;
 
+#[allow(clippy::module_inception)]
 mod evaluator;
\ No newline at end of file
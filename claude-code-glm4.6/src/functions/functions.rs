@@ -1,14 +1,360 @@
-//! Placeholder built-in functions implementation
+//! Function registry
+//!
+//! Every function callable from a BASIC expression is described by a
+//! `Function`: its name, parameter types, result type and implementation.
+//! The registry starts out with the standard functions; an embedding
+//! application can add its own Rust closures, which are then called with the
+//! same argument checking as the built-ins.
 
-#[allow(dead_code)]
+use std::collections::HashMap;
+use std::fmt;
 
-pub struct BuiltinFunctions {
-    // TODO: Add function state
+use crate::error::{BasicError, BasicResult};
+use crate::runtime::memory::Value;
+
+/// Type of a function parameter or result
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgType {
+    /// Integer or floating point value
+    Number,
+    /// String value
+    String,
+}
+
+impl ArgType {
+    /// Check whether a value has this type
+    pub fn matches(self, value: &Value) -> bool {
+        match self {
+            ArgType::Number => matches!(value, Value::Integer(_) | Value::Float(_)),
+            ArgType::String => matches!(value, Value::String(_)),
+        }
+    }
+}
+
+/// Implementation of a function
+///
+/// The arguments have already been checked against the parameter types.
+pub type NativeFunction = Box<dyn Fn(&[Value]) -> BasicResult<Value>>;
+
+/// A function callable from BASIC
+pub struct Function {
+    name: String,
+    params: Vec<ArgType>,
+    returns: ArgType,
+    implementation: NativeFunction,
 }
 
-impl BuiltinFunctions {
-    #[allow(dead_code)]
+impl Function {
+    /// Function name as written in BASIC, e.g. "LEFT$"
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Parameter types; the length is the function's arity
+    pub fn params(&self) -> &[ArgType] {
+        &self.params
+    }
+
+    /// Result type
+    pub fn returns(&self) -> ArgType {
+        self.returns
+    }
+
+    /// Check the arguments, call the implementation and check its result
+    ///
+    /// A wrong number of arguments is a SYNTAX ERROR, an argument or result
+    /// of the wrong type a TYPE MISMATCH.
+    pub fn call(&self, args: &[Value]) -> BasicResult<Value> {
+        if args.len() != self.params.len() {
+            return Err(BasicError::Syntax);
+        }
+        if !self.params.iter().zip(args).all(|(param, arg)| param.matches(arg)) {
+            return Err(BasicError::TypeMismatch);
+        }
+
+        let result = (self.implementation)(args)?;
+        if !self.returns.matches(&result) {
+            return Err(BasicError::TypeMismatch);
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("returns", &self.returns)
+            .finish()
+    }
+}
+
+/// Functions callable from BASIC, keyed by name
+#[derive(Debug)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Function>,
+}
+
+impl FunctionRegistry {
+    /// Create a registry holding no functions
+    pub fn empty() -> Self {
+        Self { functions: HashMap::new() }
+    }
+
+    /// Create a registry holding the standard functions
     pub fn new() -> Self {
-        Self {}
+        let mut registry = Self::empty();
+        for &(name, params, returns, implementation) in STANDARD_FUNCTIONS {
+            registry.register(name, params, returns, implementation);
+        }
+        registry
+    }
+
+    /// Register a function, replacing any function of the same name
+    ///
+    /// Names are case-insensitive and follow BASIC's naming rules: a function
+    /// returning a string should end in `$`. Functions whose name is not a
+    /// BASIC keyword are called with array syntax, `DB$(K$)`, and take at
+    /// least one argument; an array of the same name, once dimensioned, takes
+    /// precedence. A replaced standard function keeps its standard number of
+    /// arguments, which the parser checks.
+    pub fn register<F>(&mut self, name: &str, params: &[ArgType], returns: ArgType, implementation: F)
+    where
+        F: Fn(&[Value]) -> BasicResult<Value> + 'static,
+    {
+        let name = name.to_uppercase();
+        self.functions.insert(name.clone(), Function {
+            name,
+            params: params.to_vec(),
+            returns,
+            implementation: Box::new(implementation),
+        });
+    }
+
+    /// Remove a function, returning whether it was registered
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.functions.remove(&name.to_uppercase()).is_some()
+    }
+
+    /// Look up a function by name
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// Call a function by name
+    ///
+    /// Unknown names are an UNDEFINED FUNCTION error.
+    pub fn call(&self, name: &str, args: &[Value]) -> BasicResult<Value> {
+        self.get(name).ok_or(BasicError::UndefinedFunction)?.call(args)
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Number of arguments a standard function takes
+///
+/// FRE is answered by the evaluator, which owns memory; POS and PEEK are
+/// keywords without an implementation yet. None of them has an entry.
+pub fn standard_arity(name: &str) -> Option<usize> {
+    STANDARD_FUNCTIONS
+        .iter()
+        .find(|(standard, ..)| *standard == name)
+        .map(|(_, params, ..)| params.len())
+}
+
+type Standard = (&'static str, &'static [ArgType], ArgType, fn(&[Value]) -> BasicResult<Value>);
+
+const N: ArgType = ArgType::Number;
+const S: ArgType = ArgType::String;
+
+/// The standard functions
+static STANDARD_FUNCTIONS: &[Standard] = &[
+    ("SGN", &[N], N, sgn),
+    ("INT", &[N], N, |args| Ok(Value::Float(number(&args[0]).floor()))),
+    ("ABS", &[N], N, |args| Ok(Value::Float(number(&args[0]).abs()))),
+    ("SQR", &[N], N, sqr),
+    ("RND", &[N], N, rnd),
+    ("EXP", &[N], N, |args| Ok(Value::Float(number(&args[0]).exp()))),
+    ("LOG", &[N], N, log),
+    ("SIN", &[N], N, |args| Ok(Value::Float(number(&args[0]).sin()))),
+    ("COS", &[N], N, |args| Ok(Value::Float(number(&args[0]).cos()))),
+    ("TAN", &[N], N, |args| Ok(Value::Float(number(&args[0]).tan()))),
+    ("ATN", &[N], N, |args| Ok(Value::Float(number(&args[0]).atan()))),
+    ("LEN", &[S], N, |args| Ok(Value::Float(string(&args[0]).len() as f64))),
+    ("SPACE$", &[N], S, spaces),
+    ("TAB", &[N], S, spaces),
+    ("LEFT$", &[S, N], S, left),
+    ("RIGHT$", &[S, N], S, right),
+    ("MID$", &[S, N, N], S, mid),
+    ("STR$", &[N], S, string_of),
+    ("VAL", &[S], N, val),
+    ("ASC", &[S], N, asc),
+    ("CHR$", &[N], S, chr),
+    ("INSTR", &[S, S], N, instr),
+];
+
+/// Numeric argument; the type has already been checked
+fn number(value: &Value) -> f64 {
+    value.to_float().unwrap_or(0.0)
+}
+
+/// String argument; the type has already been checked
+fn string(value: &Value) -> &str {
+    match value {
+        Value::String(s) => s,
+        _ => "",
     }
-}
\ No newline at end of file
+}
+
+fn sgn(args: &[Value]) -> BasicResult<Value> {
+    let f = number(&args[0]);
+    Ok(Value::Float(if f > 0.0 { 1.0 } else if f < 0.0 { -1.0 } else { 0.0 }))
+}
+
+fn sqr(args: &[Value]) -> BasicResult<Value> {
+    let f = number(&args[0]);
+    if f < 0.0 {
+        return Err(BasicError::IllegalQuantity);
+    }
+    Ok(Value::Float(f.sqrt()))
+}
+
+fn rnd(_args: &[Value]) -> BasicResult<Value> {
+    // Simple random number implementation - RND(n) returns random between 0 and 1
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    let mut hasher = DefaultHasher::new();
+    std::time::SystemTime::now().hash(&mut hasher);
+    let random = (hasher.finish() % 1000000) as f64 / 1000000.0;
+    Ok(Value::Float(random))
+}
+
+fn log(args: &[Value]) -> BasicResult<Value> {
+    let f = number(&args[0]);
+    if f <= 0.0 {
+        return Err(BasicError::IllegalQuantity);
+    }
+    Ok(Value::Float(f.ln()))
+}
+
+/// SPACE$ and TAB
+fn spaces(args: &[Value]) -> BasicResult<Value> {
+    Ok(Value::String(" ".repeat(number(&args[0]) as usize)))
+}
+
+fn left(args: &[Value]) -> BasicResult<Value> {
+    let s = string(&args[0]);
+    let len = (number(&args[1]) as usize).min(s.len());
+    Ok(Value::String(s[..len].to_string()))
+}
+
+fn right(args: &[Value]) -> BasicResult<Value> {
+    let s = string(&args[0]);
+    let len = (number(&args[1]) as usize).min(s.len());
+    Ok(Value::String(s[s.len() - len..].to_string()))
+}
+
+fn mid(args: &[Value]) -> BasicResult<Value> {
+    let s = string(&args[0]);
+    let start = number(&args[1]) as usize;
+    let len = number(&args[2]) as usize;
+
+    if start == 0 || start > s.len() {
+        return Ok(Value::String(String::new()));
+    }
+
+    let start_idx = start - 1; // BASIC is 1-indexed
+    let end_idx = std::cmp::min(start_idx + len, s.len());
+    Ok(Value::String(s[start_idx..end_idx].to_string()))
+}
+
+fn string_of(args: &[Value]) -> BasicResult<Value> {
+    let f = number(&args[0]);
+    // Convert to string, removing decimal part if it's .0
+    if f.fract() == 0.0 {
+        Ok(Value::String(format!("{}", f as i64)))
+    } else {
+        Ok(Value::String(format!("{}", f)))
+    }
+}
+
+fn val(args: &[Value]) -> BasicResult<Value> {
+    // Trim whitespace from the string before parsing
+    let trimmed = string(&args[0]).trim();
+    if trimmed.is_empty() {
+        return Ok(Value::Float(0.0)); // VAL of empty string is 0 in BASIC
+    }
+    trimmed.parse::<f64>()
+        .map(Value::Float)
+        .map_err(|_| BasicError::TypeMismatch)
+}
+
+fn asc(args: &[Value]) -> BasicResult<Value> {
+    match string(&args[0]).chars().next() {
+        Some(c) => Ok(Value::Float(c as u8 as f64)),
+        None => Err(BasicError::IllegalQuantity),
+    }
+}
+
+fn chr(args: &[Value]) -> BasicResult<Value> {
+    let f = number(&args[0]);
+    if !(0.0..=255.0).contains(&f) {
+        return Err(BasicError::IllegalQuantity);
+    }
+    Ok(Value::String((f as u8 as char).to_string()))
+}
+
+fn instr(args: &[Value]) -> BasicResult<Value> {
+    // INSTR returns 1-based index, 0 if not found
+    let position = string(&args[0]).find(string(&args[1])).map_or(0, |pos| pos + 1);
+    Ok(Value::Float(position as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_functions() {
+        let registry = FunctionRegistry::new();
+        assert_eq!(registry.call("ABS", &[Value::Float(-2.0)]).unwrap(), Value::Float(2.0));
+        assert_eq!(
+            registry.call("MID$", &[Value::String("HELLO".to_string()), Value::Integer(2), Value::Float(3.0)]).unwrap(),
+            Value::String("ELL".to_string())
+        );
+        assert_eq!(registry.get("LEFT$").unwrap().params(), &[ArgType::String, ArgType::Number]);
+        assert_eq!(standard_arity("MID$"), Some(3));
+        assert_eq!(standard_arity("FRE"), None);
+    }
+
+    #[test]
+    fn test_argument_checking() {
+        let registry = FunctionRegistry::new();
+        assert_eq!(registry.call("LEN", &[Value::Float(1.0)]), Err(BasicError::TypeMismatch));
+        assert_eq!(registry.call("SIN", &[]), Err(BasicError::Syntax));
+        assert_eq!(registry.call("PEEK", &[Value::Float(0.0)]), Err(BasicError::UndefinedFunction));
+    }
+
+    #[test]
+    fn test_register_native_function() {
+        let mut registry = FunctionRegistry::empty();
+        registry.register("db$", &[ArgType::String], ArgType::String, |args| {
+            Ok(Value::String(format!("<{}>", string(&args[0]))))
+        });
+        registry.register("BAD", &[], ArgType::Number, |_| Ok(Value::String(String::new())));
+
+        assert_eq!(
+            registry.call("DB$", &[Value::String("K".to_string())]).unwrap(),
+            Value::String("<K>".to_string())
+        );
+        // The declared result type is enforced
+        assert_eq!(registry.call("BAD", &[]), Err(BasicError::TypeMismatch));
+        assert!(registry.unregister("DB$"));
+        assert!(registry.get("DB$").is_none());
+    }
+}
@@ -1,5 +1,9 @@
 //! Built-in functions for BASIC
 //!
-//! This module implements all built-in mathematical and string functions.
+//! This module implements all built-in mathematical and string functions
+//! and the registry through which they, and host-defined functions, are called.
 
-mod functions;
\ No newline at end of file
+pub use self::functions::{standard_arity, ArgType, Function, FunctionRegistry, NativeFunction};
+
+#[allow(clippy::module_inception)]
+mod functions;
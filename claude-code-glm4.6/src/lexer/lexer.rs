@@ -142,7 +142,7 @@ impl Lexer {
         let start_pos = self.position;
 
         // Check if we start with digits
-        if !self.current_char.map_or(false, |c| c.is_ascii_digit()) {
+        if !self.current_char.is_some_and(|c| c.is_ascii_digit()) {
             return Ok(None);
         }
 
@@ -158,7 +158,7 @@ impl Lexer {
         }
 
         // Check if followed by whitespace (indicating a line number)
-        if self.current_char.map_or(false, |c| c.is_whitespace()) {
+        if self.current_char.is_some_and(|c| c.is_whitespace()) {
             let line_number = number_str.parse::<u16>()
                 .map_err(|_| BasicError::IllegalQuantity)?;
             Ok(Some(line_number))
@@ -311,6 +311,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_tokenize_number() {
         let mut lexer = Lexer::new();
 
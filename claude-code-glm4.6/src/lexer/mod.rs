@@ -1,5 +1,6 @@
 pub use self::lexer::Lexer;
 pub use self::tokens::Token;
 
+#[allow(clippy::module_inception)]
 mod lexer;
 mod tokens;
\ No newline at end of file
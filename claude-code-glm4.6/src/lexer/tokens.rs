@@ -3,8 +3,6 @@
 //! This module defines all tokens used in the BASIC language,
 //! corresponding to the original token system in Microsoft BASIC.
 
-#[allow(dead_code)]
-
 use std::fmt;
 
 /// All possible tokens in BASIC, corresponding to the original token values
@@ -188,6 +186,38 @@ impl Token {
         )
     }
 
+    /// Name of the built-in function this token stands for
+    pub fn function_name(&self) -> Option<&'static str> {
+        Some(match self {
+            Token::Sgn => "SGN",
+            Token::Int => "INT",
+            Token::Abs => "ABS",
+            Token::Fre => "FRE",
+            Token::Pos => "POS",
+            Token::Sqr => "SQR",
+            Token::Rnd => "RND",
+            Token::Log => "LOG",
+            Token::Exp => "EXP",
+            Token::Cos => "COS",
+            Token::Sin => "SIN",
+            Token::Tan => "TAN",
+            Token::Atn => "ATN",
+            Token::Peek => "PEEK",
+            Token::Len => "LEN",
+            Token::Space => "SPACE$",
+            Token::Tab => "TAB",
+            Token::Str => "STR$",
+            Token::Val => "VAL",
+            Token::Asc => "ASC",
+            Token::Chr => "CHR$",
+            Token::Left => "LEFT$",
+            Token::Right => "RIGHT$",
+            Token::Mid => "MID$",
+            Token::Instr => "INSTR",
+            _ => return None,
+        })
+    }
+
     /// Check if this token is an operator
     pub fn is_operator(&self) -> bool {
         matches!(
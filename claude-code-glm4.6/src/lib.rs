@@ -22,6 +22,25 @@
 //! let mut executor = StatementExecutor::new();
 //! // Now you can evaluate BASIC expressions or execute statements
 //! ```
+//!
+//! # Host Functions
+//!
+//! Applications embedding the interpreter can make Rust closures callable
+//! from BASIC. Arguments and results are type checked like the built-ins:
+//!
+//! ```rust
+//! use basic_m6502_rust::{ArgType, ExpressionEvaluator, MemoryManager, Value};
+//! use basic_m6502_rust::lexer::Lexer;
+//!
+//! let mut evaluator = ExpressionEvaluator::new();
+//! evaluator.functions_mut().register("TWICE", &[ArgType::Number], ArgType::Number, |args| {
+//!     Ok(Value::Float(args[0].to_float()? * 2.0))
+//! });
+//!
+//! let tokens = Lexer::new().tokenize("TWICE(21)").unwrap();
+//! let value = evaluator.evaluate(&tokens, &mut MemoryManager::new()).unwrap();
+//! assert_eq!(value, Value::Float(42.0));
+//! ```
 
 pub mod lexer;
 pub mod parser;
@@ -38,6 +57,7 @@ pub use lexer::Token;
 pub use runtime::memory::{MemoryManager, Value, Variable, Array};
 pub use evaluator::ExpressionEvaluator;
 pub use statements::StatementExecutor;
+pub use functions::{ArgType, FunctionRegistry};
 
 #[cfg(test)]
 mod tests {
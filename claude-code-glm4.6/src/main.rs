@@ -1,21 +1,11 @@
 use std::io::{self, Write};
 use std::env;
 use std::fs;
+use std::rc::Rc;
 
-mod lexer;
-mod parser;
-mod evaluator;
-mod runtime;
-mod statements;
-mod functions;
-mod error;
-mod utils;
-
-use runtime::memory::MemoryManager;
-use lexer::{Lexer, Token};
-use evaluator::ExpressionEvaluator;
-use statements::StatementExecutor;
-use error::{BasicError, BasicResult};
+use basic_m6502_rust::error::BasicResult;
+use basic_m6502_rust::lexer::Lexer;
+use basic_m6502_rust::{BasicError, MemoryManager, ExpressionEvaluator, StatementExecutor, Token};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Microsoft BASIC 6502 - Rust Implementation v0.1.0");
@@ -48,15 +38,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             match lexer.tokenize(line) {
                 Ok(tokens) => {
                     if let Some(line_num) = extract_line_number(&tokens) {
-                        // Check if this is a DATA statement - if so, process it immediately
-                        if !tokens.is_empty() && matches!(tokens[1], Token::Data) {
-                            // Execute DATA statement to populate data values
-                            if let Err(e) = executor.execute_statement(&tokens[1..], &mut mem, &mut evaluator) {
-                                println!("Error in DATA statement '{}': {:?}", line, e);
-                            }
+                        // Storing the line parses it
+                        if let Err(e) = mem.store_line(line_num, tokens) {
+                            println!("Error in line '{}': {}", line, e);
                         }
-                        // Store the line regardless of type (DATA lines are also stored for LIST command)
-                        mem.store_line(line_num, tokens)?;
                     } else {
                         println!("Warning: Line without line number will be ignored: {}", line);
                     }
@@ -147,13 +132,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                 // 检查是否有行号
                 if let Some(line_number) = extract_line_number(&tokens) {
-                    // Check if this is a DATA statement - if so, process it immediately
-                    if tokens.len() > 1 && matches!(tokens[1], Token::Data) {
-                        if let Err(e) = executor.execute_statement(&tokens[1..], &mut mem, &mut evaluator) {
-                            eprintln!("ERROR: {}", e);
-                        }
-                    }
-                    // 存储程序行
+                    // 存储程序行（同时解析，语法错误在输入时报告）
                     if let Err(e) = mem.store_line(line_number, tokens) {
                         eprintln!("ERROR: {}", e);
                     }
@@ -184,22 +163,8 @@ fn run_program(mem: &mut MemoryManager, executor: &mut StatementExecutor, evalua
         return Ok(());
     }
 
-    // First, process all DATA statements to populate data values
-    for &line_number in &execution_order {
-        let line_tokens = if let Some(program_line) = mem.get_line(line_number) {
-            program_line.tokens.clone()
-        } else {
-            continue;
-        };
-
-        // Skip the LineNumber token and check if it's a DATA statement
-        if line_tokens.len() > 1 && matches!(line_tokens[1], Token::Data) {
-            let tokens = &line_tokens[1..]; // Skip LineNumber
-            if let Err(e) = executor.execute_statement(tokens, mem, evaluator) {
-                eprintln!("ERROR IN DATA STATEMENT ON LINE {}: {}", line_number, e);
-            }
-        }
-    }
+    // First, collect the values of all DATA statements
+    mem.collect_data();
 
     // Start execution from the first line
     let mut current_line_idx = 0;
@@ -208,25 +173,16 @@ fn run_program(mem: &mut MemoryManager, executor: &mut StatementExecutor, evalua
         let line_number = execution_order[current_line_idx];
         mem.set_current_line(line_number);
 
-        // Get a copy of the program line tokens to avoid borrowing issues
-        let line_tokens = if let Some(program_line) = mem.get_line(line_number) {
-            program_line.tokens.clone()
+        // Share the parsed statements to avoid borrowing issues
+        let statements = if let Some(program_line) = mem.get_line(line_number) {
+            Rc::clone(&program_line.statements)
         } else {
             eprintln!("LINE {} NOT FOUND", line_number);
             break;
         };
 
-        // Skip the first LineNumber token
-        let tokens = &line_tokens[1..];
-
-        if !tokens.is_empty() {
-            // Skip DATA statements during normal execution (already processed)
-            if matches!(tokens[0], Token::Data) {
-                current_line_idx += 1;
-                continue;
-            }
-
-            match executor.execute_statement(tokens, mem, evaluator) {
+        if !statements.is_empty() {
+            match executor.execute_statements_from(&statements, mem, evaluator, 0) {
                 Ok(_) => {
                     // Continue to next line
                     current_line_idx += 1;
@@ -255,17 +211,16 @@ fn run_program(mem: &mut MemoryManager, executor: &mut StatementExecutor, evalua
                             current_line_idx = jump_idx;
                             mem.set_current_line(current_jump_line);
                             
-                            // Get the line tokens
-                            let jump_line_tokens = if let Some(program_line) = mem.get_line(current_jump_line) {
-                                program_line.tokens.clone()
+                            // Get the line statements
+                            let jump_statements = if let Some(program_line) = mem.get_line(current_jump_line) {
+                                Rc::clone(&program_line.statements)
                             } else {
                                 eprintln!("LINE {} NOT FOUND", current_jump_line);
                                 break;
                             };
-                            let jump_tokens = &jump_line_tokens[1..];
                             
                             // Execute from the specified statement index
-                            match executor.execute_statement_from(jump_tokens, mem, evaluator, current_statement_idx) {
+                            match executor.execute_statements_from(&jump_statements, mem, evaluator, current_statement_idx) {
                                 Ok(_) => {
                                     // Statement execution completed normally, continue to next line
                                     current_line_idx += 1;
@@ -312,7 +267,7 @@ fn run_program(mem: &mut MemoryManager, executor: &mut StatementExecutor, evalua
                                 }
                                 Err(e) => {
                                     eprintln!("ERROR ON LINE {}: {}", current_jump_line, e);
-                                    break;
+                                    return Ok(());
                                 }
                             }
                         } else {
@@ -389,16 +344,14 @@ fn continue_program(mem: &mut MemoryManager, executor: &mut StatementExecutor, e
 
     // Execute from the statement after the STOP (break_statement + 1)
     mem.set_current_line(break_line);
-    let line_tokens = if let Some(program_line) = mem.get_line(break_line) {
-        program_line.tokens.clone()
+    let statements = if let Some(program_line) = mem.get_line(break_line) {
+        Rc::clone(&program_line.statements)
     } else {
         return Err(BasicError::LineNumberNotFound(break_line));
     };
 
-    let tokens = &line_tokens[1..]; // Skip LineNumber token
-
     // Execute remaining statements on the break line, starting after STOP
-    match executor.execute_statement_from(tokens, mem, evaluator, break_statement + 1) {
+    match executor.execute_statements_from(&statements, mem, evaluator, break_statement + 1) {
         Ok(_) => {
             // Finished the break line, move to next line
             current_line_idx += 1;
@@ -446,22 +399,15 @@ fn continue_program(mem: &mut MemoryManager, executor: &mut StatementExecutor, e
         let line_number = execution_order[current_line_idx];
         mem.set_current_line(line_number);
 
-        let line_tokens = if let Some(program_line) = mem.get_line(line_number) {
-            program_line.tokens.clone()
+        let statements = if let Some(program_line) = mem.get_line(line_number) {
+            Rc::clone(&program_line.statements)
         } else {
             eprintln!("LINE {} NOT FOUND", line_number);
             break;
         };
 
-        let tokens = &line_tokens[1..];
-
-        if !tokens.is_empty() {
-            if matches!(tokens[0], Token::Data) {
-                current_line_idx += 1;
-                continue;
-            }
-
-            match executor.execute_statement(tokens, mem, evaluator) {
+        if !statements.is_empty() {
+            match executor.execute_statements_from(&statements, mem, evaluator, 0) {
                 Ok(_) => {
                     current_line_idx += 1;
                 }
@@ -486,15 +432,14 @@ fn continue_program(mem: &mut MemoryManager, executor: &mut StatementExecutor, e
                             current_line_idx = jump_idx;
                             mem.set_current_line(current_jump_line);
                             
-                            let jump_line_tokens = if let Some(program_line) = mem.get_line(current_jump_line) {
-                                program_line.tokens.clone()
+                            let jump_statements = if let Some(program_line) = mem.get_line(current_jump_line) {
+                                Rc::clone(&program_line.statements)
                             } else {
                                 eprintln!("LINE {} NOT FOUND", current_jump_line);
                                 break;
                             };
-                            let jump_tokens = &jump_line_tokens[1..];
                             
-                            match executor.execute_statement_from(jump_tokens, mem, evaluator, current_statement_idx) {
+                            match executor.execute_statements_from(&jump_statements, mem, evaluator, current_statement_idx) {
                                 Ok(_) => {
                                     current_line_idx += 1;
                                     break;
@@ -538,7 +483,7 @@ fn continue_program(mem: &mut MemoryManager, executor: &mut StatementExecutor, e
                                 }
                                 Err(e) => {
                                     eprintln!("ERROR ON LINE {}: {}", current_jump_line, e);
-                                    break;
+                                    return Ok(());
                                 }
                             }
                         } else {
@@ -584,8 +529,8 @@ fn continue_program(mem: &mut MemoryManager, executor: &mut StatementExecutor, e
     Ok(())
 }
 
-fn extract_line_number(tokens: &[lexer::Token]) -> Option<u16> {
-    if let Some(lexer::Token::LineNumber(num)) = tokens.first() {
+fn extract_line_number(tokens: &[Token]) -> Option<u16> {
+    if let Some(Token::LineNumber(num)) = tokens.first() {
         Some(*num)
     } else {
         None
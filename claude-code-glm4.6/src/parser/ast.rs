@@ -0,0 +1,145 @@
+//! Abstract syntax tree for BASIC programs
+//!
+//! Every program line is parsed into these types once, when it is stored,
+//! so execution never has to re-scan the token stream.
+
+use crate::lexer::Token;
+use crate::runtime::memory::Value;
+
+/// An expression
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// Numeric literal
+    Number(f64),
+    /// String literal
+    String(String),
+    /// Simple variable reference: A, B$, I%
+    Variable(String),
+    /// Array element reference: A(I), B$(I, J)
+    ArrayElement { name: String, indices: Vec<Expr> },
+    /// Call of a function keyword; `name` is its registry name ("SIN", "MID$", ...)
+    ///
+    /// Calls of host-defined functions look like array elements and are
+    /// resolved when evaluated.
+    Function { name: String, args: Vec<Expr> },
+    /// Unary operation
+    Unary { op: UnaryOp, operand: Box<Expr> },
+    /// Binary operation
+    Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
+}
+
+/// Unary operators
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+/// Binary operators
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    /// The binary operator for a token, if the token is one
+    pub fn from_token(token: &Token) -> Option<BinaryOp> {
+        Some(match token {
+            Token::Plus => BinaryOp::Add,
+            Token::Minus => BinaryOp::Subtract,
+            Token::Multiply => BinaryOp::Multiply,
+            Token::Divide => BinaryOp::Divide,
+            Token::Power => BinaryOp::Power,
+            Token::Equal => BinaryOp::Equal,
+            Token::NotEqual => BinaryOp::NotEqual,
+            Token::Less => BinaryOp::Less,
+            Token::LessEqual => BinaryOp::LessEqual,
+            Token::Greater => BinaryOp::Greater,
+            Token::GreaterEqual => BinaryOp::GreaterEqual,
+            Token::And => BinaryOp::And,
+            Token::Or => BinaryOp::Or,
+            _ => return None,
+        })
+    }
+}
+
+/// Assignment target of LET, READ and INPUT
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Variable(String),
+    ArrayElement { name: String, indices: Vec<Expr> },
+}
+
+impl Target {
+    /// Variable or array name
+    pub fn name(&self) -> &str {
+        match self {
+            Target::Variable(name) | Target::ArrayElement { name, .. } => name,
+        }
+    }
+}
+
+/// One element of a PRINT list
+#[derive(Clone, Debug, PartialEq)]
+pub enum PrintItem {
+    Expr(Expr),
+    /// `,` - advance to the next print zone
+    Comma,
+    /// `;` - no spacing
+    Semicolon,
+}
+
+/// What follows THEN
+#[derive(Clone, Debug, PartialEq)]
+pub enum Then {
+    /// IF ... THEN 100 / IF ... THEN GOTO 100
+    Line(u16),
+    /// IF ... THEN statement: statement - the rest of the line
+    Statements(Vec<Statement>),
+}
+
+/// One array declared by DIM
+#[derive(Clone, Debug, PartialEq)]
+pub struct DimArray {
+    pub name: String,
+    /// Upper bound of each dimension
+    pub bounds: Vec<Expr>,
+}
+
+/// A statement
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    Let { target: Target, value: Expr },
+    Print { items: Vec<PrintItem> },
+    /// INPUT ["prompt";|,] A, B$ - `prompt` already includes the separator's effect
+    Input { prompt: String, variables: Vec<String> },
+    Goto(u16),
+    Gosub(u16),
+    Return,
+    If { condition: Expr, then: Then },
+    For { variable: String, start: Expr, end: Expr, step: Option<Expr> },
+    Next { variable: Option<String> },
+    Data(Vec<Value>),
+    Read(Vec<String>),
+    Restore,
+    Dim(Vec<DimArray>),
+    Load(String),
+    Save(String),
+    End,
+    Stop,
+    /// ON expr GOTO/GOSUB line, line, ...
+    On { selector: Expr, gosub: bool, targets: Vec<u16> },
+    Rem,
+}
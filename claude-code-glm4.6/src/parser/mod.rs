@@ -2,4 +2,9 @@
 //!
 //! This module handles parsing tokens into abstract syntax trees.
 
-mod parser;
\ No newline at end of file
+pub use self::ast::*;
+pub use self::parser::Parser;
+
+mod ast;
+#[allow(clippy::module_inception)]
+mod parser;
@@ -1,13 +1,698 @@
-//! Placeholder parser implementation
+//! Recursive descent parser building the AST from tokens
+//!
+//! Expression precedence follows `Token::precedence`, from loosest to tightest:
+//! OR, AND, NOT, relational operators, `+ -`, `* /`, unary minus, `^`.
 
-#[allow(dead_code)]
-pub struct Parser {
-    // TODO: Add parser state
+use crate::error::{BasicError, BasicResult};
+use crate::functions::standard_arity;
+use crate::lexer::Token;
+use crate::runtime::memory::Value;
+use super::ast::{BinaryOp, DimArray, Expr, PrintItem, Statement, Target, Then, UnaryOp};
+
+/// Parser over the tokens of one line
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Create a parser over the given tokens
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self { tokens, position: 0 }
+    }
+
+    /// Parse all colon-separated statements of a line
+    ///
+    /// A leading `LineNumber` token is skipped and empty statements are ignored.
+    /// A missing expression inside a statement is reported as a syntax error.
+    pub fn parse_statements(&mut self) -> BasicResult<Vec<Statement>> {
+        if let Some(Token::LineNumber(_)) = self.peek() {
+            self.position += 1;
+        }
+
+        self.parse_statement_list().map_err(|e| match e {
+            BasicError::ExpectedExpression => BasicError::Syntax,
+            e => e,
+        })
+    }
+
+    /// Parse a single expression that must use up all tokens
+    pub fn parse_expression(&mut self) -> BasicResult<Expr> {
+        let expr = self.parse_or()?;
+
+        // Check that all tokens were consumed
+        match self.peek() {
+            None => Ok(expr),
+            Some(Token::RightParen) => Err(BasicError::ExpectedRightParen),
+            Some(_) => Err(BasicError::ExpectedExpression),
+        }
+    }
+
+    /// Parse statements up to the end of the line
+    fn parse_statement_list(&mut self) -> BasicResult<Vec<Statement>> {
+        let mut statements = Vec::new();
+
+        loop {
+            while self.eat(&Token::Colon) {}
+            if self.peek().is_none() {
+                break;
+            }
+
+            statements.push(self.parse_statement()?);
+
+            // A statement must be followed by a colon or the end of the line
+            if self.peek().is_some() && !self.eat(&Token::Colon) {
+                return Err(BasicError::Syntax);
+            }
+        }
+
+        Ok(statements)
+    }
+
+    /// Parse one statement, dispatching on its keyword
+    fn parse_statement(&mut self) -> BasicResult<Statement> {
+        let token = self.advance().ok_or(BasicError::Syntax)?;
+
+        let statement = match token {
+            Token::Let => self.parse_let()?,
+            Token::Identifier(_) => {
+                // Implicit LET
+                self.position -= 1;
+                self.parse_let()?
+            }
+            Token::Print | Token::Question => self.parse_print()?,
+            Token::Input => self.parse_input()?,
+            Token::Goto => Statement::Goto(self.parse_line_number()?),
+            Token::Gosub => Statement::Gosub(self.parse_line_number()?),
+            Token::Return => Statement::Return,
+            Token::If => self.parse_if()?,
+            Token::For => self.parse_for()?,
+            Token::Next => Statement::Next {
+                variable: self.eat_identifier(),
+            },
+            Token::Data => self.parse_data()?,
+            Token::Read => Statement::Read(self.parse_identifier_list()?),
+            Token::Restore => Statement::Restore,
+            Token::Dim => self.parse_dim()?,
+            Token::Load => Statement::Load(self.parse_string()?),
+            Token::Save => Statement::Save(self.parse_string()?),
+            Token::End => Statement::End,
+            Token::Stop => Statement::Stop,
+            Token::On => self.parse_on()?,
+            Token::Rem => {
+                // The lexer drops the comment text, skip anything left just in case
+                self.position = self.tokens.len();
+                Statement::Rem
+            }
+            _ => return Err(BasicError::Syntax),
+        };
+
+        Ok(statement)
+    }
+
+    /// LET variable = expression / LET array(i, ...) = expression
+    fn parse_let(&mut self) -> BasicResult<Statement> {
+        let target = self.parse_target()?;
+        self.expect(&Token::Equal)?;
+        let value = self.parse_or()?;
+        Ok(Statement::Let { target, value })
+    }
+
+    /// PRINT [item {, | ; item}]
+    fn parse_print(&mut self) -> BasicResult<Statement> {
+        let mut items = Vec::new();
+
+        while !self.at_statement_end() {
+            if self.eat(&Token::Comma) {
+                items.push(PrintItem::Comma);
+            } else if self.eat(&Token::Semicolon) {
+                items.push(PrintItem::Semicolon);
+            } else {
+                // Two expressions must be separated by a comma or semicolon
+                if let Some(PrintItem::Expr(_)) = items.last() {
+                    return Err(BasicError::Syntax);
+                }
+                items.push(PrintItem::Expr(self.parse_or()?));
+            }
+        }
+
+        Ok(Statement::Print { items })
+    }
+
+    /// INPUT ["prompt" {; | ,}] variable [, variable ...]
+    fn parse_input(&mut self) -> BasicResult<Statement> {
+        let prompt = if let Some(Token::String(s)) = self.peek() {
+            let mut prompt = s.clone();
+            self.position += 1;
+
+            match self.advance() {
+                // Semicolon separates the prompt from the input with a space
+                Some(Token::Semicolon) => prompt.push(' '),
+                // Comma means no additional prompt
+                Some(Token::Comma) => {}
+                _ => return Err(BasicError::Syntax),
+            }
+            prompt
+        } else {
+            // No prompt, use default "?"
+            "? ".to_string()
+        };
+
+        let variables = self.parse_identifier_list()?;
+        Ok(Statement::Input { prompt, variables })
+    }
+
+    /// IF condition THEN line / IF condition THEN [GOTO] line / IF condition THEN statements
+    fn parse_if(&mut self) -> BasicResult<Statement> {
+        if self.at_keyword("THEN") {
+            return Err(BasicError::Syntax);
+        }
+        let condition = self.parse_or()?;
+
+        if !self.eat_keyword("THEN") {
+            // IF condition GOTO line
+            if self.eat(&Token::Goto) {
+                let line = self.parse_line_number()?;
+                return Ok(Statement::If { condition, then: Then::Line(line) });
+            }
+            return Err(BasicError::Syntax);
+        }
+
+        let then = match self.peek() {
+            Some(Token::Number(_)) => Then::Line(self.parse_line_number()?),
+            Some(Token::Goto) => {
+                self.position += 1;
+                Then::Line(self.parse_line_number()?)
+            }
+            Some(Token::Identifier(s)) if s == "GOTO" => {
+                self.position += 1;
+                Then::Line(self.parse_line_number()?)
+            }
+            Some(_) => {
+                // The rest of the line belongs to the THEN branch
+                let statements = self.parse_statement_list()?;
+                if statements.is_empty() {
+                    return Err(BasicError::Syntax);
+                }
+                Then::Statements(statements)
+            }
+            None => return Err(BasicError::Syntax),
+        };
+
+        Ok(Statement::If { condition, then })
+    }
+
+    /// FOR variable = start TO end [STEP step]
+    fn parse_for(&mut self) -> BasicResult<Statement> {
+        let variable = self.eat_identifier().ok_or(BasicError::Syntax)?;
+        self.expect(&Token::Equal)?;
+        let start = self.parse_or()?;
+
+        if !self.eat_keyword("TO") {
+            return Err(BasicError::Syntax);
+        }
+        let end = self.parse_or()?;
+
+        let step = if self.eat_keyword("STEP") {
+            Some(self.parse_or()?)
+        } else {
+            None
+        };
+
+        Ok(Statement::For { variable, start, end, step })
+    }
+
+    /// DATA value [, value ...]
+    fn parse_data(&mut self) -> BasicResult<Statement> {
+        let mut values = Vec::new();
+
+        while !self.at_statement_end() {
+            let value = match self.advance() {
+                Some(Token::Number(n)) => Value::Float(*n),
+                Some(Token::Minus) => match self.advance() {
+                    Some(Token::Number(n)) => Value::Float(-*n),
+                    _ => return Err(BasicError::Syntax),
+                },
+                Some(Token::String(s)) => Value::String(s.clone()),
+                _ => return Err(BasicError::Syntax),
+            };
+            values.push(value);
+
+            if !self.at_statement_end() {
+                self.expect(&Token::Comma)?;
+            }
+        }
+
+        Ok(Statement::Data(values))
+    }
+
+    /// DIM array(bound, ...) [, array(bound, ...) ...]
+    fn parse_dim(&mut self) -> BasicResult<Statement> {
+        let mut arrays = Vec::new();
+
+        loop {
+            let name = self.eat_identifier().ok_or(BasicError::Syntax)?;
+            self.expect(&Token::LeftParen)?;
+            let bounds = self.parse_index_list()?;
+            arrays.push(DimArray { name, bounds });
+
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+
+        Ok(Statement::Dim(arrays))
+    }
+
+    /// ON expression GOTO|GOSUB line [, line ...]
+    fn parse_on(&mut self) -> BasicResult<Statement> {
+        let selector = self.parse_or()?;
+
+        let gosub = match self.advance() {
+            Some(Token::Gosub) => true,
+            Some(Token::Goto) => false,
+            _ => return Err(BasicError::Syntax),
+        };
+
+        let mut targets = vec![self.parse_line_number()?];
+        while self.eat(&Token::Comma) {
+            targets.push(self.parse_line_number()?);
+        }
+
+        Ok(Statement::On { selector, gosub, targets })
+    }
+
+    /// Assignment target: variable or array element
+    fn parse_target(&mut self) -> BasicResult<Target> {
+        let name = self.eat_identifier().ok_or(BasicError::Syntax)?;
+
+        if self.eat(&Token::LeftParen) {
+            let indices = self.parse_index_list()?;
+            Ok(Target::ArrayElement { name, indices })
+        } else {
+            Ok(Target::Variable(name))
+        }
+    }
+
+    /// Comma-separated variable names, at least one
+    fn parse_identifier_list(&mut self) -> BasicResult<Vec<String>> {
+        let mut names = vec![self.eat_identifier().ok_or(BasicError::Syntax)?];
+        while self.eat(&Token::Comma) {
+            names.push(self.eat_identifier().ok_or(BasicError::Syntax)?);
+        }
+        Ok(names)
+    }
+
+    /// Line number literal of GOTO, GOSUB, THEN and ON
+    fn parse_line_number(&mut self) -> BasicResult<u16> {
+        match self.advance() {
+            Some(Token::Number(n)) => Ok(*n as u16),
+            _ => Err(BasicError::Syntax),
+        }
+    }
+
+    /// String literal of LOAD and SAVE
+    fn parse_string(&mut self) -> BasicResult<String> {
+        match self.advance() {
+            Some(Token::String(s)) => Ok(s.clone()),
+            _ => Err(BasicError::Syntax),
+        }
+    }
+
+    /// Parse logical OR operations
+    fn parse_or(&mut self) -> BasicResult<Expr> {
+        let mut left = self.parse_and()?;
+        while self.eat(&Token::Or) {
+            let right = self.parse_and()?;
+            left = binary(BinaryOp::Or, left, right);
+        }
+        Ok(left)
+    }
+
+    /// Parse logical AND operations
+    fn parse_and(&mut self) -> BasicResult<Expr> {
+        let mut left = self.parse_not()?;
+        while self.eat(&Token::And) {
+            let right = self.parse_not()?;
+            left = binary(BinaryOp::And, left, right);
+        }
+        Ok(left)
+    }
+
+    /// Parse logical NOT
+    fn parse_not(&mut self) -> BasicResult<Expr> {
+        if self.eat(&Token::Not) {
+            let operand = self.parse_not()?;
+            return Ok(Expr::Unary { op: UnaryOp::Not, operand: Box::new(operand) });
+        }
+        self.parse_relational()
+    }
+
+    /// Parse comparisons
+    fn parse_relational(&mut self) -> BasicResult<Expr> {
+        let mut left = self.parse_additive()?;
+        while let Some(op) = self.eat_operator(&[
+            BinaryOp::Equal,
+            BinaryOp::NotEqual,
+            BinaryOp::Less,
+            BinaryOp::LessEqual,
+            BinaryOp::Greater,
+            BinaryOp::GreaterEqual,
+        ]) {
+            let right = self.parse_additive()?;
+            left = binary(op, left, right);
+        }
+        Ok(left)
+    }
+
+    /// Parse addition and subtraction
+    fn parse_additive(&mut self) -> BasicResult<Expr> {
+        let mut left = self.parse_multiplicative()?;
+        while let Some(op) = self.eat_operator(&[BinaryOp::Add, BinaryOp::Subtract]) {
+            let right = self.parse_multiplicative()?;
+            left = binary(op, left, right);
+        }
+        Ok(left)
+    }
+
+    /// Parse multiplication and division
+    fn parse_multiplicative(&mut self) -> BasicResult<Expr> {
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.eat_operator(&[BinaryOp::Multiply, BinaryOp::Divide]) {
+            let right = self.parse_unary()?;
+            left = binary(op, left, right);
+        }
+        Ok(left)
+    }
+
+    /// Parse unary plus and minus
+    fn parse_unary(&mut self) -> BasicResult<Expr> {
+        if self.eat(&Token::Minus) {
+            let operand = self.parse_unary()?;
+            return Ok(Expr::Unary { op: UnaryOp::Negate, operand: Box::new(operand) });
+        }
+        if self.eat(&Token::Plus) {
+            return self.parse_unary();
+        }
+        self.parse_power()
+    }
+
+    /// Parse the power operator (right-associative)
+    fn parse_power(&mut self) -> BasicResult<Expr> {
+        let base = self.parse_primary()?;
+        if self.eat(&Token::Power) {
+            let exponent = self.parse_unary()?;
+            return Ok(binary(BinaryOp::Power, base, exponent));
+        }
+        Ok(base)
+    }
+
+    /// Parse a primary value (number, string, variable, array element,
+    /// function call, or parenthesized expression)
+    fn parse_primary(&mut self) -> BasicResult<Expr> {
+        if self.at_keyword("THEN") || self.at_keyword("TO") || self.at_keyword("STEP") {
+            return Err(BasicError::ExpectedExpression);
+        }
+
+        let token = self.advance().ok_or(BasicError::ExpectedExpression)?;
+
+        match token {
+            Token::Number(n) => Ok(Expr::Number(*n)),
+            Token::String(s) => Ok(Expr::String(s.clone())),
+            Token::Identifier(name) => {
+                if self.eat(&Token::LeftParen) {
+                    let indices = self.parse_index_list()?;
+                    Ok(Expr::ArrayElement { name: name.clone(), indices })
+                } else {
+                    Ok(Expr::Variable(name.clone()))
+                }
+            }
+            Token::LeftParen => {
+                let inner = self.parse_or()?;
+                if !self.eat(&Token::RightParen) {
+                    return Err(BasicError::ExpectedRightParen);
+                }
+                Ok(inner)
+            }
+            function if function.is_function() => {
+                let name = function.function_name().ok_or(BasicError::Syntax)?;
+                self.expect(&Token::LeftParen)?;
+                let args = self.parse_index_list()?;
+                if standard_arity(name).is_some_and(|arity| args.len() != arity) {
+                    return Err(BasicError::Syntax);
+                }
+                Ok(Expr::Function { name: name.to_string(), args })
+            }
+            _ => Err(BasicError::ExpectedExpression),
+        }
+    }
+
+    /// Comma-separated expressions up to and including the closing parenthesis
+    fn parse_index_list(&mut self) -> BasicResult<Vec<Expr>> {
+        let mut exprs = vec![self.parse_or()?];
+        while self.eat(&Token::Comma) {
+            exprs.push(self.parse_or()?);
+        }
+        if !self.eat(&Token::RightParen) {
+            return Err(BasicError::ExpectedRightParen);
+        }
+        Ok(exprs)
+    }
+
+    /// Get the current token
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Consume and return the current token
+    fn advance(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    /// Consume the current token if it equals `token`
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the current token or fail with a syntax error
+    fn expect(&mut self, token: &Token) -> BasicResult<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(BasicError::Syntax)
+        }
+    }
+
+    /// Consume a variable name
+    fn eat_identifier(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Token::Identifier(name)) if !is_keyword(name) => {
+                self.position += 1;
+                Some(name.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Consume the current token if it is one of the given binary operators
+    fn eat_operator(&mut self, ops: &[BinaryOp]) -> Option<BinaryOp> {
+        let op = self.peek().and_then(BinaryOp::from_token)?;
+        if ops.contains(&op) {
+            self.position += 1;
+            Some(op)
+        } else {
+            None
+        }
+    }
+
+    /// THEN, TO and STEP are not keyword tokens, the lexer leaves them as identifiers
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Identifier(s)) if s == keyword)
+    }
+
+    /// Consume THEN, TO or STEP
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.at_keyword(keyword) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Check for the end of the current statement
+    fn at_statement_end(&self) -> bool {
+        matches!(self.peek(), None | Some(Token::Colon))
+    }
+}
+
+/// Identifiers reserved by IF and FOR syntax
+fn is_keyword(name: &str) -> bool {
+    matches!(name, "THEN" | "TO" | "STEP")
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    Expr::Binary { op, left: Box::new(left), right: Box::new(right) }
 }
 
-impl Parser {
-    #[allow(dead_code)]
-    pub fn new() -> Self {
-        Self {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse_line(input: &str) -> BasicResult<Vec<Statement>> {
+        let tokens = Lexer::new().tokenize(input)?;
+        Parser::new(&tokens).parse_statements()
+    }
+
+    fn parse_expr(input: &str) -> BasicResult<Expr> {
+        let tokens = Lexer::new().tokenize(input)?;
+        Parser::new(&tokens).parse_expression()
+    }
+
+    fn num(n: f64) -> Expr {
+        Expr::Number(n)
+    }
+
+    fn var(name: &str) -> Expr {
+        Expr::Variable(name.to_string())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_precedence() {
+        // Relational operators bind looser than arithmetic
+        assert_eq!(
+            parse_expr("A > B + 1").unwrap(),
+            binary(BinaryOp::Greater, var("A"), binary(BinaryOp::Add, var("B"), num(1.0)))
+        );
+
+        // Unary minus binds looser than ^
+        assert_eq!(
+            parse_expr("-2 ^ 2").unwrap(),
+            Expr::Unary {
+                op: UnaryOp::Negate,
+                operand: Box::new(binary(BinaryOp::Power, num(2.0), num(2.0))),
+            }
+        );
+
+        // NOT binds looser than relational operators, AND tighter than OR
+        assert_eq!(
+            parse_expr("NOT A = 1 OR B AND C").unwrap(),
+            binary(
+                BinaryOp::Or,
+                Expr::Unary {
+                    op: UnaryOp::Not,
+                    operand: Box::new(binary(BinaryOp::Equal, var("A"), num(1.0))),
+                },
+                binary(BinaryOp::And, var("B"), var("C")),
+            )
+        );
+    }
+
+    #[test]
+    fn test_functions_and_arrays() {
+        assert_eq!(
+            parse_expr("MID$(A$, I, 1)").unwrap(),
+            Expr::Function {
+                name: "MID$".to_string(),
+                args: vec![var("A$"), var("I"), num(1.0)],
+            }
+        );
+        assert_eq!(
+            parse_expr("B(I, 2)").unwrap(),
+            Expr::ArrayElement { name: "B".to_string(), indices: vec![var("I"), num(2.0)] }
+        );
+
+        // Wrong number of arguments
+        assert_eq!(parse_expr("LEFT$(A$)"), Err(BasicError::Syntax));
+        assert_eq!(parse_expr("SIN(1, 2)"), Err(BasicError::Syntax));
+        assert_eq!(parse_expr("SIN(1"), Err(BasicError::ExpectedRightParen));
+    }
+
+    #[test]
+    fn test_line_number_and_empty_statements() {
+        let statements = parse_line("10 A = 1 :: PRINT").unwrap();
+        assert_eq!(
+            statements,
+            vec![
+                Statement::Let { target: Target::Variable("A".to_string()), value: num(1.0) },
+                Statement::Print { items: vec![] },
+            ]
+        );
+        let empty = [Token::LineNumber(10)];
+        assert!(Parser::new(&empty).parse_statements().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_if_then_takes_rest_of_line() {
+        let statements = parse_line("IF A THEN B = 1: GOTO 100").unwrap();
+        assert_eq!(
+            statements,
+            vec![Statement::If {
+                condition: var("A"),
+                then: Then::Statements(vec![
+                    Statement::Let { target: Target::Variable("B".to_string()), value: num(1.0) },
+                    Statement::Goto(100),
+                ]),
+            }]
+        );
+
+        assert_eq!(
+            parse_line("IF A THEN GOTO 20").unwrap(),
+            vec![Statement::If { condition: var("A"), then: Then::Line(20) }]
+        );
+        assert_eq!(
+            parse_line("IF A GOTO 20").unwrap(),
+            vec![Statement::If { condition: var("A"), then: Then::Line(20) }]
+        );
+    }
+
+    #[test]
+    fn test_for_and_data() {
+        assert_eq!(
+            parse_line("FOR I = 10 TO 1 STEP -2").unwrap(),
+            vec![Statement::For {
+                variable: "I".to_string(),
+                start: num(10.0),
+                end: num(1.0),
+                step: Some(Expr::Unary { op: UnaryOp::Negate, operand: Box::new(num(2.0)) }),
+            }]
+        );
+        assert_eq!(
+            parse_line("DATA 1, -2.5, \"X\"").unwrap(),
+            vec![Statement::Data(vec![
+                Value::Float(1.0),
+                Value::Float(-2.5),
+                Value::String("X".to_string()),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for input in [
+            "PRINT 1 2",
+            "GOTO",
+            "GOTO 10 20",
+            "FOR I = 1",
+            "IF A > 5",
+            "IF A > 5 THEN",
+            "LET A =",
+            "DATA 1 2",
+            "ON A GOTO",
+            "RUN",
+            "A = 1: LET",
+        ] {
+            assert_eq!(parse_line(input), Err(BasicError::Syntax), "{}", input);
+        }
+    }
+}
@@ -3,12 +3,16 @@
 //! This module defines the main data structures for managing
 //! BASIC program state, corresponding to the original memory layout.
 
-#[allow(dead_code)]
-
 use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::rc::Rc;
 use crate::lexer::Token;
 use crate::error::{BasicError, BasicResult};
+use crate::parser::{Parser, Statement};
+use super::string_pool::{StringDescriptor, StringPool, MAX_STRING_LENGTH};
+
+/// Size of string space: the free memory of a Commodore 64 after power-on
+pub const DEFAULT_STRING_SPACE: usize = 38911;
 
 /// Value types supported in BASIC
 #[derive(Clone, Debug, PartialEq)]
@@ -57,6 +61,7 @@ impl Value {
     }
 
     /// Convert to string
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         match self {
             Value::Integer(n) => n.to_string(),
@@ -91,16 +96,26 @@ pub enum ValueType {
     String,
 }
 
+/// A value as stored in a variable or array element
+///
+/// Strings are kept in string space and referenced by descriptor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoredValue {
+    Integer(i16),
+    Float(f64),
+    String(StringDescriptor),
+}
+
 /// Variable storage structure
 #[derive(Clone, Debug)]
 pub struct Variable {
     pub name: String,        // Variable name (1-2 chars + optional $ or %)
-    pub value: Value,
+    pub value: StoredValue,
 }
 
 impl Variable {
     /// Create a new variable
-    pub fn new(name: String, value: Value) -> Self {
+    pub fn new(name: String, value: StoredValue) -> Self {
         Self { name, value }
     }
 
@@ -116,9 +131,7 @@ impl Variable {
 
     /// Get the base name without type suffix
     pub fn base_name(&self) -> &str {
-        if self.is_string_variable() {
-            &self.name[..self.name.len()-1]
-        } else if self.is_integer_variable() {
+        if self.is_string_variable() || self.is_integer_variable() {
             &self.name[..self.name.len()-1]
         } else {
             &self.name
@@ -131,14 +144,21 @@ impl Variable {
 pub struct Array {
     pub name: String,
     pub dimensions: Vec<usize>,  // Size of each dimension
-    pub data: Vec<Value>,        // Data stored in row-major order
+    pub data: Vec<StoredValue>,  // Data stored in row-major order
 }
 
 impl Array {
     /// Create a new array with given dimensions
+    ///
+    /// Elements start as 0, or as empty strings for string arrays.
     pub fn new(name: String, dimensions: Vec<usize>) -> Self {
         let total_elements = dimensions.iter().product();
-        let data = vec![Value::Float(0.0); total_elements]; // Default to 0.0
+        let initial = if name.ends_with('$') {
+            StoredValue::String(StringDescriptor::EMPTY)
+        } else {
+            StoredValue::Float(0.0)
+        };
+        let data = vec![initial; total_elements];
 
         Self {
             name,
@@ -177,13 +197,13 @@ impl Array {
     }
 
     /// Get value at given indices
-    pub fn get(&self, indices: &[usize]) -> BasicResult<&Value> {
+    pub fn get(&self, indices: &[usize]) -> BasicResult<&StoredValue> {
         let linear = self.indices_to_linear(indices)?;
         Ok(&self.data[linear])
     }
 
     /// Set value at given indices
-    pub fn set(&mut self, indices: &[usize], value: Value) -> BasicResult<()> {
+    pub fn set(&mut self, indices: &[usize], value: StoredValue) -> BasicResult<()> {
         let linear = self.indices_to_linear(indices)?;
         self.data[linear] = value;
         Ok(())
@@ -194,13 +214,15 @@ impl Array {
 #[derive(Clone, Debug)]
 pub struct ProgramLine {
     pub number: u16,
-    pub tokens: Vec<Token>,
+    pub tokens: Vec<Token>,             // Tokens as entered, for LIST and SAVE
+    pub statements: Rc<[Statement]>,    // Statements parsed when the line was stored
 }
 
 impl ProgramLine {
-    /// Create a new program line
-    pub fn new(number: u16, tokens: Vec<Token>) -> Self {
-        Self { number, tokens }
+    /// Create a new program line, parsing its tokens
+    pub fn new(number: u16, tokens: Vec<Token>) -> BasicResult<Self> {
+        let statements = Parser::new(&tokens).parse_statements()?;
+        Ok(Self { number, tokens, statements: statements.into() })
     }
 }
 
@@ -289,14 +311,26 @@ pub struct MemoryManager {
     pub break_line: Option<u16>,         // Line where STOP was executed
     pub break_statement: Option<usize>,  // Statement index where STOP was executed
 
-    // String management (simplified for now)
-    #[allow(dead_code)]
-    string_memory: Vec<String>,
+    // String space (between FRETOP and MEMSIZ in original)
+    strings: StringPool,
+    // Strings created while evaluating the current statement (TEMPST)
+    temporaries: Vec<StringDescriptor>,
+}
+
+impl Default for MemoryManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MemoryManager {
     /// Create a new memory manager
     pub fn new() -> Self {
+        Self::with_string_space(DEFAULT_STRING_SPACE)
+    }
+
+    /// Create a new memory manager with `bytes` of string space
+    pub fn with_string_space(bytes: usize) -> Self {
         Self {
             program_lines: BTreeMap::new(),
             variables: HashMap::new(),
@@ -308,7 +342,8 @@ impl MemoryManager {
             gosub_stack: Vec::new(),
             break_line: None,
             break_statement: None,
-            string_memory: Vec::new(),
+            strings: StringPool::new(bytes),
+            temporaries: Vec::new(),
         }
     }
 
@@ -322,22 +357,26 @@ impl MemoryManager {
         self.data_values.clear();
         self.for_stack.clear();
         self.gosub_stack.clear();
+        self.strings = StringPool::new(self.strings.total_memory());
+        self.temporaries.clear();
     }
 
     /// Store a program line
+    ///
+    /// The line is parsed here, so syntax errors are reported when the line
+    /// is entered and the existing line with the same number is kept.
     pub fn store_line(&mut self, line_number: u16, tokens: Vec<Token>) -> BasicResult<()> {
+        let line = ProgramLine::new(line_number, tokens)?;
+
         // Remove existing line with same number if it exists
-        if self.program_lines.contains_key(&line_number) {
-            self.program_lines.remove(&line_number);
-        }
+        self.program_lines.remove(&line_number);
 
         // If this is an empty line (just line number), delete it
-        if tokens.len() == 1 {
+        if line.statements.is_empty() {
             return Ok(());
         }
 
         // Store the new line
-        let line = ProgramLine::new(line_number, tokens);
         self.program_lines.insert(line_number, line);
         Ok(())
     }
@@ -360,20 +399,130 @@ impl MemoryManager {
     }
 
     /// Get variable value
-    pub fn get_variable(&self, name: &str) -> BasicResult<&Value> {
+    pub fn get_variable(&self, name: &str) -> BasicResult<Value> {
         self.variables
             .get(name)
-            .map(|v| &v.value)
+            .map(|v| self.load(&v.value))
             .ok_or_else(|| BasicError::VariableNotFound(name.to_string()))
     }
 
     /// Set variable value
+    ///
+    /// A string value is stored in string space, which may collect garbage
+    /// or fail with OUT OF MEMORY.
     pub fn set_variable(&mut self, name: String, value: Value) -> BasicResult<()> {
+        let value = self.store(value)?;
         let variable = Variable::new(name.clone(), value);
         self.variables.insert(name, variable);
         Ok(())
     }
 
+    /// Get array element value
+    pub fn get_array_element(&self, name: &str, indices: &[usize]) -> BasicResult<Value> {
+        let array = self.arrays.get(name)
+            .ok_or_else(|| BasicError::VariableNotFound(name.to_string()))?;
+        Ok(self.load(array.get(indices)?))
+    }
+
+    /// Set array element value; strings are stored as by `set_variable`
+    pub fn set_array_element(&mut self, name: &str, indices: &[usize], value: Value) -> BasicResult<()> {
+        // Check the subscripts before allocating string space
+        self.arrays.get(name)
+            .ok_or_else(|| BasicError::VariableNotFound(name.to_string()))?
+            .indices_to_linear(indices)?;
+        let value = self.store(value)?;
+        match self.arrays.get_mut(name) {
+            Some(array) => array.set(indices, value),
+            None => Err(BasicError::VariableNotFound(name.to_string())),
+        }
+    }
+
+    /// Turn a stored value into a value
+    fn load(&self, value: &StoredValue) -> Value {
+        match value {
+            StoredValue::Integer(n) => Value::Integer(*n),
+            StoredValue::Float(f) => Value::Float(*f),
+            StoredValue::String(descriptor) => Value::String(self.strings.get_string(descriptor)),
+        }
+    }
+
+    /// Turn a value into a stored value, putting strings into string space
+    fn store(&mut self, value: Value) -> BasicResult<StoredValue> {
+        match value {
+            Value::Integer(n) => Ok(StoredValue::Integer(n)),
+            Value::Float(f) => Ok(StoredValue::Float(f)),
+            Value::String(s) => {
+                // Like LET, take over the temporary the expression just created
+                // instead of copying it
+                if let Some(&top) = self.temporaries.last() {
+                    if self.strings.holds(&top, &s) {
+                        self.temporaries.pop();
+                        return Ok(StoredValue::String(top));
+                    }
+                }
+                self.allocate_string(&s).map(StoredValue::String)
+            }
+        }
+    }
+
+    /// Allocate a string in string space, collecting garbage if it is full
+    fn allocate_string(&mut self, string: &str) -> BasicResult<StringDescriptor> {
+        if string.len() > MAX_STRING_LENGTH {
+            return Err(BasicError::StringTooLong);
+        }
+        if let Some(descriptor) = self.strings.store_string(string) {
+            return Ok(descriptor);
+        }
+        self.collect_garbage();
+        self.strings.store_string(string).ok_or(BasicError::OutOfMemory)
+    }
+
+    /// Put a string created by an expression (concatenation, LEFT$, ...)
+    /// into string space until the end of the statement
+    pub fn push_temporary(&mut self, string: &str) -> BasicResult<()> {
+        let descriptor = self.allocate_string(string)?;
+        if descriptor.length > 0 {
+            self.temporaries.push(descriptor);
+        }
+        Ok(())
+    }
+
+    /// Release the temporaries of the finished statement
+    ///
+    /// Temporaries at the bottom of string space give their space back at
+    /// once; the others are garbage until the next collection.
+    pub fn release_temporaries(&mut self) {
+        while let Some(descriptor) = self.temporaries.pop() {
+            self.strings.free_last(&descriptor);
+        }
+    }
+
+    /// Compact the strings still referenced by variables, arrays and
+    /// temporaries (garbage collection)
+    pub fn collect_garbage(&mut self) {
+        let mut live: Vec<&mut StringDescriptor> = Vec::new();
+        let stored = self.variables.values_mut().map(|variable| &mut variable.value)
+            .chain(self.arrays.values_mut().flat_map(|array| array.data.iter_mut()));
+        for value in stored {
+            if let StoredValue::String(descriptor) = value {
+                live.push(descriptor);
+            }
+        }
+        live.extend(self.temporaries.iter_mut());
+        self.strings.garbage_collect(live);
+    }
+
+    /// Free bytes of string space after collecting garbage (FRE)
+    pub fn free_memory(&mut self) -> usize {
+        self.collect_garbage();
+        self.strings.available_memory()
+    }
+
+    /// String space
+    pub fn strings(&self) -> &StringPool {
+        &self.strings
+    }
+
     /// Get array
     #[allow(dead_code)]
     pub fn get_array(&self, name: &str) -> BasicResult<&Array> {
@@ -390,7 +539,7 @@ impl MemoryManager {
             self.arrays.insert(name.clone(), array);
         }
         self.arrays.get_mut(&name)
-            .ok_or_else(|| BasicError::ArrayNotFound(name))
+            .ok_or(BasicError::ArrayNotFound(name))
     }
 
     /// Get all variables (for testing and debugging)
@@ -487,6 +636,19 @@ impl MemoryManager {
         self.for_stack.clear();
     }
 
+    /// Collect the values of all DATA statements in program order (done by RUN)
+    pub fn collect_data(&mut self) {
+        self.data_values.clear();
+        self.data_pointer = 0;
+        for line in self.program_lines.values() {
+            for statement in line.statements.iter() {
+                if let Statement::Data(values) = statement {
+                    self.data_values.extend(values.iter().cloned());
+                }
+            }
+        }
+    }
+
     /// Add values from DATA statement to data storage
     pub fn add_data_values(&mut self, values: Vec<Value>) {
         self.data_values.extend(values);
@@ -524,6 +686,7 @@ mod tests {
     use crate::lexer::Token;
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_value_conversions() {
         let int_val = Value::Integer(42);
         let float_val = Value::Float(3.14);
@@ -550,11 +713,11 @@ mod tests {
         assert!(array.indices_to_linear(&[0, 0, 0]).is_err()); // Too many indices
 
         // Test linear to multidimensional mapping
-        let linear = array.indices_to_linear(&[1, 2]).unwrap();
-        assert_eq!(array.get(&[1, 2]).unwrap(), &Value::Float(0.0));
+        assert_eq!(array.indices_to_linear(&[1, 2]).unwrap(), 6);
+        assert_eq!(array.get(&[1, 2]).unwrap(), &StoredValue::Float(0.0));
 
-        array.set(&[1, 2], Value::Integer(99)).unwrap();
-        assert_eq!(array.get(&[1, 2]).unwrap(), &Value::Integer(99));
+        array.set(&[1, 2], StoredValue::Integer(99)).unwrap();
+        assert_eq!(array.get(&[1, 2]).unwrap(), &StoredValue::Integer(99));
     }
 
     #[test]
@@ -565,8 +728,8 @@ mod tests {
         mem.set_variable("A$".to_string(), Value::String("test".to_string())).unwrap();
         mem.set_variable("B".to_string(), Value::Integer(42)).unwrap();
 
-        assert_eq!(mem.get_variable("A$").unwrap(), &Value::String("test".to_string()));
-        assert_eq!(mem.get_variable("B").unwrap(), &Value::Integer(42));
+        assert_eq!(mem.get_variable("A$").unwrap(), Value::String("test".to_string()));
+        assert_eq!(mem.get_variable("B").unwrap(), Value::Integer(42));
 
         // Test program line storage
         let tokens = vec![Token::Print, Token::String("Hello".to_string())];
@@ -574,6 +737,81 @@ mod tests {
 
         assert!(mem.get_line(10).is_some());
         assert!(mem.get_line(20).is_none());
+
+        // A line with a syntax error is rejected and the old line is kept
+        let bad = vec![Token::LineNumber(10), Token::Goto];
+        assert_eq!(mem.store_line(10, bad), Err(BasicError::Syntax));
+        assert!(mem.get_line(10).is_some());
+
+        // A bare line number deletes the line
+        mem.store_line(10, vec![Token::LineNumber(10)]).unwrap();
+        assert!(mem.get_line(10).is_none());
+    }
+
+    #[test]
+    fn test_string_space() {
+        let mut mem = MemoryManager::with_string_space(10);
+        mem.set_variable("A$".to_string(), Value::String("HELLO".to_string())).unwrap();
+        assert_eq!(mem.strings().available_memory(), 5);
+
+        // The old value stays behind as garbage until a collection
+        mem.set_variable("A$".to_string(), Value::String("WORLD".to_string())).unwrap();
+        assert_eq!(mem.strings().available_memory(), 0);
+        assert_eq!(mem.strings().collections(), 0);
+
+        // Out of room: collecting the garbage makes enough space
+        mem.set_variable("B$".to_string(), Value::String("ABC".to_string())).unwrap();
+        assert_eq!(mem.strings().collections(), 1);
+        assert_eq!(mem.get_variable("A$").unwrap(), Value::String("WORLD".to_string()));
+        assert_eq!(mem.get_variable("B$").unwrap(), Value::String("ABC".to_string()));
+
+        // OUT OF MEMORY only when the live strings do not leave enough space
+        assert_eq!(
+            mem.set_variable("C$".to_string(), Value::String("XYZ".to_string())),
+            Err(BasicError::OutOfMemory)
+        );
+        assert_eq!(mem.strings().collections(), 2);
+        assert_eq!(mem.free_memory(), 2);
+
+        assert_eq!(
+            mem.set_variable("D$".to_string(), Value::String("X".repeat(256))),
+            Err(BasicError::StringTooLong)
+        );
+    }
+
+    #[test]
+    fn test_string_array_elements() {
+        // The old string is still live while its replacement is allocated
+        let mut mem = MemoryManager::with_string_space(9);
+        mem.arrays_mut().insert("S$".to_string(), Array::new("S$".to_string(), vec![3]));
+        assert_eq!(mem.get_array_element("S$", &[1]).unwrap(), Value::String(String::new()));
+
+        for _ in 0..10 {
+            mem.set_array_element("S$", &[0], Value::String("ABC".to_string())).unwrap();
+            mem.set_array_element("S$", &[2], Value::String("DEF".to_string())).unwrap();
+        }
+        assert_eq!(mem.get_array_element("S$", &[0]).unwrap(), Value::String("ABC".to_string()));
+        assert_eq!(mem.get_array_element("S$", &[2]).unwrap(), Value::String("DEF".to_string()));
+        assert_eq!(mem.free_memory(), 3);
+        assert!(mem.set_array_element("S$", &[3], Value::String("X".to_string())).is_err());
+
+        // A temporary is taken over by the assignment instead of being copied
+        mem.push_temporary("XY").unwrap();
+        mem.set_array_element("S$", &[1], Value::String("XY".to_string())).unwrap();
+        mem.release_temporaries();
+        assert_eq!(mem.free_memory(), 1);
+    }
+
+    #[test]
+    fn test_collect_data() {
+        let mut mem = MemoryManager::new();
+        mem.store_line(20, vec![Token::LineNumber(20), Token::Data, Token::String("X".to_string())]).unwrap();
+        mem.store_line(10, vec![Token::LineNumber(10), Token::Data, Token::Number(1.0)]).unwrap();
+
+        mem.collect_data();
+        mem.collect_data(); // Collecting again must not duplicate values
+        assert_eq!(mem.get_data_values(), &vec![Value::Float(1.0), Value::String("X".to_string())]);
+        assert_eq!(mem.read_data_value().unwrap(), Value::Float(1.0));
     }
 
     #[test]
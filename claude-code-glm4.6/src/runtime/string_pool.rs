@@ -1,107 +1,173 @@
 //! String pool management for efficient string storage and garbage collection
 //!
 //! This module handles string storage, similar to the original BASIC's
-//! string space management and garbage collection.
+//! string space management and garbage collection. String space is a fixed
+//! block of bytes; strings are allocated downward from its top (MEMSIZ)
+//! and FRETOP marks the bottom of the allocated strings. Strings that are
+//! no longer referenced are not freed individually: they stay in place as
+//! garbage until a collection compacts the live strings back to the top.
 
-#[allow(dead_code)]
+use std::fmt;
 
-use std::collections::HashMap;
+/// Maximum string length, as in the original
+pub const MAX_STRING_LENGTH: usize = 255;
 
 /// String descriptor (3 bytes in original, expanded for Rust)
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct StringDescriptor {
-    pub length: usize,      // Length of the string
-    pub address: usize,     // Address in string pool
+    pub length: usize,      // Length of the string in bytes
+    pub address: usize,     // Offset of the first byte in string space
+}
+
+impl StringDescriptor {
+    /// The empty string, which takes no string space
+    pub const EMPTY: StringDescriptor = StringDescriptor { length: 0, address: 0 };
 }
 
 /// String pool for managing string memory
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct StringPool {
-    strings: Vec<String>,           // Actual string data
-    descriptors: HashMap<usize, StringDescriptor>, // Descriptors by address
-    next_address: usize,            // Next available address
-    total_memory: usize,            // Total memory limit
+    space: Vec<u8>,                 // String space
+    fretop: usize,                  // Bottom of the allocated strings
+    collections: usize,             // Number of garbage collections so far
 }
 
 impl StringPool {
-    /// Create a new string pool
+    /// Create a new string pool of `total_memory` bytes
     pub fn new(total_memory: usize) -> Self {
         Self {
-            strings: Vec::new(),
-            descriptors: HashMap::new(),
-            next_address: 0,
-            total_memory,
+            space: vec![0; total_memory],
+            fretop: total_memory,
+            collections: 0,
         }
     }
 
-    /// Store a string and return its descriptor
-    pub fn store_string(&mut self, string: String) -> BasicResult<StringDescriptor> {
-        if self.next_address + string.len() > self.total_memory {
-            // Try garbage collection first
-            self.garbage_collect()?;
-            if self.next_address + string.len() > self.total_memory {
-                return Err(BasicError::OutOfMemory);
-            }
-        }
-
-        let address = self.next_address;
+    /// Copy a string into string space
+    ///
+    /// Returns `None` when there is not enough room below FRETOP; the caller
+    /// decides whether to collect garbage and try again.
+    pub fn store_string(&mut self, string: &str) -> Option<StringDescriptor> {
         let length = string.len();
+        if length == 0 {
+            return Some(StringDescriptor::EMPTY);
+        }
+        if length > self.fretop {
+            return None;
+        }
 
-        let descriptor = StringDescriptor { length, address };
-        self.descriptors.insert(address, descriptor.clone());
-        self.strings.push(string);
-        self.next_address += length;
-
-        Ok(descriptor)
+        self.fretop -= length;
+        self.space[self.fretop..self.fretop + length].copy_from_slice(string.as_bytes());
+        Some(StringDescriptor { length, address: self.fretop })
     }
 
     /// Get string by descriptor
-    pub fn get_string(&self, descriptor: &StringDescriptor) -> Option<&String> {
-        self.strings.get(descriptor.address)
+    pub fn get_string(&self, descriptor: &StringDescriptor) -> String {
+        let bytes = &self.space[descriptor.address..descriptor.address + descriptor.length];
+        String::from_utf8_lossy(bytes).into_owned()
     }
 
-    /// Remove unused strings (garbage collection)
-    pub fn garbage_collect(&mut self) -> BasicResult<()> {
-        // For now, this is a simplified implementation
-        // In a full implementation, we would:
-        // 1. Mark all strings that are referenced by variables
-        // 2. Compact the string space
-        // 3. Update all descriptors
+    /// Check whether a descriptor holds the given string
+    pub fn holds(&self, descriptor: &StringDescriptor, string: &str) -> bool {
+        &self.space[descriptor.address..descriptor.address + descriptor.length] == string.as_bytes()
+    }
 
-        self.compact_strings();
-        Ok(())
+    /// Give back the space of the most recently allocated string
+    ///
+    /// Like FRETMP, this only works for the string at FRETOP; any other
+    /// string stays in place as garbage. Returns whether the space was freed.
+    pub fn free_last(&mut self, descriptor: &StringDescriptor) -> bool {
+        if descriptor.length > 0 && descriptor.address == self.fretop {
+            self.fretop += descriptor.length;
+            true
+        } else {
+            false
+        }
     }
 
-    /// Compact the string space to remove gaps
-    fn compact_strings(&mut self) {
-        let mut new_strings = Vec::new();
-        let mut new_descriptors = HashMap::new();
-        let mut current_address = 0;
-
-        for string in &self.strings {
-            let descriptor = StringDescriptor {
-                length: string.len(),
-                address: current_address,
-            };
-            new_descriptors.insert(current_address, descriptor);
-            new_strings.push(string.clone());
-            current_address += string.len();
+    /// Compact the live strings to the top of string space (garbage collection)
+    ///
+    /// `live` holds every descriptor that references string space; they are
+    /// updated to the strings' new addresses and everything else is discarded.
+    pub fn garbage_collect(&mut self, mut live: Vec<&mut StringDescriptor>) {
+        live.retain(|descriptor| descriptor.length > 0);
+        // Move the highest string first, so a move never overwrites a string
+        // that has not been moved yet
+        live.sort_by_key(|descriptor| std::cmp::Reverse(descriptor.address));
+
+        let mut top = self.space.len();
+        for descriptor in live {
+            top -= descriptor.length;
+            self.space.copy_within(descriptor.address..descriptor.address + descriptor.length, top);
+            descriptor.address = top;
         }
 
-        self.strings = new_strings;
-        self.descriptors = new_descriptors;
-        self.next_address = current_address;
+        self.fretop = top;
+        self.collections += 1;
+    }
+
+    /// Number of garbage collections so far
+    pub fn collections(&self) -> usize {
+        self.collections
+    }
+
+    /// Get total size of string space
+    pub fn total_memory(&self) -> usize {
+        self.space.len()
     }
 
-    /// Get current memory usage
+    /// Get current memory usage, including garbage
     pub fn memory_usage(&self) -> usize {
-        self.next_address
+        self.space.len() - self.fretop
     }
 
     /// Get available memory
     pub fn available_memory(&self) -> usize {
-        self.total_memory.saturating_sub(self.next_address)
+        self.fretop
+    }
+}
+
+impl fmt::Debug for StringPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StringPool")
+            .field("total_memory", &self.total_memory())
+            .field("fretop", &self.fretop)
+            .field("collections", &self.collections)
+            .finish()
     }
 }
 
-use crate::error::{BasicError, BasicResult};
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_free_last() {
+        let mut pool = StringPool::new(10);
+        let hello = pool.store_string("HELLO").unwrap();
+        assert_eq!(hello, StringDescriptor { length: 5, address: 5 });
+        assert_eq!(pool.get_string(&hello), "HELLO");
+        assert_eq!(pool.store_string(""), Some(StringDescriptor::EMPTY));
+        assert!(pool.store_string("TOO LONG").is_none());
+
+        let ab = pool.store_string("AB").unwrap();
+        assert!(!pool.free_last(&hello));
+        assert!(pool.free_last(&ab));
+        assert_eq!(pool.available_memory(), 5);
+    }
+
+    #[test]
+    fn test_garbage_collect() {
+        let mut pool = StringPool::new(12);
+        let mut a = pool.store_string("AAAA").unwrap();
+        pool.store_string("GARB").unwrap();
+        let mut c = pool.store_string("CC").unwrap();
+        assert_eq!(pool.available_memory(), 2);
+
+        pool.garbage_collect(vec![&mut c, &mut a]);
+        assert_eq!(pool.available_memory(), 6);
+        assert_eq!(pool.get_string(&a), "AAAA");
+        assert_eq!(pool.get_string(&c), "CC");
+        assert_eq!(c.address, 6);
+        assert_eq!(pool.collections(), 1);
+    }
+}
@@ -1,10 +1,11 @@
 //! Statement executor for BASIC interpreter
 //!
-//! This module handles execution of BASIC statements.
+//! This module handles execution of parsed BASIC statements.
 
 use crate::error::{BasicError, BasicResult};
 use crate::lexer::Token;
-use crate::runtime::memory::{MemoryManager, Value, ForLoop};
+use crate::parser::{DimArray, Expr, Parser, PrintItem, Statement, Target, Then};
+use crate::runtime::memory::{Array, MemoryManager, Value, ForLoop};
 use crate::evaluator::ExpressionEvaluator;
 
 /// Helper function to extract line number from tokens
@@ -25,118 +26,72 @@ impl StatementExecutor {
         Self {}
     }
 
-    /// Execute a single statement or multiple statements separated by colons
+    /// Parse and execute a single statement or multiple statements separated by colons
+    ///
+    /// The whole line is parsed before anything runs, so a syntax error in any
+    /// statement means none of them are executed.
     pub fn execute_statement(
         &mut self,
         tokens: &[Token],
         mem: &mut MemoryManager,
         evaluator: &mut ExpressionEvaluator,
     ) -> BasicResult<bool> {
-        self.execute_statement_from(tokens, mem, evaluator, 0).map(|_| true)
+        let statements = Parser::new(tokens).parse_statements()?;
+        self.execute_statements_from(&statements, mem, evaluator, 0).map(|_| true)
     }
 
-    /// Execute statements starting from a specific statement index
-    /// Returns the next statement index to execute (for single-line FOR loops)
-    pub fn execute_statement_from(
+    /// Execute parsed statements starting from a specific statement index
+    /// Returns the index after the last statement executed
+    pub fn execute_statements_from(
         &mut self,
-        tokens: &[Token],
+        statements: &[Statement],
         mem: &mut MemoryManager,
         evaluator: &mut ExpressionEvaluator,
         start_statement_idx: usize,
     ) -> BasicResult<usize> {
-        if tokens.is_empty() {
-            return Ok(0); // Empty line
-        }
-
-        // Find all statement boundaries (split by colons)
-        let mut statement_boundaries = vec![0];
-        for (i, token) in tokens.iter().enumerate() {
-            if matches!(token, Token::Colon) {
-                statement_boundaries.push(i + 1);
-            }
-        }
-        statement_boundaries.push(tokens.len());
-
-        // Execute statements starting from start_statement_idx
-        let mut current_idx = start_statement_idx;
-        while current_idx < statement_boundaries.len() - 1 {
-            let start = statement_boundaries[current_idx];
-            let end = statement_boundaries[current_idx + 1];
-            
-            // Skip empty statements
-            if start >= end || (end > start && tokens[start..end].iter().all(|t| matches!(t, Token::Colon))) {
-                current_idx += 1;
-                continue;
-            }
-            
-            let statement_tokens = &tokens[start..end];
-            let statement_tokens = if !statement_tokens.is_empty() && matches!(statement_tokens[statement_tokens.len()-1], Token::Colon) {
-                &statement_tokens[..statement_tokens.len()-1]
-            } else {
-                statement_tokens
-            };
-            
-            if statement_tokens.is_empty() {
-                current_idx += 1;
-                continue;
-            }
-            
-            match self.execute_single_statement_with_index(statement_tokens, mem, evaluator, current_idx) {
-                Ok(true) => {
-                    current_idx += 1; // Continue to next statement
-                }
-                Ok(false) => {
-                    // This should not happen anymore as END now throws an error
-                    return Ok(statement_boundaries.len());
-                }
-                Err(e) => return Err(e), // Propagate all errors including EndProgram
-            }
+        for (idx, statement) in statements.iter().enumerate().skip(start_statement_idx) {
+            let result = self.execute(statement, mem, evaluator, idx);
+            // Strings created while evaluating the statement are no longer needed
+            mem.release_temporaries();
+            // Propagate all errors including EndProgram and the control flow jumps
+            result?;
         }
 
-        Ok(current_idx)
-    }
-
-    /// Execute a single statement (no colons)
-    fn execute_single_statement(
-        &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-        evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
-        self.execute_single_statement_with_index(tokens, mem, evaluator, 0)
+        Ok(statements.len().max(start_statement_idx))
     }
 
     /// Execute a single statement with statement index information
-    fn execute_single_statement_with_index(
+    fn execute(
         &mut self,
-        tokens: &[Token],
+        statement: &Statement,
         mem: &mut MemoryManager,
         evaluator: &mut ExpressionEvaluator,
         statement_idx: usize,
-    ) -> BasicResult<bool> {
-        if tokens.is_empty() {
-            return Ok(true); // Empty statement, continue execution
-        }
-
-        // Check the first token to determine statement type
-        match &tokens[0] {
-            Token::Let => self.execute_let(&tokens[1..], mem, evaluator),
-            Token::Print => self.execute_print(&tokens[1..], mem, evaluator),
-            Token::Input => self.execute_input(&tokens[1..], mem, evaluator),
-            Token::Goto => self.execute_goto(&tokens[1..], mem),
-            Token::Gosub => self.execute_gosub(&tokens[1..], mem),
-            Token::Return => self.execute_return(mem),
-            Token::If => self.execute_if(&tokens[1..], mem, evaluator),
-            Token::For => self.execute_for_with_index(&tokens[1..], mem, evaluator, statement_idx),
-            Token::Next => self.execute_next(&tokens[1..], mem, evaluator),
-            Token::Data => self.execute_data(&tokens[1..], mem),
-            Token::Read => self.execute_read(&tokens[1..], mem, evaluator),
-            Token::Restore => self.execute_restore(mem),
-            Token::Dim => self.execute_dim(&tokens[1..], mem),
-            Token::Load => self.execute_load(&tokens[1..], mem),
-            Token::Save => self.execute_save(&tokens[1..], mem),
-            Token::End => Err(BasicError::EndProgram), // End program execution
-            Token::Stop => {
+    ) -> BasicResult<()> {
+        match statement {
+            Statement::Let { target, value } => self.execute_let(target, value, mem, evaluator),
+            Statement::Print { items } => self.execute_print(items, mem, evaluator),
+            Statement::Input { prompt, variables } => self.execute_input(prompt, variables, mem),
+            Statement::Goto(line) => self.execute_goto(*line, mem),
+            Statement::Gosub(line) => self.execute_gosub(*line, mem),
+            Statement::Return => self.execute_return(mem),
+            Statement::If { condition, then } => self.execute_if(condition, then, mem, evaluator),
+            Statement::For { variable, start, end, step } => {
+                self.execute_for(variable, start, end, step.as_ref(), mem, evaluator, statement_idx)
+            }
+            Statement::Next { variable } => self.execute_next(variable.as_deref(), mem),
+            // DATA values are collected from the program by RUN
+            Statement::Data(_) => Ok(()),
+            Statement::Read(variables) => self.execute_read(variables, mem),
+            Statement::Restore => {
+                mem.restore_data();
+                Ok(())
+            }
+            Statement::Dim(arrays) => self.execute_dim(arrays, mem, evaluator),
+            Statement::Load(filename) => self.execute_load(filename, mem),
+            Statement::Save(filename) => self.execute_save(filename, mem),
+            Statement::End => Err(BasicError::EndProgram), // End program execution
+            Statement::Stop => {
                 // STOP pauses execution - can be continued with CONT
                 let current_line = mem.current_line.unwrap_or(0);
                 // Save break point for CONT
@@ -144,209 +99,51 @@ impl StatementExecutor {
                 mem.break_statement = Some(statement_idx);
                 Err(BasicError::StopBreak(current_line, statement_idx))
             }
-            Token::On => self.execute_on(&tokens[1..], mem, evaluator),
-            Token::Rem => Ok(true), // Remark - do nothing
-            _ => {
-                // If no statement keyword, try to evaluate as expression (implicitly LET)
-                self.execute_implicit_let(tokens, mem, evaluator)
+            Statement::On { selector, gosub, targets } => {
+                self.execute_on(selector, *gosub, targets, mem, evaluator)
             }
+            Statement::Rem => Ok(()), // Remark - do nothing
         }
     }
 
     /// Execute LET statement: LET variable = expression
     fn execute_let(
         &mut self,
-        tokens: &[Token],
+        target: &Target,
+        value: &Expr,
         mem: &mut MemoryManager,
         evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
-        if tokens.len() < 3 {
-            return Err(BasicError::Syntax);
-        }
-
-        // Get variable/array name
-        let var_name = match &tokens[0] {
-            Token::Identifier(name) => name,
-            _ => return Err(BasicError::Syntax),
-        };
-
-        // Check if this is an array assignment: A(i) = value or A(i,j) = value
-        if tokens.len() > 1 && tokens[1] == Token::LeftParen {
-            // Array assignment
-            let mut i = 2; // Skip identifier and left paren
-            let mut indices = Vec::new();
-
-            // Parse array indices
-            loop {
-                // Find the end of this index expression (comma or right paren)
-                let mut end = i;
-                let mut paren_depth = 0;
-                while end < tokens.len() {
-                    match &tokens[end] {
-                        Token::LeftParen => paren_depth += 1,
-                        Token::RightParen if paren_depth == 0 => break,
-                        Token::RightParen => paren_depth -= 1,
-                        Token::Comma if paren_depth == 0 => break,
-                        _ => {}
-                    }
-                    end += 1;
-                }
-
-                // Evaluate the index expression
-                let index_value = evaluator.evaluate(&tokens[i..end], mem)?;
-                indices.push(index_value.to_float()? as usize);
-
-                i = end;
-                if i >= tokens.len() {
-                    return Err(BasicError::Syntax);
-                }
-
-                match &tokens[i] {
-                    Token::Comma => {
-                        i += 1; // Skip comma
-                        continue;
-                    }
-                    Token::RightParen => {
-                        i += 1; // Skip right paren
-                        break;
-                    }
-                    _ => return Err(BasicError::Syntax),
-                }
-            }
-
-            // Check for equal sign
-            if i >= tokens.len() || !matches!(tokens[i], Token::Equal) {
-                return Err(BasicError::Syntax);
+    ) -> BasicResult<()> {
+        match target {
+            Target::Variable(name) => {
+                // Evaluate the expression on the right side
+                let value = evaluator.eval(value, mem)?;
+                mem.set_variable(name.clone(), value)
             }
-            i += 1; // Skip equal sign
-
-            // Evaluate the value expression
-            let value = evaluator.evaluate(&tokens[i..], mem)?;
-
-            // Get the array
-            let array = mem.arrays_mut().get_mut(var_name)
-                .ok_or_else(|| BasicError::VariableNotFound(var_name.clone()))?;
+            Target::ArrayElement { name, indices } => {
+                let indices = evaluator.eval_indices(indices, mem)?;
+                let value = evaluator.eval(value, mem)?;
 
-            // Check dimensions match
-            if indices.len() != array.dimensions.len() {
-                return Err(BasicError::Syntax);
-            }
-
-            // Convert to linear index
-            let mut linear_index = 0;
-            let mut multiplier = 1;
-            for idx in (0..indices.len()).rev() {
                 // array.dimensions already includes the +1 adjustment from DIM
-                if indices[idx] >= array.dimensions[idx] {
-                    return Err(BasicError::BadSubscript);
-                }
-                linear_index += indices[idx] * multiplier;
-                multiplier *= array.dimensions[idx];
+                mem.set_array_element(name, &indices, value)
             }
-
-            // Set the array element
-            if linear_index >= array.data.len() {
-                return Err(BasicError::BadSubscript);
-            }
-            array.data[linear_index] = value;
-
-            return Ok(true);
-        }
-
-        // Regular variable assignment
-        // Check for assignment operator
-        if !matches!(tokens[1], Token::Equal) {
-            return Err(BasicError::Syntax);
-        }
-
-        // Evaluate the expression on the right side
-        let value = evaluator.evaluate(&tokens[2..], mem)?;
-
-        // Store the variable
-        mem.set_variable(var_name.clone(), value)?;
-
-        Ok(true)
-    }
-
-    /// Execute implicit LET (assignment without LET keyword)
-    fn execute_implicit_let(
-        &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-        evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
-        // Look for assignment operator (but not inside parentheses)
-        let mut equal_pos = None;
-        let mut paren_depth = 0;
-        for (i, token) in tokens.iter().enumerate() {
-            match token {
-                Token::LeftParen => paren_depth += 1,
-                Token::RightParen => paren_depth -= 1,
-                Token::Equal if paren_depth == 0 => {
-                    equal_pos = Some(i);
-                    break;
-                }
-                _ => {}
-            }
-        }
-
-        if let Some(pos) = equal_pos {
-            if pos == 0 || pos == tokens.len() - 1 {
-                return Err(BasicError::Syntax);
-            }
-
-            // Use execute_let to handle both regular and array assignments
-            return self.execute_let(tokens, mem, evaluator);
-        } else {
-            // If no assignment operator, just evaluate the expression
-            evaluator.evaluate(tokens, mem)?;
-            Ok(true)
         }
     }
 
     /// Execute PRINT statement
     fn execute_print(
         &mut self,
-        tokens: &[Token],
+        items: &[PrintItem],
         mem: &mut MemoryManager,
         evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
+    ) -> BasicResult<()> {
         use std::io::{self, Write};
 
-        if tokens.is_empty() {
-            // PRINT with no arguments - just print newline
-            println!();
-            return Ok(true);
-        }
-
-        let mut i = 0;
-
-        while i < tokens.len() {
-            // Find the next separator or end of tokens
-            // Need to track parenthesis depth to avoid breaking on commas inside function calls
-            let mut end = i;
-            let mut paren_depth = 0;
-            while end < tokens.len() {
-                match &tokens[end] {
-                    Token::LeftParen => paren_depth += 1,
-                    Token::RightParen => paren_depth -= 1,
-                    Token::Comma | Token::Semicolon if paren_depth == 0 => break,
-                    _ => {}
-                }
-                end += 1;
-            }
-
-            if i < end {
-                // We have an expression to evaluate
-                let expression = &tokens[i..end];
-
-                // Evaluate the expression
-                let value = evaluator.evaluate(expression, mem)?;
-
-                // Print the value
-                match value {
-                    crate::runtime::memory::Value::String(s) => print!("{}", s),
-                    crate::runtime::memory::Value::Float(f) => {
+        for item in items {
+            match item {
+                PrintItem::Expr(expr) => match evaluator.eval(expr, mem)? {
+                    Value::String(s) => print!("{}", s),
+                    Value::Float(f) => {
                         if f.fract() == 0.0 && f.abs() <= 32767.0 {
                             // Display as integer for values within BASIC integer range
                             print!("{}", f as i64);
@@ -354,107 +151,34 @@ impl StatementExecutor {
                             print!("{}", f);
                         }
                     }
-                    crate::runtime::memory::Value::Integer(n) => print!("{}", n),
-                }
-
-                // Check if there's a separator after this expression
-                if end < tokens.len() {
-                    match &tokens[end] {
-                        Token::Comma => {
-                            // Zone spacing (14 characters per zone in BASIC)
-                            print!("{:<14}", "");
-                        }
-                        Token::Semicolon => {
-                            // In BASIC, semicolon means no spacing at all between items
-                            // Just move to the next item without adding any space
-                        }
-                        _ => {}
-                    }
-                    i = end + 1; // Skip the separator
-                } else {
-                    i = end; // End of tokens
-                }
-            } else {
-                // No expression, just a separator or empty
-                i += 1;
+                    Value::Integer(n) => print!("{}", n),
+                },
+                // Zone spacing (14 characters per zone in BASIC)
+                PrintItem::Comma => print!("{:<14}", ""),
+                // In BASIC, semicolon means no spacing at all between items
+                PrintItem::Semicolon => {}
             }
         }
 
-        // Add newline if needed (check if the last token was not a semicolon)
-        if !tokens.is_empty() && matches!(tokens[tokens.len() - 1], Token::Semicolon) {
-            // Statement ends with semicolon, no newline
+        // Add newline unless the statement ends with a semicolon
+        if let Some(PrintItem::Semicolon) = items.last() {
             io::stdout().flush().unwrap_or(());
         } else {
-            // Statement doesn't end with semicolon, add newline
             println!();
         }
 
-        Ok(true)
+        Ok(())
     }
 
     /// Execute INPUT statement
     fn execute_input(
         &mut self,
-        tokens: &[Token],
+        prompt: &str,
+        variables: &[String],
         mem: &mut MemoryManager,
-        _evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
+    ) -> BasicResult<()> {
         use std::io::{self, Write};
 
-        if tokens.is_empty() {
-            return Err(BasicError::Syntax);
-        }
-
-        let mut i = 0;
-        let mut prompt = String::new();
-        let mut variable_names = Vec::new();
-
-        // Check if first token is a string (prompt)
-        if let Token::String(s) = &tokens[0] {
-            prompt = s.clone();
-            i += 1;
-
-            // Check for separator after prompt
-            if i >= tokens.len() {
-                return Err(BasicError::Syntax);
-            }
-
-            match &tokens[i] {
-                Token::Semicolon => {
-                    // Semicolon means add "?" to prompt
-                    prompt.push(' ');
-                    i += 1;
-                }
-                Token::Comma => {
-                    // Comma means no additional prompt
-                    i += 1;
-                }
-                _ => return Err(BasicError::Syntax),
-            }
-        } else {
-            // No prompt, use default "?"
-            prompt.push('?');
-            prompt.push(' ');
-        }
-
-        // Collect all variable names
-        while i < tokens.len() {
-            match &tokens[i] {
-                Token::Identifier(name) => {
-                    variable_names.push(name.clone());
-                    i += 1;
-                }
-                Token::Comma => {
-                    i += 1; // Skip comma
-                }
-                _ => return Err(BasicError::Syntax),
-            }
-        }
-
-        if variable_names.is_empty() {
-            return Err(BasicError::Syntax);
-        }
-
         // Display prompt
         print!("{}", prompt);
         io::stdout().flush().unwrap_or(());
@@ -467,45 +191,29 @@ impl StatementExecutor {
         let input_parts: Vec<&str> = input.trim().split(',').map(|s| s.trim()).collect();
 
         // Assign values to variables
-        for (idx, variable_name) in variable_names.iter().enumerate() {
-            let input_value = if idx < input_parts.len() {
-                input_parts[idx]
-            } else {
-                "" // If not enough inputs, use empty string
-            };
+        for (idx, variable_name) in variables.iter().enumerate() {
+            // If not enough inputs, use empty string
+            let input_value = input_parts.get(idx).copied().unwrap_or("");
 
             let value = if variable_name.ends_with('$') {
                 // String variable
-                crate::runtime::memory::Value::String(input_value.to_string())
+                Value::String(input_value.to_string())
             } else {
                 // Numeric variable - try to parse as number
                 input_value.parse::<f64>()
-                    .map(crate::runtime::memory::Value::Float)
-                    .unwrap_or(crate::runtime::memory::Value::Float(0.0)) // Default to 0 on parse error
+                    .map(Value::Float)
+                    .unwrap_or(Value::Float(0.0)) // Default to 0 on parse error
             };
 
             // Store the variable
             mem.set_variable(variable_name.clone(), value)?;
         }
 
-        Ok(true)
+        Ok(())
     }
 
     /// Execute GOTO statement
-    fn execute_goto(
-        &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-    ) -> BasicResult<bool> {
-        if tokens.len() != 1 {
-            return Err(BasicError::Syntax);
-        }
-
-        let line_number = match &tokens[0] {
-            Token::Number(n) => *n as u16,
-            _ => return Err(BasicError::Syntax),
-        };
-
+    fn execute_goto(&mut self, line_number: u16, mem: &mut MemoryManager) -> BasicResult<()> {
         // Check if the target line exists
         if !mem.program_lines().contains_key(&line_number) {
             return Err(BasicError::LineNumberNotFound(line_number));
@@ -514,25 +222,12 @@ impl StatementExecutor {
         // Set the current execution line
         mem.set_current_line(line_number);
 
-        // Return false to stop normal sequential execution and jump to the target line
+        // Stop normal sequential execution and jump to the target line
         Err(BasicError::GotoJump(line_number))
     }
 
     /// Execute GOSUB statement
-    fn execute_gosub(
-        &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-    ) -> BasicResult<bool> {
-        if tokens.len() != 1 {
-            return Err(BasicError::Syntax);
-        }
-
-        let line_number = match &tokens[0] {
-            Token::Number(n) => *n as u16,
-            _ => return Err(BasicError::Syntax),
-        };
-
+    fn execute_gosub(&mut self, line_number: u16, mem: &mut MemoryManager) -> BasicResult<()> {
         // Check if the target line exists
         if !mem.program_lines().contains_key(&line_number) {
             return Err(BasicError::LineNumberNotFound(line_number));
@@ -545,15 +240,12 @@ impl StatementExecutor {
         // Set the current execution line
         mem.set_current_line(line_number);
 
-        // Return false to stop normal sequential execution and jump to the subroutine
+        // Stop normal sequential execution and jump to the subroutine
         Err(BasicError::GosubJump(line_number))
     }
 
     /// Execute RETURN statement
-    fn execute_return(
-        &mut self,
-        mem: &mut MemoryManager,
-    ) -> BasicResult<bool> {
+    fn execute_return(&mut self, mem: &mut MemoryManager) -> BasicResult<()> {
         // Pop return address from stack
         let return_line = mem.pop_gosub_return()
             .ok_or(BasicError::ReturnWithoutGosub)?;
@@ -561,191 +253,78 @@ impl StatementExecutor {
         // Set the current execution line to the return address
         mem.set_current_line(return_line);
 
-        // Return false to stop normal sequential execution and jump back
+        // Stop normal sequential execution and jump back
         Err(BasicError::ReturnJump(return_line))
     }
 
     /// Execute IF statement
     fn execute_if(
         &mut self,
-        tokens: &[Token],
+        condition: &Expr,
+        then: &Then,
         mem: &mut MemoryManager,
         evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
-        // IF syntax: IF condition THEN statement_or_line_number
-        if tokens.is_empty() {
-            return Err(BasicError::Syntax);
-        }
-
-        // Find the THEN token - everything before is the condition
-        let mut then_pos = 0;
-        while then_pos < tokens.len() && !matches!(tokens[then_pos], Token::Identifier(ref s) if s.to_uppercase() == "THEN") {
-            then_pos += 1;
-        }
-
-        // Must have THEN
-        if then_pos >= tokens.len() {
-            return Err(BasicError::Syntax);
-        }
-
-        // Must have something after THEN
-        if then_pos + 1 >= tokens.len() {
-            return Err(BasicError::Syntax);
-        }
-
-        // Must have a condition (not empty before THEN)
-        if then_pos == 0 {
-            return Err(BasicError::Syntax);
-        }
-
-        // Evaluate the condition
-        let condition_value = evaluator.evaluate(&tokens[..then_pos], mem)?;
-
+    ) -> BasicResult<()> {
         // Convert condition to boolean (0 = false, non-zero = true)
-        let condition_is_true = match condition_value.to_float()? {
-            f if f.abs() < f64::EPSILON => false,
-            _ => true,
-        };
+        let condition_is_true = evaluator.eval(condition, mem)?.to_float()?.abs() >= f64::EPSILON;
 
-        let then_tokens = &tokens[then_pos + 1..];
+        if !condition_is_true {
+            // If condition is false, skip the rest of the line
+            return Ok(());
+        }
 
-        if condition_is_true {
-            // If condition is true, execute what's after THEN
-            match then_tokens[0] {
-                // Check if it's a line number (GOTO)
-                Token::Number(n) => {
-                    // IF condition THEN line_number - equivalent to GOTO
-                    let line_number = n as u16;
-                    return Err(BasicError::GotoJump(line_number));
-                }
-                Token::Identifier(ref s) if s.to_uppercase() == "GOTO" && then_tokens.len() > 1 => {
-                    // IF condition THEN GOTO line_number
-                    if let Token::Number(n) = then_tokens[1] {
-                        let line_number = n as u16;
-                        return Err(BasicError::GotoJump(line_number));
-                    } else {
-                        return Err(BasicError::Syntax);
-                    }
-                }
-                _ => {
-                    // IF condition THEN statement - execute the statement directly
-                    return self.execute_statement(then_tokens, mem, evaluator);
-                }
+        match then {
+            // IF condition THEN line_number - equivalent to GOTO
+            Then::Line(line_number) => Err(BasicError::GotoJump(*line_number)),
+            Then::Statements(statements) => {
+                self.execute_statements_from(statements, mem, evaluator, 0).map(|_| ())
             }
-        } else {
-            // If condition is false, do nothing and continue
-            Ok(true)
         }
     }
 
     /// Execute FOR statement
+    #[allow(clippy::too_many_arguments)]
     fn execute_for(
         &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-        evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
-        self.execute_for_with_index(tokens, mem, evaluator, 0)
-    }
-
-    /// Execute FOR statement with statement index
-    fn execute_for_with_index(
-        &mut self,
-        tokens: &[Token],
+        variable_name: &str,
+        start: &Expr,
+        end: &Expr,
+        step: Option<&Expr>,
         mem: &mut MemoryManager,
         evaluator: &mut ExpressionEvaluator,
         statement_idx: usize,
-    ) -> BasicResult<bool> {
-        // FOR syntax: FOR variable = start TO end [STEP step]
-        if tokens.len() < 5 {
-            return Err(BasicError::Syntax);
-        }
-
-        // Get variable name (should be Identifier)
-        let variable_name = match &tokens[0] {
-            Token::Identifier(name) => name.clone(),
-            _ => return Err(BasicError::Syntax),
-        };
-
-        // Next should be Equal
-        if !matches!(tokens[1], Token::Equal) {
-            return Err(BasicError::Syntax);
-        }
-
-        // Find the TO position - the tokens before TO are the start expression
-        let mut to_pos = 2;
-        while to_pos < tokens.len() && !matches!(tokens[to_pos], Token::Identifier(ref s) if s.to_uppercase() == "TO") {
-            to_pos += 1;
-        }
-
-        if to_pos >= tokens.len() {
-            return Err(BasicError::Syntax);
-        }
-
-        // Evaluate start value
-        let start_value = evaluator.evaluate(&tokens[2..to_pos], mem)?;
-
-        // Find the STEP position (if exists) - tokens after TO up to STEP are the end expression
-        let mut step_pos = to_pos + 1;
-        let mut end_pos = tokens.len();
-        while step_pos < tokens.len() {
-            if matches!(tokens[step_pos], Token::Identifier(ref s) if s.to_uppercase() == "STEP") {
-                end_pos = step_pos;
-                break;
-            }
-            step_pos += 1;
-        }
-
-        // Evaluate end value
-        let end_value = evaluator.evaluate(&tokens[to_pos + 1..end_pos], mem)?;
+    ) -> BasicResult<()> {
+        let start_value = evaluator.eval(start, mem)?;
+        let end_value = evaluator.eval(end, mem)?;
 
         // Evaluate step value (default to 1 if no STEP)
-        let step_value = if end_pos < tokens.len() && matches!(tokens[end_pos], Token::Identifier(ref s) if s.to_uppercase() == "STEP") {
-            if end_pos + 1 >= tokens.len() {
-                return Err(BasicError::Syntax);
-            }
-            evaluator.evaluate(&tokens[end_pos + 1..], mem)?
-        } else {
-            Value::Integer(1)
+        let step_value = match step {
+            Some(step) => evaluator.eval(step, mem)?,
+            None => Value::Integer(1),
         };
 
         // Set the variable to the start value
-        mem.set_variable(variable_name.clone(), start_value.clone())?;
+        mem.set_variable(variable_name.to_string(), start_value.clone())?;
 
         // Create FOR loop context
         let current_line = mem.current_line();
 
-        // Check if this is a single-line FOR loop by looking at the current line's tokens
-        // Get all tokens from the current line
-        let current_line_tokens = if let Some(program_line) = mem.get_line(current_line) {
-            program_line.tokens.clone()
-        } else {
-            vec![]
-        };
-        
-        // Check if there's a NEXT token after the current position (single-line FOR)
-        let is_single_line_for = current_line_tokens.iter().skip(1).any(|t| matches!(t, Token::Next));
-        
+        // A NEXT on the same line makes this a single-line FOR loop
+        let is_single_line_for = mem.get_line(current_line).is_some_and(|line| {
+            line.statements.iter().any(|s| matches!(s, Statement::Next { .. }))
+        });
+
         let next_line = if is_single_line_for {
             // Single-line FOR: next_line should be current_line
             current_line
         } else {
             // Multi-line FOR: next_line is the next line in execution order
-            let execution_order = mem.get_execution_order();
-            if let Some(current_idx) = execution_order.iter().position(|&line| line == current_line) {
-                if current_idx + 1 < execution_order.len() {
-                    execution_order[current_idx + 1]
-                } else {
-                    current_line + 1
-                }
-            } else {
-                current_line + 1
-            }
+            mem.find_next_line(current_line).unwrap_or(current_line + 1)
         };
 
         // Create FOR loop - use new_with_statement to support single-line FOR loops
         let for_loop = ForLoop::new_with_statement(
-            variable_name,
+            variable_name.to_string(),
             start_value,
             end_value,
             step_value,
@@ -757,35 +336,16 @@ impl StatementExecutor {
         // Push onto for stack
         mem.push_for_loop(for_loop);
 
-        Ok(true)
+        Ok(())
     }
 
     /// Execute NEXT statement
-    fn execute_next(
-        &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-        evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
-        // NEXT syntax: NEXT [variable]
-        let variable_name = if tokens.is_empty() {
-            // No variable specified, use the current FOR loop variable
-            match mem.current_for_loop() {
-                Some(for_loop) => for_loop.variable_name.clone(),
-                None => return Err(BasicError::NextWithoutFor),
-            }
-        } else {
-            // Variable specified
-            match &tokens[0] {
-                Token::Identifier(name) => name.clone(),
-                _ => return Err(BasicError::Syntax),
-            }
-        };
-
+    fn execute_next(&mut self, variable: Option<&str>, mem: &mut MemoryManager) -> BasicResult<()> {
         // Check if we have a matching FOR loop on the stack
         let for_loop = match mem.current_for_loop() {
             Some(fl) => {
-                if fl.variable_name.to_uppercase() != variable_name.to_uppercase() {
+                // No variable specified means the current FOR loop variable
+                if variable.is_some_and(|name| !fl.variable_name.eq_ignore_ascii_case(name)) {
                     return Err(BasicError::NextWithoutFor);
                 }
                 fl.clone()
@@ -793,22 +353,9 @@ impl StatementExecutor {
             None => return Err(BasicError::NextWithoutFor),
         };
 
-        // Get current value of the loop variable
-        let _current_value = mem.get_variable(&for_loop.variable_name)?;
-
         // Increment the loop variable by the step value
-        // Create a simple expression: variable + step
-        let step_tokens = vec![
-            Token::Identifier(for_loop.variable_name.clone()),
-            Token::Plus,
-            match &for_loop.step_value {
-                Value::Integer(n) => Token::Number(*n as f64),
-                Value::Float(f) => Token::Number(*f),
-                _ => return Err(BasicError::TypeMismatch),
-            },
-        ];
-
-        let new_value = evaluator.evaluate(&step_tokens, mem)?;
+        let current_value = mem.get_variable(&for_loop.variable_name)?.to_float()?;
+        let new_value = Value::Float(current_value + for_loop.step_value.to_float()?);
         mem.set_variable(for_loop.variable_name.clone(), new_value.clone())?;
 
         // Check if the loop should continue
@@ -816,35 +363,26 @@ impl StatementExecutor {
             // Continue the loop - jump back to the line/statement after FOR
             let jump_to_line = for_loop.next_line;
             let current_line = mem.current_line();
-            
+
             // Check if this is a single-line FOR loop (FOR and NEXT on the same line)
             if jump_to_line == current_line && for_loop.next_statement > 0 {
                 // Single-line FOR loop - jump to specific statement
-                return Err(BasicError::GotoJumpWithStatement(jump_to_line, for_loop.next_statement));
+                Err(BasicError::GotoJumpWithStatement(jump_to_line, for_loop.next_statement))
             } else {
                 // Multi-line FOR loop - jump to next line
-                return Err(BasicError::GotoJump(jump_to_line));
+                Err(BasicError::GotoJump(jump_to_line))
             }
         } else {
             // Loop is done, pop it from the stack
             mem.pop_for_loop();
+            Ok(())
         }
-
-        Ok(true)
     }
 
     /// Execute LOAD statement: LOAD "filename"
-    fn execute_load(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<bool> {
+    fn execute_load(&mut self, filename: &str, mem: &mut MemoryManager) -> BasicResult<()> {
         use std::fs;
-
-        if tokens.len() != 1 {
-            return Err(BasicError::Syntax);
-        }
-
-        let filename = match &tokens[0] {
-            Token::String(s) => s,
-            _ => return Err(BasicError::Syntax),
-        };
+        use crate::lexer::Lexer;
 
         // Clear current program
         mem.clear();
@@ -853,7 +391,6 @@ impl StatementExecutor {
         let content = fs::read_to_string(filename)
             .map_err(|_| BasicError::Generic(format!("Cannot open file: {}", filename)))?;
 
-        use crate::lexer::Lexer;
         let mut lexer = Lexer::new();
 
         for line in content.lines() {
@@ -862,37 +399,24 @@ impl StatementExecutor {
                 continue; // Skip empty lines and comments
             }
 
-            match lexer.tokenize(line) {
-                Ok(tokens) => {
-                    if let Some(line_num) = extract_line_number(&tokens) {
-                        mem.store_line(line_num, tokens)?;
-                    } else {
-                        println!("Warning: Line without line number ignored: {}", line);
-                    }
-                }
-                Err(e) => {
-                    return Err(BasicError::Generic(format!("Error in line '{}': {:?}", line, e)));
-                }
+            let tokens = lexer.tokenize(line)
+                .map_err(|e| BasicError::Generic(format!("Error in line '{}': {:?}", line, e)))?;
+            if let Some(line_num) = extract_line_number(&tokens) {
+                mem.store_line(line_num, tokens)
+                    .map_err(|e| BasicError::Generic(format!("Error in line '{}': {}", line, e)))?;
+            } else {
+                println!("Warning: Line without line number ignored: {}", line);
             }
         }
 
         println!("PROGRAM LOADED FROM {}", filename);
-        Ok(true)
+        Ok(())
     }
 
     /// Execute SAVE statement: SAVE "filename"
-    fn execute_save(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<bool> {
+    fn execute_save(&mut self, filename: &str, mem: &mut MemoryManager) -> BasicResult<()> {
         use std::fs;
 
-        if tokens.len() != 1 {
-            return Err(BasicError::Syntax);
-        }
-
-        let filename = match &tokens[0] {
-            Token::String(s) => s,
-            _ => return Err(BasicError::Syntax),
-        };
-
         // Get program lines in order
         let execution_order = mem.get_execution_order();
         if execution_order.is_empty() {
@@ -916,272 +440,93 @@ impl StatementExecutor {
             .map_err(|_| BasicError::Generic(format!("Cannot write to file: {}", filename)))?;
 
         println!("PROGRAM SAVED TO {}", filename);
-        Ok(true)
-    }
-
-    /// Execute DATA statement: DATA value1, value2, value3...
-    fn execute_data(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<bool> {
-        if tokens.is_empty() {
-            return Ok(true); // Empty DATA statement is valid
-        }
-
-        let mut values = Vec::new();
-        let mut i = 0;
-
-        while i < tokens.len() {
-            // Parse each value in the DATA statement
-            match &tokens[i] {
-                Token::Number(n) => {
-                    values.push(Value::Float(*n));
-                    i += 1;
-                }
-                Token::String(s) => {
-                    values.push(Value::String(s.clone()));
-                    i += 1;
-                }
-                Token::Comma => {
-                    i += 1; // Skip comma separators
-                }
-                _ => {
-                    return Err(BasicError::Syntax);
-                }
-            }
-        }
-
-        // Add parsed values to memory
-        mem.add_data_values(values);
-        Ok(true)
+        Ok(())
     }
 
     /// Execute READ statement: READ variable1, variable2, variable3...
-    fn execute_read(
-        &mut self,
-        tokens: &[Token],
-        mem: &mut MemoryManager,
-        _evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
-        if tokens.is_empty() {
-            return Err(BasicError::Syntax);
-        }
-
-        let mut i = 0;
-        while i < tokens.len() {
-            // Skip commas
-            if matches!(tokens[i], Token::Comma) {
-                i += 1;
-                continue;
-            }
-
-            // Get variable name
-            let var_name = match &tokens[i] {
-                Token::Identifier(name) => name,
-                _ => return Err(BasicError::Syntax),
-            };
-
-            // Read next value from DATA
-            let data_value = match mem.read_data_value() {
-                Ok(value) => value,
-                Err(BasicError::OutOfData) => {
-                    // OUT OF DATA error - return the error to be handled by main loop
-                    return Err(BasicError::OutOfData);
-                }
-                Err(e) => return Err(e),
-            };
+    fn execute_read(&mut self, variables: &[String], mem: &mut MemoryManager) -> BasicResult<()> {
+        for var_name in variables {
+            // Read next value from DATA; OUT OF DATA is handled by the main loop
+            let data_value = mem.read_data_value()?;
 
             // Check type compatibility and store
             if var_name.ends_with('$') {
-                // String variable
-                match data_value {
-                    Value::String(s) => {
-                        mem.set_variable(var_name.clone(), Value::String(s))?;
-                    }
-                    _ => {
-                        // Convert non-string to string
-                        mem.set_variable(var_name.clone(), Value::String(data_value.to_string()))?;
-                    }
-                }
-            } else {
+                // String variable - convert non-string data to string
+                let s = match data_value {
+                    Value::String(s) => s,
+                    other => other.to_string(),
+                };
+                mem.set_variable(var_name.clone(), Value::String(s))?;
+            } else if data_value.is_numeric() {
                 // Numeric variable
-                if data_value.is_numeric() {
-                    mem.set_variable(var_name.clone(), data_value)?;
-                } else {
-                    return Err(BasicError::TypeMismatch);
-                }
+                mem.set_variable(var_name.clone(), data_value)?;
+            } else {
+                return Err(BasicError::TypeMismatch);
             }
-
-            i += 1;
         }
 
-        Ok(true)
-    }
-
-    /// Execute RESTORE statement: RESTORE
-    fn execute_restore(&mut self, mem: &mut MemoryManager) -> BasicResult<bool> {
-        mem.restore_data();
-        Ok(true)
+        Ok(())
     }
 
     /// Execute ON statement: ON expression GOSUB line1, line2, line3...
     fn execute_on(
         &mut self,
-        tokens: &[Token],
+        selector: &Expr,
+        is_gosub: bool,
+        line_numbers: &[u16],
         mem: &mut MemoryManager,
         evaluator: &mut ExpressionEvaluator,
-    ) -> BasicResult<bool> {
-        if tokens.is_empty() {
-            return Err(BasicError::Syntax);
-        }
-
-        // Find GOSUB or GOTO keyword
-        let mut gosub_pos = 0;
-        let mut is_gosub = false;
-        while gosub_pos < tokens.len() {
-            match &tokens[gosub_pos] {
-                Token::Gosub => {
-                    is_gosub = true;
-                    break;
-                }
-                Token::Goto => {
-                    is_gosub = false;
-                    break;
-                }
-                _ => gosub_pos += 1,
-            }
-        }
-
-        if gosub_pos >= tokens.len() {
-            return Err(BasicError::Syntax);
-        }
-
-        // Evaluate the expression before GOSUB/GOTO
-        let expression_tokens = &tokens[..gosub_pos];
-        let value = evaluator.evaluate(expression_tokens, mem)?;
-        let index = value.to_float()? as usize;
-
-        // Parse the list of line numbers after GOSUB/GOTO
-        let mut line_numbers = Vec::new();
-        let mut i = gosub_pos + 1;
-        while i < tokens.len() {
-            match &tokens[i] {
-                Token::Number(n) => {
-                    line_numbers.push(*n as u16);
-                }
-                Token::Comma => {
-                    // Skip commas
-                }
-                _ => return Err(BasicError::Syntax),
-            }
-            i += 1;
-        }
+    ) -> BasicResult<()> {
+        let index = evaluator.eval(selector, mem)?.to_float()? as usize;
 
         // Check if index is valid (1-based)
         if index < 1 || index > line_numbers.len() {
             // Out of range - do nothing (just continue)
-            return Ok(true);
+            return Ok(());
         }
 
         let target_line = line_numbers[index - 1];
 
-        // Check if the target line exists
-        if !mem.program_lines().contains_key(&target_line) {
-            return Err(BasicError::LineNumberNotFound(target_line));
-        }
-
         if is_gosub {
-            // Push return address onto the stack
-            let current_line = mem.current_line();
-            mem.push_gosub_return(current_line);
-            mem.set_current_line(target_line);
-            Err(BasicError::GosubJump(target_line))
+            self.execute_gosub(target_line, mem)
         } else {
-            // GOTO
-            mem.set_current_line(target_line);
-            Err(BasicError::GotoJump(target_line))
+            self.execute_goto(target_line, mem)
         }
     }
 
     /// Execute DIM statement: DIM array1(size1), array2(size2, size3)...
-    fn execute_dim(&mut self, tokens: &[Token], mem: &mut MemoryManager) -> BasicResult<bool> {
-        if tokens.is_empty() {
-            return Err(BasicError::Syntax);
-        }
-
-        let mut i = 0;
-        while i < tokens.len() {
-            // Parse array name
-            let array_name = match &tokens[i] {
-                Token::Identifier(name) => name,
-                _ => return Err(BasicError::Syntax),
-            };
-
-            i += 1;
-
-            // Expect left parenthesis
-            if i >= tokens.len() || !matches!(tokens[i], Token::LeftParen) {
-                return Err(BasicError::Syntax);
-            }
-            i += 1;
-
-            // Parse dimensions
-            let mut dimensions = Vec::new();
-            while i < tokens.len() && !matches!(tokens[i], Token::RightParen) {
-                match &tokens[i] {
-                    Token::Number(n) => {
-                        if *n <= 0.0 || n.fract() != 0.0 {
-                            return Err(BasicError::IllegalQuantity);
-                        }
-                        dimensions.push(*n as usize);
-                    }
-                    Token::Comma => {
-                        // Just skip commas between dimensions
-                    }
-                    _ => return Err(BasicError::Syntax),
-                }
-                i += 1;
-            }
-
-            // Expect right parenthesis
-            if i >= tokens.len() || !matches!(tokens[i], Token::RightParen) {
-                return Err(BasicError::Syntax);
-            }
-            i += 1;
-
-            // Check if we have more arrays (comma separator)
-            if i < tokens.len() {
-                if !matches!(tokens[i], Token::Comma) {
-                    return Err(BasicError::Syntax);
-                }
-                i += 1;
-            }
-
-            // Create the array with initial zero values
+    fn execute_dim(
+        &mut self,
+        arrays: &[DimArray],
+        mem: &mut MemoryManager,
+        evaluator: &mut ExpressionEvaluator,
+    ) -> BasicResult<()> {
+        for DimArray { name, bounds } in arrays {
             // In BASIC, DIM A(10) creates array with indices 0-10 (11 elements)
             // So we need to add 1 to each dimension
-            let adjusted_dimensions: Vec<usize> = dimensions.iter().map(|&d| d + 1).collect();
-            let total_elements: usize = adjusted_dimensions.iter().product();
-            let mut data = Vec::with_capacity(total_elements);
-            for _ in 0..total_elements {
-                if array_name.ends_with('$') {
-                    // String array - initialize with empty strings
-                    data.push(Value::String(String::new()));
-                } else {
-                    // Numeric array - initialize with 0
-                    data.push(Value::Float(0.0));
+            let mut dimensions = Vec::with_capacity(bounds.len());
+            for bound in bounds {
+                let n = evaluator.eval(bound, mem)?.to_float()?;
+                if n <= 0.0 || n.fract() != 0.0 {
+                    return Err(BasicError::IllegalQuantity);
                 }
+                dimensions.push(n as usize + 1);
             }
 
-            // Store the array in memory
-            use crate::runtime::memory::Array;
-            let array = Array {
-                name: array_name.clone(),
-                dimensions: adjusted_dimensions,
-                data,
-            };
+            // String arrays start out with empty strings
+            let array = Array::new(name.clone(), dimensions);
 
-            mem.arrays_mut().insert(array_name.clone(), array);
+            // Store the array in memory
+            mem.arrays_mut().insert(name.clone(), array);
         }
 
-        Ok(true)
+        Ok(())
+    }
+}
+
+impl Default for StatementExecutor {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -1189,7 +534,7 @@ impl StatementExecutor {
 mod tests {
     use super::*;
     use crate::lexer::Token;
-    use crate::runtime::memory::{MemoryManager, Value};
+    use crate::runtime::memory::{MemoryManager, StoredValue, Value};
 
     #[test]
     fn test_let_statement() {
@@ -1210,7 +555,7 @@ mod tests {
 
         // Check if variable was stored correctly
         let value = mem.get_variable("A").unwrap();
-        assert_eq!(value, &Value::Float(42.0));
+        assert_eq!(value, Value::Float(42.0));
     }
 
     #[test]
@@ -1219,11 +564,11 @@ mod tests {
         let mut mem = MemoryManager::new();
         let mut evaluator = ExpressionEvaluator::new();
 
-        // Test implicit LET: B = 3.14
+        // Test implicit LET: B = 2.5
         let tokens = vec![
             Token::Identifier("B".to_string()),
             Token::Equal,
-            Token::Number(3.14),
+            Token::Number(2.5),
         ];
 
         let result = executor.execute_statement(&tokens, &mut mem, &mut evaluator).unwrap();
@@ -1231,7 +576,7 @@ mod tests {
 
         // Check if variable was stored correctly
         let value = mem.get_variable("B").unwrap();
-        assert_eq!(value, &Value::Float(3.14));
+        assert_eq!(value, Value::Float(2.5));
     }
 
     #[test]
@@ -1257,7 +602,7 @@ mod tests {
 
         // Check if variable was stored correctly (2 + 3 * 4 = 14)
         let value = mem.get_variable("C").unwrap();
-        assert_eq!(value, &Value::Float(14.0));
+        assert_eq!(value, Value::Float(14.0));
     }
 
     #[test]
@@ -1279,7 +624,7 @@ mod tests {
 
         // Check if string variable was stored correctly
         let value = mem.get_variable("A$").unwrap();
-        assert_eq!(value, &Value::String("HELLO".to_string()));
+        assert_eq!(value, Value::String("HELLO".to_string()));
     }
 
     #[test]
@@ -1296,7 +641,7 @@ mod tests {
         ];
 
         executor.execute_statement(&tokens1, &mut mem, &mut evaluator).unwrap();
-        assert_eq!(mem.get_variable("X").unwrap(), &Value::Float(10.0));
+        assert_eq!(mem.get_variable("X").unwrap(), Value::Float(10.0));
 
         // Second assignment: X = X + 5
         let tokens2 = vec![
@@ -1308,7 +653,7 @@ mod tests {
         ];
 
         executor.execute_statement(&tokens2, &mut mem, &mut evaluator).unwrap();
-        assert_eq!(mem.get_variable("X").unwrap(), &Value::Float(15.0));
+        assert_eq!(mem.get_variable("X").unwrap(), Value::Float(15.0));
     }
 
     #[test]
@@ -1499,45 +844,34 @@ mod tests {
 
     #[test]
     fn test_input_prompt_parsing() {
-        let mut executor = StatementExecutor::new();
-        let mut mem = MemoryManager::new();
-        let mut evaluator = ExpressionEvaluator::new();
-
-        // These tests check that the structure is parsed correctly
-        // but won't actually read input since that would require manual interaction
-
-        // Test INPUT with semicolon prompt (should add "?")
-        let tokens1 = vec![
+        // Reading input would require mocking stdin, so only check how prompts are parsed
+        let parse = |tokens: Vec<Token>| Parser::new(&tokens).parse_statements().unwrap();
+        let input = |prompt: &str, name: &str| vec![Statement::Input {
+            prompt: prompt.to_string(),
+            variables: vec![name.to_string()],
+        }];
+
+        // INPUT with semicolon prompt (prompt followed by a space)
+        let statements = parse(vec![
             Token::Input,
             Token::String("Enter name".to_string()),
             Token::Semicolon,
             Token::Identifier("NAME$".to_string()),
-        ];
-        // We can't test the actual input without mocking stdin, but we can test that parsing doesn't fail immediately
-        // The actual read_line will fail in test environment, but structure should be correct
+        ]);
+        assert_eq!(statements, input("Enter name ", "NAME$"));
 
-        // Test INPUT with comma prompt (no additional "?")
-        let tokens2 = vec![
+        // INPUT with comma prompt (no additional spacing)
+        let statements = parse(vec![
             Token::Input,
             Token::String("Enter name".to_string()),
             Token::Comma,
             Token::Identifier("NAME$".to_string()),
-        ];
+        ]);
+        assert_eq!(statements, input("Enter name", "NAME$"));
 
-        // Test INPUT without prompt (default "?")
-        let tokens3 = vec![
-            Token::Input,
-            Token::Identifier("NAME$".to_string()),
-        ];
-
-        // Test INPUT with numeric variable
-        let tokens4 = vec![
-            Token::Input,
-            Token::Identifier("AGE".to_string()),
-        ];
-
-        // All of these should have proper structure, even if they fail at read_line
-        // We can't easily test the input reading without mocking stdin
+        // INPUT without prompt (default "? ")
+        let statements = parse(vec![Token::Input, Token::Identifier("AGE".to_string())]);
+        assert_eq!(statements, input("? ", "AGE"));
     }
 
     #[test]
@@ -1548,10 +882,10 @@ mod tests {
 
         // Set up a program line to jump to
         use crate::runtime::memory::ProgramLine;
-        mem.program_lines.insert(100, ProgramLine {
-            number: 100,
-            tokens: vec![Token::Print, Token::String("Target line".to_string())],
-        });
+        mem.program_lines.insert(100, ProgramLine::new(
+            100,
+            vec![Token::Print, Token::String("Target line".to_string())],
+        ).unwrap());
 
         // Test GOTO to existing line
         let tokens = vec![Token::Goto, Token::Number(100.0)];
@@ -1590,10 +924,10 @@ mod tests {
 
         // Set up a program line to jump to
         use crate::runtime::memory::ProgramLine;
-        mem.program_lines.insert(100, ProgramLine {
-            number: 100,
-            tokens: vec![Token::Print, Token::String("Subroutine".to_string())],
-        });
+        mem.program_lines.insert(100, ProgramLine::new(
+            100,
+            vec![Token::Print, Token::String("Subroutine".to_string())],
+        ).unwrap());
 
         // Set current line so we have something to return to
         mem.set_current_line(10);
@@ -1673,13 +1007,6 @@ mod tests {
         mem.set_current_line(100);
 
         // Test simple FOR statement: FOR I = 1 TO 5
-        let tokens = vec![
-            Token::Identifier("I".to_string()),
-            Token::Equal,
-            Token::Number(1.0),
-            Token::Identifier("TO".to_string()),
-            Token::Number(5.0),
-        ];
 
         // This should be parsed as a FOR statement, not as tokens for execute_statement
         // We need to include the For token at the beginning
@@ -1697,7 +1024,7 @@ mod tests {
 
         // Check if variable was initialized correctly
         let value = mem.get_variable("I").unwrap();
-        assert_eq!(value, &Value::Float(1.0));
+        assert_eq!(value, Value::Float(1.0));
 
         // Check if FOR loop was pushed onto stack
         assert_eq!(mem.for_stack_size(), 1);
@@ -1718,15 +1045,6 @@ mod tests {
         mem.set_current_line(200);
 
         // Test FOR with STEP: FOR J = 10 TO 1 STEP -2
-        let tokens = vec![
-            Token::Identifier("J".to_string()),
-            Token::Equal,
-            Token::Number(10.0),
-            Token::Identifier("TO".to_string()),
-            Token::Number(1.0),
-            Token::Identifier("STEP".to_string()),
-            Token::Number(-2.0),
-        ];
 
         let for_tokens = vec![
             Token::For,
@@ -1744,7 +1062,7 @@ mod tests {
 
         // Check variable initialization
         let value = mem.get_variable("J").unwrap();
-        assert_eq!(value, &Value::Float(10.0));
+        assert_eq!(value, Value::Float(10.0));
 
         // Check FOR loop parameters
         let for_loop = mem.current_for_loop().unwrap();
@@ -1789,7 +1107,7 @@ mod tests {
 
         // Variable should be incremented
         let value = mem.get_variable("I").unwrap();
-        assert_eq!(value, &Value::Float(2.0));
+        assert_eq!(value, Value::Float(2.0));
 
         // FOR loop should still be on stack
         assert_eq!(mem.for_stack_size(), 1);
@@ -1829,7 +1147,7 @@ mod tests {
 
         // Variable should be 3
         let value = mem.get_variable("I").unwrap();
-        assert_eq!(value, &Value::Float(3.0));
+        assert_eq!(value, Value::Float(3.0));
 
         // FOR loop should be popped from stack
         assert_eq!(mem.for_stack_size(), 0);
@@ -1909,7 +1227,7 @@ mod tests {
 
         // Check if B was set
         let value = mem.get_variable("B").unwrap();
-        assert_eq!(value, &Value::Float(20.0));
+        assert_eq!(value, Value::Float(20.0));
     }
 
     #[test]
@@ -2021,7 +1339,7 @@ mod tests {
 
         // Check if FOUND was set
         let value = mem.get_variable("FOUND").unwrap();
-        assert_eq!(value, &Value::Float(1.0));
+        assert_eq!(value, Value::Float(1.0));
     }
 
     #[test]
@@ -2103,7 +1421,32 @@ mod tests {
 
         // RESULT should exist (42 is true)
         let value = mem.get_variable("RESULT").unwrap();
-        assert_eq!(value, &Value::Float(1.0));
+        assert_eq!(value, Value::Float(1.0));
+    }
+
+    #[test]
+    fn test_if_then_covers_rest_of_line() {
+        let mut executor = StatementExecutor::new();
+        let mut mem = MemoryManager::new();
+        let mut evaluator = ExpressionEvaluator::new();
+
+        // IF 0 THEN A = 1: B = 2 - both assignments belong to THEN
+        let tokens = vec![
+            Token::If,
+            Token::Number(0.0),
+            Token::Identifier("THEN".to_string()),
+            Token::Identifier("A".to_string()),
+            Token::Equal,
+            Token::Number(1.0),
+            Token::Colon,
+            Token::Identifier("B".to_string()),
+            Token::Equal,
+            Token::Number(2.0),
+        ];
+
+        executor.execute_statement(&tokens, &mut mem, &mut evaluator).unwrap();
+        assert!(mem.get_variable("A").is_err());
+        assert!(mem.get_variable("B").is_err());
     }
 
     #[test]
@@ -2130,7 +1473,7 @@ mod tests {
         assert_eq!(array.name, "A");
         assert_eq!(array.dimensions, vec![11]); // 0-10 = 11 elements
         assert_eq!(array.data.len(), 11);
-        assert_eq!(array.data[0], Value::Float(0.0)); // Should be initialized to 0
+        assert_eq!(array.data[0], StoredValue::Float(0.0)); // Should be initialized to 0
 
         // Test multi-dimension array: DIM B(3,4)
         let tokens2 = vec![
@@ -2234,9 +1577,9 @@ mod tests {
         assert!(result.unwrap()); // Should continue execution
 
         // Verify all variables were set correctly
-        assert_eq!(mem.get_variable("A").unwrap(), &Value::Float(10.0));
-        assert_eq!(mem.get_variable("B").unwrap(), &Value::Float(20.0));
-        assert_eq!(mem.get_variable("C").unwrap(), &Value::Float(30.0));
+        assert_eq!(mem.get_variable("A").unwrap(), Value::Float(10.0));
+        assert_eq!(mem.get_variable("B").unwrap(), Value::Float(20.0));
+        assert_eq!(mem.get_variable("C").unwrap(), Value::Float(30.0));
     }
 
     #[test]
@@ -2266,8 +1609,8 @@ mod tests {
         assert!(result.unwrap()); // Should continue execution
 
         // Verify variable assignments
-        assert_eq!(mem.get_variable("X").unwrap(), &Value::Float(42.0));
-        assert_eq!(mem.get_variable("Y$").unwrap(), &Value::String("Hello".to_string()));
+        assert_eq!(mem.get_variable("X").unwrap(), Value::Float(42.0));
+        assert_eq!(mem.get_variable("Y$").unwrap(), Value::String("Hello".to_string()));
     }
 
     #[test]
@@ -2295,8 +1638,8 @@ mod tests {
         assert!(result.unwrap()); // Should continue execution
 
         // Verify variable assignments
-        assert_eq!(mem.get_variable("A").unwrap(), &Value::Float(5.0));
-        assert_eq!(mem.get_variable("B").unwrap(), &Value::Float(10.0));
+        assert_eq!(mem.get_variable("A").unwrap(), Value::Float(5.0));
+        assert_eq!(mem.get_variable("B").unwrap(), Value::Float(10.0));
     }
 
     #[test]
@@ -2305,7 +1648,7 @@ mod tests {
         let mut mem = MemoryManager::new();
         let mut evaluator = ExpressionEvaluator::new();
 
-        // Test that execution stops when first statement fails
+        // Test that a syntax error anywhere on the line stops the whole line
         let tokens = vec![
             Token::Let,
             Token::Identifier("A".to_string()),
@@ -2321,10 +1664,10 @@ mod tests {
         ];
 
         let result = executor.execute_statement(&tokens, &mut mem, &mut evaluator);
-        assert!(result.is_err()); // Should fail due to syntax error in second statement
+        assert_eq!(result.unwrap_err(), BasicError::Syntax); // Syntax error in second statement
 
-        // First variable should still be set, but not the third one
-        assert_eq!(mem.get_variable("A").unwrap(), &Value::Float(5.0));
-        assert!(mem.get_variable("B").is_err()); // B should not be set
+        // The line is parsed before it runs, so no statement is executed
+        assert!(mem.get_variable("A").is_err());
+        assert!(mem.get_variable("B").is_err());
     }
 }
\ No newline at end of file
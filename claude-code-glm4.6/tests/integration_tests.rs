@@ -8,7 +8,7 @@ use std::io::Write;
 fn run_basic_program(program: &str) -> Result<String, Box<dyn std::error::Error>> {
     // Start the interpreter process
     let mut child = Command::new("cargo")
-        .args(&["run", "--"])
+        .args(["run", "--"])
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -332,6 +332,19 @@ fn test_multiple_data_statements() {
     assert_eq!(lines[1], "ABC");
 }
 
+#[test]
+fn test_error_after_statement_jump_stops_run() {
+    let program = r#"
+10 FOR I=1 TO 3: X=10/(2-I): NEXT I
+20 PRINT "NOT REACHED"
+"#;
+
+    let output = run_basic_program(program).expect("Program should run successfully");
+
+    // NEXT jumps back within line 10 before the error; the run must end there
+    assert!(!output.contains("NOT REACHED"));
+}
+
 #[test]
 fn test_out_of_data_error() {
     let program = r#"
@@ -345,4 +358,23 @@ fn test_out_of_data_error() {
     // Should print the first two values, third variable should be 0 (default)
     // In our implementation, the OUT OF DATA error is handled gracefully and execution continues
     assert!(output.contains("1") && output.contains("2"));
-}
\ No newline at end of file
+}
+#[test]
+fn test_string_space() {
+    let program = r#"
+10 FOR I=1 TO 50: A$=STR$(I)+"XYZ": NEXT I
+20 PRINT A$
+30 PRINT FRE(0)
+40 X$="": FOR I=1 TO 300: X$=X$+"A": NEXT I
+50 PRINT "NOT REACHED"
+"#;
+
+    let output = run_basic_program(program).expect("Program should run successfully");
+
+    // Garbage from the loop is collected, so only A$ is left in string space
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[0], "50XYZ");
+    assert_eq!(lines[1], "38906");
+    // The error stops the program instead of re-running the line
+    assert!(!output.contains("NOT REACHED"));
+}
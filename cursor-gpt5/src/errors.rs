@@ -1,25 +1,91 @@
 use thiserror::Error;
 
 /// Unified error type with BASIC-like short messages.
+/// The two-letter codes of the original are noted per variant.
 #[derive(Debug, Error)]
 #[allow(dead_code)]
 pub enum BasicError {
-    #[error("SYNTAX ERROR")]
-    Syntax,
-    #[error("TYPE MISMATCH")]
-    TypeMismatch,
+    /// ?NF
     #[error("NEXT WITHOUT FOR")]
     NextWithoutFor,
+    /// ?SN
+    #[error("SYNTAX ERROR")]
+    Syntax,
+    /// ?RG
     #[error("RETURN WITHOUT GOSUB")]
     ReturnWithoutGosub,
-    #[error("UNDEFINED VARIABLE: {0}")]
-    UndefVar(String),
+    /// ?OD
     #[error("OUT OF DATA")]
     OutOfData,
+    /// ?FC
+    #[error("ILLEGAL QUANTITY")]
+    IllegalQuantity,
+    /// ?OV
+    #[error("OVERFLOW")]
+    Overflow,
+    /// ?OM
+    #[error("OUT OF MEMORY")]
+    OutOfMemory,
+    /// ?UL
+    #[error("UNDEF'D STATEMENT")]
+    UndefinedStatement,
+    /// ?BS
     #[error("BAD SUBSCRIPT")]
     BadSubscript,
-    #[error("UNDEFINED ARRAY")]
-    UndefinedArray,
+    /// ?DD
+    #[error("REDIM'D ARRAY")]
+    RedimensionedArray,
+    /// ?/0
+    #[error("DIVISION BY ZERO")]
+    DivisionByZero,
+    /// ?ID
+    #[error("ILLEGAL DIRECT")]
+    IllegalDirect,
+    /// ?TM
+    #[error("TYPE MISMATCH")]
+    TypeMismatch,
+    /// ?LS
+    #[error("STRING TOO LONG")]
+    StringTooLong,
+    /// ?ST
+    #[error("FORMULA TOO COMPLEX")]
+    FormulaTooComplex,
+    /// ?CN
+    #[error("CAN'T CONTINUE")]
+    CantContinue,
+    /// ?UF
+    #[error("UNDEF'D FUNCTION")]
+    UndefinedFunction,
     #[error("IO ERROR")]
     Io,
+    /// An error raised by a running program, with where it happened.
+    #[error("{error} IN {line}")]
+    InLine { error: Box<BasicError>, line: u16, stmt: usize },
+}
+
+impl BasicError {
+    /// Attach a program position, unless the error already carries one
+    /// (e.g. it comes from a nested RUN).
+    pub fn in_line(self, line: u16, stmt: usize) -> Self {
+        match self {
+            e @ BasicError::InLine { .. } => e,
+            e => BasicError::InLine { error: Box::new(e), line, stmt },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_line_attaches_the_first_position_only() {
+        let e = BasicError::Syntax.in_line(10, 1);
+        assert!(matches!(&e, BasicError::InLine { error, line: 10, stmt: 1 } if matches!(**error, BasicError::Syntax)));
+        assert_eq!(e.to_string(), "SYNTAX ERROR IN 10");
+        // An error from a nested RUN keeps the line it happened in
+        let e = e.in_line(200, 0);
+        assert!(matches!(e, BasicError::InLine { line: 10, stmt: 1, .. }));
+        assert_eq!(e.to_string(), "SYNTAX ERROR IN 10");
+    }
 }
@@ -0,0 +1,277 @@
+//! rustyline helper for the REPL, driven by the lexer's `crunch_spans`.
+//!
+//! - highlighting: keywords and built-in functions, strings, numbers, line-number
+//!   references (after GOTO/GOSUB/THEN/RESTORE, in ON lists, and the number that
+//!   starts a program line) and REM comment text
+//! - validation: a line with an unterminated string is not accepted
+//! - hints: the signature of the built-in function being typed or called
+//! - completion: keywords, function names and variables; after GOTO/GOSUB/THEN
+//!   the line numbers of the stored program
+//!
+//! The helper cannot borrow the VM while rustyline owns it, so it keeps a copy
+//! of the line numbers and variable names, refreshed after every input line.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+
+use crate::lexer::crunch_spans;
+use crate::runtime::Vm;
+use crate::tokens::{Tok, TokenKind};
+
+/// Statement, operator and command keywords offered by completion.
+const KEYWORDS: &[&str] = &[
+    // Statements
+    "PRINT", "LET", "INPUT", "IF", "THEN", "GOTO", "GOSUB", "RETURN",
+    "FOR", "NEXT", "STEP", "TO", "END", "STOP", "CONT",
+    "DATA", "READ", "RESTORE", "DIM", "REM",
+    "ON", "SAVE", "LOAD", "USING", "GET",
+    "DEF", "FN", "POKE", "WAIT", "NULL",
+    // Operators
+    "AND", "OR", "NOT",
+    // Commands
+    "RUN", "LIST", "NEW", "CLEAR", "HELP",
+];
+
+/// Built-in functions with their signatures, for hints and completion.
+const FUNCTIONS: &[(&str, &str)] = &[
+    ("ABS", "ABS(x)"),
+    ("INT", "INT(x)"),
+    ("SGN", "SGN(x)"),
+    ("SQR", "SQR(x)"),
+    ("SIN", "SIN(x)"),
+    ("COS", "COS(x)"),
+    ("TAN", "TAN(x)"),
+    ("ATN", "ATN(x)"),
+    ("EXP", "EXP(x)"),
+    ("LOG", "LOG(x)"),
+    ("RND", "RND(x)"),
+    ("LEN", "LEN(s$)"),
+    ("LEFT$", "LEFT$(s$, n)"),
+    ("RIGHT$", "RIGHT$(s$, n)"),
+    ("MID$", "MID$(s$, start[, n])"),
+    ("CHR$", "CHR$(code)"),
+    ("ASC", "ASC(s$)"),
+    ("VAL", "VAL(s$)"),
+    ("STR$", "STR$(x)"),
+    ("SPACE$", "SPACE$(n)"),
+    ("INSTR", "INSTR(s$, find$[, start])"),
+    ("PEEK", "PEEK(addr)"),
+    ("FRE", "FRE(x)"),
+    ("POS", "POS(x)"),
+    ("TAB", "TAB(n)"),
+];
+
+// ANSI colours
+const KEYWORD: &str = "\x1b[1;34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const LINE_REF: &str = "\x1b[4;36m";
+const UNTERMINATED: &str = "\x1b[31m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Default)]
+pub struct BasicHelper {
+    line_numbers: Vec<u16>,
+    variables: Vec<String>,
+    arrays: Vec<String>,
+}
+
+impl BasicHelper {
+    /// Copy what completion needs from the VM.
+    pub fn refresh(&mut self, vm: &Vm) {
+        self.line_numbers = vm.program.lines.keys().copied().collect();
+        self.variables = vm.vars.keys().cloned().collect();
+        self.variables.sort();
+        self.arrays = vm.arrays.keys().cloned().collect();
+        self.arrays.sort();
+    }
+}
+
+fn signature(name: &str) -> Option<&'static str> {
+    FUNCTIONS.iter().find(|(f, _)| *f == name).map(|(_, sig)| *sig)
+}
+
+/// A string token without its closing quote (allowed at end of line by
+/// the interpreter, but almost always a typo at the prompt).
+fn is_unterminated(line: &str, span: &Range<usize>) -> bool {
+    span.len() < 2 || !line[span.clone()].ends_with('"')
+}
+
+/// Whether a line number is expected after these tokens: right after
+/// GOTO/GOSUB/THEN/RESTORE, or further on in an `ON x GOTO 10,20,...` list.
+fn takes_line_number(prev: &[(Tok, Range<usize>)]) -> bool {
+    let mut toks = prev.iter().rev().map(|(t, _)| t);
+    match toks.next() {
+        Some(Tok::Keyword(TokenKind::Goto | TokenKind::Gosub | TokenKind::Then | TokenKind::Restore)) => true,
+        Some(Tok::Symbol(',')) => {
+            for t in toks {
+                match t {
+                    Tok::Number(_) | Tok::Symbol(',') => continue,
+                    Tok::Keyword(TokenKind::Goto | TokenKind::Gosub) => return true,
+                    _ => return false,
+                }
+            }
+            false
+        }
+        _ => false,
+    }
+}
+
+impl Highlighter for BasicHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let toks = crunch_spans(line);
+        if toks.is_empty() {
+            return Cow::Borrowed(line);
+        }
+        let mut out = String::with_capacity(line.len() * 2);
+        let mut last = 0;
+        for (k, (tok, span)) in toks.iter().enumerate() {
+            let colour = match tok {
+                Tok::Keyword(_) => Some(KEYWORD),
+                Tok::Ident(name) if signature(name).is_some() => Some(KEYWORD),
+                Tok::String(_) if is_unterminated(line, span) => Some(UNTERMINATED),
+                Tok::String(_) => Some(STRING),
+                Tok::Number(_) if k == 0 || takes_line_number(&toks[..k]) => Some(LINE_REF),
+                Tok::Number(_) => Some(NUMBER),
+                Tok::Raw(_) if matches!(toks[k - 1].0, Tok::Keyword(TokenKind::Rem)) => Some(DIM),
+                _ => None,
+            };
+            out.push_str(&line[last..span.start]);
+            match colour {
+                Some(c) => {
+                    out.push_str(c);
+                    out.push_str(&line[span.clone()]);
+                    out.push_str(RESET);
+                }
+                None => out.push_str(&line[span.clone()]),
+            }
+            last = span.end;
+        }
+        out.push_str(&line[last..]);
+        Cow::Owned(out)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("{DIM}{hint}{RESET}"))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, kind: CmdKind) -> bool {
+        // Colours depend on the whole line, not on the cursor
+        kind != CmdKind::MoveCursor
+    }
+}
+
+impl Validator for BasicHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input();
+        let open = crunch_spans(input)
+            .iter()
+            .any(|(tok, span)| matches!(tok, Tok::String(_)) && is_unterminated(input, span));
+        if open {
+            return Ok(ValidationResult::Invalid(Some("  ?UNTERMINATED STRING".to_string())));
+        }
+        Ok(ValidationResult::Valid(None))
+    }
+}
+
+/// A function signature shown after the cursor; never inserted into the line.
+pub struct SignatureHint(String);
+
+impl Hint for SignatureHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for BasicHelper {
+    type Hint = SignatureHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<Self::Hint> {
+        if pos < line.len() {
+            return None;
+        }
+        let toks = crunch_spans(line);
+        // A function name just typed: show its argument list
+        if let Some((Tok::Ident(name), span)) = toks.last()
+            && span.end == pos
+            && let Some(sig) = signature(name)
+        {
+            return Some(SignatureHint(sig[name.len()..].to_string()));
+        }
+        // Inside a call: the innermost unclosed '(' that follows a function name
+        let mut depth = 0usize;
+        for (k, (tok, _)) in toks.iter().enumerate().rev() {
+            match tok {
+                Tok::Symbol(')') => depth += 1,
+                Tok::Symbol('(') if depth > 0 => depth -= 1,
+                Tok::Symbol('(') => {
+                    if k > 0
+                        && let Tok::Ident(name) = &toks[k - 1].0
+                        && let Some(sig) = signature(name)
+                    {
+                        return Some(SignatureHint(format!("  {sig}")));
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+impl Completer for BasicHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        // Start of the word being completed
+        let start = line[..pos].trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '$').len();
+        let prefix = line[start..pos].to_uppercase();
+
+        // GOTO/GOSUB/THEN: the program's line numbers
+        if takes_line_number(&crunch_spans(&line[..start])) {
+            let matches = self
+                .line_numbers
+                .iter()
+                .map(|ln| ln.to_string())
+                .filter(|ln| ln.starts_with(&prefix))
+                .map(|ln| Pair { display: ln.clone(), replacement: ln })
+                .collect();
+            return Ok((start, matches));
+        }
+
+        if prefix.is_empty() || prefix.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok((start, vec![]));
+        }
+
+        let words = KEYWORDS
+            .iter()
+            .map(|kw| (kw.to_string(), kw.to_string()))
+            .chain(FUNCTIONS.iter().map(|(f, sig)| (sig.to_string(), f.to_string())))
+            .chain(self.variables.iter().map(|v| (v.clone(), v.clone())))
+            .chain(self.arrays.iter().map(|a| (format!("{a}()"), format!("{a}("))));
+        let matches: Vec<Pair> = words
+            .filter(|(_, replacement)| replacement.starts_with(&prefix))
+            .map(|(display, replacement)| Pair { display, replacement })
+            .collect();
+
+        Ok((start, matches))
+    }
+}
+
+impl Helper for BasicHelper {}
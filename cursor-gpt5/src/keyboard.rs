@@ -0,0 +1,301 @@
+//! Keyboard input for GET.
+//!
+//! GET must not wait for Enter. The first GET switches the terminal to cbreak
+//! mode (no line buffering, no echo; Ctrl-C still raises SIGINT and output
+//! newlines are still translated), and every GET after that only reads bytes
+//! that have already arrived. Escape sequences for arrow and function keys are
+//! decoded into `Key`s and mapped to control codes. Keys go through a small
+//! queue like the original's keyboard buffer; keys arriving while it is full
+//! are dropped.
+//!
+//! The bytes come from a `KeySource`: the terminal, or a fake one in tests.
+
+use std::collections::VecDeque;
+
+/// Keyboard queue capacity (the original buffer holds 10 characters).
+pub const BUFFER_SIZE: usize = 10;
+
+/// A decoded key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    /// F1-F12
+    Function(u8),
+}
+
+impl Key {
+    /// Character returned by GET for this key.
+    /// Cursor keys use the Apple IIe codes; keys without an ASCII code return None.
+    pub fn to_char(self) -> Option<char> {
+        let code: u8 = match self {
+            Key::Char(c) => return Some(c),
+            Key::Enter => 13,
+            Key::Backspace | Key::Delete => 127,
+            Key::Escape => 27,
+            Key::Up => 11,
+            Key::Down => 10,
+            Key::Left => 8,
+            Key::Right => 21,
+            Key::Home | Key::Function(_) => return None,
+        };
+        Some(code as char)
+    }
+}
+
+/// Where raw key bytes come from: returns what has arrived since the last
+/// call, without blocking (empty when nothing is waiting).
+pub type KeySource = Box<dyn FnMut() -> Vec<u8>>;
+
+/// Pending key presses.
+pub struct Keyboard {
+    queue: VecDeque<Key>,
+    source: KeySource,
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Keyboard {
+    /// A keyboard reading the terminal.
+    pub fn new() -> Self {
+        Self::with_source(Box::new(terminal::read_available))
+    }
+
+    pub fn with_source(source: KeySource) -> Self {
+        Keyboard { queue: VecDeque::with_capacity(BUFFER_SIZE), source }
+    }
+
+    /// Queue a key; dropped when the queue is full.
+    pub fn push(&mut self, key: Key) {
+        if self.queue.len() < BUFFER_SIZE {
+            self.queue.push_back(key);
+        }
+    }
+
+    /// Collect whatever the source has sent and pop the oldest key.
+    /// Returns None immediately when no key is waiting.
+    pub fn poll(&mut self) -> Option<Key> {
+        for key in parse_keys(&(self.source)()) {
+            self.push(key);
+        }
+        self.queue.pop_front()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+/// Put the terminal back into the mode it had before the first GET.
+/// Called when returning to the prompt, before INPUT reads a line, and on exit.
+pub fn restore_terminal() {
+    terminal::restore();
+}
+
+/// Decode raw terminal bytes into keys (VT100/xterm sequences for arrows,
+/// Home, Delete and F1-F12). Unknown escape sequences are dropped.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+    let mut keys = Vec::new();
+
+    while let Some(ch) = chars.next() {
+        let key = match ch {
+            '\r' | '\n' => Key::Enter,
+            '\x08' | '\x7f' => Key::Backspace,
+            '\x1b' => {
+                // ESC starts a sequence only when followed by '[' or 'O'
+                let introducer = match chars.peek() {
+                    Some(&c @ ('[' | 'O')) => c,
+                    _ => {
+                        keys.push(Key::Escape);
+                        continue;
+                    }
+                };
+                chars.next();
+                let mut parameter = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_digit() && c != ';' {
+                        break;
+                    }
+                    parameter.push(c);
+                    chars.next();
+                }
+                match (introducer, parameter.as_str(), chars.next()) {
+                    (_, _, Some('A')) => Key::Up,
+                    (_, _, Some('B')) => Key::Down,
+                    (_, _, Some('C')) => Key::Right,
+                    (_, _, Some('D')) => Key::Left,
+                    (_, _, Some('H')) | ('[', "1" | "7", Some('~')) => Key::Home,
+                    ('[', "3", Some('~')) => Key::Delete,
+                    ('O', _, Some(c @ 'P'..='S')) => Key::Function(c as u8 - b'P' + 1),
+                    ('[', number, Some('~')) => match number {
+                        "15" => Key::Function(5),
+                        "17" => Key::Function(6),
+                        "18" => Key::Function(7),
+                        "19" => Key::Function(8),
+                        "20" => Key::Function(9),
+                        "21" => Key::Function(10),
+                        "23" => Key::Function(11),
+                        "24" => Key::Function(12),
+                        _ => continue,
+                    },
+                    _ => continue,
+                }
+            }
+            ch => Key::Char(ch),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::sync::Mutex;
+
+    /// Terminal settings saved before switching to cbreak mode.
+    static SAVED: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+    /// Switch stdin to cbreak mode if it is a terminal (no-op when already switched).
+    fn enable() -> bool {
+        let mut saved = SAVED.lock().unwrap_or_else(|e| e.into_inner());
+        if saved.is_some() {
+            return true;
+        }
+        // SAFETY: termios calls on stdin only; the struct is filled by tcgetattr
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) != 1 {
+                return false;
+            }
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return false;
+            }
+            let original = termios;
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            termios.c_cc[libc::VMIN] = 0;
+            termios.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                return false;
+            }
+            *saved = Some(original);
+        }
+        true
+    }
+
+    pub fn restore() {
+        let mut saved = SAVED.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(original) = saved.take() {
+            // SAFETY: original was read by tcgetattr
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original);
+            }
+        }
+    }
+
+    /// Read the bytes that are already available without blocking.
+    /// When stdin is not a terminal (pipe/file) read at most one byte so the
+    /// following program lines are left alone.
+    pub fn read_available() -> Vec<u8> {
+        let limit = if enable() { 64 } else { 1 };
+        let mut bytes = Vec::new();
+        while bytes.len() < limit {
+            let mut poll = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+            let mut byte = 0u8;
+            // SAFETY: poll and read only touch locals
+            let read = unsafe {
+                if libc::poll(&mut poll, 1, 0) <= 0 || poll.revents & libc::POLLIN == 0 {
+                    break;
+                }
+                libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1)
+            };
+            if read != 1 {
+                break;
+            }
+            bytes.push(byte);
+        }
+        bytes
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    /// Non-blocking reads are not supported here; GET only sees keys pushed
+    /// onto the queue.
+    pub fn read_available() -> Vec<u8> {
+        Vec::new()
+    }
+
+    pub fn restore() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A keyboard whose source hands out `chunks`, one per poll.
+    fn fake(chunks: &[&[u8]]) -> Keyboard {
+        let mut chunks: Vec<Vec<u8>> = chunks.iter().map(|c| c.to_vec()).collect();
+        chunks.reverse();
+        Keyboard::with_source(Box::new(move || chunks.pop().unwrap_or_default()))
+    }
+
+    #[test]
+    fn queue_is_fifo_and_drops_keys_when_full() {
+        let mut kb = fake(&[]);
+        assert_eq!(kb.poll(), None);
+        for c in "ABCDEFGHIJKL".chars() {
+            kb.push(Key::Char(c));
+        }
+        let got: String = std::iter::from_fn(|| kb.poll()).filter_map(Key::to_char).collect();
+        assert_eq!(got, "ABCDEFGHIJ");
+
+        kb.push(Key::Enter);
+        kb.clear();
+        assert_eq!(kb.poll(), None);
+    }
+
+    #[test]
+    fn poll_reads_and_decodes_the_source() {
+        let mut kb = fake(&[b"a\x1b[A", b"", b"", b"0123456789XYZ"]);
+        assert_eq!(kb.poll(), Some(Key::Char('a')));
+        // Keys from one read stay queued for later polls
+        assert_eq!(kb.poll(), Some(Key::Up));
+        assert_eq!(kb.poll(), None);
+        // A read that overflows the queue loses its last keys
+        let got: String = std::iter::from_fn(|| kb.poll()).filter_map(Key::to_char).collect();
+        assert_eq!(got, "0123456789");
+    }
+
+    #[test]
+    fn decodes_escape_sequences() {
+        assert_eq!(parse_keys(b"a\r\x7f"), vec![Key::Char('a'), Key::Enter, Key::Backspace]);
+        assert_eq!(parse_keys(b"\x1b[A\x1b[B\x1b[C\x1b[D"), vec![Key::Up, Key::Down, Key::Right, Key::Left]);
+        assert_eq!(parse_keys(b"\x1bOH\x1b[1~\x1b[3~"), vec![Key::Home, Key::Home, Key::Delete]);
+        assert_eq!(parse_keys(b"\x1bOP\x1b[15~\x1b[24~"), vec![Key::Function(1), Key::Function(5), Key::Function(12)]);
+        // Lone ESC is a key; unknown sequences are dropped
+        assert_eq!(parse_keys(b"\x1bx\x1b[99~y"), vec![Key::Escape, Key::Char('x'), Key::Char('y')]);
+    }
+
+    #[test]
+    fn keys_map_to_get_characters() {
+        assert_eq!(Key::Char('Q').to_char(), Some('Q'));
+        assert_eq!(Key::Enter.to_char(), Some('\r'));
+        assert_eq!(Key::Left.to_char(), Some('\x08'));
+        assert_eq!(Key::Up.to_char(), Some('\x0b'));
+        assert_eq!(Key::Function(3).to_char(), None);
+    }
+}
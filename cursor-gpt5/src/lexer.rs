@@ -1,10 +1,22 @@
-use crate::tokens::{lookup_keyword_upper, Tok, TokenKind};
+use std::ops::Range;
 
-/// Crunch-like lexer:
-/// - collapse reserved words into a single Keyword token; otherwise follow BASIC-ish rules.
-/// - special-case REM: once seen, treat the rest of the line as a comment and stop.
+use crate::tokens::{lookup_keyword_upper, Tok, TokenKind, RESERVED_WORDS};
+
+/// Crunch-like lexer following CRUNCH in the original:
+/// - reserved words are matched anywhere outside strings, even inside a
+///   name, trying `RESERVED_WORDS` in order (`FORI=1TO10` is `FOR I = 1 TO 10`)
+/// - numbers follow the FIN grammar: `12`, `.5`, `1.5E-3`; an `E` that starts
+///   a reserved word (`1END`) is not an exponent
+/// - after DATA the text up to a ':' outside quotes, and after REM the rest of
+///   the line, are kept verbatim as one `Raw` token
 pub fn crunch(src: &str) -> Vec<Tok> {
-    let mut out = Vec::new();
+    crunch_spans(src).into_iter().map(|(t, _)| t).collect()
+}
+
+/// `crunch`, keeping the byte range of source text behind each token
+/// (for the REPL's highlighting, hints and completion).
+pub fn crunch_spans(src: &str) -> Vec<(Tok, Range<usize>)> {
+    let mut out: Vec<(Tok, Range<usize>)> = Vec::new();
     let mut i = 0usize;
     let b = src.as_bytes();
     while i < b.len() {
@@ -16,76 +28,84 @@ pub fn crunch(src: &str) -> Vec<Tok> {
             '?' => {
                 // Alias: '?' is PRINT
                 i += 1;
-                out.push(Tok::Keyword(TokenKind::Print));
+                out.push((Tok::Keyword(TokenKind::Print), i - 1..i));
             }
-            '0'..='9' => {
+            '0'..='9' | '.' => {
                 let start = i;
-                i += 1;
-                while i < b.len() && (b[i] as char).is_ascii_digit() {
-                    i += 1;
-                }
-                if i < b.len() && (b[i] as char) == '.' {
-                    i += 1;
-                    while i < b.len() && (b[i] as char).is_ascii_digit() {
-                        i += 1;
-                    }
-                }
-                let n: f64 = src[start..i].parse().unwrap_or(0.0);
-                out.push(Tok::Number(n));
-            }
-            'A'..='Z' | '_' | 'a'..='z' => {
-                let start = i;
-                i += 1;
-                while i < b.len() {
-                    let ch = b[i] as char;
-                    if ch.is_ascii_alphanumeric() || ch == '_' || ch == '$' {
-                        i += 1;
-                    } else {
-                        break;
-                    }
-                }
-                let word = &src[start..i];
-                let upper = word.to_ascii_uppercase();
-                if let Some(kw) = lookup_keyword_upper(&upper) {
-                    if kw == TokenKind::Rem {
-                        out.push(Tok::Keyword(kw));
-                        // Stop lexing remainder (comment to end of line)
-                        break;
-                    }
-                    out.push(Tok::Keyword(kw));
-                } else {
-                    out.push(Tok::Ident(upper));
-                }
+                let (end, n) = scan_number(b, i, |e| reserved_at(b, e).is_none());
+                i = end;
+                out.push((Tok::Number(n), start..i));
             }
             '"' => {
-                // String literal
+                // String literal; the closing quote is optional at end of line
+                let open = i;
                 i += 1;
                 let start = i;
                 while i < b.len() && (b[i] as char) != '"' {
                     i += 1;
                 }
                 let s = src[start..i].to_string();
-                out.push(Tok::String(s));
                 if i < b.len() {
                     i += 1;
                 }
+                out.push((Tok::String(s), open..i));
             }
             _ => {
-                out.push(Tok::Symbol(c));
-                i += 1;
+                if let Some(word) = reserved_at(b, i) {
+                    let start = i;
+                    i += word.len();
+                    match lookup_keyword_upper(word) {
+                        Some(kw) => {
+                            out.push((Tok::Keyword(kw), start..i));
+                            match kw {
+                                TokenKind::Rem => {
+                                    out.push((Tok::Raw(src[i..].to_string()), i..b.len()));
+                                    i = b.len();
+                                }
+                                TokenKind::Data => {
+                                    let end = data_end(b, i);
+                                    out.push((Tok::Raw(src[i..end].to_string()), i..end));
+                                    i = end;
+                                }
+                                _ => {}
+                            }
+                        }
+                        // A function name
+                        None => out.push((Tok::Ident(word.to_string()), start..i)),
+                    }
+                } else if c.is_ascii_alphabetic() || c == '_' {
+                    // A name runs until a reserved word starts; '$' ends it
+                    let start = i;
+                    i += 1;
+                    while i < b.len()
+                        && ((b[i] as char).is_ascii_alphanumeric() || b[i] == b'_')
+                        && reserved_at(b, i).is_none()
+                    {
+                        i += 1;
+                    }
+                    if i < b.len() && b[i] == b'$' {
+                        i += 1;
+                    }
+                    out.push((Tok::Ident(src[start..i].to_ascii_uppercase()), start..i));
+                } else {
+                    // Whole character, so spans stay on char boundaries
+                    let ch = src[i..].chars().next().unwrap_or(c);
+                    out.push((Tok::Symbol(ch), i..i + ch.len_utf8()));
+                    i += ch.len_utf8();
+                }
             }
         }
     }
     // Post-process: merge "GO TO" into GOTO for compatibility
-    let mut merged: Vec<Tok> = Vec::new();
+    let mut merged: Vec<(Tok, Range<usize>)> = Vec::new();
     let mut j = 0usize;
     while j < out.len() {
         if j + 1 < out.len() {
-            let is_go = matches!(&out[j], Tok::Ident(s) if s == "GO");
-            let is_to_kw = matches!(&out[j+1], Tok::Keyword(TokenKind::To));
-            let is_to_id = matches!(&out[j+1], Tok::Ident(s) if s == "TO");
+            let is_go = matches!(&out[j].0, Tok::Ident(s) if s == "GO");
+            let is_to_kw = matches!(&out[j+1].0, Tok::Keyword(TokenKind::To));
+            let is_to_id = matches!(&out[j+1].0, Tok::Ident(s) if s == "TO");
             if is_go && (is_to_kw || is_to_id) {
-                merged.push(Tok::Keyword(TokenKind::Goto));
+                merged.push((Tok::Keyword(TokenKind::Goto), out[j].1.start..out[j+1].1.end));
                 j += 2;
                 continue;
             }
@@ -96,6 +116,91 @@ pub fn crunch(src: &str) -> Vec<Tok> {
     merged
 }
 
+/// The reserved word starting at `i`, if any (case-insensitive).
+fn reserved_at(b: &[u8], i: usize) -> Option<&'static str> {
+    let rest = &b[i..];
+    RESERVED_WORDS
+        .iter()
+        .find(|w| rest.len() >= w.len() && rest[..w.len()].eq_ignore_ascii_case(w.as_bytes()))
+        .copied()
+}
+
+/// End of DATA text: the first ':' outside quotes, or the end of the line.
+fn data_end(b: &[u8], mut i: usize) -> usize {
+    let mut quoted = false;
+    while i < b.len() {
+        match b[i] {
+            b'"' => quoted = !quoted,
+            b':' if !quoted => break,
+            _ => {}
+        }
+        i += 1;
+    }
+    i
+}
+
+/// Scan a number at `i`: digits, an optional '.' and more digits, then an
+/// optional exponent `E[+|-]digits` if `exponent_at` allows one at the `E`.
+/// A lone '.' is 0 and a missing exponent value is 0, as in FIN.
+/// Returns the end of the literal and its value (infinite beyond f64 range).
+fn scan_number(b: &[u8], mut i: usize, exponent_at: impl Fn(usize) -> bool) -> (usize, f64) {
+    let start = i;
+    while i < b.len() && b[i].is_ascii_digit() {
+        i += 1;
+    }
+    if i < b.len() && b[i] == b'.' {
+        i += 1;
+        while i < b.len() && b[i].is_ascii_digit() {
+            i += 1;
+        }
+    }
+    // "0" in front and behind a final '.' make ".5" and "5." valid for Rust
+    let mut text = format!("0{}", std::str::from_utf8(&b[start..i]).unwrap_or(""));
+    if text.ends_with('.') {
+        text.push('0');
+    }
+    if i < b.len() && b[i].eq_ignore_ascii_case(&b'E') && exponent_at(i) {
+        i += 1;
+        let mut exponent = String::from("e");
+        if i < b.len() && (b[i] == b'+' || b[i] == b'-') {
+            exponent.push(b[i] as char);
+            i += 1;
+        }
+        let digits = i;
+        while i < b.len() && b[i].is_ascii_digit() {
+            i += 1;
+        }
+        exponent.push_str(std::str::from_utf8(&b[digits..i]).unwrap_or(""));
+        if i > digits {
+            text.push_str(&exponent);
+        }
+    }
+    (i, text.parse().unwrap_or(0.0))
+}
+
+/// Parse a whole string as a number with an optional sign, as READ and INPUT
+/// do (leading/trailing spaces ignored, empty is 0). None if anything is left.
+pub fn parse_number(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let (negative, digits) = match s.as_bytes().first() {
+        Some(b'-') => (true, s[1..].trim_start()),
+        Some(b'+') => (false, s[1..].trim_start()),
+        _ => (false, s),
+    };
+    if digits.is_empty() {
+        return Some(0.0);
+    }
+    let b = digits.as_bytes();
+    if !(b[0].is_ascii_digit() || b[0] == b'.') {
+        return None;
+    }
+    let (end, n) = scan_number(b, 0, |_| true);
+    if end != b.len() {
+        return None;
+    }
+    Some(if negative { -n } else { n })
+}
+
 /// Parse an optional leading line number. Returns (line_no, rest).
 pub fn take_leading_line_number(src: &str) -> Option<(u16, &str)> {
     let s = src.trim_start();
@@ -117,3 +222,64 @@ pub fn take_leading_line_number(src: &str) -> Option<(u16, &str)> {
     let ln: u16 = s[..i].parse().ok()?;
     Some((ln, &s[i..]))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::TokenKind::*;
+
+    fn kw(k: TokenKind) -> Tok {
+        Tok::Keyword(k)
+    }
+
+    fn id(s: &str) -> Tok {
+        Tok::Ident(s.to_string())
+    }
+
+    #[test]
+    fn reserved_words_are_found_inside_names() {
+        assert_eq!(
+            crunch("FORI=1TO10"),
+            vec![kw(For), id("I"), Tok::Symbol('='), Tok::Number(1.0), kw(To), Tok::Number(10.0)]
+        );
+        // "SCORE" hides OR; a '$' ends a name
+        assert_eq!(crunch("SCORE"), vec![id("SC"), kw(Or), id("E")]);
+        assert_eq!(crunch("sc$=a"), vec![id("SC$"), Tok::Symbol('='), id("A")]);
+        assert_eq!(crunch("GO TO 10"), vec![kw(Goto), Tok::Number(10.0)]);
+        assert_eq!(crunch("?"), vec![kw(Print)]);
+    }
+
+    #[test]
+    fn numbers_follow_fin() {
+        assert_eq!(crunch(".5"), vec![Tok::Number(0.5)]);
+        assert_eq!(crunch("5."), vec![Tok::Number(5.0)]);
+        assert_eq!(crunch("1E-3"), vec![Tok::Number(0.001)]);
+        assert_eq!(crunch("1.5E+2"), vec![Tok::Number(150.0)]);
+        assert_eq!(crunch("2E"), vec![Tok::Number(2.0)]);
+        // The E of END is not an exponent
+        assert_eq!(crunch("1END"), vec![Tok::Number(1.0), kw(End)]);
+        assert_eq!(parse_number(" -1.5E1 "), Some(-15.0));
+        assert_eq!(parse_number(""), Some(0.0));
+        assert_eq!(parse_number("1X"), None);
+    }
+
+    #[test]
+    fn spans_cover_the_source_text() {
+        let src = "10 A=1.5E+2";
+        let spans: Vec<&str> = crunch_spans(src).into_iter().map(|(_, r)| &src[r]).collect();
+        assert_eq!(spans, ["10", "A", "=", "1.5E+2"]);
+    }
+
+    #[test]
+    fn data_and_rem_text_is_verbatim() {
+        assert_eq!(
+            crunch(r#"DATA 1, "A:B" ,FOR:PRINT"#),
+            vec![kw(Data), Tok::Raw(r#" 1, "A:B" ,FOR"#.to_string()), Tok::Symbol(':'), kw(Print)]
+        );
+        assert_eq!(
+            crunch("REM GOTO 10: PRINT"),
+            vec![kw(Rem), Tok::Raw(" GOTO 10: PRINT".to_string())]
+        );
+        assert_eq!(crunch("REM"), vec![kw(Rem), Tok::Raw(String::new())]);
+    }
+}
@@ -1,6 +1,9 @@
 mod errors;
+mod helper;
+mod keyboard;
 mod lexer;
 mod parser;
+mod print_using;
 mod program;
 mod runtime;
 mod statements;
@@ -15,11 +18,9 @@ use std::sync::{
 };
 use signal_hook::consts::SIGINT;
 use signal_hook::flag as signal_flag;
-use rustyline::{Editor, error::ReadlineError, completion::{Completer, Pair}, Context, Helper, Config};
-use rustyline::validate::{Validator, ValidationResult, ValidationContext};
-use rustyline::highlight::{Highlighter, CmdKind};
-use rustyline::hint::Hinter;
-use std::borrow::Cow;
+use rustyline::{Editor, error::ReadlineError, Config};
+use helper::BasicHelper;
+use runtime::{LogCategory, LogLevel};
 
 /// Notes:
 /// - Goal: Recreate 6502 BASIC semantics/behavior in Rust.
@@ -28,8 +29,8 @@ use std::borrow::Cow;
 fn main() -> Result<()> {
     let mut vm = runtime::Vm::new();
 
-    // switch off debug mode
-    vm.debug = false;
+    // Diagnostics are off unless BASIC_LOG or --log asks for them
+    vm.diag = diagnostics_from_args()?;
 
     // Ctrl-C handling - flag will be set by signal handler
     let interrupted = Arc::new(AtomicBool::new(false));
@@ -47,6 +48,28 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Diagnostics from the environment, overridden by the command line:
+/// `--log SPEC`, `--log-file PATH`, `--log-json`.
+fn diagnostics_from_args() -> Result<runtime::Diagnostics> {
+    let mut diag = runtime::Diagnostics::from_env().map_err(anyhow::Error::msg)?;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log" => {
+                let spec = args.next().ok_or_else(|| anyhow::anyhow!("--log needs a spec, e.g. run,for:trace"))?;
+                diag.enable(&spec).map_err(anyhow::Error::msg)?;
+            }
+            "--log-file" => {
+                let path = args.next().ok_or_else(|| anyhow::anyhow!("--log-file needs a path"))?;
+                diag.log_to_file(&path).map_err(|e| anyhow::anyhow!("--log-file {}: {}", path, e))?;
+            }
+            "--log-json" => diag.set_json(true),
+            _ => anyhow::bail!("unknown argument: {} (expected --log, --log-file or --log-json)", arg),
+        }
+    }
+    Ok(diag)
+}
+
 /// Interactive mode with rustyline (line editing, history, completion)
 fn run_interactive_mode(vm: &mut runtime::Vm, interrupted: Arc<AtomicBool>) -> Result<()> {
     // Configure rustyline
@@ -55,8 +78,8 @@ fn run_interactive_mode(vm: &mut runtime::Vm, interrupted: Arc<AtomicBool>) -> R
     
     let mut rl = Editor::<BasicHelper, _>::with_config(config)?;
     
-    // Set up helper with command completion
-    rl.set_helper(Some(BasicHelper));
+    // Set up helper: highlighting, hints and completion
+    rl.set_helper(Some(BasicHelper::default()));
     
     // IMPORTANT: Register signal handler AFTER creating rustyline Editor
     // This ensures our handler works even with rustyline's terminal modifications
@@ -69,23 +92,16 @@ fn run_interactive_mode(vm: &mut runtime::Vm, interrupted: Arc<AtomicBool>) -> R
     }
 
     println!("M6502 BASIC (Rust) — interactive REPL; type HELP for help");
-    println!("Features: Command history (↑/↓), line editing, Tab completion");
+    println!("Features: Command history (↑/↓), line editing, syntax colours, Tab completion");
     println!("Note: Press Ctrl-C during RUN to interrupt program execution");
 
     loop {
-        // Check for Ctrl-C during program execution
+        // A running program handles Ctrl-C itself; one pressed outside a run
+        // must not break the next RUN
         if interrupted.swap(false, Ordering::SeqCst) {
-            if let Some(cl) = vm.current_line {
-                vm.log_debug(format!("[REPL] Ctrl-C at line {}", cl));
-                eprintln!("?BREAK IN {}", cl);
-                if let Some(nl) = vm.next_line_after(cl) {
-                    vm.jump_to = Some(nl);
-                }
-                vm.halted = true;
-                continue;
-            }
-            // Ctrl-C at READY prompt is handled by rustyline
+            vm.log(LogCategory::Run, LogLevel::Debug, "break", || "outside a run".to_string());
         }
+        // Ctrl-C at READY prompt is handled by rustyline
 
         match rl.readline("READY. ") {
             Ok(line) => {
@@ -97,16 +113,22 @@ fn run_interactive_mode(vm: &mut runtime::Vm, interrupted: Arc<AtomicBool>) -> R
                 if let Err(e) = handle_line(vm, &line) {
                     eprintln!("?{}", e);
                 }
+                // Line numbers and variables for completion may have changed
+                if let Some(h) = rl.helper_mut() {
+                    h.refresh(vm);
+                }
+                // Leave the cbreak mode a GET may have switched to
+                keyboard::restore_terminal();
             }
             Err(ReadlineError::Interrupted) => {
                 // Ctrl-C at READY prompt
-                vm.log_debug("[REPL] Ctrl-C at READY");
+                vm.log(LogCategory::Run, LogLevel::Debug, "break", || "at READY".to_string());
                 println!("^C");
                 continue;
             }
             Err(ReadlineError::Eof) => {
                 // Ctrl-D to exit
-                vm.log_debug("[REPL] EOF (Ctrl-D)");
+                vm.log(LogCategory::Run, LogLevel::Info, "exit", || "EOF".to_string());
                 println!("BYE");
                 break;
             }
@@ -117,6 +139,8 @@ fn run_interactive_mode(vm: &mut runtime::Vm, interrupted: Arc<AtomicBool>) -> R
         }
     }
 
+    keyboard::restore_terminal();
+
     // Save history before exit
     if let Err(e) = rl.save_history(history_file) {
         eprintln!("Warning: Could not save history: {}", e);
@@ -140,87 +164,9 @@ fn run_batch_mode(vm: &mut runtime::Vm) -> Result<()> {
     Ok(())
 }
 
-/// Helper for rustyline with command completion
-struct BasicHelper;
-
-impl Completer for BasicHelper {
-    type Candidate = Pair;
-
-    fn complete(
-        &self,
-        line: &str,
-        pos: usize,
-        _ctx: &Context<'_>,
-    ) -> rustyline::Result<(usize, Vec<Pair>)> {
-        // BASIC keywords and commands for completion
-        let keywords = vec![
-            // Statements
-            "PRINT", "LET", "INPUT", "IF", "THEN", "GOTO", "GOSUB", "RETURN",
-            "FOR", "NEXT", "STEP", "TO", "END", "STOP", "CONT",
-            "DATA", "READ", "RESTORE", "DIM", "REM",
-            "ON", "SAVE", "LOAD",
-            // Commands
-            "RUN", "LIST", "NEW", "CLEAR", "HELP",
-            // Functions
-            "ABS", "INT", "SGN", "SQR", "SIN", "COS", "TAN", "ATN",
-            "EXP", "LOG", "RND",
-            "LEN", "LEFT$", "RIGHT$", "MID$", "CHR$", "ASC",
-            "VAL", "STR$", "SPACE$", "INSTR",
-        ];
-
-        // Find the start of the current word
-        let start = line[..pos]
-            .rfind(|c: char| c.is_whitespace() || c == '(' || c == ',')
-            .map(|i| i + 1)
-            .unwrap_or(0);
-        
-        let prefix = line[start..pos].to_uppercase();
-        
-        if prefix.is_empty() {
-            return Ok((start, vec![]));
-        }
-
-        // Filter keywords that start with the prefix
-        let matches: Vec<Pair> = keywords
-            .iter()
-            .filter(|kw| kw.starts_with(&prefix))
-            .map(|kw| Pair {
-                display: kw.to_string(),
-                replacement: kw.to_string(),
-            })
-            .collect();
-
-        Ok((start, matches))
-    }
-}
-
-impl Validator for BasicHelper {
-    fn validate(&self, _ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
-        Ok(ValidationResult::Valid(None))
-    }
-}
-
-impl Highlighter for BasicHelper {
-    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
-        Cow::Borrowed(line)
-    }
-
-    fn highlight_char(&self, _line: &str, _pos: usize, _forced: CmdKind) -> bool {
-        false
-    }
-}
-
-impl Hinter for BasicHelper {
-    type Hint = String;
-
-    fn hint(&self, _line: &str, _pos: usize, _ctx: &Context<'_>) -> Option<Self::Hint> {
-        None
-    }
-}
-
-impl Helper for BasicHelper {}
-
-fn handle_line(vm: &mut runtime::Vm, src: &str) -> Result<()> {
+/// Store a program line or execute a direct-mode line. Every BASIC error
+/// comes back here, already carrying its line number when raised by a program.
+fn handle_line(vm: &mut runtime::Vm, src: &str) -> std::result::Result<(), errors::BasicError> {
     // Supported input forms:
     //  1) empty line: ignore
     //  2) starts with digits: program line (insert into Program)
@@ -238,52 +184,34 @@ fn handle_line(vm: &mut runtime::Vm, src: &str) -> Result<()> {
 
     if let Some((first_no, rest)) = program::parse_leading_line_number(s) {
         // Support multiple line entries in one input: 10 ... :20 ... :30 ...
-        // Split by ':' then treat a chunk starting with digits as a new line.
-        let mut parts: Vec<&str> = Vec::new();
+        // A ':' followed by a number starts a new line (no statement starts
+        // with a number). The lexer finds them, so colons in strings, DATA
+        // and REM text stay part of the line.
+        let mut chunks: Vec<&str> = Vec::new();
         let mut start = 0usize;
-        let bytes = rest.as_bytes();
-        for i in 0..bytes.len() {
-            if bytes[i] as char == ':' {
-                parts.push(&rest[start..i]);
-                start = i + 1;
+        for w in lexer::crunch_spans(rest).windows(2) {
+            if let [(tokens::Tok::Symbol(':'), colon), (tokens::Tok::Number(_), _)] = w {
+                chunks.push(&rest[start..colon.start]);
+                start = colon.end;
             }
         }
-        parts.push(&rest[start..]);
+        chunks.push(&rest[start..]);
 
         // First chunk belongs to first_no
-        let first_chunk = parts.get(0).map(|s| s.trim()).unwrap_or("");
+        let first_chunk = chunks[0].trim();
         if first_chunk.is_empty() {
             vm.program.delete_line(first_no);
         } else {
-            let tokens = lexer::crunch(first_chunk);
-            vm.program.insert_line(first_no, tokens);
+            vm.program.insert_line(first_no, lexer::crunch(first_chunk));
         }
 
         // Subsequent chunks like "20 READ A" → parse leading line number
-        for chunk in parts.into_iter().skip(1) {
-            let c = chunk.trim_start();
-            if c.is_empty() {
-                continue;
-            }
-            if let Some((ln, stmt)) = program::parse_leading_line_number(c) {
+        for chunk in chunks.into_iter().skip(1) {
+            if let Some((ln, stmt)) = program::parse_leading_line_number(chunk) {
                 if stmt.trim().is_empty() {
                     vm.program.delete_line(ln);
                 } else {
-                    let toks = lexer::crunch(stmt);
-                    vm.program.insert_line(ln, toks);
-                }
-            } else {
-                // If no leading number after ':', treat as same line's additional statements
-                // Append to the previous line by concatenating tokens with ':'
-                // Simpler: insert as part of first_no line tail
-                let mut toks = lexer::crunch(":");
-                let mut more = lexer::crunch(c);
-                toks.append(&mut more);
-                // Re-read existing tokens and append
-                if let Some(pl) = vm.program.lines.get(&first_no).cloned() {
-                    let mut combined = pl.tokens;
-                    combined.append(&mut toks);
-                    vm.program.insert_line(first_no, combined);
+                    vm.program.insert_line(ln, lexer::crunch(stmt));
                 }
             }
         }
@@ -291,6 +219,8 @@ fn handle_line(vm: &mut runtime::Vm, src: &str) -> Result<()> {
     }
 
     // Immediate statement: parse and execute.
+    // The Enter that ended this line left the cursor at column 0.
+    vm.column = 0;
     let tokens = lexer::crunch(s);
     statements::execute_direct(vm, &tokens)
 }
@@ -307,11 +237,16 @@ fn print_help() {
     println!();
     println!("Statements:");
     println!("  PRINT / ?      - Output text and values");
+    println!("  PRINT USING f$; values - Formatted output (# . , $$ ** ^^^^ ! & \\  \\)");
     println!("  LET var=expr   - Assign value to variable");
     println!("  INPUT \"prompt\"; var - Read user input");
+    println!("  GET var        - Read a key without waiting (\"\" if none)");
     println!("  IF cond THEN   - Conditional execution");
-    println!("  GOTO line      - Jump to line number");
+    println!("  GOTO line      - Jump to line number (any expression)");
     println!("  GOSUB line     - Call subroutine");
+    println!("  ON x GOTO a,b  - Jump to the x-th target (also GOSUB)");
+    println!("  DEF FNA(X)=expr - Define a numeric function, called as FNA(...)");
+    println!("  POKE a,b / WAIT a,m[,x] - Write / wait on the memory image");
     println!("  RETURN         - Return from subroutine");
     println!("  FOR..NEXT      - Loop with counter");
     println!("  END            - End program");
@@ -326,10 +261,12 @@ fn print_help() {
     println!("Functions:");
     println!("  Math: ABS INT SGN SQR SIN COS TAN ATN EXP LOG RND");
     println!("  String: LEN LEFT$ RIGHT$ MID$ CHR$ ASC VAL STR$ SPACE$ INSTR");
+    println!("  Other: PEEK(addr) POS(X) FRE(X) TAB(n)");
     println!();
     println!("Tips:");
     println!("  - Use ↑/↓ arrows to navigate command history");
-    println!("  - Press Tab for keyword completion");
+    println!("  - Press Tab to complete keywords, variables, and line numbers after GOTO/GOSUB/THEN");
+    println!("  - Function signatures are hinted while typing a call");
     println!("  - String variables end with $ (e.g., NAME$)");
     println!("  - Ctrl-C during RUN to break, then CONT to continue");
     println!("  - Ctrl-D or Ctrl-C at prompt to exit");
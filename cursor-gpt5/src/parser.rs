@@ -1,19 +1,37 @@
+use crate::errors::BasicError;
 use crate::runtime::Vm;
-use crate::tokens::Tok;
+use crate::tokens::{Tok, TokenKind};
 use crate::value::Value;
 
-/// Minimal Pratt-style expression parser:
-/// supports + - * /, parentheses, and string concatenation via '+'.
+/// Recursive-descent expression parser and evaluator following the
+/// Microsoft BASIC operator precedence table (lowest to highest):
+///
+/// | Operators                       | Level |
+/// |---------------------------------|-------|
+/// | `OR`                            | 70    |
+/// | `AND`                           | 80    |
+/// | `NOT`                           | 90    |
+/// | `=` `<>` `<` `>` `<=` `>=`      | 100   |
+/// | `+` `-`                         | 121   |
+/// | `*` `/`                         | 123   |
+/// | unary `-`                       | 125   |
+/// | `^`                             | 127   |
+///
+/// Binary operators are left-associative. Relational operators yield -1
+/// (true) or 0 (false); `AND`/`OR`/`NOT` work bitwise on operands converted
+/// to 16-bit signed integers, so they double as logical operators.
 
 #[derive(Clone)]
 pub struct Cursor<'a> {
     pub toks: &'a [Tok],
     pub i: usize,
+    // Current expression nesting
+    depth: usize,
 }
 
 impl<'a> Cursor<'a> {
     pub fn new(toks: &'a [Tok]) -> Self {
-        Self { toks, i: 0 }
+        Self { toks, i: 0, depth: 0 }
     }
     pub fn peek(&self) -> Option<&'a Tok> {
         self.toks.get(self.i)
@@ -27,147 +45,250 @@ impl<'a> Cursor<'a> {
     }
 }
 
-#[allow(dead_code)]
-pub fn parse_expression(cur: &mut Cursor) -> Option<Value> {
-    parse_term(cur).and_then(|mut lhs| {
-        while let Some(tok) = cur.peek() {
-            match tok {
-                Tok::Symbol('+') => {
-                    cur.next();
-                    let rhs = parse_term(cur)?;
-                    lhs = match (lhs, rhs) {
-                        (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
-                        (Value::Str(a), Value::Str(b)) => Value::Str(a + &b),
-                        (Value::Str(a), Value::Number(b)) => Value::Str(a + &b.to_string()),
-                        (Value::Number(a), Value::Str(b)) => Value::Str(a.to_string() + &b),
-                        (Value::Tab(_), v) => v,
-                        (v, Value::Tab(_)) => v,
-                    };
-                }
-                Tok::Symbol('-') => {
-                    cur.next();
-                    let rhs = parse_term(cur)?;
-                    lhs = match (lhs, rhs) {
-                        (Value::Number(a), Value::Number(b)) => Value::Number(a - b),
-                        (Value::Tab(_), v) => v,
-                        (v, Value::Tab(_)) => v,
-                        (a, b) => Value::Number(a.as_number() - b.as_number()),
-                    };
-                }
-                _ => break,
+/// Nesting allowed for parenthesised and function-argument expressions;
+/// deeper formulas raise ?ST instead of overflowing the Rust stack.
+const MAX_NESTING: usize = 64;
+
+/// Largest magnitude of the original's floating-point format; beyond it ?OV.
+const MAX_NUMBER: f64 = 1.701_411_834_6e38;
+
+/// Parse and evaluate an expression, resolving identifiers through VM variables.
+pub fn parse_expression_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Result<Value, BasicError> {
+    if cur.depth >= MAX_NESTING {
+        return Err(BasicError::FormulaTooComplex);
+    }
+    cur.depth += 1;
+    let result = parse_or(cur, vm);
+    cur.depth -= 1;
+    result
+}
+
+/// Parse and evaluate an expression that must be numeric (?TM for a string).
+pub fn parse_number_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Result<f64, BasicError> {
+    arith(&parse_expression_with_vm(cur, vm)?)
+}
+
+/// Parse an address or line number: 0..65535, truncated (?FC otherwise).
+pub fn parse_word_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Result<u16, BasicError> {
+    let n = parse_number_with_vm(cur, vm)?.trunc();
+    if (0.0..=65535.0).contains(&n) { Ok(n as u16) } else { Err(BasicError::IllegalQuantity) }
+}
+
+/// Parse an array subscript or DIM bound: 0..32767, truncated (?FC otherwise).
+pub fn parse_subscript_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Result<usize, BasicError> {
+    subscript(parse_number_with_vm(cur, vm)?)
+}
+
+fn subscript(n: f64) -> Result<usize, BasicError> {
+    let n = n.trunc();
+    if (0.0..=32767.0).contains(&n) { Ok(n as usize) } else { Err(BasicError::IllegalQuantity) }
+}
+
+/// Parse a byte value: 0..255, truncated (?FC otherwise).
+pub fn parse_byte_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Result<u8, BasicError> {
+    let n = parse_number_with_vm(cur, vm)?.trunc();
+    if (0.0..=255.0).contains(&n) { Ok(n as u8) } else { Err(BasicError::IllegalQuantity) }
+}
+
+fn parse_or(cur: &mut Cursor, vm: &mut Vm) -> Result<Value, BasicError> {
+    let mut lhs = parse_and(cur, vm)?;
+    while let Some(Tok::Keyword(TokenKind::Or)) = cur.peek() {
+        cur.next();
+        let rhs = parse_and(cur, vm)?;
+        lhs = Value::Number((to_integer(&lhs)? | to_integer(&rhs)?) as f64);
+    }
+    Ok(lhs)
+}
+
+fn parse_and(cur: &mut Cursor, vm: &mut Vm) -> Result<Value, BasicError> {
+    let mut lhs = parse_relational(cur, vm)?;
+    while let Some(Tok::Keyword(TokenKind::And)) = cur.peek() {
+        cur.next();
+        let rhs = parse_relational(cur, vm)?;
+        lhs = Value::Number((to_integer(&lhs)? & to_integer(&rhs)?) as f64);
+    }
+    Ok(lhs)
+}
+
+fn parse_relational(cur: &mut Cursor, vm: &mut Vm) -> Result<Value, BasicError> {
+    let mut lhs = parse_additive(cur, vm)?;
+    loop {
+        // Any run of '<', '=', '>' forms one operator (e.g. "<>", "=<", "><")
+        let (mut less, mut equal, mut greater) = (false, false, false);
+        while let Some(Tok::Symbol(c @ ('<' | '=' | '>'))) = cur.peek() {
+            match c {
+                '<' => less = true,
+                '=' => equal = true,
+                _ => greater = true,
             }
+            cur.next();
         }
-        Some(lhs)
-    })
+        if !(less || equal || greater) {
+            return Ok(lhs);
+        }
+        let rhs = parse_additive(cur, vm)?;
+        let ord = match (&lhs, &rhs) {
+            (Value::Str(a), Value::Str(b)) => a.as_bytes().cmp(b.as_bytes()),
+            (Value::Str(_), _) | (_, Value::Str(_)) => return Err(BasicError::TypeMismatch),
+            (a, b) => a.as_number().total_cmp(&b.as_number()),
+        };
+        let holds = match ord {
+            std::cmp::Ordering::Less => less,
+            std::cmp::Ordering::Equal => equal,
+            std::cmp::Ordering::Greater => greater,
+        };
+        lhs = Value::Number(if holds { -1.0 } else { 0.0 });
+    }
 }
 
-/// Variant that resolves identifiers through VM variables.
-pub fn parse_expression_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
-    parse_term_with_vm(cur, vm).and_then(|mut lhs| {
-        while let Some(tok) = cur.peek() {
-            match tok {
-                Tok::Symbol('+') => {
-                    cur.next();
-                    let rhs = parse_term_with_vm(cur, vm)?;
-                    lhs = match (lhs, rhs) {
-                        (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
-                        (Value::Str(a), Value::Str(b)) => Value::Str(a + &b),
-                        (Value::Str(a), Value::Number(b)) => Value::Str(a + &b.to_string()),
-                        (Value::Number(a), Value::Str(b)) => Value::Str(a.to_string() + &b),
-                        (Value::Tab(_), v) => v,
-                        (v, Value::Tab(_)) => v,
-                    };
-                }
-                Tok::Symbol('-') => {
-                    cur.next();
-                    let rhs = parse_term_with_vm(cur, vm)?;
-                    lhs = Value::Number(lhs.as_number() - rhs.as_number());
-                }
-                _ => break,
+fn parse_additive(cur: &mut Cursor, vm: &mut Vm) -> Result<Value, BasicError> {
+    let mut lhs = parse_term_with_vm(cur, vm)?;
+    while let Some(tok) = cur.peek() {
+        match tok {
+            Tok::Symbol('+') => {
+                cur.next();
+                let rhs = parse_term_with_vm(cur, vm)?;
+                lhs = match (lhs, rhs) {
+                    (Value::Number(a), Value::Number(b)) => number(a + b)?,
+                    (Value::Str(a), Value::Str(b)) => string(a + &b)?,
+                    (Value::Tab(_), v) => v,
+                    (v, Value::Tab(_)) => v,
+                    _ => return Err(BasicError::TypeMismatch),
+                };
+            }
+            Tok::Symbol('-') => {
+                cur.next();
+                let rhs = parse_term_with_vm(cur, vm)?;
+                lhs = number(arith(&lhs)? - arith(&rhs)?)?;
             }
+            _ => break,
         }
-        Some(lhs)
-    })
+    }
+    Ok(lhs)
 }
 
-#[allow(dead_code)]
-fn parse_term(cur: &mut Cursor) -> Option<Value> {
-    parse_factor(cur).and_then(|mut lhs| {
-        while let Some(tok) = cur.peek() {
-            match tok {
-                Tok::Symbol('*') => {
-                    cur.next();
-                    let rhs = parse_factor(cur)?;
-                    lhs = Value::Number(lhs.as_number() * rhs.as_number());
-                }
-                Tok::Symbol('/') => {
-                    cur.next();
-                    let rhs = parse_factor(cur)?;
-                    lhs = Value::Number(lhs.as_number() / rhs.as_number());
-                }
-                _ => break,
+fn parse_term_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Result<Value, BasicError> {
+    let mut lhs = parse_unary(cur, vm)?;
+    while let Some(tok) = cur.peek() {
+        match tok {
+            Tok::Symbol('*') => {
+                cur.next();
+                let rhs = parse_unary(cur, vm)?;
+                lhs = match (lhs, rhs) {
+                    (Value::Tab(_), v) => v,
+                    (v, Value::Tab(_)) => v,
+                    (a, b) => number(arith(&a)? * arith(&b)?)?,
+                };
+            }
+            Tok::Symbol('/') => {
+                cur.next();
+                let rhs = parse_unary(cur, vm)?;
+                lhs = match (lhs, rhs) {
+                    (Value::Tab(_), v) => v,
+                    (v, Value::Tab(_)) => v,
+                    (a, b) => {
+                        let (a, b) = (arith(&a)?, arith(&b)?);
+                        if b == 0.0 { return Err(BasicError::DivisionByZero); }
+                        number(a / b)?
+                    }
+                };
             }
+            _ => break,
         }
-        Some(lhs)
-    })
+    }
+    Ok(lhs)
 }
 
-fn parse_term_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
-    parse_factor_with_vm(cur, vm).and_then(|mut lhs| {
-        while let Some(tok) = cur.peek() {
-            match tok {
-                Tok::Symbol('*') => {
-                    cur.next();
-                    let rhs = parse_factor_with_vm(cur, vm)?;
-                    lhs = match (lhs, rhs) {
-                        (Value::Number(a), Value::Number(b)) => Value::Number(a * b),
-                        (Value::Str(a), Value::Str(b)) => Value::Str(format!("{}{}", a, b)),
-                        (Value::Tab(_), v) => v,
-                        (v, Value::Tab(_)) => v,
-                        (a, b) => Value::Number(a.as_number() * b.as_number()),
-                    };
-                }
-                Tok::Symbol('/') => {
-                    cur.next();
-                    let rhs = parse_factor_with_vm(cur, vm)?;
-                    lhs = match (lhs, rhs) {
-                        (Value::Number(a), Value::Number(b)) => Value::Number(a / b),
-                        (Value::Tab(_), v) => v,
-                        (v, Value::Tab(_)) => v,
-                        (a, b) => Value::Number(a.as_number() / b.as_number()),
-                    };
-                }
-                _ => break,
-            }
+/// Unary operators in operand position.
+///
+/// `NOT` takes everything down to the relational level as its operand, so
+/// `NOT A=B` is `NOT (A=B)`; unary minus binds below `^`, so `-2^2` is -4.
+fn parse_unary(cur: &mut Cursor, vm: &mut Vm) -> Result<Value, BasicError> {
+    match cur.peek() {
+        Some(Tok::Keyword(TokenKind::Not)) => {
+            cur.next();
+            let v = parse_relational(cur, vm)?;
+            Ok(Value::Number(!to_integer(&v)? as f64))
+        }
+        Some(Tok::Symbol('-')) => {
+            cur.next();
+            let v = parse_unary(cur, vm)?;
+            Ok(Value::Number(-arith(&v)?))
+        }
+        Some(Tok::Symbol('+')) => {
+            cur.next();
+            parse_unary(cur, vm)
         }
-        Some(lhs)
-    })
+        _ => parse_power(cur, vm),
+    }
 }
 
-#[allow(dead_code)]
-fn parse_factor(cur: &mut Cursor) -> Option<Value> {
-    match cur.next()? {
-        Tok::Number(n) => Some(Value::Number(*n)),
-        Tok::String(s) => Some(Value::Str(s.clone())),
-        Tok::Symbol('(') => {
-            let v = parse_expression(cur)?;
-            if !matches!(cur.next(), Some(Tok::Symbol(')'))) {
-                return None;
-            }
-            Some(v)
+fn parse_power(cur: &mut Cursor, vm: &mut Vm) -> Result<Value, BasicError> {
+    let mut lhs = parse_factor_with_vm(cur, vm)?;
+    while let Some(Tok::Symbol('^')) = cur.peek() {
+        cur.next();
+        // A sign is allowed on the exponent: 2^-1
+        let negate = matches!(cur.peek(), Some(Tok::Symbol('-')));
+        if negate {
+            cur.next();
         }
-        Tok::Symbol('-') => {
-            let v = parse_factor(cur)?;
-            Some(Value::Number(-v.as_number()))
+        let rhs = arith(&parse_factor_with_vm(cur, vm)?)?;
+        let rhs = if negate { -rhs } else { rhs };
+        let base = arith(&lhs)?;
+        if base == 0.0 && rhs < 0.0 {
+            return Err(BasicError::DivisionByZero);
         }
-        _ => None,
+        lhs = number(base.powf(rhs))?;
+    }
+    Ok(lhs)
+}
+
+/// A numeric operand of an arithmetic operator (?TM for a string).
+fn arith(v: &Value) -> Result<f64, BasicError> {
+    match v {
+        Value::Str(_) => Err(BasicError::TypeMismatch),
+        v => Ok(v.as_number()),
     }
 }
 
-fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
-    match cur.next()? {
-        Tok::Number(n) => Some(Value::Number(*n)),
-        Tok::String(s) => Some(Value::Str(s.clone())),
+/// A numeric result: ?OV beyond the original's range, ?FC if undefined.
+fn number(n: f64) -> Result<Value, BasicError> {
+    if n.is_nan() {
+        Err(BasicError::IllegalQuantity)
+    } else if n.abs() > MAX_NUMBER {
+        Err(BasicError::Overflow)
+    } else {
+        Ok(Value::Number(n))
+    }
+}
+
+/// A string result: ?LS beyond 255 characters.
+fn string(s: String) -> Result<Value, BasicError> {
+    if s.chars().count() > 255 {
+        Err(BasicError::StringTooLong)
+    } else {
+        Ok(Value::Str(s))
+    }
+}
+
+/// Convert an operand of `AND`/`OR`/`NOT` to a 16-bit signed integer.
+fn to_integer(v: &Value) -> Result<i16, BasicError> {
+    let n = match v {
+        Value::Str(_) => return Err(BasicError::TypeMismatch),
+        v => v.as_number().trunc(),
+    };
+    if (-32768.0..=32767.0).contains(&n) {
+        Ok(n as i16)
+    } else {
+        Err(BasicError::IllegalQuantity)
+    }
+}
+
+fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Result<Value, BasicError> {
+    match cur.next().ok_or(BasicError::Syntax)? {
+        // A literal beyond the original's range (1E39) is ?OV
+        Tok::Number(n) => number(*n),
+        Tok::String(s) => Ok(Value::Str(s.clone())),
         Tok::Ident(name) => {
             // Function call or array indexing? e.g., LEN(x) / A(5)
             if let Some(Tok::Symbol('(')) = cur.peek() {
@@ -177,14 +298,10 @@ fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
                 let mut args: Vec<Value> = vec![arg1];
                 while let Some(Tok::Symbol(',')) = cur.peek() {
                     cur.next();
-                    if let Some(v) = parse_expression_with_vm(cur, vm) {
-                        args.push(v);
-                    } else {
-                        return None;
-                    }
+                    args.push(parse_expression_with_vm(cur, vm)?);
                 }
                 if !matches!(cur.next(), Some(Tok::Symbol(')'))) {
-                    return None;
+                    return Err(BasicError::Syntax);
                 }
                 let up = name.to_ascii_uppercase();
                 let res = match up.as_str() {
@@ -197,16 +314,17 @@ fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
                         }
                     }
                     "CHR$" => {
-                        let code = args.get(0).unwrap().as_number();
-                        let ch = (code as i64).clamp(0, 255) as u8 as char;
+                        let code = args.first().unwrap().as_number();
+                        if !(0.0..256.0).contains(&code) { return Err(BasicError::IllegalQuantity); }
+                        let ch = code as u8 as char;
                         Value::Str(ch.to_string())
                     }
                     "ASC" => match args.remove(0) {
                         Value::Str(s) => {
-                            let copt = s.chars().next();
-                            Value::Number(copt.map(|c| c as u32 as f64).unwrap_or(0.0))
+                            let c = s.chars().next().ok_or(BasicError::IllegalQuantity)?;
+                            Value::Number(c as u32 as f64)
                         }
-                        Value::Number(n) => Value::Number(n as f64),
+                        Value::Number(n) => Value::Number(n),
                         Value::Tab(_) => Value::Number(0.0),
                     },
                     "VAL" => match args.remove(0) {
@@ -238,15 +356,17 @@ fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
                     "ATN" => Value::Number(args.remove(0).as_number().atan()),
                     "SQR" => {
                         let v = args.remove(0).as_number();
-                        Value::Number(if v >= 0.0 { v.sqrt() } else { f64::NAN })
+                        if v < 0.0 { return Err(BasicError::IllegalQuantity); }
+                        Value::Number(v.sqrt())
                     }
-                    "EXP" => Value::Number(args.remove(0).as_number().exp()),
+                    "EXP" => number(args.remove(0).as_number().exp())?,
                     "LOG" => {
                         let v = args.remove(0).as_number();
-                        Value::Number(if v > 0.0 { v.ln() } else { f64::NAN })
+                        if v <= 0.0 { return Err(BasicError::IllegalQuantity); }
+                        Value::Number(v.ln())
                     }
                     "LEFT$" => {
-                        let s = match args.get(0) {
+                        let s = match args.first() {
                             Some(Value::Str(s)) => s.clone(),
                             v => v
                                 .as_ref()
@@ -263,7 +383,7 @@ fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
                         Value::Str(s.chars().take(n).collect())
                     }
                     "RIGHT$" => {
-                        let s = match args.get(0) {
+                        let s = match args.first() {
                             Some(Value::Str(s)) => s.clone(),
                             v => v
                                 .as_ref()
@@ -283,7 +403,7 @@ fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
                     }
                     "MID$" => {
                         // MID$(s, start[, len])  start 1-based
-                        let s = match args.get(0) {
+                        let s = match args.first() {
                             Some(Value::Str(s)) => s.clone(),
                             v => v
                                 .as_ref()
@@ -300,11 +420,11 @@ fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
                         let len_opt = args.get(2).map(|v| v.as_number() as usize);
                         let chars: Vec<char> = s.chars().collect();
                         if start <= 0 {
-                            return Some(Value::Str(String::new()));
+                            return Err(BasicError::IllegalQuantity);
                         }
                         let idx0 = (start as usize).saturating_sub(1);
                         if idx0 >= chars.len() {
-                            return Some(Value::Str(String::new()));
+                            return Ok(Value::Str(String::new()));
                         }
                         let slice = if let Some(l) = len_opt {
                             &chars[idx0..(idx0 + l).min(chars.len())]
@@ -315,22 +435,20 @@ fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
                     }
                     "RND" => {
                         // RND() -> next; RND(n): if n<=0 reseed simple; if n>0 next
-                        if let Some(v) = args.get(0) {
-                            if v.as_number() <= 0.0 {
-                                // simple reseed using a xor of current seed
-                                vm.rng_seed ^= 0x9E3779B97F4A7C15;
-                            }
+                        if let Some(v) = args.first() && v.as_number() <= 0.0 {
+                            // simple reseed using a xor of current seed
+                            vm.rng_seed ^= 0x9E3779B97F4A7C15;
                         }
                         Value::Number(vm.next_rand())
                     }
                     "SPACE$" => {
-                        let n = args.get(0).map(|v| v.as_number() as isize).unwrap_or(0);
-                        let n = if n <= 0 { 0 } else { n as usize };
-                        Value::Str(" ".repeat(n))
+                        let n = args.first().map(|v| v.as_number()).unwrap_or(0.0);
+                        if !(0.0..256.0).contains(&n) { return Err(BasicError::IllegalQuantity); }
+                        Value::Str(" ".repeat(n as usize))
                     }
                     "INSTR" => {
                         // INSTR(string, substring[, start]) 1-based; return 0 if not found
-                        let s = match args.get(0) {
+                        let s = match args.first() {
                             Some(Value::Str(s)) => s.clone(),
                             Some(Value::Number(n)) => n.to_string(),
                             _ => String::new(),
@@ -344,7 +462,7 @@ fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
                         if sub.is_empty() { Value::Number(1.0) } else {
                             let chars: Vec<char> = s.chars().collect();
                             let subchars: Vec<char> = sub.chars().collect();
-                            if start <= 0 { return Some(Value::Number(0.0)); }
+                            if start <= 0 { return Ok(Value::Number(0.0)); }
                             let begin = (start as usize).saturating_sub(1);
                             let mut found = 0usize;
                             'outer: for i in begin..=chars.len().saturating_sub(subchars.len()) {
@@ -357,49 +475,147 @@ fn parse_factor_with_vm(cur: &mut Cursor, vm: &mut Vm) -> Option<Value> {
                             Value::Number(found as f64)
                         }
                     }
+                    "PEEK" => {
+                        let addr = args.remove(0).as_number().trunc();
+                        if !(0.0..=65535.0).contains(&addr) { return Err(BasicError::IllegalQuantity); }
+                        Value::Number(vm.peek(addr as u16) as f64)
+                    }
+                    // POS(X): the argument is a dummy
+                    "POS" => Value::Number(vm.column as f64),
                     "TAB" => {
-                        let n = args.get(0).map(|v| v.as_number() as usize).unwrap_or(0);
+                        let n = args.first().map(|v| v.as_number() as usize).unwrap_or(0);
                         Value::Tab(n)
                     }
+                    // FRE(X): the argument is a dummy
+                    "FRE" => Value::Number(vm.free_memory() as f64),
                     _ => {
                         // Treat as array indexing: NAME(idx[,idx...])
-                        let idxs: Vec<usize> = args.iter().map(|v| {
-                            let x = v.as_number(); if x <= 0.0 { 0usize } else { x as usize }
-                        }).collect();
-                        if idxs.iter().any(|&k| k==0) {
-                            vm.error_override = Some(crate::errors::BasicError::BadSubscript);
-                            Value::Number(0.0)
-                        } else if !vm.arrays.contains_key(&up) {
-                            vm.error_override = Some(crate::errors::BasicError::UndefinedArray);
-                            Value::Number(0.0)
-                        } else {
-                            match vm.get_array_element(&up, &idxs) {
-                                Some(v) => v,
-                                None => { vm.error_override = Some(crate::errors::BasicError::BadSubscript); Value::Number(0.0) }
-                            }
-                        }
+                        let idxs = args.iter().map(|v| subscript(v.as_number())).collect::<Result<Vec<_>, _>>()?;
+                        vm.get_array_element(&up, &idxs)?
                     }
                 };
-                Some(res)
+                Ok(res)
             } else {
                 // Variable value; defaults to 0 if undefined
                 match vm.vars.get(name) {
-                    Some(v) => Some(v.clone()),
-                    None => Some(Value::Number(0.0)),
+                    Some(v) => Ok(v.clone()),
+                    None => Ok(Value::Number(0.0)),
                 }
             }
         }
+        Tok::Keyword(TokenKind::Fn) => {
+            // FNA(X): call a DEF FN function
+            let Some(Tok::Ident(name)) = cur.next() else { return Err(BasicError::Syntax) };
+            if !matches!(cur.next(), Some(Tok::Symbol('('))) {
+                return Err(BasicError::Syntax);
+            }
+            let arg = parse_number_with_vm(cur, vm)?;
+            if !matches!(cur.next(), Some(Tok::Symbol(')'))) {
+                return Err(BasicError::Syntax);
+            }
+            call_user_fn(cur, vm, name, arg)
+        }
         Tok::Symbol('(') => {
             let v = parse_expression_with_vm(cur, vm)?;
             if !matches!(cur.next(), Some(Tok::Symbol(')'))) {
-                return None;
+                return Err(BasicError::Syntax);
             }
-            Some(v)
+            Ok(v)
         }
-        Tok::Symbol('-') => {
-            let v = parse_factor_with_vm(cur, vm)?;
-            Some(Value::Number(-v.as_number()))
+        _ => Err(BasicError::Syntax),
+    }
+}
+
+/// Evaluate a DEF FN body with its parameter bound to `arg`.
+///
+/// The parameter shadows a variable of the same name only during the call;
+/// the variable's previous value (or its absence) is restored afterwards,
+/// even when the body fails. The body counts towards the caller's nesting,
+/// so runaway recursion ends in ?ST.
+fn call_user_fn(cur: &Cursor, vm: &mut Vm, name: &str, arg: f64) -> Result<Value, BasicError> {
+    let f = vm.user_fns.get(name).cloned().ok_or(BasicError::UndefinedFunction)?;
+    let saved = vm.vars.insert(f.param.clone(), Value::Number(arg));
+    let mut body = Cursor { toks: &f.body, i: 0, depth: cur.depth };
+    let result = parse_number_with_vm(&mut body, vm)
+        .and_then(|v| if body.peek().is_some() { Err(BasicError::Syntax) } else { number(v) });
+    match saved {
+        Some(v) => { vm.vars.insert(f.param, v); }
+        None => { vm.vars.remove(&f.param); }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::crunch;
+
+    /// Evaluate `src` as a whole expression in a fresh VM.
+    fn eval(src: &str) -> Result<Value, BasicError> {
+        let toks = crunch(src);
+        let mut cur = Cursor::new(&toks);
+        let v = parse_expression_with_vm(&mut cur, &mut Vm::new())?;
+        assert!(cur.peek().is_none(), "{} left tokens unparsed", src);
+        Ok(v)
+    }
+
+    fn num(src: &str) -> f64 {
+        match eval(src) {
+            Ok(Value::Number(n)) => n,
+            other => panic!("{} gave {:?}", src, other),
         }
-        _ => None,
+    }
+
+    #[test]
+    fn arithmetic_precedence() {
+        assert_eq!(num("1+2*3"), 7.0);
+        assert_eq!(num("(1+2)*3"), 9.0);
+        // Unary minus binds below ^
+        assert_eq!(num("-2^2"), -4.0);
+        assert_eq!(num("2^-1"), 0.5);
+        // ^ is left-associative like every other operator
+        assert_eq!(num("2^3^2"), 64.0);
+        assert_eq!(num("8-2-1"), 5.0);
+    }
+
+    #[test]
+    fn relational_and_logical_operators() {
+        assert_eq!(num("1<2"), -1.0);
+        assert_eq!(num("2<=1"), 0.0);
+        assert_eq!(num("1><2"), -1.0);
+        // NOT takes the whole comparison as its operand
+        assert_eq!(num("NOT 1=2"), -1.0);
+        assert_eq!(num("NOT 0"), -1.0);
+        assert_eq!(num("5 AND 3"), 1.0);
+        assert_eq!(num("5 OR 2"), 7.0);
+        assert_eq!(num("1=1 AND 2=2"), -1.0);
+        assert!(matches!(eval("40000 AND 1"), Err(BasicError::IllegalQuantity)));
+        assert!(matches!(eval("NOT -32769"), Err(BasicError::IllegalQuantity)));
+    }
+
+    #[test]
+    fn string_comparison_and_type_errors() {
+        assert_eq!(num(r#""ABC"<"ABD""#), -1.0);
+        assert_eq!(num(r#""AB"<"ABC""#), -1.0);
+        assert_eq!(num(r#""B"="B""#), -1.0);
+        assert_eq!(eval(r#""AB"+"CD""#).unwrap(), Value::Str("ABCD".into()));
+        assert!(matches!(eval(r#""A"=1"#), Err(BasicError::TypeMismatch)));
+        assert!(matches!(eval(r#""A" AND 1"#), Err(BasicError::TypeMismatch)));
+        assert!(matches!(eval(r#"-"A""#), Err(BasicError::TypeMismatch)));
+    }
+
+    #[test]
+    fn incomplete_expressions_are_syntax_errors() {
+        assert!(matches!(eval("(1+"), Err(BasicError::Syntax)));
+        assert!(matches!(eval("2*"), Err(BasicError::Syntax)));
+        let err = crate::statements::execute_direct(&mut Vm::new(), &crunch("PRINT (1+")).unwrap_err();
+        assert!(matches!(err, BasicError::Syntax));
+    }
+
+    #[test]
+    fn range_errors() {
+        assert!(matches!(eval("1/0"), Err(BasicError::DivisionByZero)));
+        assert!(matches!(eval("0^-1"), Err(BasicError::DivisionByZero)));
+        assert!(matches!(eval("1E38*10"), Err(BasicError::Overflow)));
     }
 }
@@ -0,0 +1,399 @@
+//! PRINT USING formatter.
+//! - numeric fields: `#`, `.`, `,` (thousands), leading `+`, trailing `+`/`-`,
+//!   leading `**` (asterisk fill), `$$` (floating dollar), `**$`, exponent `^^^^`
+//! - string fields: `!` (first char), `&` (whole string), `\  \` (fixed width)
+//! - `_` escapes the next character; anything else is printed literally
+//!
+//! Numbers that do not fit their field are printed in full with a `%` prefix.
+
+use crate::errors::BasicError;
+use crate::value::Value;
+
+type Result<T> = std::result::Result<T, BasicError>;
+
+/// One piece of a format string.
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    /// Literal text
+    Literal(String),
+    /// Numeric field
+    Number(NumberField),
+    /// `!`: first character of a string
+    FirstChar,
+    /// `&`: whole string
+    WholeString,
+    /// `\  \`: fixed-width string
+    FixedString(usize),
+}
+
+/// Layout of a numeric field.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct NumberField {
+    /// Positions left of the decimal point (including `**`, `$$`, `,` and a leading `+`)
+    integer_width: usize,
+    /// Number of `#` left of the decimal point
+    digits: usize,
+    /// Digits after the decimal point (`None`: no decimal point)
+    decimals: Option<usize>,
+    /// Group the integer part with commas
+    commas: bool,
+    /// Leading `+`: always print the sign
+    leading_plus: bool,
+    /// Trailing `+` or `-`
+    trailing_sign: Option<char>,
+    /// `**`: fill leading blanks with asterisks
+    fill_asterisk: bool,
+    /// `$$`: dollar sign right before the digits
+    dollar: bool,
+    /// `^^^^`: exponential format
+    exponent: bool,
+}
+
+/// Format values with a PRINT USING format string.
+/// The format is reused while values remain; output stops before the first field without a value.
+pub fn format_using(format: &str, values: &[Value]) -> Result<String> {
+    let pieces = parse_format(format);
+    if !pieces.iter().any(|piece| !matches!(piece, Piece::Literal(_))) {
+        return Err(BasicError::IllegalQuantity);
+    }
+
+    let mut output = String::new();
+    let mut values = values.iter().peekable();
+    loop {
+        for piece in &pieces {
+            if let Piece::Literal(text) = piece {
+                output.push_str(text);
+                continue;
+            }
+            let Some(value) = values.next() else {
+                return Ok(output);
+            };
+            output.push_str(&format_field(piece, value)?);
+        }
+        if values.peek().is_none() {
+            return Ok(output);
+        }
+    }
+}
+
+/// Format one field.
+fn format_field(piece: &Piece, value: &Value) -> Result<String> {
+    match (piece, value) {
+        (Piece::Number(field), Value::Number(n)) => Ok(format_number(field, *n)),
+        (Piece::FirstChar, Value::Str(s)) => Ok(pad_string(s, 1)),
+        (Piece::WholeString, Value::Str(s)) => Ok(s.clone()),
+        (Piece::FixedString(width), Value::Str(s)) => Ok(pad_string(s, *width)),
+        _ => Err(BasicError::TypeMismatch),
+    }
+}
+
+/// Truncate or pad with spaces to `width`.
+fn pad_string(s: &str, width: usize) -> String {
+    let mut result: String = s.chars().take(width).collect();
+    let len = result.chars().count();
+    result.push_str(&" ".repeat(width - len));
+    result
+}
+
+/// Split a format string into literals and fields.
+fn parse_format(format: &str) -> Vec<Piece> {
+    let chars: Vec<char> = format.chars().collect();
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+
+        let field = match ch {
+            '_' => {
+                // Escape: next character is literal
+                literal.push(chars.get(i + 1).copied().unwrap_or('_'));
+                i += 2;
+                continue;
+            }
+            '!' => {
+                i += 1;
+                Some(Piece::FirstChar)
+            }
+            '&' => {
+                i += 1;
+                Some(Piece::WholeString)
+            }
+            '\\' => {
+                // Only spaces may appear between the two backslashes
+                let spaces = chars[i + 1..].iter().take_while(|&&c| c == ' ').count();
+                if chars.get(i + 1 + spaces) == Some(&'\\') {
+                    i += spaces + 2;
+                    Some(Piece::FixedString(spaces + 2))
+                } else {
+                    None
+                }
+            }
+            _ => parse_number_field(&chars, &mut i).map(Piece::Number),
+        };
+
+        match field {
+            Some(field) => {
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                }
+                pieces.push(field);
+            }
+            None => {
+                literal.push(ch);
+                i += 1;
+            }
+        }
+    }
+
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+    pieces
+}
+
+/// Parse a numeric field at `*i`; returns None (leaving `*i` alone) if there is none.
+fn parse_number_field(chars: &[char], i: &mut usize) -> Option<NumberField> {
+    let at = |pos: usize| chars.get(pos).copied();
+    let starts_number = |pos: usize| match at(pos) {
+        Some('#') => true,
+        Some('.') => at(pos + 1) == Some('#'),
+        Some('*') => at(pos + 1) == Some('*'),
+        Some('$') => at(pos + 1) == Some('$'),
+        _ => false,
+    };
+
+    let mut pos = *i;
+    let mut field = NumberField::default();
+
+    if at(pos) == Some('+') && starts_number(pos + 1) {
+        field.leading_plus = true;
+        field.integer_width += 1;
+        pos += 1;
+    }
+    if !starts_number(pos) {
+        return None;
+    }
+
+    if at(pos) == Some('*') {
+        field.fill_asterisk = true;
+        field.integer_width += 2;
+        pos += 2;
+        if at(pos) == Some('$') {
+            field.dollar = true;
+            field.integer_width += 1;
+            pos += 1;
+        }
+    } else if at(pos) == Some('$') {
+        field.dollar = true;
+        field.integer_width += 2;
+        pos += 2;
+    }
+
+    // Integer part: # and thousands separator ,
+    while let Some(c) = at(pos) {
+        match c {
+            '#' => field.digits += 1,
+            ',' => field.commas = true,
+            _ => break,
+        }
+        field.integer_width += 1;
+        pos += 1;
+    }
+
+    // Fraction
+    if at(pos) == Some('.') {
+        pos += 1;
+        let mut decimals = 0;
+        while at(pos) == Some('#') {
+            decimals += 1;
+            pos += 1;
+        }
+        field.decimals = Some(decimals);
+    }
+
+    // Exponent ^^^^
+    if (0..4).all(|k| at(pos + k) == Some('^')) {
+        field.exponent = true;
+        pos += 4;
+    }
+
+    // Trailing sign
+    if !field.leading_plus && let Some(sign @ ('+' | '-')) = at(pos) {
+        field.trailing_sign = Some(sign);
+        pos += 1;
+    }
+
+    *i = pos;
+    Some(field)
+}
+
+/// Format a number into a numeric field.
+fn format_number(field: &NumberField, n: f64) -> String {
+    let negative = n < 0.0;
+    let decimals = field.decimals.unwrap_or(0);
+
+    // Sign: leading + always shown, trailing sign goes last, otherwise '-' in front of negatives
+    let (lead_sign, trail_sign) = match field.trailing_sign {
+        Some('+') => ("", if negative { "-" } else { "+" }),
+        Some(_) => ("", if negative { "-" } else { " " }),
+        None if field.leading_plus => (if negative { "-" } else { "+" }, ""),
+        None => (if negative { "-" } else { "" }, ""),
+    };
+
+    let (mut int_part, frac_part, exponent) = if field.exponent {
+        let (mantissa, exponent) = scale_for_exponent(field, n.abs());
+        let (int_part, frac_part) = split_fixed(mantissa, decimals);
+        (int_part, frac_part, Some(exponent))
+    } else {
+        let (int_part, frac_part) = split_fixed(n.abs(), decimals);
+        (int_part, frac_part, None)
+    };
+
+    // No integer digits: drop the leading 0 (e.g. ".##")
+    if int_part == "0" && field.digits == 0 && field.decimals.is_some() {
+        int_part.clear();
+    }
+    if field.commas {
+        int_part = group_thousands(&int_part);
+    }
+
+    let mut left = String::from(lead_sign);
+    if field.dollar {
+        left.push('$');
+    }
+    left.push_str(&int_part);
+
+    let width = left.chars().count();
+    let mut result = if width > field.integer_width {
+        // Overflow: print in full with a % prefix
+        format!("%{}", left)
+    } else {
+        let fill = if field.fill_asterisk { "*" } else { " " };
+        format!("{}{}", fill.repeat(field.integer_width - width), left)
+    };
+
+    if field.decimals.is_some() {
+        result.push('.');
+        result.push_str(&frac_part);
+    }
+    if let Some(exponent) = exponent {
+        result.push_str(&format!("E{}{:02}", if exponent < 0 { '-' } else { '+' }, exponent.abs()));
+    }
+    result.push_str(trail_sign);
+    result
+}
+
+/// Round to `decimals` places and split into integer and fraction digits.
+fn split_fixed(value: f64, decimals: usize) -> (String, String) {
+    let text = format!("{:.*}", decimals, value);
+    match text.split_once('.') {
+        Some((int_part, frac_part)) => (int_part.to_string(), frac_part.to_string()),
+        None => (text, String::new()),
+    }
+}
+
+/// Scale a value so the mantissa fills the integer `#` positions; returns (mantissa, exponent).
+fn scale_for_exponent(field: &NumberField, value: f64) -> (f64, i32) {
+    if value == 0.0 {
+        return (0.0, 0);
+    }
+    // Without a sign specifier one # position is reserved for the sign
+    let reserve_sign = field.trailing_sign.is_none() && !field.leading_plus;
+    let int_digits = if reserve_sign {
+        field.digits.saturating_sub(1)
+    } else {
+        field.digits
+    } as i32;
+    let decimals = field.decimals.unwrap_or(0);
+
+    let mut exponent = value.log10().floor() as i32 - (int_digits - 1);
+    let mut mantissa = value / 10f64.powi(exponent);
+    // Rounding may carry (9.99 -> 10.0); rescale once more
+    let rounded: f64 = format!("{:.*}", decimals, mantissa).parse().unwrap_or(mantissa);
+    if rounded >= 10f64.powi(int_digits.max(0)) {
+        exponent += 1;
+        mantissa = value / 10f64.powi(exponent);
+    }
+    (mantissa, exponent)
+}
+
+/// Insert a comma every three digits.
+fn group_thousands(digits: &str) -> String {
+    let mut result = String::new();
+    for (index, ch) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(ch);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(format: &str, n: f64) -> String {
+        format_using(format, &[Value::Number(n)]).unwrap()
+    }
+
+    fn text(format: &str, s: &str) -> String {
+        format_using(format, &[Value::Str(s.to_string())]).unwrap()
+    }
+
+    #[test]
+    fn numeric_fields() {
+        assert_eq!(num("##.##", 0.5), " 0.50");
+        assert_eq!(num("###.##", 12.345), " 12.35");
+        assert_eq!(num("##.##", -1.5), "-1.50");
+        assert_eq!(num(".##", 0.25), ".25");
+        assert_eq!(num("###", 7.0), "  7");
+        assert_eq!(num("#,###,###.##", 1234567.891), "1,234,567.89");
+        assert_eq!(num("###,###.##", 1234567.891), "%1,234,567.89");
+        assert_eq!(num("#,###", 1234.0), "1,234");
+    }
+
+    #[test]
+    fn sign_dollar_and_asterisk() {
+        assert_eq!(num("+##.#", 2.0), " +2.0");
+        assert_eq!(num("+##.#", -2.0), " -2.0");
+        assert_eq!(num("##.#-", -2.0), " 2.0-");
+        assert_eq!(num("##.#-", 2.0), " 2.0 ");
+        assert_eq!(num("##.#+", 2.0), " 2.0+");
+        assert_eq!(num("$$###.##", 45.5), "  $45.50");
+        assert_eq!(num("**###.##", 45.5), "***45.50");
+        assert_eq!(num("**$##.##", 45.5), "**$45.50");
+    }
+
+    #[test]
+    fn overflow_and_exponent() {
+        assert_eq!(num("##.##", 123.456), "%123.46");
+        assert_eq!(num("##", -10.0), "%-10");
+        assert_eq!(num("##.##^^^^", 234.56), " 2.35E+02");
+        assert_eq!(num("##.##^^^^", 0.000123), " 1.23E-04");
+        assert_eq!(num("##.##^^^^", 9.999), " 1.00E+01");
+        assert_eq!(num("+#.##^^^^", -1500.0), "-1.50E+03");
+    }
+
+    #[test]
+    fn string_fields_and_literals() {
+        assert_eq!(text("!", "HELLO"), "H");
+        assert_eq!(text("&!", "HI"), "HI");
+        assert_eq!(text("\\  \\", "HELLO"), "HELL");
+        assert_eq!(text("[\\  \\]", "AB"), "[AB  ]");
+        assert_eq!(num("_#_#: ##", 5.0), "##:  5");
+        assert_eq!(num("TOTAL ##%", 42.0), "TOTAL 42%");
+    }
+
+    #[test]
+    fn format_reuse_and_errors() {
+        let values = [Value::Number(1.0), Value::Number(2.0), Value::Number(3.0)];
+        assert_eq!(format_using("[##]", &values).unwrap(), "[ 1][ 2][ 3]");
+        assert_eq!(format_using("## AND ## ", &values[..1]).unwrap(), " 1 AND ");
+
+        assert!(matches!(format_using("NO FIELDS", &values), Err(BasicError::IllegalQuantity)));
+        assert!(matches!(format_using("&", &[Value::Number(1.0)]), Err(BasicError::TypeMismatch)));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::lexer::take_leading_line_number;
-use crate::tokens::{keyword_name, Tok};
-use std::collections::BTreeMap;
+use crate::tokens::{keyword_name, Tok, TokenKind};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
 
 /// Program line structure, conceptually like [line_no][text]\0.
 #[derive(Debug, Clone)]
@@ -8,53 +9,220 @@ pub struct ProgramLine {
     #[allow(dead_code)]
     pub line_no: u16,
     pub tokens: Vec<Tok>,
+    /// Statements of the line, split on ':' once when the line is stored
+    pub statements: Vec<Rc<[Tok]>>,
+}
+
+impl ProgramLine {
+    pub fn new(line_no: u16, tokens: Vec<Tok>) -> Self {
+        let statements = tokens
+            .split(|t| matches!(t, Tok::Symbol(':')))
+            .filter(|s| !s.is_empty())
+            .map(Rc::from)
+            .collect();
+        Self { line_no, tokens, statements }
+    }
 }
 
 #[derive(Default, Debug)]
 pub struct Program {
     pub lines: BTreeMap<u16, ProgramLine>,
+    // Compiled form of `lines`; dropped on every edit and rebuilt on demand
+    compiled: Option<Rc<CompiledProgram>>,
+}
+
+/// Where a jump lands: a line index in `CompiledProgram::lines`, or nowhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Index(usize),
+    Missing,
+}
+
+#[derive(Debug)]
+pub struct CompiledStatement {
+    pub tokens: Rc<[Tok]>,
+    /// Resolved target of a plain `GOTO <line>` statement
+    pub goto: Option<Target>,
+}
+
+#[derive(Debug)]
+pub struct CompiledLine {
+    pub line_no: u16,
+    pub statements: Vec<CompiledStatement>,
+}
+
+/// Program lines in execution order with a line-number index, so that
+/// running never re-splits lines or scans for a jump target.
+#[derive(Debug, Default)]
+pub struct CompiledProgram {
+    pub lines: Vec<CompiledLine>,
+    index: HashMap<u16, usize>,
+}
+
+impl CompiledProgram {
+    fn build(lines: &BTreeMap<u16, ProgramLine>) -> Self {
+        let index: HashMap<u16, usize> = lines.keys().enumerate().map(|(i, ln)| (*ln, i)).collect();
+        let lines = lines
+            .values()
+            .map(|pl| CompiledLine {
+                line_no: pl.line_no,
+                statements: pl
+                    .statements
+                    .iter()
+                    .map(|s| {
+                        let goto = match &s[..] {
+                            // Out-of-range literals take the slow path, which reports ?FC
+                            [Tok::Keyword(TokenKind::Goto), Tok::Number(n)] if (0.0..=65535.0).contains(&n.trunc()) => {
+                                let dst = n.trunc() as u16;
+                                Some(index.get(&dst).map_or(Target::Missing, |i| Target::Index(*i)))
+                            }
+                            _ => None,
+                        };
+                        CompiledStatement { tokens: Rc::clone(s), goto }
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self { lines, index }
+    }
+
+    /// Index of a line number, if the line exists.
+    pub fn index_of(&self, line_no: u16) -> Option<usize> {
+        self.index.get(&line_no).copied()
+    }
+
+    pub fn target(&self, line_no: u16) -> Target {
+        self.index_of(line_no).map_or(Target::Missing, Target::Index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
 }
 
 impl Program {
     pub fn insert_line(&mut self, line_no: u16, tokens: Vec<Tok>) {
+        self.compiled = None;
         if tokens.is_empty() {
             self.lines.remove(&line_no);
         } else {
-            self.lines.insert(line_no, ProgramLine { line_no, tokens });
+            self.lines.insert(line_no, ProgramLine::new(line_no, tokens));
         }
     }
     pub fn delete_line(&mut self, line_no: u16) {
+        self.compiled = None;
         self.lines.remove(&line_no);
     }
 
     pub fn clear(&mut self) {
+        self.compiled = None;
         self.lines.clear();
     }
 
+    /// Compiled program, rebuilt only if lines changed since the last call.
+    pub fn compiled(&mut self) -> Rc<CompiledProgram> {
+        Rc::clone(self.compiled.get_or_insert_with(|| Rc::new(CompiledProgram::build(&self.lines))))
+    }
+
     /// List program lines in ascending order.
     pub fn list(&self) {
         for (ln, pl) in &self.lines {
-            print!("{} ", ln);
-            let mut first = true;
-            for t in &pl.tokens {
-                if !first {
-                    print!(" ");
-                }
-                first = false;
-                match t {
-                    Tok::Keyword(k) => print!("{}", keyword_name(*k)),
-                    Tok::Ident(s) => print!("{}", s),
-                    Tok::Number(n) => print!("{}", n),
-                    Tok::String(s) => print!("\"{}\"", s),
-                    Tok::Symbol(c) => print!("{}", c),
-                }
-            }
-            println!();
+            println!("{} {}", ln, detokenize(&pl.tokens));
         }
     }
 }
 
+/// Program text of a token list (for LIST and SAVE): tokens separated by
+/// spaces, except DATA/REM text, which follows its keyword exactly as typed.
+pub fn detokenize(tokens: &[Tok]) -> String {
+    let mut out = String::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if i > 0 && !matches!(t, Tok::Raw(_)) {
+            out.push(' ');
+        }
+        match t {
+            Tok::Keyword(k) => out.push_str(keyword_name(*k)),
+            Tok::Ident(s) | Tok::Raw(s) => out.push_str(s),
+            Tok::Number(n) => out.push_str(&n.to_string()),
+            Tok::String(s) => { out.push('"'); out.push_str(s); out.push('"'); }
+            Tok::Symbol(c) => out.push(*c),
+        }
+    }
+    out
+}
+
 /// Parse an optional leading line number (wrapper for lexer helper).
 pub fn parse_leading_line_number(src: &str) -> Option<(u16, &str)> {
     take_leading_line_number(src)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::crunch;
+    use crate::runtime::Vm;
+
+    fn program(lines: &[&str]) -> Program {
+        let mut p = Program::default();
+        for src in lines {
+            let (line_no, rest) = parse_leading_line_number(src).unwrap();
+            p.insert_line(line_no, crunch(rest.trim()));
+        }
+        p
+    }
+
+    #[test]
+    fn compiled_program_is_cached_until_an_edit() {
+        let mut p = program(&["10 PRINT 1", "20 END"]);
+        let first = p.compiled();
+        assert!(Rc::ptr_eq(&first, &p.compiled()));
+
+        p.insert_line(15, crunch("PRINT 2"));
+        let inserted = p.compiled();
+        assert!(!Rc::ptr_eq(&first, &inserted));
+        assert_eq!(inserted.len(), 3);
+        assert_eq!(inserted.index_of(20), Some(2));
+
+        p.delete_line(10);
+        let deleted = p.compiled();
+        assert!(!Rc::ptr_eq(&inserted, &deleted));
+        assert_eq!(deleted.index_of(10), None);
+        assert_eq!(deleted.index_of(15), Some(0));
+
+        // A line number alone deletes the line, too
+        p.insert_line(15, Vec::new());
+        assert_eq!(p.compiled().len(), 1);
+
+        p.clear();
+        assert_eq!(p.compiled().len(), 0);
+    }
+
+    #[test]
+    fn goto_targets_are_resolved_when_compiled() {
+        let mut p = program(&["10 GOTO 30", "20 GOTO 25: PRINT", "30 END"]);
+        let code = p.compiled();
+        assert_eq!(code.lines[0].statements[0].goto, Some(Target::Index(2)));
+        assert_eq!(code.lines[1].statements[0].goto, Some(Target::Missing));
+        assert_eq!(code.lines[1].statements[1].goto, None);
+        assert_eq!(code.target(30), Target::Index(2));
+        assert_eq!(code.target(25), Target::Missing);
+
+        // Adding the missing line resolves the jump on the next build
+        p.insert_line(25, crunch("END"));
+        assert_eq!(p.compiled().lines[1].statements[0].goto, Some(Target::Index(2)));
+    }
+
+    #[test]
+    fn edits_between_runs_take_effect() {
+        let mut vm = Vm::with_program(&["10 X=1"]);
+        vm.prepare_full_run();
+        vm.run().unwrap();
+        assert_eq!(vm.vars.get("X"), Some(&crate::value::Value::Number(1.0)));
+
+        vm.program.insert_line(10, crunch("X=2"));
+        vm.program.insert_line(20, crunch("GOTO 40"));
+        vm.prepare_full_run();
+        assert_eq!(vm.run().unwrap_err().to_string(), "UNDEF'D STATEMENT IN 20");
+        assert_eq!(vm.vars.get("X"), Some(&crate::value::Value::Number(2.0)));
+    }
+}
@@ -1,11 +1,23 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
-use std::fs::OpenOptions;
-use std::io::Write as IoWrite;
-use crate::program::Program;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write as IoWrite};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::program::{CompiledProgram, Program, Target};
 use crate::value::Value;
 use crate::tokens::{Tok, TokenKind};
 use crate::statements::execute_direct;
+use crate::keyboard::Keyboard;
+use crate::errors::BasicError;
+
+/// FOR and GOSUB frames allowed at once; deeper nesting raises ?OM like the
+/// original's 256-byte stack (sized generously here).
+pub const MAX_STACK_FRAMES: usize = 256;
+/// Bytes available to arrays (what FRE reports with none); more raises ?OM.
+pub const ARRAY_MEMORY: usize = 32767;
+/// Upper bound of every dimension of an array created without DIM.
+pub const DEFAULT_ARRAY_BOUND: usize = 10;
 
 /// Virtual machine state: holds program and variables.
 #[derive(Default)]
@@ -13,141 +25,219 @@ pub struct Vm {
     pub program: Program,
     pub vars: HashMap<String, Value>,
     pub halted: bool,
-    pub jump_to: Option<u16>,
+    pub jump_to: Option<TextPos>,
     pub gosub_stack: Vec<GosubFrame>,
     pub for_stack: Vec<ForFrame>,
+    // Line being executed; None in direct mode
     pub current_line: Option<u16>,
-    pub line_order: Vec<u16>,
+    // Program as compiled for the current run
+    pub code: Rc<CompiledProgram>,
     // DATA/READ cursor
     pub data_line_pos: usize,
-    pub data_tok_pos: Option<usize>,
+    // Within that line: token index of the DATA text, byte offset of the next item
+    pub data_pos: Option<(usize, usize)>,
     // Pseudo RNG state
     pub rng_seed: u64,
-    pub debug: bool,
+    // Opt-in diagnostics (BASIC_LOG / --log)
+    pub diag: Diagnostics,
     pub interrupt_flag: Option<Arc<AtomicBool>>,
     // Arrays: name -> descriptor
     pub arrays: HashMap<String, ArrayValue>,
     pub current_stmt_index: usize,
-    pub inline_stmt_restart: Option<usize>,
-    pub error_override: Option<crate::errors::BasicError>,
+    // Where CONT resumes; cleared by RUN and by errors
+    pub cont_pos: Option<TextPos>,
+    // Pending key presses for GET
+    pub keyboard: Keyboard,
+    // DEF FN functions by name (without the FN prefix)
+    pub user_fns: HashMap<String, UserFn>,
+    // 64K memory image for PEEK/POKE/WAIT
+    pub memory: Vec<u8>,
+    // Output column, for POS, TAB and print zones
+    pub column: usize,
+    // NULL count; accepted for compatibility, no effect on output
+    pub nulls: u8,
 }
 
 impl Vm {
-    pub fn new() -> Self { Self { program: Program::default(), vars: HashMap::new(), halted: false, jump_to: None, gosub_stack: Vec::new(), for_stack: Vec::new(), current_line: None, line_order: Vec::new(), data_line_pos: 0, data_tok_pos: None, rng_seed: 0x1234_5678_9abc_def0, debug: true, interrupt_flag: None, arrays: HashMap::new(), current_stmt_index: 0, inline_stmt_restart: None, error_override: None } }
+    pub fn new() -> Self { Self { program: Program::default(), vars: HashMap::new(), halted: false, jump_to: None, gosub_stack: Vec::new(), for_stack: Vec::new(), current_line: None, code: Rc::default(), data_line_pos: 0, data_pos: None, rng_seed: 0x1234_5678_9abc_def0, diag: Diagnostics::default(), interrupt_flag: None, arrays: HashMap::new(), current_stmt_index: 0, cont_pos: None, keyboard: Keyboard::new(), user_fns: HashMap::new(), memory: vec![0; 1 << 16], column: 0, nulls: 0 } }
+
+    /// CLEAR: forget all variables, arrays and DEF FN functions.
+    pub fn clear_variables(&mut self) {
+        self.vars.clear();
+        self.arrays.clear();
+        self.user_fns.clear();
+    }
 
     /// Prepare a fresh run: clear variables and reset DATA pointer.
     pub fn prepare_full_run(&mut self) {
-        self.vars.clear();
+        self.clear_variables();
         self.restore_data(None);
         self.jump_to = None;
     }
 
-    /// Run the current program (respects existing jump_to/current_line for CONT).
-    pub fn run(&mut self) {
-        let lines: Vec<u16> = self.program.lines.keys().cloned().collect();
-        let mut i = 0usize;
-        self.halted = false;
-        self.line_order = lines.clone();
+    /// Run the current program from its first line.
+    pub fn run(&mut self) -> Result<(), BasicError> {
         self.gosub_stack.clear();
         self.for_stack.clear();
+        self.cont_pos = None;
         // DATA cursor unchanged here; RUN command should call prepare_full_run() beforehand
-        // If a resume target exists (e.g., CONT after STOP), start there
-        if let Some(dst) = self.jump_to.take() {
-            if let Some(pos) = lines.iter().position(|x| *x == dst) { i = pos; }
+        self.execute(None)
+    }
+
+    /// CONT: resume where STOP, END or Ctrl-C halted the program.
+    /// Refused after an error or once the program has been edited.
+    pub fn cont(&mut self) -> Result<(), BasicError> {
+        let pos = self.cont_pos.take().ok_or(BasicError::CantContinue)?;
+        // Any edit drops the compiled program, so a new one means the text changed
+        if !Rc::ptr_eq(&self.code, &self.program.compiled()) {
+            return Err(BasicError::CantContinue);
+        }
+        self.execute(Some(pos))
+    }
+
+    /// GOTO in direct mode: run from `line`, keeping variables.
+    pub fn goto(&mut self, line: u16) -> Result<(), BasicError> {
+        self.cont_pos = None;
+        self.execute(Some(TextPos { line, stmt: 0 }))
+    }
+
+    /// Statement loop (NEWSTT). Errors come back with their line and statement.
+    fn execute(&mut self, start: Option<TextPos>) -> Result<(), BasicError> {
+        let code = self.program.compiled();
+        let mut i = 0usize;
+        // Statement index to start the current line at
+        let mut si = 0usize;
+        self.halted = false;
+        self.jump_to = None;
+        self.code = Rc::clone(&code);
+        if let Some(pos) = start {
+            i = code.index_of(pos.line).ok_or(BasicError::UndefinedStatement)?;
+            si = pos.stmt;
         }
-        while i < lines.len() {
-            let ln = lines[i];
+        self.log(LogCategory::Run, LogLevel::Info, "start", || match start {
+            Some(pos) => format!("at {}:{}", pos.line, pos.stmt),
+            None => "at first line".to_string(),
+        });
+        while i < code.len() {
+            let line = &code.lines[i];
+            let ln = line.line_no;
             self.current_line = Some(ln);
             // Poll Ctrl-C interrupt flag while running
-            if let Some(flag) = &self.interrupt_flag {
-                if flag.swap(false, Ordering::SeqCst) {
-                    self.log_debug(format!("[RUN] Ctrl-C at line {}", ln));
-                    eprintln!("?BREAK IN {}", ln);
-                    // Don't set jump_to here - let CONT resume from current position
-                    // Setting jump_to would break FOR/NEXT loops
-                    self.halted = true;
-                }
-            }
-            let Some(pl) = self.program.lines.get(&ln) else { i += 1; continue };
-            // Split by ':' for multiple statements per line (simple pass over symbols)
-            let mut stmt: Vec<Tok> = Vec::new();
-            let mut stmts: Vec<Vec<Tok>> = Vec::new();
-            for t in &pl.tokens {
-                if matches!(t, Tok::Symbol(':')) {
-                    if !stmt.is_empty() { stmts.push(std::mem::take(&mut stmt)); }
-                } else {
-                    stmt.push(t.clone());
-                }
+            if let Some(flag) = &self.interrupt_flag && flag.swap(false, Ordering::SeqCst) {
+                self.log(LogCategory::Run, LogLevel::Info, "break", String::new);
+                eprintln!("?BREAK IN {}", ln);
+                // CONT resumes with the statement that was about to run
+                self.cont_pos = Some(TextPos { line: ln, stmt: si });
+                self.halted = true;
+                break;
             }
-            if !stmt.is_empty() { stmts.push(stmt); }
+            let stmts = &line.statements;
+            let mut goto: Option<Target> = None;
 
-            // If there is an inline restart point (e.g., NEXT in same line), start from that stmt index
-            let mut si = self.inline_stmt_restart.take().unwrap_or(0);
             while si < stmts.len() {
                 let s = &stmts[si];
-                if s.is_empty() { continue; }
                 self.current_stmt_index = si;
-                self.log_debug(format!("[RUN] line {} stmt {}/{}", ln, si, stmts.len()));
-                // handle minimal GOTO inline: [GOTO] <number>
-                if let Some(Tok::Keyword(TokenKind::Goto)) = s.get(0) {
-                    if let Some(Tok::Number(n)) = s.get(1) {
-                        self.jump_to = Some((*n as i64).clamp(0, u16::MAX as i64) as u16);
-                        break;
-                    }
+                self.log(LogCategory::Run, LogLevel::Trace, "stmt", || crate::program::detokenize(&s.tokens));
+                // handle minimal GOTO inline: [GOTO] <number>, resolved when compiled
+                if s.goto.is_some() {
+                    goto = s.goto;
+                    break;
                 }
-                // otherwise execute as immediate; propagate errors as halts with line number
-                match execute_direct(self, &s) {
-                    Ok(()) => {}
-                    Err(e) => {
-                        if let Some(cl) = self.current_line { eprintln!("?{} IN {}", e, cl); } else { eprintln!("?{}", e); }
-                        self.halted = true;
-                    }
+                // otherwise execute as immediate; an error ends the run
+                if let Err(e) = execute_direct(self, &s.tokens) {
+                    self.log(LogCategory::Run, LogLevel::Info, "error", || e.to_string());
+                    self.current_line = None;
+                    return Err(e.in_line(ln, si));
                 }
-                // If expression evaluation set an error override (e.g., BAD SUBSCRIPT), raise it here with line info.
-                if let Some(err) = self.error_override.take() {
-                    if let Some(cl) = self.current_line { eprintln!("?{} IN {}", err, cl); } else { eprintln!("?{}", err); }
+                // Also allow breaking mid-line if interrupt was raised
+                if let Some(flag) = &self.interrupt_flag && flag.swap(false, Ordering::SeqCst) {
+                    self.log(LogCategory::Run, LogLevel::Info, "break", String::new);
+                    eprintln!("?BREAK IN {}", ln);
                     self.halted = true;
-                    break;
                 }
-                if self.halted { break; }
-                // Also allow breaking mid-line if interrupt was raised
-                if let Some(flag) = &self.interrupt_flag {
-                    if flag.swap(false, Ordering::SeqCst) {
-                        self.log_debug(format!("[RUN] Ctrl-C mid-line at {}", ln));
-                        eprintln!("?BREAK IN {}", ln);
-                        // Don't set jump_to - preserve FOR/NEXT loop context
-                        self.halted = true;
-                        break;
-                    }
+                // STOP/END/Ctrl-C: CONT resumes with the next statement
+                if self.halted {
+                    self.cont_pos = Some(TextPos { line: ln, stmt: si + 1 });
+                    break;
                 }
                 if self.jump_to.is_some() { break; }
                 si += 1;
             }
 
             if self.halted { break; }
-            if let Some(dst) = self.jump_to.take() {
-                // find index of destination line (if exists), else stop
-                if let Some(pos) = lines.iter().position(|x| *x == dst) { i = pos; } else { break; }
-            } else {
-                i += 1;
+            let (next, stmt) = match (goto, self.jump_to.take()) {
+                (Some(target), _) => (target, 0),
+                (None, Some(dst)) => (code.target(dst.line), dst.stmt),
+                (None, None) => (Target::Index(i + 1), 0),
+            };
+            match next {
+                Target::Index(pos) => { i = pos; si = stmt; }
+                Target::Missing => {
+                    self.log(LogCategory::Run, LogLevel::Info, "error", || BasicError::UndefinedStatement.to_string());
+                    self.current_line = None;
+                    return Err(BasicError::UndefinedStatement.in_line(ln, si));
+                }
             }
         }
-        // Preserve current_line on halt (e.g., STOP) to allow CONT to resume
-        if !self.halted {
-            self.current_line = None;
+        self.log(LogCategory::Run, LogLevel::Info, "end", || if self.halted { "halted" } else { "ran off the end" }.to_string());
+        // Back in direct mode
+        self.current_line = None;
+        Ok(())
+    }
+
+    /// Fail with ?OM when another FOR or GOSUB frame would not fit on the stack.
+    pub fn check_stack(&self) -> Result<(), BasicError> {
+        if self.gosub_stack.len() + self.for_stack.len() >= MAX_STACK_FRAMES {
+            return Err(BasicError::OutOfMemory);
         }
+        Ok(())
+    }
+
+    /// Fail with ?ID for statements that only work inside a program.
+    pub fn require_program(&self) -> Result<(), BasicError> {
+        self.current_line.map(|_| ()).ok_or(BasicError::IllegalDirect)
+    }
+
+    /// Jump to the start of a line.
+    pub fn jump_to_line(&mut self, line: u16) {
+        self.jump_to = Some(TextPos { line, stmt: 0 });
+    }
+
+    /// PEEK: a byte of the memory image.
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.memory.get(addr as usize).copied().unwrap_or(0)
     }
 
-    pub fn next_line_after(&self, ln: u16) -> Option<u16> {
-        if let Some(pos) = self.line_order.iter().position(|x| *x == ln) {
-            self.line_order.get(pos + 1).cloned()
-        } else { None }
+    /// POKE: store a byte into the memory image.
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        if let Some(b) = self.memory.get_mut(addr as usize) { *b = val; }
     }
+
+    /// Position of the statement after the one being executed.
+    pub fn next_stmt_pos(&self) -> Option<TextPos> {
+        self.current_line.map(|line| TextPos { line, stmt: self.current_stmt_index + 1 })
+    }
+}
+
+/// A point in the program text: a line and a statement index within it,
+/// like the text pointer saved in the original stack frames.
+/// A statement index past the end of the line continues with the next line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextPos {
+    pub line: u16,
+    pub stmt: usize,
+}
+
+/// A DEF FN function: one numeric parameter and the tokens of its body.
+#[derive(Debug, Clone)]
+pub struct UserFn {
+    pub param: String,
+    pub body: Rc<[Tok]>,
 }
 
 #[derive(Debug, Clone)]
 pub struct GosubFrame {
-    pub return_line: u16,
+    pub return_pos: TextPos,
 }
 
 #[derive(Debug, Clone)]
@@ -155,50 +245,51 @@ pub struct ForFrame {
     pub var: String,
     pub end: f64,
     pub step: f64,
-    pub start_line: u16,
-    pub restart_stmt_index: usize,
+    // Statement after the FOR, where each iteration starts
+    pub loop_pos: TextPos,
+    // gosub_stack depth when the loop was entered; a FOR/NEXT only sees
+    // loops of its own subroutine level, and RETURN drops the deeper ones
+    pub gosub_depth: usize,
 }
 
 impl Vm {
     pub fn restore_data(&mut self, at_line: Option<u16>) {
         if let Some(ln) = at_line {
-            if let Some(pos) = self.line_order.iter().position(|x| *x == ln) {
-                self.data_line_pos = pos;
-            } else {
-                self.data_line_pos = 0;
-            }
+            self.data_line_pos = self.code.index_of(ln).unwrap_or(0);
         } else {
             self.data_line_pos = 0;
         }
-        self.data_tok_pos = None;
+        self.data_pos = None;
+        self.log(LogCategory::Data, LogLevel::Debug, "restore", || match at_line {
+            Some(ln) => format!("to line {}", ln),
+            None => "to start".to_string(),
+        });
     }
 
-    /// Fetch next DATA value scanning program lines. Returns None if no more.
-    pub fn next_data_value(&mut self) -> Option<Value> {
-        while self.data_line_pos < self.line_order.len() {
-            let ln = self.line_order[self.data_line_pos];
+    /// Fetch the next DATA item, scanning DATA statements in program order.
+    /// Returns None if no more.
+    pub fn next_data_item(&mut self) -> Option<DataItem> {
+        while self.data_line_pos < self.code.len() {
+            let ln = self.code.lines[self.data_line_pos].line_no;
             let pl = self.program.lines.get(&ln)?;
-            let mut pos = match self.data_tok_pos {
+            let (ti, off) = match self.data_pos {
                 Some(p) => p,
-                None => {
-                    // find DATA token
-                    let mut idx = None;
-                    for (i, t) in pl.tokens.iter().enumerate() {
-                        if let Tok::Keyword(TokenKind::Data) = t { idx = Some(i+1); break; }
-                    }
-                    match idx { Some(p) => p, None => { self.data_line_pos += 1; continue; } }
-                }
+                None => match data_text_index(&pl.tokens, 0) {
+                    Some(ti) => (ti, 0),
+                    None => { self.data_line_pos += 1; continue; }
+                },
             };
-            // skip commas
-            while pos < pl.tokens.len() { if matches!(pl.tokens[pos], Tok::Symbol(',')) { pos+=1; } else { break; } }
-            if pos >= pl.tokens.len() { self.data_line_pos += 1; self.data_tok_pos = None; continue; }
-            match &pl.tokens[pos] {
-                Tok::Number(n) => { self.data_tok_pos = Some(pos+1); return Some(Value::Number(*n)); }
-                Tok::String(s) => { self.data_tok_pos = Some(pos+1); return Some(Value::Str(s.clone())); }
-                // end of DATA on this line (e.g., colon or not a literal)
-                Tok::Symbol(':') => { self.data_line_pos += 1; self.data_tok_pos = None; continue; }
-                _ => { self.data_line_pos += 1; self.data_tok_pos = None; continue; }
+            let text = match pl.tokens.get(ti) { Some(Tok::Raw(t)) => t, _ => "" };
+            if off > text.len() {
+                // This DATA statement is used up; go on with the next one
+                self.data_pos = data_text_index(&pl.tokens, ti + 1).map(|t| (t, 0));
+                if self.data_pos.is_none() { self.data_line_pos += 1; }
+                continue;
             }
+            let (item, next) = split_data_item(text, off);
+            self.data_pos = Some((ti, next));
+            self.log(LogCategory::Data, LogLevel::Debug, "read", || format!("line {} item {:?}", ln, item.text));
+            return Some(item);
         }
         None
     }
@@ -211,54 +302,132 @@ impl Vm {
         if v >= 1.0 { 0.999999999999 } else { v }
     }
 
-    /// Append debug line to debug.log if debug is enabled.
-    pub fn log_debug<S: AsRef<str>>(&self, s: S) {
-        if !self.debug { return; }
-        if let Ok(mut f) = OpenOptions::new().create(true).append(true).open("debug.log") {
-            let _ = writeln!(f, "{}", s.as_ref());
+    /// Record a diagnostic event at the current program position.
+    /// `detail` is only built when the category is enabled at `level`.
+    pub fn log(&self, cat: LogCategory, level: LogLevel, event: &str, detail: impl FnOnce() -> String) {
+        if self.diag.enabled(cat, level) {
+            let pos = self.current_line.map(|line| TextPos { line, stmt: self.current_stmt_index });
+            self.diag.emit(cat, level, pos, event, &detail());
         }
     }
 
+    /// Assign a simple variable (logged under `vars`).
+    pub fn set_var(&mut self, name: String, val: Value) {
+        self.log(LogCategory::Vars, LogLevel::Trace, "set", || format!("{} = {}", name, log_value(&val)));
+        self.vars.insert(name, val);
+    }
+
     pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
         self.interrupt_flag = Some(flag);
     }
 
-    pub fn dim_array(&mut self, name: String, dims: Vec<usize>, is_string: bool) {
-        let total = dims.iter().copied().product::<usize>();
-        let data = if is_string { (0..total).map(|_| Value::Str(String::new())).collect() } else { (0..total).map(|_| Value::Number(0.0)).collect() };
-        self.arrays.insert(name, ArrayValue { dims, is_string, data });
+    /// DIM: create an array with the given upper bounds (`DIM A(0)` has one element).
+    pub fn dim_array(&mut self, name: String, bounds: Vec<usize>) -> Result<(), BasicError> {
+        if self.arrays.contains_key(&name) { return Err(BasicError::RedimensionedArray); }
+        let is_string = name.ends_with('$');
+        let dims: Vec<usize> = bounds.iter().map(|&b| b + 1).collect();
+        let total = dims.iter().try_fold(1usize, |acc, &d| acc.checked_mul(d)).ok_or(BasicError::OutOfMemory)?;
+        let av = ArrayValue { dims, is_string, data: Vec::new() };
+        let size = total.checked_mul(av.element_size()).map(|n| n + av.header_size());
+        if size.is_none_or(|n| n > self.free_memory()) { return Err(BasicError::OutOfMemory); }
+        let zero = if is_string { Value::Str(String::new()) } else { Value::Number(0.0) };
+        self.log(LogCategory::Vars, LogLevel::Debug, "dim", || format!("{}{:?}", name, bounds));
+        self.arrays.insert(name, ArrayValue { data: vec![zero; total], ..av });
+        Ok(())
     }
 
-    pub fn get_array_element(&self, name: &str, idxs: &[usize]) -> Option<Value> {
-        let av = self.arrays.get(name)?;
-        let off = av.linear_index(idxs)?;
-        av.data.get(off).cloned()
+    /// FRE: bytes left for arrays.
+    pub fn free_memory(&self) -> usize {
+        ARRAY_MEMORY.saturating_sub(self.arrays.values().map(ArrayValue::memory_size).sum())
     }
 
-    pub fn set_array_element(&mut self, name: &str, idxs: &[usize], val: Value) -> Result<(), &'static str> {
-        let av = self.arrays.get(name).ok_or("UNDEFINED ARRAY")?;
-        if av.is_string {
-            if !matches!(val, Value::Str(_)) { return Err("TYPE MISMATCH"); }
-        } else {
-            if !matches!(val, Value::Number(_)) { return Err("TYPE MISMATCH"); }
+    /// The array `name`, created on first reference like the original: one
+    /// dimension per subscript of that reference, each 0..10.
+    fn array(&mut self, name: &str, subscripts: usize) -> Result<&ArrayValue, BasicError> {
+        if !self.arrays.contains_key(name) {
+            self.dim_array(name.to_string(), vec![DEFAULT_ARRAY_BOUND; subscripts])?;
         }
-        let off = av.linear_index(idxs).ok_or("BAD SUBSCRIPT")?;
-        if let Some(avm) = self.arrays.get_mut(name) {
-            avm.data[off] = val;
-            Ok(())
-        } else { Err("UNDEFINED ARRAY") }
+        Ok(&self.arrays[name])
+    }
+
+    pub fn get_array_element(&mut self, name: &str, idxs: &[usize]) -> Result<Value, BasicError> {
+        let av = self.array(name, idxs.len())?;
+        let off = av.linear_index(idxs).ok_or(BasicError::BadSubscript)?;
+        Ok(av.data[off].clone())
+    }
+
+    pub fn set_array_element(&mut self, name: &str, idxs: &[usize], val: Value) -> Result<(), BasicError> {
+        let av = self.array(name, idxs.len())?;
+        if av.is_string != matches!(val, Value::Str(_)) { return Err(BasicError::TypeMismatch); }
+        let off = av.linear_index(idxs).ok_or(BasicError::BadSubscript)?;
+        self.log(LogCategory::Vars, LogLevel::Trace, "set", || format!("{}{:?} = {}", name, idxs, log_value(&val)));
+        if let Some(av) = self.arrays.get_mut(name) { av.data[off] = val; }
+        Ok(())
     }
 }
 
+/// One DATA item: its text, and whether it was quoted.
+#[derive(Debug, Clone)]
+pub struct DataItem {
+    pub text: String,
+    pub quoted: bool,
+}
+
+/// Index of the text token of the first DATA statement at or after `from`.
+fn data_text_index(tokens: &[Tok], from: usize) -> Option<usize> {
+    tokens
+        .get(from..)?
+        .windows(2)
+        .position(|w| matches!(w, [Tok::Keyword(TokenKind::Data), Tok::Raw(_)]))
+        .map(|k| from + k + 1)
+}
+
+/// Split the DATA item starting at byte `off` of `text`. Leading spaces are
+/// skipped; a quoted item ends at its closing quote, an unquoted one at the
+/// next ',' and is kept verbatim. Also returns where the next item starts,
+/// past the end of `text` after the last one.
+fn split_data_item(text: &str, off: usize) -> (DataItem, usize) {
+    let rest = &text[off..];
+    let body = rest.trim_start_matches(' ');
+    let start = off + rest.len() - body.len();
+    let (item, end) = match body.strip_prefix('"') {
+        Some(quoted) => {
+            let len = quoted.find('"').unwrap_or(quoted.len());
+            (DataItem { text: quoted[..len].to_string(), quoted: true }, (start + len + 2).min(text.len()))
+        }
+        None => {
+            let len = body.find(',').unwrap_or(body.len());
+            (DataItem { text: body[..len].to_string(), quoted: false }, start + len)
+        }
+    };
+    let next = text[end..].find(',').map_or(text.len() + 1, |c| end + c + 1);
+    (item, next)
+}
+
 #[derive(Debug, Clone)]
 pub struct ArrayValue {
+    // Elements per dimension (upper bound + 1)
     pub dims: Vec<usize>,
     pub is_string: bool,
     pub data: Vec<Value>,
 }
 
 impl ArrayValue {
-    // BASIC subscripts are 1-based in this simplified model
+    /// Bytes the original's layout takes: name 2, length 2, dimension count 1,
+    /// 2 per dimension, then 5 per number or 3 per string descriptor.
+    pub fn memory_size(&self) -> usize {
+        self.header_size() + self.element_size() * self.data.len()
+    }
+
+    fn header_size(&self) -> usize {
+        5 + 2 * self.dims.len()
+    }
+
+    fn element_size(&self) -> usize {
+        if self.is_string { 3 } else { 5 }
+    }
+
+    /// Offset of an element; None if the subscript count or a subscript is wrong.
     pub fn linear_index(&self, idxs: &[usize]) -> Option<usize> {
         if idxs.len() != self.dims.len() { return None; }
         // compute strides
@@ -270,12 +439,264 @@ impl ArrayValue {
         }
         let mut off = 0usize;
         for (i, &sub) in idxs.iter().enumerate() {
-            if sub == 0 { return None; }
-            let zero_based = sub - 1;
-            if zero_based >= self.dims[i] { return None; }
-            off += zero_based * strides[i];
+            if sub >= self.dims[i] { return None; }
+            off += sub * strides[i];
         }
         Some(off)
     }
 }
 
+
+/// What a diagnostic event is about; each category is switched on separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogCategory {
+    /// Run loop: statements, jumps, breaks, errors
+    Run,
+    /// FOR/NEXT stack
+    For,
+    /// GOSUB/RETURN stack
+    Gosub,
+    /// DATA pointer: READ and RESTORE
+    Data,
+    /// Variable and array element writes
+    Vars,
+}
+
+impl LogCategory {
+    pub const ALL: [LogCategory; 5] = [LogCategory::Run, LogCategory::For, LogCategory::Gosub, LogCategory::Data, LogCategory::Vars];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogCategory::Run => "run",
+            LogCategory::For => "for",
+            LogCategory::Gosub => "gosub",
+            LogCategory::Data => "data",
+            LogCategory::Vars => "vars",
+        }
+    }
+}
+
+/// Verbosity. A category enabled at a level also logs the levels below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Run start/end, breaks and errors
+    Info,
+    /// Stack pushes/pops, DATA reads
+    Debug,
+    /// Every statement, loop iteration and variable write
+    Trace,
+}
+
+impl LogLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// Opt-in diagnostics: off unless configured, written to stderr or a file,
+/// as text lines or JSON events (one object per line).
+///
+/// Configuration comes from the environment (`BASIC_LOG`, `BASIC_LOG_FILE`,
+/// `BASIC_LOG_FORMAT=json`) or the matching command-line flags. A spec is a
+/// comma-separated list of `category[:level]`, where the category may be `all`
+/// and the level defaults to `debug`; a bare level enables every category.
+/// Example: `BASIC_LOG=run:info,for,gosub:trace`.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    levels: [Option<LogLevel>; LogCategory::ALL.len()],
+    // None writes to stderr
+    file: Option<File>,
+    json: bool,
+}
+
+impl Diagnostics {
+    /// Configuration from `BASIC_LOG`, `BASIC_LOG_FILE` and `BASIC_LOG_FORMAT`.
+    pub fn from_env() -> Result<Self, String> {
+        let mut diag = Diagnostics::default();
+        if let Ok(spec) = std::env::var("BASIC_LOG") {
+            diag.enable(&spec)?;
+        }
+        if let Ok(path) = std::env::var("BASIC_LOG_FILE") {
+            diag.log_to_file(&path).map_err(|e| format!("BASIC_LOG_FILE {}: {}", path, e))?;
+        }
+        if let Ok(format) = std::env::var("BASIC_LOG_FORMAT") {
+            match format.as_str() {
+                "json" => diag.json = true,
+                "text" => diag.json = false,
+                _ => return Err(format!("BASIC_LOG_FORMAT must be text or json, not {}", format)),
+            }
+        }
+        Ok(diag)
+    }
+
+    /// Enable categories from a spec such as `run:info,for,vars:trace`.
+    pub fn enable(&mut self, spec: &str) -> Result<(), String> {
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let item = item.to_ascii_lowercase();
+            let (cat, level) = match item.split_once(':') {
+                Some((cat, level)) => (cat, LogLevel::parse(level).ok_or(format!("unknown log level: {}", level))?),
+                None => match LogLevel::parse(&item) {
+                    Some(level) => ("all", level),
+                    None => (item.as_str(), LogLevel::Debug),
+                },
+            };
+            if cat == "all" {
+                self.levels = [Some(level); LogCategory::ALL.len()];
+            } else {
+                let c = LogCategory::ALL.iter().find(|c| c.name() == cat).ok_or(format!("unknown log category: {}", cat))?;
+                self.levels[*c as usize] = Some(level);
+            }
+        }
+        Ok(())
+    }
+
+    /// Append events to `path` instead of stderr.
+    pub fn log_to_file(&mut self, path: &str) -> io::Result<()> {
+        self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(())
+    }
+
+    pub fn set_json(&mut self, json: bool) {
+        self.json = json;
+    }
+
+    pub fn enabled(&self, cat: LogCategory, level: LogLevel) -> bool {
+        self.levels[cat as usize].is_some_and(|l| level <= l)
+    }
+
+    fn emit(&self, cat: LogCategory, level: LogLevel, pos: Option<TextPos>, event: &str, detail: &str) {
+        let text = if self.json {
+            let ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+            let at = pos.map_or(String::new(), |p| format!(",\"line\":{},\"stmt\":{}", p.line, p.stmt));
+            format!(
+                "{{\"ts_ms\":{},\"cat\":\"{}\",\"level\":\"{}\",\"event\":{}{},\"detail\":{}}}",
+                ms, cat.name(), level.name(), json_string(event), at, json_string(detail)
+            )
+        } else {
+            let at = pos.map_or("direct".to_string(), |p| format!("{}:{}", p.line, p.stmt));
+            format!("[{}] {} {} {} {}", cat.name(), level.name(), at, event, detail)
+        };
+        // Diagnostics must never disturb the program, so write errors are ignored
+        match &self.file {
+            Some(f) => { let _ = writeln!(&*f, "{}", text); }
+            None => { let _ = writeln!(io::stderr(), "{}", text); }
+        }
+    }
+}
+
+/// A value as it appears in a log line: numbers bare, strings quoted.
+fn log_value(v: &Value) -> String {
+    match v {
+        Value::Str(s) => format!("{:?}", s),
+        v => v.as_number().to_string(),
+    }
+}
+
+/// A JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+impl Vm {
+    /// A VM holding `lines` ("10 PRINT X") as its stored program, as if typed at the prompt.
+    pub fn with_program(lines: &[&str]) -> Self {
+        let mut vm = Vm::new();
+        // Tests never read the real terminal; GET sees only pushed keys
+        vm.keyboard = Keyboard::with_source(Box::new(Vec::new));
+        for src in lines {
+            let (line_no, rest) = crate::program::parse_leading_line_number(src).expect("line number");
+            vm.program.insert_line(line_no, crate::lexer::crunch(rest.trim()));
+        }
+        vm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enable_sets_levels_per_category() {
+        let mut diag = Diagnostics::default();
+        diag.enable("run:info, FOR ,vars:trace").unwrap();
+        assert!(diag.enabled(LogCategory::Run, LogLevel::Info));
+        assert!(!diag.enabled(LogCategory::Run, LogLevel::Debug));
+        // Without a level a category logs at debug
+        assert!(diag.enabled(LogCategory::For, LogLevel::Debug));
+        assert!(!diag.enabled(LogCategory::For, LogLevel::Trace));
+        assert!(diag.enabled(LogCategory::Vars, LogLevel::Trace));
+        assert!(!diag.enabled(LogCategory::Gosub, LogLevel::Info));
+
+        // A bare level applies to every category; later items override it
+        let mut diag = Diagnostics::default();
+        diag.enable("trace,data:info").unwrap();
+        assert!(diag.enabled(LogCategory::Gosub, LogLevel::Trace));
+        assert!(!diag.enabled(LogCategory::Data, LogLevel::Debug));
+        let mut diag = Diagnostics::default();
+        diag.enable("all:info").unwrap();
+        assert!(LogCategory::ALL.iter().all(|c| diag.enabled(*c, LogLevel::Info) && !diag.enabled(*c, LogLevel::Debug)));
+    }
+
+    #[test]
+    fn enable_rejects_unknown_names() {
+        let mut diag = Diagnostics::default();
+        assert_eq!(diag.enable("run,loops").unwrap_err(), "unknown log category: loops");
+        assert_eq!(diag.enable("run:verbose").unwrap_err(), "unknown log level: verbose");
+        assert!(diag.enable("").is_ok());
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        assert_eq!(json_string("plain"), r#""plain""#);
+        assert_eq!(json_string("say \"hi\"\\"), r#""say \"hi\"\\""#);
+        assert_eq!(json_string("a\nb\tc\u{1}"), r#""a\nb\u0009c\u0001""#);
+        assert_eq!(json_string("π"), "\"π\"");
+    }
+
+    #[test]
+    fn emit_writes_text_and_json_lines() {
+        let path = std::env::temp_dir().join(format!("basic-log-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut diag = Diagnostics::default();
+        diag.log_to_file(path.to_str().unwrap()).unwrap();
+        diag.emit(LogCategory::For, LogLevel::Debug, Some(TextPos { line: 20, stmt: 1 }), "push", "I");
+        diag.emit(LogCategory::Run, LogLevel::Info, None, "end", "halted");
+        diag.set_json(true);
+        diag.emit(LogCategory::Vars, LogLevel::Trace, Some(TextPos { line: 30, stmt: 0 }), "set", "A$ = \"X\"");
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "[for] debug 20:1 push I");
+        assert_eq!(lines[1], "[run] info direct end halted");
+        assert!(lines[2].starts_with("{\"ts_ms\":"), "{}", lines[2]);
+        assert!(lines[2].ends_with(r#","cat":"vars","level":"trace","event":"set","line":30,"stmt":0,"detail":"A$ = \"X\""}"#), "{}", lines[2]);
+        assert_eq!(lines.len(), 3);
+    }
+}
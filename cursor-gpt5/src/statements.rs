@@ -1,12 +1,15 @@
-use anyhow::{bail, Result};
-use crate::runtime::Vm;
+use crate::runtime::{LogCategory, LogLevel, Vm};
 use crate::tokens::{Tok, TokenKind};
-use crate::parser::{Cursor, parse_expression_with_vm};
+use crate::parser::{Cursor, parse_byte_with_vm, parse_expression_with_vm, parse_number_with_vm, parse_subscript_with_vm, parse_word_with_vm};
 use crate::value::Value;
 use std::io::{self, Write};
 use crate::errors::BasicError;
+use crate::keyboard;
+use crate::lexer::parse_number;
 use std::fs;
 
+type Result<T> = std::result::Result<T, BasicError>;
+
 /// Execute immediate statements (no line number).
 /// Supports: PRINT, LET, and implicit assignment (IDENT = expr).
 pub fn execute_direct(vm: &mut Vm, toks: &[Tok]) -> Result<()> {
@@ -30,10 +33,10 @@ pub fn execute_direct(vm: &mut Vm, toks: &[Tok]) -> Result<()> {
             match up.as_str() {
                 "PRINT" => { cur.next(); exec_print(vm, &mut cur)?; continue; }
                 "IF" => { cur.next(); exec_if(vm, &mut cur)?; continue; }
-                "RUN" => { cur.next(); vm.run(); continue; }
+                "RUN" => { cur.next(); vm.prepare_full_run(); vm.run()?; continue; }
                 "LIST" => { cur.next(); vm.program.list(); continue; }
-                "CLEAR" => { cur.next(); vm.vars.clear(); println!("READY."); continue; }
-                "NEW" => { cur.next(); vm.vars.clear(); vm.program.clear(); println!("READY."); continue; }
+                "CLEAR" => { cur.next(); vm.clear_variables(); println!("READY."); continue; }
+                "NEW" => { cur.next(); vm.clear_variables(); vm.program.clear(); println!("READY."); continue; }
                 "GOSUB" => { cur.next(); exec_gosub(vm, &mut cur)?; continue; }
                 "RETURN" => { cur.next(); exec_return(vm)?; continue; }
                 "FOR" => { cur.next(); exec_for(vm, &mut cur)?; continue; }
@@ -43,6 +46,7 @@ pub fn execute_direct(vm: &mut Vm, toks: &[Tok]) -> Result<()> {
                 "RESTORE" => { cur.next(); exec_restore(vm, &mut cur)?; continue; }
                 "ON" => { cur.next(); exec_on(vm, &mut cur)?; continue; }
                 "INPUT" => { cur.next(); exec_input(vm, &mut cur)?; continue; }
+                "GET" => { cur.next(); exec_get(vm, &mut cur)?; continue; }
                 "SAVE" => { cur.next(); exec_save(vm, &mut cur)?; continue; }
                 "LOAD" => { cur.next(); exec_load(vm, &mut cur)?; continue; }
                 "CONT" => { cur.next(); exec_cont(vm)?; continue; }
@@ -55,6 +59,7 @@ pub fn execute_direct(vm: &mut Vm, toks: &[Tok]) -> Result<()> {
             Some(Tok::Keyword(TokenKind::Print)) => exec_print(vm, &mut cur),
             Some(Tok::Keyword(TokenKind::Rem)) => { /* ignore rest of this part */ Ok(()) },
             Some(Tok::Keyword(TokenKind::If)) => { cur.next(); exec_if(vm, &mut cur) }
+            Some(Tok::Keyword(TokenKind::Goto)) => { cur.next(); exec_goto(vm, &mut cur) }
             Some(Tok::Keyword(TokenKind::Gosub)) => { cur.next(); exec_gosub(vm, &mut cur) }
             Some(Tok::Keyword(TokenKind::Return)) => { cur.next(); exec_return(vm) }
             Some(Tok::Keyword(TokenKind::For)) => { cur.next(); exec_for(vm, &mut cur) }
@@ -64,96 +69,96 @@ pub fn execute_direct(vm: &mut Vm, toks: &[Tok]) -> Result<()> {
             Some(Tok::Keyword(TokenKind::Restore)) => { cur.next(); exec_restore(vm, &mut cur) }
             Some(Tok::Keyword(TokenKind::On)) => { cur.next(); exec_on(vm, &mut cur) }
             Some(Tok::Keyword(TokenKind::Input)) => { cur.next(); exec_input(vm, &mut cur) }
+            Some(Tok::Keyword(TokenKind::Get)) => { cur.next(); exec_get(vm, &mut cur) }
+            Some(Tok::Keyword(TokenKind::Def)) => { cur.next(); exec_def(vm, &mut cur) }
+            Some(Tok::Keyword(TokenKind::Poke)) => { cur.next(); exec_poke(vm, &mut cur) }
+            Some(Tok::Keyword(TokenKind::Wait)) => { cur.next(); exec_wait(vm, &mut cur) }
+            Some(Tok::Keyword(TokenKind::Null)) => { cur.next(); vm.nulls = parse_byte_with_vm(&mut cur, vm)?; Ok(()) }
             Some(Tok::Keyword(TokenKind::Save)) => { cur.next(); exec_save(vm, &mut cur) }
             Some(Tok::Keyword(TokenKind::Load)) => { cur.next(); exec_load(vm, &mut cur) }
             Some(Tok::Keyword(TokenKind::Cont)) => { cur.next(); exec_cont(vm) }
             Some(Tok::Keyword(TokenKind::End)) => { vm.halted = true; Ok(()) }
             Some(Tok::Keyword(TokenKind::Stop)) => {
-                if let Some(cl)=vm.current_line {
-                    eprintln!("?BREAK IN {}", cl);
-                    if let Some(nl) = vm.next_line_after(cl) { vm.jump_to = Some(nl); }
-                }
+                if let Some(cl)=vm.current_line { eprintln!("?BREAK IN {}", cl); }
                 vm.halted = true; Ok(()) }
         Some(Tok::Keyword(TokenKind::Let)) => { cur.next(); exec_assignment(vm, &mut cur) },
         Some(Tok::Ident(_)) => exec_assignment(vm, &mut cur),
-            Some(Tok::Keyword(TokenKind::Run)) => { cur.next(); vm.prepare_full_run(); vm.run(); Ok(()) }
+            Some(Tok::Keyword(TokenKind::Run)) => { cur.next(); vm.prepare_full_run(); vm.run() }
         Some(Tok::Keyword(TokenKind::List)) => { cur.next(); vm.program.list(); Ok(()) }
-        Some(Tok::Keyword(TokenKind::Clear)) => { cur.next(); vm.vars.clear(); println!("READY."); Ok(()) }
-        Some(Tok::Keyword(TokenKind::New)) => { cur.next(); vm.vars.clear(); vm.program.clear(); println!("READY."); Ok(()) }
+        Some(Tok::Keyword(TokenKind::Clear)) => { cur.next(); vm.clear_variables(); println!("READY."); Ok(()) }
+        Some(Tok::Keyword(TokenKind::New)) => { cur.next(); vm.clear_variables(); vm.program.clear(); println!("READY."); Ok(()) }
         Some(Tok::Keyword(TokenKind::Dim)) => { cur.next(); exec_dim(vm, &mut cur) }
-        _ => bail!("SYNTAX ERROR"),
+        _ => return Err(BasicError::Syntax),
         }?;
-        // After executing a part in direct mode, surface any deferred expression error
-        if let Some(err) = vm.error_override.take() {
-            eprintln!("?{}", err);
-            break;
-        }
     }
     Ok(())
 }
 
 fn exec_print(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // Skip PRINT token or '?' symbol
-    match cur.peek() { Some(Tok::Keyword(TokenKind::Print)) => { cur.next(); }, _ => {} }
+    if let Some(Tok::Keyword(TokenKind::Print)) = cur.peek() { cur.next(); }
+    if let Some(Tok::Keyword(TokenKind::Using)) = cur.peek() {
+        cur.next();
+        return exec_print_using(vm, cur);
+    }
     // Simplified: read expressions to end of line, separated by comma/semicolon.
     let mut first = true;
-    let mut col: usize = 0;
+    // vm.column follows every item, so POS inside the statement sees it; it
+    // carries over from a previous PRINT ending in ';' or ','
     let zone: usize = 14; // classic print zone width
     let mut want_newline = true;
     loop {
-        vm.log_debug(format!("[PRINT] start expr at tok {}", cur.i));
         let mut consumed = false;
         let start_i = cur.i;
-        if let Some(val) = parse_expression_with_vm(cur, vm) {
-            // If expression signaled an error (e.g., BAD SUBSCRIPT), don't print placeholder value
-            if vm.error_override.is_some() { return Ok(()); }
-            if !first { print!(" "); col += 1; }
-            match val {
-                Value::Number(n) => { vm.log_debug(format!("[PRINT] number {}", n)); let s = format!("{}", n); col += s.len(); print!("{}", s); }
-                Value::Str(s) => { vm.log_debug(format!("[PRINT] str {}", s)); col += s.len(); print!("{}", s); }
-                Value::Tab(n) => {
-                    let target = n;
-                    if col < target { let spaces = target - col; for _ in 0..spaces { print!(" "); } col = target; }
+        match parse_expression_with_vm(cur, vm) {
+            Ok(val) => {
+                if !first { print!(" "); vm.column += 1; }
+                match val {
+                    Value::Number(n) => { let s = format!("{}", n); vm.column += s.len(); print!("{}", s); }
+                    Value::Str(s) => { vm.column += s.len(); print!("{}", s); }
+                    Value::Tab(n) => {
+                        let target = n;
+                        if vm.column < target { let spaces = target - vm.column; for _ in 0..spaces { print!(" "); } vm.column = target; }
+                    }
                 }
-            }
-            io::stdout().flush().ok();
+                io::stdout().flush().ok();
             
-            // Check for interrupts after each print (for responsive Ctrl-C)
-            if let Some(flag) = &vm.interrupt_flag {
-                if flag.load(std::sync::atomic::Ordering::SeqCst) {
+                // Check for interrupts after each print (for responsive Ctrl-C)
+                if let Some(flag) = &vm.interrupt_flag && flag.load(std::sync::atomic::Ordering::SeqCst) {
                     // Don't clear flag here, let runtime handle it
                     vm.halted = true;
                     return Ok(());
                 }
-            }
             
-            first = false;
-            consumed = true;
-        } else {
-            // If parser advanced but failed to produce a value, it's a syntax error (e.g., PRINT () or PRINT ()
-            if cur.i != start_i {
-                return Err(BasicError::Syntax.into());
+                first = false;
+                consumed = true;
             }
-            // Fallback: directly print literal when expression parser refuses (e.g., edge tokens)
-            match cur.peek() {
-                Some(Tok::Number(n)) => { vm.log_debug(format!("[PRINT] fallback number {}", n)); cur.next(); let s = format!("{}", n); if !first { print!(" "); col+=1; } col += s.len(); print!("{}", s); first=false; consumed = true; }
-                Some(Tok::String(sv)) => { vm.log_debug(format!("[PRINT] fallback str {}", sv)); cur.next(); if !first { print!(" "); col+=1; } col += sv.len(); print!("{}", sv); first=false; consumed = true; }
-                _ => {}
+            Err(e) => {
+                // If parser advanced but failed to produce a value, report its error (e.g., PRINT () or PRINT ()
+                if cur.i != start_i {
+                    return Err(e);
+                }
+                // Fallback: directly print literal when expression parser refuses (e.g., edge tokens)
+                match cur.peek() {
+                    Some(Tok::Number(n)) => { cur.next(); let s = format!("{}", n); if !first { print!(" "); vm.column+=1; } vm.column += s.len(); print!("{}", s); first=false; consumed = true; }
+                    Some(Tok::String(sv)) => { cur.next(); if !first { print!(" "); vm.column+=1; } vm.column += sv.len(); print!("{}", sv); first=false; consumed = true; }
+                    _ => {}
+                }
             }
         }
         // If未消费任何表达式且下一个不是分隔符/结束，判为语法错误
         match cur.peek() {
             Some(Tok::Symbol(',')) | Some(Tok::Symbol(';')) | None => {}
-            _ => { if !consumed { return Err(BasicError::Syntax.into()); } }
+            _ => { if !consumed { return Err(BasicError::Syntax); } }
         }
         match cur.peek() {
             Some(Tok::Symbol(',')) => {
                 // advance to next print zone
                 cur.next();
-                let next_zone = ((col / zone) + 1) * zone;
-                if col < next_zone {
-                    for _ in 0..(next_zone - col) { print!(" "); }
-                    col = next_zone;
+                let next_zone = ((vm.column / zone) + 1) * zone;
+                if vm.column < next_zone {
+                    for _ in 0..(next_zone - vm.column) { print!(" "); }
+                    vm.column = next_zone;
                 }
                 want_newline = true;
                 continue;
@@ -171,61 +176,91 @@ fn exec_print(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     }
     if want_newline {
         println!();
+        vm.column = 0;
+    } else {
+        io::stdout().flush().ok();
+    }
+    Ok(())
+}
+
+fn exec_print_using(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
+    // PRINT USING fmt$; expr[{,|;} expr...][;]
+    let format = match parse_expression_with_vm(cur, vm)? {
+        Value::Str(s) => s,
+        _ => return Err(BasicError::TypeMismatch),
+    };
+    match cur.next() { Some(Tok::Symbol(';')) => {}, _ => return Err(BasicError::Syntax) }
+    let mut values = Vec::new();
+    let mut want_newline = true;
+    loop {
+        let val = parse_expression_with_vm(cur, vm)?;
+        values.push(val);
+        match cur.peek() {
+            Some(Tok::Symbol(',')) | Some(Tok::Symbol(';')) => {
+                cur.next();
+                // trailing separator suppresses the newline
+                if matches!(cur.peek(), None | Some(Tok::Symbol(':'))) { want_newline = false; break; }
+            }
+            None | Some(Tok::Symbol(':')) => break,
+            _ => return Err(BasicError::Syntax),
+        }
+    }
+    let text = crate::print_using::format_using(&format, &values)?;
+    print!("{}", text);
+    if want_newline {
+        println!();
+        vm.column = 0;
     } else {
-        io::stdout().flush().ok(); 
+        io::stdout().flush().ok();
+        vm.column += text.chars().count();
     }
     Ok(())
 }
 
 fn exec_assignment(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // IDENT or IDENT '(' subscripts ')'
-    let name = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => bail!("SYNTAX ERROR") };
+    let name = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => return Err(BasicError::Syntax) };
     let is_indexed = matches!(cur.peek(), Some(Tok::Symbol('(')));
     if is_indexed {
         cur.next(); // '('
         let mut idxs: Vec<usize> = Vec::new();
         loop {
-            let v = parse_expression_with_vm(cur, vm).ok_or_else(|| anyhow::anyhow!("SYNTAX ERROR"))?.as_number();
-            if v <= 0.0 { bail!("BAD SUBSCRIPT") }
-            idxs.push(v as usize);
-            match cur.next() { Some(Tok::Symbol(',')) => continue, Some(Tok::Symbol(')')) => break, _ => bail!("SYNTAX ERROR") }
+            idxs.push(parse_subscript_with_vm(cur, vm)?);
+            match cur.next() { Some(Tok::Symbol(',')) => continue, Some(Tok::Symbol(')')) => break, _ => return Err(BasicError::Syntax) }
         }
-        match cur.next() { Some(Tok::Symbol('=')) => {}, _ => bail!("SYNTAX ERROR") }
-        let val = parse_expression_with_vm(cur, vm).ok_or_else(|| anyhow::anyhow!("SYNTAX ERROR"))?;
+        match cur.next() { Some(Tok::Symbol('=')) => {}, _ => return Err(BasicError::Syntax) }
+        let val = parse_expression_with_vm(cur, vm)?;
         let nm = name.clone();
-        vm.set_array_element(&nm, &idxs, val).map_err(|e| match e {
-            "UNDEFINED ARRAY" => BasicError::UndefinedArray.into(),
-            "BAD SUBSCRIPT" => BasicError::BadSubscript.into(),
-            _ => anyhow::anyhow!(e),
-        })?;
+        vm.set_array_element(&nm, &idxs, val)?;
     } else {
-        match cur.next() { Some(Tok::Symbol('=')) => {}, _ => bail!("SYNTAX ERROR") }
-        let val = parse_expression_with_vm(cur, vm).ok_or_else(|| anyhow::anyhow!("SYNTAX ERROR"))?;
+        match cur.next() { Some(Tok::Symbol('=')) => {}, _ => return Err(BasicError::Syntax) }
+        let val = parse_expression_with_vm(cur, vm)?;
         let is_str = name.ends_with('$');
         match (&val, is_str) {
             (Value::Str(_), true) => {}
             (Value::Number(_), false) => {}
-            _ => bail!("TYPE MISMATCH"),
+            _ => return Err(BasicError::TypeMismatch),
         }
-        vm.vars.insert(name, val);
+        vm.set_var(name, val);
     }
     Ok(())
 }
 
 fn exec_if(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // Minimal: IF <expr> THEN <line>|<immediate statements>
-    let cond = parse_expression_with_vm(cur, vm).ok_or_else(|| anyhow::anyhow!("SYNTAX ERROR"))?;
+    let cond = parse_expression_with_vm(cur, vm)?;
     let is_then = match cur.next() {
         Some(Tok::Keyword(TokenKind::Then)) => true,
-        Some(Tok::Ident(s)) if s.to_ascii_uppercase()=="THEN" => true,
+        Some(Tok::Ident(s)) if s.eq_ignore_ascii_case("THEN") => true,
         _ => false,
     };
-    if !is_then { bail!("SYNTAX ERROR"); }
+    if !is_then { return Err(BasicError::Syntax); }
     // Next token: number => branch to line, else treat remainder as immediate
     match cur.peek() {
-        Some(Tok::Number(n)) => {
+        // IF ... THEN 100 is IF ... GOTO 100
+        Some(Tok::Number(_)) => {
             if cond.as_number() != 0.0 {
-                vm.jump_to = Some((*n as i64).clamp(0, u16::MAX as i64) as u16);
+                exec_goto(vm, cur)?;
             }
             Ok(())
         }
@@ -233,98 +268,150 @@ fn exec_if(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
             if cond.as_number() != 0.0 {
                 // Execute remaining tokens as immediate statement
                 let rest = &cur.toks[cur.i..];
-                let _ = execute_direct(vm, rest);
+                execute_direct(vm, rest)?;
             }
             Ok(())
         }
     }
 }
 
+fn exec_goto(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
+    // GOTO expr: inside a program jump; in direct mode start running there
+    let line = parse_word_with_vm(cur, vm)?;
+    if vm.current_line.is_some() {
+        vm.jump_to_line(line);
+        Ok(())
+    } else {
+        vm.goto(line)
+    }
+}
+
 fn exec_gosub(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
-    let Tok::Number(n) = cur.next().ok_or_else(|| anyhow::anyhow!("SYNTAX ERROR"))? else { bail!("SYNTAX ERROR") };
-    let ret = vm.current_line.and_then(|ln| vm.next_line_after(ln)).ok_or_else(|| anyhow::anyhow!("RETURN WITHOUT GOSUB"))?;
-    vm.gosub_stack.push(crate::runtime::GosubFrame { return_line: ret });
-    vm.jump_to = Some((*n as i64).clamp(0, u16::MAX as i64) as u16);
+    let line = parse_word_with_vm(cur, vm)?;
+    // RETURN continues with the statement after the GOSUB, even mid-line
+    push_gosub(vm, line)?;
+    vm.jump_to_line(line);
+    Ok(())
+}
+
+/// Push a GOSUB frame returning to the statement after the current one.
+fn push_gosub(vm: &mut Vm, line: u16) -> Result<()> {
+    let ret = vm.next_stmt_pos().ok_or(BasicError::IllegalDirect)?;
+    vm.check_stack()?;
+    vm.gosub_stack.push(crate::runtime::GosubFrame { return_pos: ret });
+    vm.log(LogCategory::Gosub, LogLevel::Debug, "push", || format!("to {} depth {}", line, vm.gosub_stack.len()));
     Ok(())
 }
 
 fn exec_return(vm: &mut Vm) -> Result<()> {
-    let frame = vm.gosub_stack.pop().ok_or_else(|| anyhow::anyhow!("RETURN WITHOUT GOSUB"))?;
-    vm.jump_to = Some(frame.return_line);
+    let frame = vm.gosub_stack.pop().ok_or(BasicError::ReturnWithoutGosub)?;
+    // Loops entered inside the subroutine end with it
+    let depth = vm.gosub_stack.len();
+    vm.for_stack.retain(|f| f.gosub_depth <= depth);
+    vm.log(LogCategory::Gosub, LogLevel::Debug, "return", || {
+        format!("to {}:{} depth {}", frame.return_pos.line, frame.return_pos.stmt, depth)
+    });
+    vm.jump_to = Some(frame.return_pos);
     Ok(())
 }
 
 fn exec_for(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // FOR I=1 TO 10 STEP 2
-    let var = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => bail!("SYNTAX ERROR") };
-    match cur.next() { Some(Tok::Symbol('=')) => {}, _ => bail!("SYNTAX ERROR") }
-    let start = parse_expression_with_vm(cur, vm).ok_or_else(|| anyhow::anyhow!("SYNTAX ERROR"))?.as_number();
+    let var = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => return Err(BasicError::Syntax) };
+    if var.ends_with('$') { return Err(BasicError::TypeMismatch); }
+    match cur.next() { Some(Tok::Symbol('=')) => {}, _ => return Err(BasicError::Syntax) }
+    let start = parse_number_with_vm(cur, vm)?;
     let is_to = match cur.next() {
         Some(Tok::Keyword(TokenKind::To)) => true,
-        Some(Tok::Ident(s)) if s.to_ascii_uppercase()=="TO" => true,
+        Some(Tok::Ident(s)) if s.eq_ignore_ascii_case("TO") => true,
         _ => false,
     };
-    if !is_to { bail!("SYNTAX ERROR"); }
-    let end = parse_expression_with_vm(cur, vm).ok_or_else(|| anyhow::anyhow!("SYNTAX ERROR"))?.as_number();
+    if !is_to { return Err(BasicError::Syntax); }
+    let end = parse_number_with_vm(cur, vm)?;
     let mut step = 1.0;
     if let Some(tok) = cur.peek() {
         match tok {
-            Tok::Ident(s) if s.to_ascii_uppercase()=="STEP" => { cur.next(); step = parse_expression_with_vm(cur, vm).ok_or_else(|| anyhow::anyhow!("SYNTAX ERROR"))?.as_number(); }
+            Tok::Keyword(TokenKind::Step) => { cur.next(); step = parse_number_with_vm(cur, vm)?; }
+            Tok::Ident(s) if s.eq_ignore_ascii_case("STEP") => { cur.next(); step = parse_number_with_vm(cur, vm)?; }
             _ => {}
         }
     }
-    vm.vars.insert(var.clone(), Value::Number(start));
-    let line = vm.current_line.ok_or_else(|| anyhow::anyhow!("FOR without line context"))?;
-    // 下次从本行 FOR 之后的语句继续（若本行没有后续语句，则在该行不会执行，随后进入下一行）
-    let restart_stmt = vm.current_stmt_index + 1;
-    vm.inline_stmt_restart = Some(restart_stmt);
-    vm.for_stack.push(crate::runtime::ForFrame { var, end, step, start_line: line, restart_stmt_index: restart_stmt });
+    vm.set_var(var.clone(), Value::Number(start));
+    // 每次循环从 FOR 之后的语句开始（若 FOR 是本行最后一条语句，则从下一行开始）
+    let loop_pos = vm.next_stmt_pos().ok_or(BasicError::IllegalDirect)?;
+    let gosub_depth = vm.gosub_stack.len();
+    // Like the original: a FOR on a variable that already has an active loop
+    // discards that loop and every loop nested inside it
+    if let Some(k) = vm.for_stack.iter().rposition(|f| f.gosub_depth == gosub_depth && f.var == var) {
+        vm.for_stack.truncate(k);
+    }
+    vm.check_stack()?;
+    vm.log(LogCategory::For, LogLevel::Debug, "push", || {
+        format!("{} = {} TO {} STEP {} depth {}", var, start, end, step, vm.for_stack.len() + 1)
+    });
+    vm.for_stack.push(crate::runtime::ForFrame { var, end, step, loop_pos, gosub_depth });
     Ok(())
 }
 
 fn exec_next(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
-    // NEXT I  (variable optional; if present must match top of stack)
-    let name_opt = match cur.peek() { Some(Tok::Ident(s)) => Some(s.clone()), _ => None };
+    // NEXT [I[,J...]]: without a variable, the innermost loop; a named loop
+    // also ends any loops nested inside it
+    let mut name_opt = match cur.peek() { Some(Tok::Ident(s)) => Some(s.clone()), _ => None };
     if name_opt.is_some() { cur.next(); }
-    let frame = match vm.for_stack.last_mut() { Some(f) => f, None => bail!("NEXT WITHOUT FOR") };
-    if let Some(nm) = &name_opt { if *nm != frame.var { bail!("NEXT WITHOUT FOR") } }
-    let curv = vm.vars.get(&frame.var).and_then(|v| if let Value::Number(n)=v { Some(*n) } else { None }).unwrap_or(0.0);
-    let newv = curv + frame.step;
-    vm.vars.insert(frame.var.clone(), Value::Number(newv));
-    let continue_loop = if frame.step >= 0.0 { newv <= frame.end } else { newv >= frame.end };
-    if continue_loop {
-        // 回到 FOR 所在行，并从该行下一条语句继续
-        vm.inline_stmt_restart = Some(frame.restart_stmt_index);
-        vm.jump_to = Some(frame.start_line);
-    } else {
+    loop {
+        let depth = vm.gosub_stack.len();
+        let k = match &name_opt {
+            Some(nm) => vm.for_stack.iter().rposition(|f| f.gosub_depth == depth && f.var == *nm),
+            None => vm.for_stack.len().checked_sub(1).filter(|&k| vm.for_stack[k].gosub_depth == depth),
+        };
+        let Some(k) = k else { return Err(BasicError::NextWithoutFor) };
+        vm.for_stack.truncate(k + 1);
+        let frame = &vm.for_stack[k];
+        let curv = vm.vars.get(&frame.var).and_then(|v| if let Value::Number(n)=v { Some(*n) } else { None }).unwrap_or(0.0);
+        let newv = curv + frame.step;
+        let continue_loop = if frame.step >= 0.0 { newv <= frame.end } else { newv >= frame.end };
+        let (var, loop_pos) = (frame.var.clone(), frame.loop_pos);
+        vm.set_var(var.clone(), Value::Number(newv));
+        if continue_loop {
+            vm.log(LogCategory::For, LogLevel::Trace, "next", || format!("{} = {}", var, newv));
+            // 回到 FOR 所在行，并从该行下一条语句继续
+            vm.jump_to = Some(loop_pos);
+            return Ok(());
+        }
         vm.for_stack.pop();
+        vm.log(LogCategory::For, LogLevel::Debug, "done", || format!("{} = {} depth {}", var, newv, vm.for_stack.len()));
+        // NEXT I,J: once a loop is done, go on with the next variable
+        if name_opt.is_none() || !matches!(cur.peek(), Some(Tok::Symbol(','))) { return Ok(()); }
+        cur.next();
+        name_opt = match cur.next() { Some(Tok::Ident(s)) => Some(s.clone()), _ => return Err(BasicError::Syntax) };
     }
-    Ok(())
 }
 
 fn exec_on(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // ON expr GOTO l1[,l2...] | ON expr GOSUB l1[,l2...]
-    let idx = parse_expression_with_vm(cur, vm).ok_or_else(|| anyhow::anyhow!("SYNTAX ERROR"))?.as_number() as isize;
+    let idx = parse_number_with_vm(cur, vm)?;
+    if !(0.0..256.0).contains(&idx) { return Err(BasicError::IllegalQuantity); }
+    let idx = idx as isize;
     // read target keyword (GOTO or GOSUB), handling both keyword and identifier forms
     let is_gosub = match cur.next() {
         Some(Tok::Keyword(TokenKind::Goto)) => false,
         Some(Tok::Keyword(TokenKind::Gosub)) => true,
         Some(Tok::Ident(name)) => {
             let up = name.to_ascii_uppercase();
-            if up == "GOTO" { false } else if up == "GOSUB" { true } else { bail!("SYNTAX ERROR") }
+            if up == "GOTO" { false } else if up == "GOSUB" { true } else { return Err(BasicError::Syntax) }
         }
-        _ => bail!("SYNTAX ERROR"),
+        _ => return Err(BasicError::Syntax),
     };
     if idx <= 0 { return Ok(()); }
+    // Targets are expressions, evaluated up to the chosen one
     let mut i = 1isize;
     loop {
-        let ln = match cur.next() { Some(Tok::Number(n)) => *n as u16, _ => bail!("SYNTAX ERROR") };
+        let ln = parse_word_with_vm(cur, vm)?;
         if i == idx {
             if is_gosub {
-                let ret = vm.current_line.and_then(|ln| vm.next_line_after(ln)).ok_or_else(|| anyhow::anyhow!("RETURN WITHOUT GOSUB"))?;
-                vm.gosub_stack.push(crate::runtime::GosubFrame { return_line: ret });
+                push_gosub(vm, ln)?;
             }
-            vm.jump_to = Some(ln);
+            vm.jump_to_line(ln);
             return Ok(());
         }
         match cur.peek() {
@@ -337,33 +424,20 @@ fn exec_on(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
 
 fn exec_save(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // SAVE "filename"
-    let path = match cur.next() { Some(Tok::String(s)) => s.clone(), _ => bail!("SYNTAX ERROR") };
-    // Write as plain text lines: "<line> <tokens... as text>" (using list printer)
+    let path = match cur.next() { Some(Tok::String(s)) => s.clone(), _ => return Err(BasicError::Syntax) };
+    // Write as plain text lines: "<line> <tokens... as text>" (as LIST prints them)
     let mut out = String::new();
     for (ln, pl) in &vm.program.lines {
-        out.push_str(&format!("{} ", ln));
-        let mut first = true;
-        for t in &pl.tokens {
-            if !first { out.push(' '); }
-            first = false;
-            match t {
-                Tok::Keyword(k) => out.push_str(crate::tokens::keyword_name(*k)),
-                Tok::Ident(s) => out.push_str(s),
-                Tok::Number(n) => out.push_str(&format!("{}", n)),
-                Tok::String(s) => { out.push('"'); out.push_str(s); out.push('"'); }
-                Tok::Symbol(c) => out.push(*c),
-            }
-        }
-        out.push('\n');
+        out.push_str(&format!("{} {}\n", ln, crate::program::detokenize(&pl.tokens)));
     }
-    fs::write(path, out).map_err(|_| anyhow::anyhow!("IO ERROR"))?;
+    fs::write(path, out).map_err(|_| BasicError::Io)?;
     Ok(())
 }
 
 fn exec_load(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // LOAD "filename"
-    let path = match cur.next() { Some(Tok::String(s)) => s.clone(), _ => bail!("SYNTAX ERROR") };
-    let data = fs::read_to_string(path).map_err(|_| anyhow::anyhow!("IO ERROR"))?;
+    let path = match cur.next() { Some(Tok::String(s)) => s.clone(), _ => return Err(BasicError::Syntax) };
+    let data = fs::read_to_string(path).map_err(|_| BasicError::Io)?;
     vm.program.clear();
     for line in data.lines() {
         let trimmed = line.trim();
@@ -372,7 +446,7 @@ fn exec_load(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
         let mut parts = trimmed.splitn(2, ' ');
         let ln_str = parts.next().unwrap_or("");
         let rest = parts.next().unwrap_or("");
-        let ln: u16 = ln_str.parse().map_err(|_| anyhow::anyhow!("SYNTAX ERROR"))?;
+        let ln: u16 = ln_str.parse().map_err(|_| BasicError::Syntax)?;
         let toks = crate::lexer::crunch(rest);
         vm.program.insert_line(ln, toks);
     }
@@ -380,30 +454,25 @@ fn exec_load(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
 }
 
 fn exec_cont(vm: &mut Vm) -> Result<()> {
-    // CONT: resume program execution from next line after BREAK
-    vm.halted = false;
-    vm.run();
-    Ok(())
+    // CONT: resume where STOP/END/Ctrl-C halted the program
+    vm.cont()
 }
 
 fn exec_dim(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // DIM A(10), B$(3,4)
     loop {
-        let name = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => bail!("SYNTAX ERROR") };
-        let is_string = name.ends_with('$');
-        match cur.next() { Some(Tok::Symbol('(')) => {}, _ => bail!("SYNTAX ERROR") }
-        let mut dims: Vec<usize> = Vec::new();
+        let name = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => return Err(BasicError::Syntax) };
+        match cur.next() { Some(Tok::Symbol('(')) => {}, _ => return Err(BasicError::Syntax) }
+        let mut bounds: Vec<usize> = Vec::new();
         loop {
-            let n = match cur.next() { Some(Tok::Number(v)) => *v as isize, _ => bail!("SYNTAX ERROR") };
-            if n <= 0 { bail!("SYNTAX ERROR") }
-            dims.push(n as usize);
+            bounds.push(parse_subscript_with_vm(cur, vm)?);
             match cur.next() {
                 Some(Tok::Symbol(',')) => continue,
                 Some(Tok::Symbol(')')) => break,
-                _ => bail!("SYNTAX ERROR"),
+                _ => return Err(BasicError::Syntax),
             }
         }
-        vm.dim_array(name, dims, is_string);
+        vm.dim_array(name, bounds)?;
         match cur.peek() {
             Some(Tok::Symbol(',')) => { cur.next(); continue; }
             _ => break,
@@ -414,18 +483,17 @@ fn exec_dim(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
 fn exec_read(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // READ A, B, C ...  assign from DATA pool; strings/numbers supported
     loop {
-        let name = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => bail!("SYNTAX ERROR") };
-        let val = match vm.next_data_value() {
-            Some(v) => v,
-            None => { return Err(BasicError::OutOfData.into()); }
+        let name = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => return Err(BasicError::Syntax) };
+        let item = vm.next_data_item().ok_or(BasicError::OutOfData)?;
+        // A string variable takes the item's text; a number must parse as one
+        let val = if name.ends_with('$') {
+            Value::Str(item.text)
+        } else if item.quoted {
+            return Err(BasicError::Syntax);
+        } else {
+            Value::Number(parse_number(&item.text).ok_or(BasicError::Syntax)?)
         };
-        let is_str = name.ends_with('$');
-        match (&val, is_str) {
-            (Value::Str(_), true) => {}
-            (Value::Number(_), false) => {}
-            _ => bail!("TYPE MISMATCH"),
-        }
-        vm.vars.insert(name, val);
+        vm.set_var(name, val);
         match cur.peek() {
             Some(Tok::Symbol(',')) => { cur.next(); continue; }
             _ => break,
@@ -445,30 +513,33 @@ fn exec_restore(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
 
 fn exec_input(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     // INPUT ["prompt"][;|,] var[,var...]
+    vm.require_program()?;
     let mut prompt: Option<String> = None;
     if let Some(Tok::String(s)) = cur.peek() { prompt = Some(s.clone()); cur.next(); }
-    if let Some(Tok::Symbol(sym)) = cur.peek() { if *sym==';' || *sym==',' { cur.next(); } }
+    if let Some(Tok::Symbol(sym)) = cur.peek() && (*sym==';' || *sym==',') { cur.next(); }
 
     // Collect variable names
     let mut vars: Vec<String> = Vec::new();
-    loop {
-        match cur.next() {
-            Some(Tok::Ident(s)) => vars.push(s.clone()),
-            _ => break,
-        }
+    while let Some(Tok::Ident(s)) = cur.next() {
+        vars.push(s.clone());
         match cur.peek() {
             Some(Tok::Symbol(',')) => { cur.next(); continue; }
             _ => break,
         }
     }
-    if vars.is_empty() { bail!("SYNTAX ERROR"); }
+    if vars.is_empty() { return Err(BasicError::Syntax); }
+
+    // INPUT needs the terminal's line editing back
+    keyboard::restore_terminal();
+    vm.keyboard.clear();
 
     // Prompt and read loop until success
     loop {
         if let Some(p) = &prompt { print!("{}", p); } else { print!("? "); }
         io::stdout().flush().ok();
         let mut line = String::new();
-        if io::stdin().read_line(&mut line).is_err() { bail!("INPUT ERROR"); }
+        if io::stdin().read_line(&mut line).is_err() { return Err(BasicError::Io); }
+        vm.column = 0;
         let fields: Vec<String> = line.trim_end_matches(['\n','\r']).split(',').map(|s| s.trim().to_string()).collect();
         if fields.len() < vars.len() { println!("?REDO FROM START"); continue; }
 
@@ -483,12 +554,12 @@ fn exec_input(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
                 } else {
                     Value::Str(raw)
                 };
-                vm.vars.insert(name.clone(), val);
+                vm.set_var(name.clone(), val);
             } else {
                 // Numeric: must parse as f64
-                match raw.parse::<f64>() {
-                    Ok(n) => { vm.vars.insert(name.clone(), Value::Number(n)); }
-                    Err(_) => { println!("?REDO FROM START"); ok = false; break; }
+                match parse_number(&raw) {
+                    Some(n) => { vm.set_var(name.clone(), Value::Number(n)); }
+                    None => { println!("?REDO FROM START"); ok = false; break; }
                 }
             }
         }
@@ -497,3 +568,316 @@ fn exec_input(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
     Ok(())
 }
 
+
+fn exec_get(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
+    // GET var[,var...]: never waits; no key gives "" (or 0 for a numeric variable)
+    vm.require_program()?;
+    let mut vars: Vec<String> = Vec::new();
+    while let Some(Tok::Ident(s)) = cur.next() {
+        vars.push(s.clone());
+        match cur.peek() {
+            Some(Tok::Symbol(',')) => { cur.next(); continue; }
+            _ => break,
+        }
+    }
+    if vars.is_empty() { return Err(BasicError::Syntax); }
+
+    for name in vars {
+        let key = vm.keyboard.poll().and_then(|k| k.to_char());
+        let val = if name.ends_with('$') {
+            Value::Str(key.map(String::from).unwrap_or_default())
+        } else {
+            // Numeric GET accepts only digits, like the original
+            match key {
+                None => Value::Number(0.0),
+                Some(c) => match c.to_digit(10) {
+                    Some(d) => Value::Number(d as f64),
+                    None => return Err(BasicError::Syntax),
+                },
+            }
+        };
+        vm.set_var(name, val);
+    }
+    Ok(())
+}
+
+fn exec_def(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
+    // DEF FNA(X)=expr: the body is kept as tokens and evaluated by each call
+    vm.require_program()?;
+    if !matches!(cur.next(), Some(Tok::Keyword(TokenKind::Fn))) { return Err(BasicError::Syntax); }
+    let name = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => return Err(BasicError::Syntax) };
+    if !matches!(cur.next(), Some(Tok::Symbol('('))) { return Err(BasicError::Syntax); }
+    let param = match cur.next() { Some(Tok::Ident(s)) => s.clone(), _ => return Err(BasicError::Syntax) };
+    if !matches!(cur.next(), Some(Tok::Symbol(')'))) { return Err(BasicError::Syntax); }
+    if !matches!(cur.next(), Some(Tok::Symbol('='))) { return Err(BasicError::Syntax); }
+    // Functions are numeric only, as in the original
+    if name.ends_with('$') || param.ends_with('$') { return Err(BasicError::TypeMismatch); }
+    if cur.peek().is_none() { return Err(BasicError::Syntax); }
+    let body = cur.toks[cur.i..].into();
+    cur.i = cur.toks.len();
+    vm.user_fns.insert(name, crate::runtime::UserFn { param, body });
+    Ok(())
+}
+
+fn exec_poke(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
+    // POKE addr,byte
+    let addr = parse_word_with_vm(cur, vm)?;
+    if !matches!(cur.next(), Some(Tok::Symbol(','))) { return Err(BasicError::Syntax); }
+    let val = parse_byte_with_vm(cur, vm)?;
+    vm.poke(addr, val);
+    Ok(())
+}
+
+fn exec_wait(vm: &mut Vm, cur: &mut Cursor) -> Result<()> {
+    // WAIT addr,mask[,xor]: wait until (PEEK(addr) XOR xor) AND mask is non-zero
+    let addr = parse_word_with_vm(cur, vm)?;
+    if !matches!(cur.next(), Some(Tok::Symbol(','))) { return Err(BasicError::Syntax); }
+    let mask = parse_byte_with_vm(cur, vm)?;
+    let xor = match cur.peek() {
+        Some(Tok::Symbol(',')) => { cur.next(); parse_byte_with_vm(cur, vm)? }
+        _ => 0,
+    };
+    // Nothing changes the memory image behind the program's back, so an
+    // unsatisfied WAIT lasts until Ctrl-C (left set for the runtime to report)
+    while (vm.peek(addr) ^ xor) & mask == 0 {
+        if let Some(flag) = &vm.interrupt_flag && flag.load(std::sync::atomic::Ordering::SeqCst) {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::Key;
+    use crate::runtime::ARRAY_MEMORY;
+
+    /// RUN the program and return the VM for inspection.
+    fn run(lines: &[&str]) -> Result<Vm> {
+        let mut vm = Vm::with_program(lines);
+        vm.prepare_full_run();
+        vm.run().map(|()| vm)
+    }
+
+    fn num(vm: &Vm, name: &str) -> f64 {
+        vm.vars.get(name).map(Value::as_number).unwrap_or_else(|| panic!("{} not set", name))
+    }
+
+    /// RUN a program that must fail and return its error message.
+    fn error(lines: &[&str]) -> String {
+        match run(lines) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("program ran without error"),
+        }
+    }
+
+    fn text(vm: &Vm, name: &str) -> String {
+        match vm.vars.get(name) {
+            Some(Value::Str(s)) => s.clone(),
+            other => panic!("{} is {:?}", name, other),
+        }
+    }
+
+    #[test]
+    fn get_reads_queued_keys_without_waiting() {
+        let mut vm = Vm::with_program(&["10 GET A$,B$,N,E$"]);
+        vm.keyboard.push(Key::Char('X'));
+        vm.keyboard.push(Key::Char('7'));
+        vm.prepare_full_run();
+        vm.run().unwrap();
+        assert_eq!(text(&vm, "A$"), "X");
+        assert_eq!(text(&vm, "B$"), "7");
+        // No key waiting: 0 and ""
+        assert_eq!(num(&vm, "N"), 0.0);
+        assert_eq!(text(&vm, "E$"), "");
+    }
+
+    #[test]
+    fn numeric_get_accepts_only_digits() {
+        let mut vm = Vm::with_program(&["10 GET N"]);
+        vm.keyboard.push(Key::Char('5'));
+        vm.prepare_full_run();
+        vm.run().unwrap();
+        assert_eq!(num(&vm, "N"), 5.0);
+
+        vm.keyboard.push(Key::Char('Q'));
+        vm.prepare_full_run();
+        assert_eq!(vm.run().unwrap_err().to_string(), "SYNTAX ERROR IN 10");
+    }
+
+    #[test]
+    fn get_is_illegal_in_direct_mode() {
+        let mut vm = Vm::new();
+        let err = execute_direct(&mut vm, &crate::lexer::crunch("GET A$")).unwrap_err();
+        assert!(matches!(err, BasicError::IllegalDirect));
+        assert!(run(&["10 GET A$"]).is_ok());
+    }
+
+    #[test]
+    fn gosub_resumes_after_the_call_in_the_same_line() {
+        let vm = run(&["10 A=1: GOSUB 100: B=A: C=3", "20 END", "100 A=2: RETURN"]).unwrap();
+        assert_eq!(num(&vm, "B"), 2.0);
+        assert_eq!(num(&vm, "C"), 3.0);
+        assert!(vm.gosub_stack.is_empty());
+    }
+
+    #[test]
+    fn for_with_an_active_variable_discards_that_loop_and_those_inside_it() {
+        let err = error(&[
+            "10 FOR K=1 TO 3",
+            "20 FOR J=1 TO 2",
+            "30 FOR K=5 TO 6",
+            "40 NEXT K",
+            "50 NEXT J",
+        ]);
+        assert_eq!(err, "NEXT WITHOUT FOR IN 50");
+    }
+
+    #[test]
+    fn next_with_several_variables_closes_inner_loop_first() {
+        let vm = run(&["10 FOR I=1 TO 3: FOR J=1 TO 2", "20 N=N+1", "30 NEXT J,I"]).unwrap();
+        assert_eq!(num(&vm, "N"), 6.0);
+        assert_eq!(num(&vm, "I"), 4.0);
+        assert_eq!(num(&vm, "J"), 3.0);
+        assert!(vm.for_stack.is_empty());
+
+        assert_eq!(error(&["10 FOR I=1 TO 2: FOR J=1 TO 2", "20 NEXT I,J"]), "NEXT WITHOUT FOR IN 20");
+    }
+
+    #[test]
+    fn cont_resumes_after_stop() {
+        let mut vm = run(&["10 A=1: STOP: A=2", "20 B=3"]).unwrap();
+        assert_eq!(num(&vm, "A"), 1.0);
+        vm.cont().unwrap();
+        assert_eq!(num(&vm, "A"), 2.0);
+        assert_eq!(num(&vm, "B"), 3.0);
+        // Ran off the end: nothing left to continue
+        assert!(matches!(vm.cont(), Err(BasicError::CantContinue)));
+    }
+
+    #[test]
+    fn cont_is_refused_after_an_error_or_an_edit() {
+        let mut vm = run(&["10 STOP", "20 A=1/0", "30 B=1"]).unwrap();
+        assert_eq!(vm.cont().unwrap_err().to_string(), "DIVISION BY ZERO IN 20");
+        assert!(matches!(vm.cont(), Err(BasicError::CantContinue)));
+
+        let mut vm = run(&["10 STOP", "20 B=1"]).unwrap();
+        vm.program.insert_line(30, crate::lexer::crunch("B=2"));
+        assert!(matches!(vm.cont(), Err(BasicError::CantContinue)));
+        assert!(!vm.vars.contains_key("B"));
+    }
+
+    #[test]
+    fn errors_in_then_branch_report_the_if_line() {
+        assert_eq!(error(&["10 IF 1 THEN X=1/0"]), "DIVISION BY ZERO IN 10");
+        assert_eq!(error(&["10 IF 1 THEN PRINT: GOTO 50"]), "UNDEF'D STATEMENT IN 10");
+        assert_eq!(error(&["10 A=1: IF A THEN RETURN"]), "RETURN WITHOUT GOSUB IN 10");
+        assert_eq!(error(&["10 IF 1 PRINT"]), "SYNTAX ERROR IN 10");
+        // A false condition skips the branch without evaluating it
+        let vm = run(&["10 IF 0 THEN X=1/0", "20 Y=1"]).unwrap();
+        assert_eq!(num(&vm, "Y"), 1.0);
+    }
+
+    #[test]
+    fn pos_sees_the_column_inside_print() {
+        // LEFT$ takes as many stars as POS reports after "ABC", then a space
+        let vm = run(&[r#"10 PRINT "ABC";LEFT$("******",POS(0));"#]).unwrap();
+        assert_eq!(vm.column, 3 + 1 + 3);
+        let vm = run(&[r#"10 PRINT "AB";"#, r#"20 PRINT "C",LEFT$("****************",POS(0));"#]).unwrap();
+        assert_eq!(vm.column, 14 + 1 + 14);
+        let vm = run(&[r#"10 PRINT "ABC""#, "20 X=POS(0)"]).unwrap();
+        assert_eq!(num(&vm, "X"), 0.0);
+    }
+
+    #[test]
+    fn fn_parameter_shadows_a_variable_only_during_the_call() {
+        let vm = run(&["10 X=5: DEF FN A(X)=X*2+Y", "20 Y=1: R=FN A(3)", "30 S=FN A(FN A(1))"]).unwrap();
+        assert_eq!(num(&vm, "R"), 7.0);
+        assert_eq!(num(&vm, "S"), 7.0);
+        assert_eq!(num(&vm, "X"), 5.0);
+        // A parameter with no variable behind it leaves none behind
+        let vm = run(&["10 DEF FN B(Q)=Q+1", "20 R=FN B(1)"]).unwrap();
+        assert!(!vm.vars.contains_key("Q"));
+
+        assert_eq!(error(&["10 R=FN C(1)"]), "UNDEF'D FUNCTION IN 10");
+        assert_eq!(error(&["10 DEF FN A$(X)=X"]), "TYPE MISMATCH IN 10");
+        assert_eq!(error(&["10 DEF FN A(X)=FN A(X)", "20 R=FN A(1)"]), "FORMULA TOO COMPLEX IN 20");
+    }
+
+    #[test]
+    fn jump_targets_are_expressions() {
+        let vm = run(&["10 L=30: GOTO L", "20 A=1", "30 GOSUB L*2: B=2: END", "60 C=3: RETURN"]).unwrap();
+        assert!(!vm.vars.contains_key("A"));
+        assert_eq!(num(&vm, "B"), 2.0);
+        assert_eq!(num(&vm, "C"), 3.0);
+
+        let vm = run(&["10 I=2: ON I GOTO 20,10+20", "20 A=1: END", "30 ON I-1 GOSUB 50: END", "50 B=1: RETURN"]).unwrap();
+        assert!(!vm.vars.contains_key("A"));
+        assert_eq!(num(&vm, "B"), 1.0);
+        // Out of range: fall through; negative: ?FC
+        let vm = run(&["10 ON 3 GOTO 20,30: A=1", "20 END", "30 END"]).unwrap();
+        assert_eq!(num(&vm, "A"), 1.0);
+        assert_eq!(error(&["10 ON -1 GOTO 10"]), "ILLEGAL QUANTITY IN 10");
+        assert_eq!(error(&["10 GOTO 7E4+0"]), "ILLEGAL QUANTITY IN 10");
+        assert_eq!(error(&["10 GOTO 70000"]), "ILLEGAL QUANTITY IN 10");
+        assert_eq!(error(&["10 GOTO 20"]), "UNDEF'D STATEMENT IN 10");
+    }
+
+    #[test]
+    fn poke_peek_and_null() {
+        let vm = run(&["10 POKE 1024,65: POKE 65535,255: A=PEEK(1024): B=PEEK(65535): C=PEEK(0)"]).unwrap();
+        assert_eq!((num(&vm, "A"), num(&vm, "B"), num(&vm, "C")), (65.0, 255.0, 0.0));
+        assert_eq!(error(&["10 POKE 1,256"]), "ILLEGAL QUANTITY IN 10");
+        assert_eq!(error(&["10 POKE -1,0"]), "ILLEGAL QUANTITY IN 10");
+        assert_eq!(error(&["10 A=PEEK(65536)"]), "ILLEGAL QUANTITY IN 10");
+        // WAIT returns at once when the condition already holds
+        assert!(run(&["10 POKE 10,4: WAIT 10,6: WAIT 10,4,1"]).is_ok());
+
+        let vm = run(&["10 NULL 3"]).unwrap();
+        assert_eq!(vm.nulls, 3);
+        assert_eq!(error(&["10 NULL 256"]), "ILLEGAL QUANTITY IN 10");
+    }
+
+    #[test]
+    fn arrays_are_created_on_first_use_with_bound_10() {
+        let vm = run(&["10 A(10)=5: B=A(10): C=M(2,3)"]).unwrap();
+        assert_eq!(num(&vm, "B"), 5.0);
+        assert_eq!(vm.arrays["A"].dims, [11]);
+        assert_eq!(vm.arrays["M"].dims, [11, 11]);
+        assert_eq!(error(&["10 A(11)=1"]), "BAD SUBSCRIPT IN 10");
+        assert_eq!(error(&["10 A$(1)=1"]), "TYPE MISMATCH IN 10");
+    }
+
+    #[test]
+    fn dim_after_implicit_creation_is_redimensioning() {
+        assert_eq!(error(&["10 X=A(1)", "20 DIM A(20)"]), "REDIM'D ARRAY IN 20");
+        assert_eq!(error(&["10 DIM A(5)", "20 DIM A(5)"]), "REDIM'D ARRAY IN 20");
+        // CLEAR forgets arrays, so DIM works again
+        assert!(run(&["10 X=A(1): CLEAR: DIM A(20)"]).is_ok());
+    }
+
+    #[test]
+    fn subscript_count_and_range_are_checked() {
+        assert_eq!(error(&["10 DIM A(5): A(1,1)=0"]), "BAD SUBSCRIPT IN 10");
+        assert_eq!(error(&["10 A(1)=1: X=A(1,2)"]), "BAD SUBSCRIPT IN 10");
+        assert_eq!(error(&["10 DIM A(2,2): X=A(1)"]), "BAD SUBSCRIPT IN 10");
+        assert_eq!(error(&["10 A(-1)=0"]), "ILLEGAL QUANTITY IN 10");
+        assert_eq!(error(&["10 X=A(40000)"]), "ILLEGAL QUANTITY IN 10");
+        assert_eq!(error(&["10 DIM A(32768)"]), "ILLEGAL QUANTITY IN 10");
+        // Subscripts are truncated
+        let vm = run(&["10 DIM A(2): A(1.9)=7: B=A(1)"]).unwrap();
+        assert_eq!(num(&vm, "B"), 7.0);
+    }
+
+    #[test]
+    fn fre_reports_memory_left_after_dim() {
+        let vm = run(&["10 F=FRE(0): DIM A(9): G=FRE(0): DIM B$(9): H=FRE(0)"]).unwrap();
+        assert_eq!(num(&vm, "F"), ARRAY_MEMORY as f64);
+        // Header of 5 bytes plus 2 per dimension, then 5 per number or 3 per string
+        assert_eq!(num(&vm, "G"), (ARRAY_MEMORY - (7 + 50)) as f64);
+        assert_eq!(num(&vm, "H"), (ARRAY_MEMORY - (7 + 50) - (7 + 30)) as f64);
+        assert_eq!(error(&["10 DIM A(10000)"]), "OUT OF MEMORY IN 10");
+    }
+}
@@ -29,9 +29,20 @@ pub enum TokenKind {
     Stop,
     On,
     Print,
+    Get,
+    Def,
+    Poke,
+    Wait,
+    Null,
     // Operators/Separators
     Then,
     To,
+    Using,
+    And,
+    Or,
+    Not,
+    Fn,
+    Step,
 }
 
 /// Token items. The crunching phase maps keywords to single-byte tokens (stored as u16 here).
@@ -42,8 +53,25 @@ pub enum Tok {
     Number(f64),
     String(String),
     Symbol(char), // e.g., = + - * / ( ) , ; :
+    /// Text kept verbatim: what follows DATA (up to a ':' outside quotes)
+    /// or REM (to the end of the line)
+    Raw(String),
 }
 
+/// Reserved words in the order the cruncher tries them: the original RESLST
+/// order, with this interpreter's additions. The first entry that matches
+/// wins, even inside a longer name (`SCORE` crunches as `SC` `OR` `E`).
+/// Function names are reserved too, but are kept as `Ident` tokens.
+pub const RESERVED_WORDS: &[&str] = &[
+    "END", "FOR", "NEXT", "DATA", "INPUT", "DIM", "READ", "LET", "GOTO", "RUN",
+    "IF", "RESTORE", "GOSUB", "RETURN", "REM", "STOP", "ON", "NULL", "WAIT",
+    "LOAD", "SAVE", "DEF", "POKE", "PRINT", "CONT", "LIST", "CLEAR", "NEW",
+    "GET", "TO", "FN", "THEN", "NOT", "STEP", "USING", "AND", "OR",
+    "SGN", "INT", "ABS", "FRE", "POS", "SQR", "RND", "LOG", "EXP", "COS", "SIN", "TAN",
+    "ATN", "PEEK", "LEN", "STR$", "VAL", "ASC", "CHR$", "LEFT$", "RIGHT$", "MID$",
+    "SPACE$", "INSTR",
+];
+
 /// Keyword lookup table (uppercase input expected).
 pub fn lookup_keyword_upper(s: &str) -> Option<TokenKind> {
     use TokenKind::*;
@@ -72,8 +100,19 @@ pub fn lookup_keyword_upper(s: &str) -> Option<TokenKind> {
         "STOP" => Some(Stop),
         "ON" => Some(On),
         "PRINT" | "?" => Some(Print),
+        "GET" => Some(Get),
+        "DEF" => Some(Def),
+        "POKE" => Some(Poke),
+        "WAIT" => Some(Wait),
+        "NULL" => Some(Null),
         "THEN" => Some(Then),
         "TO" => Some(To),
+        "USING" => Some(Using),
+        "AND" => Some(And),
+        "OR" => Some(Or),
+        "NOT" => Some(Not),
+        "FN" => Some(Fn),
+        "STEP" => Some(Step),
         _ => None,
     }
 }
@@ -106,8 +145,19 @@ pub fn keyword_name(k: TokenKind) -> &'static str {
         Stop => "STOP",
         On => "ON",
         Print => "PRINT",
+        Get => "GET",
+        Def => "DEF",
+        Poke => "POKE",
+        Wait => "WAIT",
+        Null => "NULL",
         Then => "THEN",
         To => "TO",
+        Using => "USING",
+        And => "AND",
+        Or => "OR",
+        Not => "NOT",
+        Fn => "FN",
+        Step => "STEP",
     }
 }
 
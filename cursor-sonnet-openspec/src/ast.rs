@@ -1,10 +1,22 @@
-/// 抽象语法树（AST）数据结构
-///
-/// 定义 BASIC 程序的语法元素
+//! 抽象语法树（AST）数据结构
+//!
+//! 定义 BASIC 程序的语法元素。
+//! 所有节点都可以用 serde 序列化，JSON 格式见 `json` 模块
+
+use serde::{Deserialize, Serialize};
+
+/// 表达式节点：表达式本身及其源码范围
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ExprKind", into = "ExprKind")]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
 
-/// 表达式节点
-#[derive(Debug, Clone, PartialEq)]
-pub enum Expr {
+/// 表达式的种类
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExprKind {
     // 字面量
     Number(f64),
     String(String),
@@ -37,7 +49,8 @@ pub enum Expr {
 }
 
 /// 二元运算符
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum BinaryOperator {
     // 算术
     Add,
@@ -60,15 +73,25 @@ pub enum BinaryOperator {
 }
 
 /// 一元运算符
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum UnaryOperator {
     Minus,
     Not,
 }
 
-/// 语句
-#[derive(Debug, Clone, PartialEq)]
-pub enum Statement {
+/// 语句节点：语句本身及其源码范围
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StatementKind", into = "StatementKind")]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+/// 语句的种类
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementKind {
     // LET 赋值
     Let {
         target: AssignTarget,
@@ -213,18 +236,36 @@ pub enum Statement {
     Save {
         filename: String,
     },
+    
+    // MERGE 把文件中的程序行合并到当前程序
+    Merge {
+        filename: String,
+    },
+    
+    // CHAIN 加载并运行另一个程序，保留变量
+    Chain {
+        filename: String,
+        line_number: Option<u16>,
+    },
+    
+    // COMMON 声明 CHAIN 时保留的变量
+    Common {
+        variables: Vec<CommonVariable>,
+    },
 }
 
 /// THEN 部分（行号或语句）
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ThenPart {
     LineNumber(u16),
-    Statement(Statement),
+    Statement(Box<Statement>),
     Statements(Vec<Statement>), // 支持 THEN 后跟多条语句
 }
 
 /// 赋值目标（变量或数组元素）
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AssignTarget {
     Variable(String),
     ArrayElement {
@@ -234,67 +275,162 @@ pub enum AssignTarget {
 }
 
 /// PRINT 语句的项
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PrintItem {
     Expr(Expr),
     Tab(Expr),       // TAB(x)
     Spc(Expr),       // SPC(x)
     Comma,           // 列分隔
     Semicolon,       // 紧密连接
+    Using {          // USING 格式串; 值列表
+        format: Expr,
+        values: Vec<Expr>,
+    },
 }
 
 /// 数组维度声明
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ArrayDim {
     pub name: String,
     pub dimensions: Vec<Expr>,
 }
 
+/// COMMON 语句中的变量（`A()` 表示整个数组）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommonVariable {
+    pub name: String,
+    pub is_array: bool,
+}
+
 /// DATA 语句的值
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum DataValue {
     Number(f64),
     String(String),
 }
 
 /// 程序行
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProgramLine {
     pub line_number: u16,
     pub statements: Vec<Statement>,
 }
 
+/// 源码范围
+///
+/// `start`/`end` 为输入行中的字符偏移（左闭右开），与 `SpannedToken` 一致。
+/// `Expr` 和 `Statement` 节点各带一个范围，但范围不参与节点的相等比较，
+/// 也不写进 JSON，所以两者都不受源码写法影响。
+/// 没有源码位置的节点（手工构造或由不带位置的 token 解析）范围为空
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// 是否为空范围（节点没有源码位置）
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    /// 合并两个范围
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
 impl Expr {
+    /// 创建带源码范围的表达式
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+
     /// 创建数值字面量
     pub fn number(val: f64) -> Self {
-        Expr::Number(val)
+        ExprKind::Number(val).into()
     }
     
     /// 创建字符串字面量
     pub fn string(val: String) -> Self {
-        Expr::String(val)
+        ExprKind::String(val).into()
     }
     
     /// 创建变量引用
     pub fn variable(name: String) -> Self {
-        Expr::Variable(name)
+        ExprKind::Variable(name).into()
     }
     
-    /// 创建二元运算
+    /// 创建二元运算，范围覆盖两个操作数
     pub fn binary(left: Expr, op: BinaryOperator, right: Expr) -> Self {
-        Expr::BinaryOp {
-            left: Box::new(left),
-            op,
-            right: Box::new(right),
-        }
+        let span = left.span.to(right.span);
+        Expr::new(
+            ExprKind::BinaryOp {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            },
+            span,
+        )
     }
     
     /// 创建一元运算
     pub fn unary(op: UnaryOperator, operand: Expr) -> Self {
-        Expr::UnaryOp {
+        ExprKind::UnaryOp {
             op,
             operand: Box::new(operand),
         }
+        .into()
+    }
+}
+
+/// 范围不参与比较
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Self {
+        Expr { kind, span: Span::default() }
+    }
+}
+
+impl From<Expr> for ExprKind {
+    fn from(expr: Expr) -> Self {
+        expr.kind
+    }
+}
+
+impl Statement {
+    /// 创建带源码范围的语句
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Statement { kind, span }
+    }
+}
+
+/// 范围不参与比较
+impl PartialEq for Statement {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl From<StatementKind> for Statement {
+    fn from(kind: StatementKind) -> Self {
+        Statement { kind, span: Span::default() }
+    }
+}
+
+impl From<Statement> for StatementKind {
+    fn from(statement: Statement) -> Self {
+        statement.kind
     }
 }
 
@@ -305,13 +441,13 @@ mod tests {
     #[test]
     fn test_expr_number() {
         let expr = Expr::number(42.0);
-        assert_eq!(expr, Expr::Number(42.0));
+        assert_eq!(expr.kind, ExprKind::Number(42.0));
     }
 
     #[test]
     fn test_expr_variable() {
         let expr = Expr::variable("A".to_string());
-        assert_eq!(expr, Expr::Variable("A".to_string()));
+        assert_eq!(expr.kind, ExprKind::Variable("A".to_string()));
     }
 
     #[test]
@@ -320,11 +456,11 @@ mod tests {
         let right = Expr::number(3.0);
         let expr = Expr::binary(left, BinaryOperator::Add, right);
         
-        match expr {
-            Expr::BinaryOp { left, op, right } => {
-                assert_eq!(*left, Expr::Number(2.0));
+        match expr.kind {
+            ExprKind::BinaryOp { left, op, right } => {
+                assert_eq!(*left, Expr::number(2.0));
                 assert_eq!(op, BinaryOperator::Add);
-                assert_eq!(*right, Expr::Number(3.0));
+                assert_eq!(*right, Expr::number(3.0));
             }
             _ => panic!("Expected BinaryOp"),
         }
@@ -335,10 +471,10 @@ mod tests {
         let operand = Expr::number(5.0);
         let expr = Expr::unary(UnaryOperator::Minus, operand);
         
-        match expr {
-            Expr::UnaryOp { op, operand } => {
+        match expr.kind {
+            ExprKind::UnaryOp { op, operand } => {
                 assert_eq!(op, UnaryOperator::Minus);
-                assert_eq!(*operand, Expr::Number(5.0));
+                assert_eq!(*operand, Expr::number(5.0));
             }
             _ => panic!("Expected UnaryOp"),
         }
@@ -346,15 +482,15 @@ mod tests {
 
     #[test]
     fn test_statement_let() {
-        let stmt = Statement::Let {
+        let stmt = StatementKind::Let {
             target: AssignTarget::Variable("A".to_string()),
             value: Expr::number(10.0),
         };
         
         match stmt {
-            Statement::Let { target, value } => {
+            StatementKind::Let { target, value } => {
                 assert_eq!(target, AssignTarget::Variable("A".to_string()));
-                assert_eq!(value, Expr::Number(10.0));
+                assert_eq!(value, Expr::number(10.0));
             }
             _ => panic!("Expected Let statement"),
         }
@@ -362,14 +498,14 @@ mod tests {
 
     #[test]
     fn test_statement_print() {
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![
                 PrintItem::Expr(Expr::string("HELLO".to_string())),
             ],
         };
         
         match stmt {
-            Statement::Print { items } => {
+            StatementKind::Print { items } => {
                 assert_eq!(items.len(), 1);
             }
             _ => panic!("Expected Print statement"),
@@ -378,7 +514,7 @@ mod tests {
 
     #[test]
     fn test_statement_if_with_line_number() {
-        let stmt = Statement::If {
+        let stmt = StatementKind::If {
             condition: Expr::binary(
                 Expr::variable("A".to_string()),
                 BinaryOperator::Greater,
@@ -388,7 +524,7 @@ mod tests {
         };
         
         match stmt {
-            Statement::If { condition: _, then_part } => {
+            StatementKind::If { condition: _, then_part } => {
                 assert_eq!(*then_part, ThenPart::LineNumber(100));
             }
             _ => panic!("Expected If statement"),
@@ -397,7 +533,7 @@ mod tests {
 
     #[test]
     fn test_statement_for() {
-        let stmt = Statement::For {
+        let stmt = StatementKind::For {
             var: "I".to_string(),
             start: Expr::number(1.0),
             end: Expr::number(10.0),
@@ -405,11 +541,11 @@ mod tests {
         };
         
         match stmt {
-            Statement::For { var, start, end, step } => {
+            StatementKind::For { var, start, end, step } => {
                 assert_eq!(var, "I");
-                assert_eq!(start, Expr::Number(1.0));
-                assert_eq!(end, Expr::Number(10.0));
-                assert_eq!(step, Some(Expr::Number(1.0)));
+                assert_eq!(start, Expr::number(1.0));
+                assert_eq!(end, Expr::number(10.0));
+                assert_eq!(step, Some(Expr::number(1.0)));
             }
             _ => panic!("Expected For statement"),
         }
@@ -420,10 +556,10 @@ mod tests {
         let line = ProgramLine {
             line_number: 10,
             statements: vec![
-                Statement::Let {
+                StatementKind::Let {
                     target: AssignTarget::Variable("A".to_string()),
                     value: Expr::number(5.0),
-                },
+                }.into(),
             ],
         };
         
@@ -433,13 +569,13 @@ mod tests {
 
     #[test]
     fn test_array_access() {
-        let expr = Expr::ArrayAccess {
+        let expr = ExprKind::ArrayAccess {
             name: "A".to_string(),
             indices: vec![Expr::number(5.0)],
         };
         
         match expr {
-            Expr::ArrayAccess { name, indices } => {
+            ExprKind::ArrayAccess { name, indices } => {
                 assert_eq!(name, "A");
                 assert_eq!(indices.len(), 1);
             }
@@ -449,13 +585,13 @@ mod tests {
 
     #[test]
     fn test_function_call() {
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "SIN".to_string(),
             args: vec![Expr::variable("X".to_string())],
         };
         
         match expr {
-            Expr::FunctionCall { name, args } => {
+            ExprKind::FunctionCall { name, args } => {
                 assert_eq!(name, "SIN");
                 assert_eq!(args.len(), 1);
             }
@@ -0,0 +1,18 @@
+//! BASIC 语言服务器入口
+//!
+//! 通过 stdin/stdout 上的 JSON-RPC（LSP 协议）与编辑器通信
+
+use std::io;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let code = match basic_m6502::lsp::run(stdin.lock(), stdout.lock()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("basic-lsp: {}", e);
+            1
+        }
+    };
+    std::process::exit(code);
+}
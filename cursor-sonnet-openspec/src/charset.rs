@@ -0,0 +1,288 @@
+//! 字符集转换
+//!
+//! 解释器内部的字符串保存显示字符（Unicode），字符集负责在字符码和显示字符之间转换：
+//! CHR$ 把字符码转换为字符，ASC 把字符转换回字符码，GET/INPUT 把键盘输入转换为
+//! 目标机器上按键产生的字符，输出时把控制码转换为终端能理解的形式。
+//! 图形字符映射到 Unicode 制表符和方块字符。
+
+use crate::keyboard::Key;
+
+/// 可选字符集
+///
+/// 有些图形字符只能近似显示，几个字符码会对应同一个字符
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Charset {
+    /// ASCII / Latin-1（默认，字符码即 Unicode 码点）
+    #[default]
+    Ascii,
+    /// PETSCII 大写/图形字符集（Commodore 开机时的字符集）
+    Petscii,
+    /// PETSCII 小写/大写字符集（CHR$(14) 切换）
+    PetsciiLower,
+    /// Apple II 字符集（只有大写，最高位被忽略）
+    AppleII,
+}
+
+/// PETSCII 96-127（大写/图形字符集）对应的字符，192-223 与之相同
+const PETSCII_GRAPHICS_LOW: [char; 32] = [
+    '─', '♠', '│', '─', '─', '─', '─', '│', '│', '╮', '╰', '╯', '⌞', '╲', '╱', '⌜',
+    '⌝', '●', '▁', '♥', '▏', '╭', '╳', '○', '♣', '▕', '♦', '┼', '▒', '│', 'π', '◥',
+];
+
+/// PETSCII 160-191 对应的字符，224-254 与之相同
+const PETSCII_GRAPHICS_HIGH: [char; 32] = [
+    '\u{a0}', '▌', '▄', '▔', '▁', '▏', '▒', '▕', '▒', '◤', '▕', '├', '▗', '└', '┐', '▂',
+    '┌', '┴', '┬', '┤', '▎', '▍', '▐', '▔', '▀', '▃', '⌟', '▖', '▝', '┘', '▘', '▚',
+];
+
+impl Charset {
+    /// 按名称选择字符集：ascii / petscii / petscii-lower / apple2
+    pub fn from_name(name: &str) -> Option<Charset> {
+        match name.to_ascii_lowercase().as_str() {
+            "ascii" => Some(Charset::Ascii),
+            "petscii" => Some(Charset::Petscii),
+            "petscii-lower" => Some(Charset::PetsciiLower),
+            "apple2" => Some(Charset::AppleII),
+            _ => None,
+        }
+    }
+
+    /// 是否为 PETSCII 字符集（大写或小写）
+    pub fn is_petscii(self) -> bool {
+        matches!(self, Charset::Petscii | Charset::PetsciiLower)
+    }
+
+    /// 字符码转换为字符（CHR$）
+    ///
+    /// 控制码保持原码点，由 `render` 和虚拟屏幕解释
+    pub fn to_char(self, code: u8) -> char {
+        match self {
+            Charset::Ascii => code as char,
+            Charset::Petscii | Charset::PetsciiLower => petscii_to_char(code, self == Charset::PetsciiLower),
+            Charset::AppleII => match code & 0x7F {
+                c @ 0..=31 => c as char,
+                c @ 96..=126 => (c - 32) as char,
+                127 => '▒',
+                c => c as char,
+            },
+        }
+    }
+
+    /// 字符转换为字符码（ASC），字符集中没有的字符返回 None
+    ///
+    /// 一个字符对应多个字符码时返回键盘产生的那个
+    pub fn from_char(self, ch: char) -> Option<u8> {
+        match self {
+            Charset::Ascii => u8::try_from(ch as u32).ok(),
+            Charset::AppleII => {
+                let code = u8::try_from(ch.to_ascii_uppercase() as u32).ok()?;
+                (code < 128).then_some(code)
+            }
+            Charset::Petscii | Charset::PetsciiLower => {
+                let lower = self == Charset::PetsciiLower;
+                if (ch as u32) < 32 || (128..160).contains(&(ch as u32)) {
+                    return Some(ch as u8);
+                }
+                if !lower && ch.is_ascii_lowercase() {
+                    return Some(ch.to_ascii_uppercase() as u8);
+                }
+                if lower && ch.is_ascii_uppercase() {
+                    return Some(ch as u8 + 128);
+                }
+                (32..=255).find(|&code| petscii_to_char(code, lower) == ch)
+            }
+        }
+    }
+
+    /// 键盘输入转换为目标机器上的字符（GET/INPUT）
+    ///
+    /// 大写/图形字符集和 Apple II 上没有小写字母，按大写处理；回车键产生 CHR$(13)
+    pub fn translate_input(self, ch: char) -> char {
+        match (self, ch) {
+            (Charset::Ascii, ch) => ch,
+            (_, '\n') => '\r',
+            (Charset::PetsciiLower, ch) => ch,
+            (_, ch) => ch.to_ascii_uppercase(),
+        }
+    }
+
+    /// 按键转换为 GET 读到的字符，方向键和功能键转换为对应机器的控制码
+    ///
+    /// 字符集中没有对应控制码的按键返回 None
+    pub fn translate_key(self, key: Key) -> Option<char> {
+        let code = match (self.is_petscii(), key) {
+            (_, Key::Char(ch)) => return Some(self.translate_input(ch)),
+            (_, Key::Enter) => 13,
+            // PETSCII：光标键、HOME、INST/DEL、RUN/STOP 和 F1-F8
+            (true, Key::Up) => 145,
+            (true, Key::Down) => 17,
+            (true, Key::Left) => 157,
+            (true, Key::Right) => 29,
+            (true, Key::Home) => 19,
+            (true, Key::Backspace | Key::Delete) => 20,
+            (true, Key::Escape) => 3,
+            (true, Key::Function(n @ 1..=8)) => [133, 137, 134, 138, 135, 139, 136, 140][n as usize - 1],
+            // ASCII / Apple II：Apple IIe 的方向键码
+            (false, Key::Up) => 11,
+            (false, Key::Down) => 10,
+            (false, Key::Left) => 8,
+            (false, Key::Right) => 21,
+            (false, Key::Backspace) if self == Charset::AppleII => 8,
+            (false, Key::Backspace | Key::Delete) => 127,
+            (false, Key::Escape) => 27,
+            _ => return None,
+        };
+        Some(self.to_char(code))
+    }
+
+    /// 把程序输出转换为终端文本
+    ///
+    /// PETSCII 控制码（清屏、HOME、光标移动、反色）转换为 ANSI 转义序列，
+    /// CHR$(14) / CHR$(142) 切换大小写字符集，其余控制码丢弃
+    pub fn render(&mut self, text: &str) -> String {
+        if *self == Charset::Ascii {
+            return text.to_string();
+        }
+
+        let mut out = String::with_capacity(text.len());
+        for ch in text.chars() {
+            if ch == '\n' {
+                out.push('\n');
+                continue;
+            }
+            let code = ch as u32;
+            let is_control = code < 32 || (128..160).contains(&code);
+            if !is_control {
+                out.push(ch);
+                continue;
+            }
+            match (self.is_petscii(), code) {
+                (_, 13) => out.push('\n'),
+                (_, 7) => out.push('\x07'),
+                (true, 147) => out.push_str("\x1b[2J\x1b[H"),
+                (true, 19) => out.push_str("\x1b[H"),
+                (true, 17) => out.push_str("\x1b[B"),
+                (true, 145) => out.push_str("\x1b[A"),
+                (true, 29) => out.push_str("\x1b[C"),
+                (true, 157) => out.push_str("\x1b[D"),
+                (true, 18) => out.push_str("\x1b[7m"),
+                (true, 146) => out.push_str("\x1b[27m"),
+                (true, 14) => *self = Charset::PetsciiLower,
+                (true, 142) => *self = Charset::Petscii,
+                _ => {}
+            }
+        }
+        out
+    }
+}
+
+/// PETSCII 字符码转换为字符
+fn petscii_to_char(code: u8, lower: bool) -> char {
+    match code {
+        0..=31 | 128..=159 => code as char,
+        32..=63 => code as char,
+        64 => '@',
+        65..=90 if lower => (code + 32) as char,
+        65..=90 => code as char,
+        91 => '[',
+        92 => '£',
+        93 => ']',
+        94 => '↑',
+        95 => '←',
+        97..=122 if lower => (code - 32) as char,
+        193..=218 if lower => (code - 128) as char,
+        126 | 222 if lower => '▒',
+        127 | 223 if lower => '▨',
+        96..=127 => PETSCII_GRAPHICS_LOW[(code - 96) as usize],
+        160..=191 => PETSCII_GRAPHICS_HIGH[(code - 160) as usize],
+        192..=223 => PETSCII_GRAPHICS_LOW[(code - 192) as usize],
+        224..=254 => PETSCII_GRAPHICS_HIGH[(code - 224) as usize],
+        255 => 'π',
+    }
+}
+
+/// 屏幕码转换为 PETSCII 字符码（忽略反色位）
+pub fn screen_code_to_petscii(code: u8) -> u8 {
+    match code & 0x7F {
+        c @ 0..=31 => c + 64,
+        c @ 32..=63 => c,
+        c @ 64..=95 => c + 32,
+        c => c + 64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Requirement: 字符集 - CHR$ 和 ASC 互为逆运算
+    #[test]
+    fn test_round_trip() {
+        for charset in [Charset::Ascii, Charset::Petscii, Charset::PetsciiLower] {
+            for code in (32..=127).chain(160..=191) {
+                let ch = charset.to_char(code);
+                let back = charset.from_char(ch).unwrap();
+                assert_eq!(charset.to_char(back), ch, "{:?} {}", charset, code);
+            }
+        }
+        assert_eq!(Charset::Petscii.from_char('♠'), Some(97));
+        assert_eq!(Charset::Petscii.from_char('é'), None);
+    }
+
+    // Requirement: 字符集 - PETSCII 图形字符映射到制表符和方块字符
+    #[test]
+    fn test_petscii_graphics() {
+        assert_eq!(Charset::Petscii.to_char(65), 'A');
+        assert_eq!(Charset::Petscii.to_char(115), '♥');
+        assert_eq!(Charset::Petscii.to_char(176), '┌');
+        assert_eq!(Charset::Petscii.to_char(219), '┼');
+        assert_eq!(Charset::Petscii.to_char(255), 'π');
+
+        // 小写字符集：65-90 是小写字母，97-122 和 193-218 是大写字母
+        assert_eq!(Charset::PetsciiLower.to_char(65), 'a');
+        assert_eq!(Charset::PetsciiLower.to_char(97), 'A');
+        assert_eq!(Charset::PetsciiLower.from_char('A'), Some(193));
+        assert_eq!(Charset::PetsciiLower.from_char('a'), Some(65));
+
+        // Apple II 只有大写字母，最高位被忽略
+        assert_eq!(Charset::AppleII.to_char(193), 'A');
+        assert_eq!(Charset::AppleII.to_char(97), 'A');
+    }
+
+    // Requirement: 字符集 - 键盘输入转换
+    #[test]
+    fn test_translate_input() {
+        assert_eq!(Charset::Ascii.translate_input('a'), 'a');
+        assert_eq!(Charset::Ascii.translate_input('\n'), '\n');
+        assert_eq!(Charset::Petscii.translate_input('a'), 'A');
+        assert_eq!(Charset::Petscii.translate_input('\n'), '\r');
+        assert_eq!(Charset::PetsciiLower.translate_input('a'), 'a');
+        assert_eq!(Charset::AppleII.translate_input('q'), 'Q');
+    }
+
+    // Requirement: 字符集 - 方向键和功能键转换为控制码
+    #[test]
+    fn test_translate_key() {
+        assert_eq!(Charset::Petscii.translate_key(Key::Up), Some('\u{91}'));
+        assert_eq!(Charset::Petscii.translate_key(Key::Function(1)), Some('\u{85}'));
+        assert_eq!(Charset::Petscii.translate_key(Key::Char('x')), Some('X'));
+        assert_eq!(Charset::Ascii.translate_key(Key::Left), Some('\u{8}'));
+        assert_eq!(Charset::AppleII.translate_key(Key::Right), Some('\u{15}'));
+        assert_eq!(Charset::Ascii.translate_key(Key::Enter), Some('\r'));
+        assert_eq!(Charset::Ascii.translate_key(Key::Function(1)), None);
+    }
+
+    // Requirement: 字符集 - 输出渲染
+    #[test]
+    fn test_render() {
+        let mut charset = Charset::Petscii;
+        assert_eq!(charset.render("\u{93}HI\u{12}X\u{92}\r"), "\x1b[2J\x1b[HHI\x1b[7mX\x1b[27m\n");
+
+        // CHR$(14) 切换到小写字符集
+        charset.render("\u{e}");
+        assert_eq!(charset, Charset::PetsciiLower);
+
+        let mut ascii = Charset::Ascii;
+        assert_eq!(ascii.render("A\u{93}"), "A\u{93}");
+    }
+}
@@ -0,0 +1,191 @@
+//! 60Hz 时钟（jiffy clock）
+//!
+//! 原版每秒中断 60 次，每次把时钟加一（一个 jiffy）。TI 读出开机以来的 jiffy 数，
+//! TI$ 以 "HHMMSS" 格式读写同一个时钟，24 小时后回到零。
+//!
+//! 时钟有两种模式：实时模式按真实时间计数；确定模式每执行一条语句前进固定的
+//! jiffy 数，与机器快慢无关，同一个程序每次运行得到相同的 TI，便于测试。
+
+use crate::error::{BasicError, Result};
+use std::time::{Duration, Instant};
+
+/// 每秒的 jiffy 数
+pub const JIFFIES_PER_SECOND: u64 = 60;
+
+/// 一天的 jiffy 数（TI 和 TI$ 在此回绕）
+pub const JIFFIES_PER_DAY: u64 = 24 * 60 * 60 * JIFFIES_PER_SECOND;
+
+/// 时钟模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClockMode {
+    /// 按真实时间计数（默认）
+    #[default]
+    RealTime,
+    /// 每执行一条语句前进固定的 jiffy 数
+    Deterministic { jiffies_per_statement: u64 },
+}
+
+impl ClockMode {
+    /// 按名称选择模式：real / deterministic / deterministic:N（每条语句 N 个 jiffy，默认 1）
+    pub fn from_name(name: &str) -> Option<ClockMode> {
+        let name = name.to_ascii_lowercase();
+        if name == "real" {
+            return Some(ClockMode::RealTime);
+        }
+        let rest = name.strip_prefix("deterministic")?;
+        let jiffies_per_statement = match rest.strip_prefix(':') {
+            Some(n) => n.parse().ok()?,
+            None if rest.is_empty() => 1,
+            None => return None,
+        };
+        Some(ClockMode::Deterministic { jiffies_per_statement })
+    }
+}
+
+/// 60Hz 时钟
+#[derive(Debug, Clone)]
+pub struct JiffyClock {
+    mode: ClockMode,
+    /// 上次设置时钟时的 jiffy 数
+    base: u64,
+    /// 实时模式：上次设置时钟的时刻
+    started: Instant,
+    /// 确定模式：上次设置时钟以来前进的 jiffy 数
+    elapsed: u64,
+}
+
+impl JiffyClock {
+    /// 创建从零开始计数的时钟
+    pub fn new(mode: ClockMode) -> Self {
+        JiffyClock {
+            mode,
+            base: 0,
+            started: Instant::now(),
+            elapsed: 0,
+        }
+    }
+
+    /// 时钟模式
+    pub fn mode(&self) -> ClockMode {
+        self.mode
+    }
+
+    /// 切换模式，当前时间保持不变
+    pub fn set_mode(&mut self, mode: ClockMode) {
+        let now = self.jiffies();
+        self.mode = mode;
+        self.set_jiffies(now);
+    }
+
+    /// 当前 jiffy 数（TI）
+    pub fn jiffies(&self) -> u64 {
+        let elapsed = match self.mode {
+            ClockMode::RealTime => {
+                (self.started.elapsed().as_millis() as u64) * JIFFIES_PER_SECOND / 1000
+            }
+            ClockMode::Deterministic { .. } => self.elapsed,
+        };
+        (self.base + elapsed) % JIFFIES_PER_DAY
+    }
+
+    /// 设置当前 jiffy 数
+    pub fn set_jiffies(&mut self, jiffies: u64) {
+        self.base = jiffies % JIFFIES_PER_DAY;
+        self.started = Instant::now();
+        self.elapsed = 0;
+    }
+
+    /// 执行一条语句：确定模式下时钟前进，实时模式下什么也不做
+    pub fn tick(&mut self) {
+        if let ClockMode::Deterministic { jiffies_per_statement } = self.mode {
+            self.elapsed += jiffies_per_statement;
+        }
+    }
+
+    /// 等待若干 jiffy：实时模式下睡眠，确定模式下直接前进
+    pub fn wait(&mut self, jiffies: u64) {
+        match self.mode {
+            ClockMode::RealTime => {
+                std::thread::sleep(Duration::from_millis(jiffies * 1000 / JIFFIES_PER_SECOND));
+            }
+            ClockMode::Deterministic { .. } => self.elapsed += jiffies,
+        }
+    }
+
+    /// 当前时间（TI$），格式 "HHMMSS"
+    pub fn time_string(&self) -> String {
+        let seconds = self.jiffies() / JIFFIES_PER_SECOND;
+        format!("{:02}{:02}{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    }
+
+    /// 按 "HHMMSS" 设置时间（TI$ = "..."）
+    pub fn set_time_string(&mut self, text: &str) -> Result<()> {
+        let invalid = || BasicError::IllegalQuantity(format!("TI$ must be \"HHMMSS\": {:?}", text));
+        if text.len() != 6 || !text.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let field = |i: usize| text[i..i + 2].parse::<u64>().unwrap();
+        let (hours, minutes, seconds) = (field(0), field(2), field(4));
+        if hours >= 24 || minutes >= 60 || seconds >= 60 {
+            return Err(invalid());
+        }
+        self.set_jiffies(((hours * 60 + minutes) * 60 + seconds) * JIFFIES_PER_SECOND);
+        Ok(())
+    }
+}
+
+impl Default for JiffyClock {
+    fn default() -> Self {
+        Self::new(ClockMode::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Requirement: 时钟 - 确定模式按语句前进
+    #[test]
+    fn test_deterministic_clock() {
+        let mut clock = JiffyClock::new(ClockMode::Deterministic { jiffies_per_statement: 2 });
+        assert_eq!(clock.jiffies(), 0);
+        for _ in 0..30 {
+            clock.tick();
+        }
+        assert_eq!(clock.jiffies(), 60);
+        assert_eq!(clock.time_string(), "000001");
+
+        clock.wait(3540);
+        assert_eq!(clock.time_string(), "000100");
+    }
+
+    // Requirement: 时钟 - TI$ 设置和回绕
+    #[test]
+    fn test_time_string() {
+        let mut clock = JiffyClock::new(ClockMode::Deterministic { jiffies_per_statement: 1 });
+        clock.set_time_string("235959").unwrap();
+        assert_eq!(clock.jiffies(), JIFFIES_PER_DAY - 60);
+        clock.wait(60);
+        assert_eq!(clock.time_string(), "000000");
+
+        assert!(clock.set_time_string("240000").is_err());
+        assert!(clock.set_time_string("1234").is_err());
+        assert!(clock.set_time_string("12AB56").is_err());
+    }
+
+    // Requirement: 时钟 - 模式名称
+    #[test]
+    fn test_mode_from_name() {
+        assert_eq!(ClockMode::from_name("real"), Some(ClockMode::RealTime));
+        assert_eq!(
+            ClockMode::from_name("deterministic"),
+            Some(ClockMode::Deterministic { jiffies_per_statement: 1 })
+        );
+        assert_eq!(
+            ClockMode::from_name("deterministic:5"),
+            Some(ClockMode::Deterministic { jiffies_per_statement: 5 })
+        );
+        assert_eq!(ClockMode::from_name("deterministicx"), None);
+        assert_eq!(ClockMode::from_name("fast"), None);
+    }
+}
@@ -0,0 +1,747 @@
+//! BASIC 到 Rust 的编译器
+//!
+//! `basic compile prog.bas -o prog.rs` 把程序翻译为一个独立的 Rust 源文件，
+//! 用 `rustc -O prog.rs` 即可得到可执行文件，不依赖任何 crate。
+//!
+//! 生成的程序是按行号展开的状态机：每行的每条语句是一个状态，
+//! 行号映射到该行第一个状态，GOTO / GOSUB / ON / 计算 GOTO 在运行时查表跳转；
+//! GOSUB 和 FOR 压入"下一个状态"，与解释器的 (行号, 语句序号) 位置一一对应。
+//! IF 的 THEN 部分内联在 IF 所在的状态里。
+//!
+//! 变量、数组和 DEF FN 在编译时分配槽位；表达式保持动态类型，
+//! 按解释器的求值顺序和错误检查生成代码，运行时支持见 `compiler/runtime.rs`。
+//!
+//! 依赖解释器外部环境的语句（LOAD/SAVE/CHAIN、POKE/PEEK/WAIT、GET、PRINT USING 等）
+//! 不能编译，编译时报错。
+
+use crate::ast::*;
+use crate::error::{BasicError, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[cfg(test)]
+mod runtime;
+
+/// 嵌入到生成代码中的运行时支持模块
+const RUNTIME: &str = include_str!("compiler/runtime.rs");
+
+/// 编译程序文件，返回生成的 Rust 源代码
+pub fn compile_file(filename: &str) -> Result<String> {
+    let lines: Vec<ProgramLine> = crate::Executor::read_program_file(filename)?
+        .into_iter()
+        .map(|(line, _)| line)
+        .collect();
+    compile_program(&lines, filename)
+}
+
+/// 编译程序行，返回生成的 Rust 源代码
+///
+/// 同号的行以后出现的为准（与逐行输入程序相同）
+pub fn compile_program(lines: &[ProgramLine], source_name: &str) -> Result<String> {
+    let program: BTreeMap<u16, &ProgramLine> = lines.iter().map(|line| (line.line_number, line)).collect();
+    let mut compiler = Compiler::default();
+    for line in program.values() {
+        compiler.compile_line(line)?;
+    }
+    Ok(compiler.finish(source_name))
+}
+
+/// 一个 DEF FN 定义
+struct Definition {
+    param: usize,
+    body: String,
+}
+
+#[derive(Default)]
+struct Compiler {
+    /// 简单变量名（大写），下标即槽位
+    variables: Vec<String>,
+    arrays: Vec<String>,
+    functions: Vec<String>,
+    definitions: Vec<Definition>,
+    /// 行号和第一个状态
+    lines: Vec<(u16, usize)>,
+    /// 每个状态的行号和代码
+    states: Vec<(u16, String)>,
+    data: Vec<DataValue>,
+    /// 含 DATA 的行：行号和第一个值的下标
+    data_lines: Vec<(u16, usize)>,
+    /// 正在生成的行号
+    line: u16,
+}
+
+impl Compiler {
+    fn compile_line(&mut self, line: &ProgramLine) -> Result<()> {
+        self.line = line.line_number;
+        self.lines.push((line.line_number, self.states.len()));
+        for stmt in &line.statements {
+            // DATA 按程序顺序收集（只收集行内的顶层语句，与解释器相同）
+            if let StatementKind::Data { values } = &stmt.kind {
+                if self.data_lines.last().map(|&(line, _)| line) != Some(line.line_number) {
+                    self.data_lines.push((line.line_number, self.data.len()));
+                }
+                self.data.extend(values.iter().cloned());
+            }
+            let mut code = format!("// {}: {}\n", line.line_number, crate::Executor::serialize_statement(stmt));
+            let resume = self.states.len() + 1;
+            self.statement(stmt, resume, &mut code)?;
+            self.states.push((line.line_number, code));
+        }
+        Ok(())
+    }
+
+    /// 生成一条语句；resume 是下一个状态（GOSUB 返回和 FOR 循环体的位置）
+    fn statement(&mut self, stmt: &Statement, resume: usize, code: &mut String) -> Result<()> {
+        match &stmt.kind {
+            StatementKind::Let { target, value } => {
+                let value = self.expr(value)?;
+                self.assign(target, value, code)?;
+            }
+            StatementKind::Print { items } => self.print(items, code)?,
+            StatementKind::If { condition, then_part } => {
+                let condition = self.expr(condition)?;
+                writeln!(code, "if rt::truth({})? {{", condition).unwrap();
+                match then_part.as_ref() {
+                    ThenPart::LineNumber(line) => writeln!(code, "m.goto({})?;", line).unwrap(),
+                    ThenPart::Statement(stmt) => self.statement(stmt, resume, code)?,
+                    ThenPart::Statements(stmts) => {
+                        for stmt in stmts {
+                            self.statement(stmt, resume, code)?;
+                        }
+                    }
+                }
+                code.push_str("}\n");
+            }
+            StatementKind::Goto { line_number } => {
+                let target = self.expr(line_number)?;
+                writeln!(code, "m.goto_value({})?;", target).unwrap();
+            }
+            StatementKind::Gosub { line_number } => {
+                // 解释器先压栈再求值行号
+                writeln!(code, "m.push_gosub({})?;", resume).unwrap();
+                let target = self.expr(line_number)?;
+                writeln!(code, "m.goto_value({})?;", target).unwrap();
+            }
+            StatementKind::Return => code.push_str("m.ret()?;\n"),
+            StatementKind::On { expr, targets, is_gosub } => {
+                let index = self.expr(expr)?;
+                let resume = if *is_gosub { format!("Some({})", resume) } else { "None".to_string() };
+                writeln!(code, "m.on({}, &{:?}, {})?;", index, targets, resume).unwrap();
+            }
+            StatementKind::For { var, start, end, step } => {
+                let slot = self.variable(var);
+                let start = self.expr(start)?;
+                let end = self.expr(end)?;
+                let step = match step {
+                    Some(step) => self.expr(step)?,
+                    None => "Value::Number(1.0)".to_string(),
+                };
+                writeln!(code, "let start = {};", start).unwrap();
+                writeln!(code, "let end = {};", end).unwrap();
+                writeln!(code, "let step = {};", step).unwrap();
+                writeln!(code, "m.for_loop({:?}, {}, start, end, step, {})?;", var, slot, resume).unwrap();
+            }
+            StatementKind::Next { var } => writeln!(code, "m.next({:?})?;", var.as_deref()).unwrap(),
+            StatementKind::Input { prompt, variables } => {
+                let mut slots = Vec::new();
+                for target in variables {
+                    match target {
+                        AssignTarget::Variable(name) => slots.push(self.variable(name)),
+                        AssignTarget::ArrayElement { .. } => {
+                            writeln!(code, "{}?;", syntax_error("INPUT does not support array elements")).unwrap();
+                            return Ok(());
+                        }
+                    }
+                }
+                writeln!(code, "m.input({:?}, &{:?})?;", prompt.as_deref(), slots).unwrap();
+            }
+            StatementKind::Dim { arrays } => {
+                for array in arrays {
+                    let slot = self.array(&array.name);
+                    let mut dimensions = Vec::new();
+                    for dimension in &array.dimensions {
+                        dimensions.push(format!("rt::subscript({})?", self.expr(dimension)?));
+                    }
+                    writeln!(code, "let dimensions = vec![{}];", dimensions.join(", ")).unwrap();
+                    writeln!(code, "m.dim({}, dimensions)?;", slot).unwrap();
+                }
+            }
+            StatementKind::Read { variables } => {
+                for target in variables {
+                    match target {
+                        AssignTarget::Variable(name) => {
+                            let slot = self.variable(name);
+                            writeln!(code, "m.read({})?;", slot).unwrap();
+                        }
+                        AssignTarget::ArrayElement { .. } => {
+                            writeln!(code, "{}?;", syntax_error("READ does not support array elements")).unwrap();
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            StatementKind::Restore { line_number: None } => code.push_str("m.restore();\n"),
+            StatementKind::Restore { line_number: Some(line) } => writeln!(code, "m.restore_to({});", line).unwrap(),
+            StatementKind::DefFn { name, param, body } => {
+                let function = self.function(name);
+                let param = self.variable(param);
+                let body = self.expr(body)?;
+                self.definitions.push(Definition { param, body });
+                writeln!(code, "m.define({}, {});", function, self.definitions.len() - 1).unwrap();
+            }
+            StatementKind::End => code.push_str("m.end();\n"),
+            StatementKind::Stop => code.push_str("m.stop();\n"),
+            StatementKind::Clear => code.push_str("m.clear();\n"),
+            StatementKind::Rem { .. } | StatementKind::Data { .. } | StatementKind::Null => {}
+            StatementKind::Poke { .. } => return Err(self.unsupported("POKE")),
+            StatementKind::Wait { .. } => return Err(self.unsupported("WAIT")),
+            StatementKind::Get { .. } => return Err(self.unsupported("GET")),
+            StatementKind::New => return Err(self.unsupported("NEW")),
+            StatementKind::List { .. } => return Err(self.unsupported("LIST")),
+            StatementKind::Run { .. } => return Err(self.unsupported("RUN")),
+            StatementKind::Cont => return Err(self.unsupported("CONT")),
+            StatementKind::Load { .. } => return Err(self.unsupported("LOAD")),
+            StatementKind::Save { .. } => return Err(self.unsupported("SAVE")),
+            StatementKind::Merge { .. } => return Err(self.unsupported("MERGE")),
+            StatementKind::Chain { .. } => return Err(self.unsupported("CHAIN")),
+            StatementKind::Common { .. } => return Err(self.unsupported("COMMON")),
+        }
+        Ok(())
+    }
+
+    /// 赋值：先求值右边，再按顺序求值下标
+    fn assign(&mut self, target: &AssignTarget, value: String, code: &mut String) -> Result<()> {
+        match target {
+            AssignTarget::Variable(name) => {
+                let slot = self.variable(name);
+                writeln!(code, "let value = {};", value).unwrap();
+                writeln!(code, "m.set_var({}, value)?;", slot).unwrap();
+            }
+            AssignTarget::ArrayElement { name, indices } => {
+                let slot = self.array(name);
+                writeln!(code, "let value = {};", value).unwrap();
+                let mut subscripts = Vec::new();
+                for index in indices {
+                    subscripts.push(format!("rt::subscript({})?", self.expr(index)?));
+                }
+                writeln!(code, "let indices = [{}];", subscripts.join(", ")).unwrap();
+                writeln!(code, "m.set_elem({}, &indices, value)?;", slot).unwrap();
+            }
+        }
+        Ok(())
+    }
+
+    fn print(&mut self, items: &[PrintItem], code: &mut String) -> Result<()> {
+        for item in items {
+            match item {
+                PrintItem::Expr(expr) => {
+                    let value = self.expr(expr)?;
+                    writeln!(code, "let value = {};", value).unwrap();
+                    code.push_str("m.print(&value);\n");
+                }
+                PrintItem::Tab(expr) => {
+                    let column = self.expr(expr)?;
+                    writeln!(code, "let column = {};", column).unwrap();
+                    code.push_str("m.tab(column)?;\n");
+                }
+                PrintItem::Spc(expr) => {
+                    let count = self.expr(expr)?;
+                    writeln!(code, "let count = {};", count).unwrap();
+                    code.push_str("m.spc(count)?;\n");
+                }
+                PrintItem::Comma => code.push_str("m.comma();\n"),
+                PrintItem::Semicolon => {}
+                PrintItem::Using { .. } => return Err(self.unsupported("PRINT USING")),
+            }
+        }
+        if !matches!(items.last(), Some(PrintItem::Comma | PrintItem::Semicolon)) {
+            code.push_str("m.newline();\n");
+        }
+        Ok(())
+    }
+
+    /// 生成求值表达式的 Rust 表达式（类型为 Value，错误用 ? 传播）
+    fn expr(&mut self, expr: &Expr) -> Result<String> {
+        Ok(match &expr.kind {
+            ExprKind::Number(n) => format!("Value::Number({})", number_literal(*n)),
+            ExprKind::String(s) => format!("Value::String({:?}.to_string())", s),
+            ExprKind::Variable(name) => format!("m.var({})", self.variable(name)),
+            ExprKind::ArrayAccess { name, indices } => {
+                let slot = self.array(name);
+                let mut code = String::from("{ ");
+                for (i, index) in indices.iter().enumerate() {
+                    write!(code, "let i{} = rt::subscript({})?; ", i, self.expr(index)?).unwrap();
+                }
+                let names: Vec<String> = (0..indices.len()).map(|i| format!("i{}", i)).collect();
+                write!(code, "m.elem({}, &[{}])? }}", slot, names.join(", ")).unwrap();
+                code
+            }
+            ExprKind::BinaryOp { left, op, right } => {
+                let function = match op {
+                    BinaryOperator::Add => "add",
+                    BinaryOperator::Subtract => "sub",
+                    BinaryOperator::Multiply => "mul",
+                    BinaryOperator::Divide => "div",
+                    BinaryOperator::Power => "pow",
+                    BinaryOperator::Equal => "eq",
+                    BinaryOperator::NotEqual => "ne",
+                    BinaryOperator::Less => "lt",
+                    BinaryOperator::Greater => "gt",
+                    BinaryOperator::LessEqual => "le",
+                    BinaryOperator::GreaterEqual => "ge",
+                    BinaryOperator::And => "and",
+                    BinaryOperator::Or => "or",
+                };
+                let left = self.expr(left)?;
+                let right = self.expr(right)?;
+                format!("{{ let l = {}; let r = {}; rt::{}(l, r)? }}", left, right, function)
+            }
+            ExprKind::UnaryOp { op, operand } => {
+                let function = match op {
+                    UnaryOperator::Minus => "neg",
+                    UnaryOperator::Not => "not",
+                };
+                format!("rt::{}({})?", function, self.expr(operand)?)
+            }
+            ExprKind::FunctionCall { name, args } => self.call(name, args)?,
+        })
+    }
+
+    /// 函数调用：参数按解释器的顺序逐个求值并转换类型，参数个数不对时运行到才报错
+    fn call(&mut self, name: &str, args: &[Expr]) -> Result<String> {
+        if name.starts_with("FN") && name.len() > 2 {
+            let function_name = name[2..].trim();
+            let function = self.function(function_name);
+            if args.len() != 1 {
+                let message = format!("FN {} requires 1 argument", function_name);
+                return Ok(format!("{{ m.function({})?; {}? }}", function, syntax_error(&message)));
+            }
+            let arg = self.expr(&args[0])?;
+            return Ok(format!("{{ let def = m.function({})?; let arg = {}; call(m, def, arg)? }}", function, arg));
+        }
+
+        let upper = name.to_uppercase();
+        // 参数类型：N 数值，S 字符串，A 任意（只求值）
+        let (kinds, function): (&[&str], &str) = match upper.as_str() {
+            "SGN" => (&["N"], "sgn"),
+            "INT" => (&["N"], "int"),
+            "ABS" => (&["N"], "abs"),
+            "SQR" => (&["N"], "sqr"),
+            "SIN" => (&["N"], "sin"),
+            "COS" => (&["N"], "cos"),
+            "TAN" => (&["N"], "tan"),
+            "ATN" => (&["N"], "atn"),
+            "LOG" => (&["N"], "log"),
+            "EXP" => (&["N"], "exp"),
+            "LEN" => (&["S"], "len"),
+            "ASC" => (&["S"], "asc"),
+            "CHR$" => (&["N"], "chr"),
+            "STR$" => (&["N"], "str"),
+            "VAL" => (&["S"], "val"),
+            "LEFT$" => (&["S", "N"], "left"),
+            "RIGHT$" => (&["S", "N"], "right"),
+            "SPACE$" => (&["N"], "space"),
+            "RND" => {
+                // RND 不检查参数个数，没有参数时等于 RND(1)
+                return Ok(match args.first() {
+                    Some(arg) => format!("{{ ({}).as_number()?; m.rnd()? }}", self.expr(arg)?),
+                    None => "m.rnd()?".to_string(),
+                });
+            }
+            "MID$" => {
+                if args.len() < 2 || args.len() > 3 {
+                    return Ok(format!("{}?", syntax_error("MID$ requires 2 or 3 arguments")));
+                }
+                let s = self.expr(&args[0])?;
+                let start = self.expr(&args[1])?;
+                let length = match args.get(2) {
+                    Some(length) => format!("Some(({}).as_number()?)", self.expr(length)?),
+                    None => "None".to_string(),
+                };
+                return Ok(format!(
+                    "{{ let s = ({}).as_string()?; let start = ({}).as_number()?; let length = {}; rt::mid(s, start, length)? }}",
+                    s, start, length
+                ));
+            }
+            "INSTR" => {
+                if args.len() < 2 || args.len() > 3 {
+                    return Ok(format!("{}?", syntax_error("INSTR requires 2 or 3 arguments")));
+                }
+                let args = args.iter().map(|arg| self.expr(arg)).collect::<Result<Vec<_>>>()?;
+                let (start, s1, s2) = match args.as_slice() {
+                    [start, s1, s2] => (format!("({}).as_number()?", start), s1, s2),
+                    [s1, s2] => ("1.0".to_string(), s1, s2),
+                    _ => unreachable!(),
+                };
+                return Ok(format!(
+                    "{{ let start = {}; let s1 = ({}).as_string()?; let s2 = ({}).as_string()?; rt::instr(start, s1, s2)? }}",
+                    start, s1, s2
+                ));
+            }
+            "POS" | "FRE" => {
+                if args.len() != 1 {
+                    return Ok(format!("{}?", syntax_error(&format!("{} requires 1 argument", upper))));
+                }
+                let call = if upper == "POS" { "m.pos()?" } else { "m.fre()?" };
+                return Ok(format!("{{ {}; {} }}", self.expr(&args[0])?, call));
+            }
+            "PEEK" | "USR" => return Err(self.unsupported(&upper)),
+            _ => return Ok(format!("{}?", syntax_error(&format!("Unknown function: {}", name)))),
+        };
+
+        if args.len() != kinds.len() {
+            let plural = if kinds.len() == 1 { "argument" } else { "arguments" };
+            let message = format!("{} requires {} {}", upper, kinds.len(), plural);
+            return Ok(format!("{}?", syntax_error(&message)));
+        }
+        let mut code = String::from("{ ");
+        for (i, (arg, kind)) in args.iter().zip(kinds).enumerate() {
+            let arg = self.expr(arg)?;
+            match *kind {
+                "S" => write!(code, "let a{} = ({}).as_string()?; ", i, arg).unwrap(),
+                _ => write!(code, "let a{} = ({}).as_number()?; ", i, arg).unwrap(),
+            }
+        }
+        let names: Vec<String> = (0..args.len()).map(|i| format!("a{}", i)).collect();
+        write!(code, "rt::{}({})? }}", function, names.join(", ")).unwrap();
+        Ok(code)
+    }
+
+    fn variable(&mut self, name: &str) -> usize {
+        slot(&mut self.variables, name)
+    }
+
+    fn array(&mut self, name: &str) -> usize {
+        slot(&mut self.arrays, name)
+    }
+
+    fn function(&mut self, name: &str) -> usize {
+        slot(&mut self.functions, name)
+    }
+
+    fn unsupported(&self, what: &str) -> BasicError {
+        BasicError::SyntaxError(format!("{} cannot be compiled (line {})", what, self.line))
+    }
+
+    /// 输出完整的 Rust 源文件
+    fn finish(self, source_name: &str) -> String {
+        let mut out = String::new();
+        writeln!(out, "// Generated by `basic compile` from {}. Do not edit.", source_name).unwrap();
+        out.push_str("#![allow(dead_code, unreachable_code, unused_variables, unused_braces)]\n\n");
+        out.push_str("use rt::{Machine, Value};\n\n");
+
+        out.push_str("static PROGRAM: rt::Program = rt::Program {\n");
+        writeln!(out, "    lines: &{:?},", self.lines).unwrap();
+        let state_lines: Vec<u16> = self.states.iter().map(|(line, _)| *line).collect();
+        writeln!(out, "    state_lines: &{:?},", state_lines).unwrap();
+        out.push_str("    data: &[\n");
+        for value in &self.data {
+            match value {
+                DataValue::Number(n) => writeln!(out, "        rt::Data::Number({}),", number_literal(*n)).unwrap(),
+                DataValue::String(s) => writeln!(out, "        rt::Data::String({:?}),", s).unwrap(),
+            }
+        }
+        out.push_str("    ],\n");
+        writeln!(out, "    data_lines: &{:?},", self.data_lines).unwrap();
+        writeln!(out, "    variables: &{:?},", self.variables).unwrap();
+        writeln!(out, "    arrays: &{:?},", self.arrays).unwrap();
+        writeln!(out, "    functions: &{:?},", self.functions).unwrap();
+        out.push_str("};\n\n");
+
+        out.push_str("fn main() {\n    rt::run(&PROGRAM, step);\n}\n\n");
+
+        out.push_str("fn step(m: &mut Machine, pc: usize) -> rt::Result<()> {\n    match pc {\n");
+        for (index, (_, code)) in self.states.iter().enumerate() {
+            writeln!(out, "        {} => {{", index).unwrap();
+            indent(&mut out, code, 12);
+            out.push_str("        }\n");
+        }
+        out.push_str("        _ => unreachable!(),\n    }\n    Ok(())\n}\n\n");
+
+        out.push_str("/// DEF FN definitions: the parameter is saved and restored around the body.\n");
+        out.push_str("fn call(m: &mut Machine, def: usize, arg: Value) -> rt::Result<Value> {\n    match def {\n");
+        for (index, definition) in self.definitions.iter().enumerate() {
+            writeln!(out, "        {} => {{", index).unwrap();
+            let body = format!(
+                "let old = m.var({param});\nm.set_var({param}, arg)?;\nlet result = {body};\nlet _ = m.set_var({param}, old);\nOk(result)\n",
+                param = definition.param,
+                body = definition.body
+            );
+            indent(&mut out, &body, 12);
+            out.push_str("        }\n");
+        }
+        out.push_str("        _ => unreachable!(),\n    }\n}\n\n");
+
+        out.push_str("mod rt {\n");
+        indent(&mut out, RUNTIME, 4);
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// 名称（不区分大小写）对应的槽位，没有则新分配
+fn slot(names: &mut Vec<String>, name: &str) -> usize {
+    let name = name.to_uppercase();
+    match names.iter().position(|existing| *existing == name) {
+        Some(index) => index,
+        None => {
+            names.push(name);
+            names.len() - 1
+        }
+    }
+}
+
+/// 运行到时才报告的语法错误
+fn syntax_error(message: &str) -> String {
+    format!("rt::fail(rt::BasicError::SyntaxError({:?}.to_string()))", message)
+}
+
+/// f64 的 Rust 字面量（保证精确还原）
+fn number_literal(n: f64) -> String {
+    if n.is_nan() {
+        "f64::NAN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "f64::INFINITY" } else { "f64::NEG_INFINITY" }.to_string()
+    } else {
+        format!("{:?}", n)
+    }
+}
+
+/// 按缩进追加多行代码（空行不缩进）
+fn indent(out: &mut String, code: &str, width: usize) {
+    for line in code.lines() {
+        if !line.is_empty() {
+            out.push_str(&" ".repeat(width));
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Executor;
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::io::Write as _;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+
+    /// 每个测试独立的临时目录
+    fn work_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("basic_compile_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// 用解释器运行程序，返回输出和运行时错误
+    fn interpret(dir: &Path, source: &str, input: &[&str]) -> (String, Option<BasicError>) {
+        let path = dir.join("prog.bas");
+        fs::write(&path, source).unwrap();
+        let mut exec = Executor::new();
+        exec.execute_statement(&StatementKind::Load { filename: path.to_string_lossy().into_owned() }.into()).unwrap();
+        let mut input: VecDeque<String> = input.iter().map(|line| line.to_string()).collect();
+        exec.set_input_callback(move |_| input.pop_front());
+        exec.runtime_mut().start_execution(None).unwrap();
+        while let Some(stmt) = exec.runtime_mut().get_next_statement() {
+            if let Err(e) = exec.execute_statement(&stmt) {
+                return (exec.get_output(), Some(e));
+            }
+            if exec.runtime().is_stopped() {
+                break;
+            }
+        }
+        (exec.get_output(), None)
+    }
+
+    /// 编译程序，用 rustc 构建并运行，返回标准输出和标准错误
+    fn compile_and_run(dir: &Path, source: &str, input: &[&str]) -> (String, String) {
+        let bas = dir.join("prog.bas");
+        fs::write(&bas, source).unwrap();
+        let rust = compile_file(&bas.to_string_lossy()).unwrap();
+        let rs = dir.join("prog.rs");
+        fs::write(&rs, rust).unwrap();
+
+        let exe = dir.join("prog");
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let build = Command::new(rustc)
+            .args(["-O", "-o"])
+            .arg(&exe)
+            .arg(&rs)
+            .output()
+            .expect("failed to run rustc");
+        assert!(build.status.success(), "rustc failed:\n{}", String::from_utf8_lossy(&build.stderr));
+
+        let mut child = Command::new(&exe)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        for line in input {
+            writeln!(stdin, "{}", line).unwrap();
+        }
+        drop(stdin);
+        let output = child.wait_with_output().unwrap();
+        (
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    }
+
+    /// 编译后的程序与解释器输出相同，返回该输出
+    fn assert_conforms(name: &str, source: &str, input: &[&str]) -> String {
+        let dir = work_dir(name);
+        let (expected, error) = interpret(&dir, source, input);
+        assert_eq!(error, None);
+        let (stdout, stderr) = compile_and_run(&dir, source, input);
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(stderr, "");
+        assert_eq!(stdout, expected);
+        stdout
+    }
+
+    // Requirement: 编译器 - 计算 GOTO 和 ON...GOTO/GOSUB
+    #[test]
+    fn test_computed_goto_and_on() {
+        let source = "\
+10 FOR I=0 TO 4
+20 ON I GOSUB 100,200,300: PRINT \"AFTER\";I
+30 NEXT I
+40 T=2: ON T GOTO 50,60
+50 PRINT \"WRONG\"
+60 G=70+T*5: GOTO G
+70 PRINT \"WRONG\"
+80 PRINT \"COMPUTED\": END
+100 PRINT \"ONE\": RETURN
+200 PRINT \"TWO\": RETURN
+300 PRINT \"THREE\": RETURN
+";
+        let output = assert_conforms("on", source, &[]);
+        // ON...GOSUB 返回到同一行的下一条语句；超出范围时直接继续
+        assert_eq!(
+            output,
+            "AFTER 0 \nONE\nAFTER 1 \nTWO\nAFTER 2 \nTHREE\nAFTER 3 \nAFTER 4 \nCOMPUTED\n"
+        );
+    }
+
+    // Requirement: 编译器 - DATA/READ/RESTORE
+    #[test]
+    fn test_data_read() {
+        let source = "\
+10 READ A, B$, C$
+20 PRINT A; B$; C$
+30 DATA 1.5, \"HELLO, WORLD\"
+40 READ N: IF N < 0 THEN 70
+50 S = S + N
+60 GOTO 40
+70 PRINT \"SUM\"; S
+80 RESTORE: READ X: PRINT X
+85 RESTORE 86: READ Y: PRINT Y: RESTORE 95
+90 DATA 2.25, 10, 20, -1
+95 DATA 7: DATA 8: READ Y, Z: PRINT Y; Z
+";
+        assert_conforms("data", source, &[]);
+    }
+
+    // Requirement: 编译器 - DEF FN
+    #[test]
+    fn test_def_fn() {
+        let source = "\
+10 DEF FN SQ(X) = X * X
+20 DEF FN HY(A) = SQR(FN SQ(A) + FN SQ(B))
+30 X = 5: B = 4
+40 PRINT FN SQ(3); FN HY(3); X
+50 DEF FN SQ(X) = X + 100
+60 PRINT FN SQ(1)
+70 DEF FN L$(S$) = LEFT$(S$, 2) + \"!\"
+80 PRINT FN L$(\"HELLO\")
+";
+        assert_conforms("deffn", source, &[]);
+    }
+
+    // Requirement: 编译器 - INPUT
+    #[test]
+    fn test_input() {
+        let source = "\
+10 INPUT \"NAME\"; N$
+20 INPUT A, B
+30 PRINT N$; A + B
+40 INPUT Q$, R
+50 PRINT Q$; R
+60 INPUT S$
+70 PRINT S$
+";
+        assert_conforms("input", source, &["BOB", "3,4", "\"X, Y\",2,9", "ONE,TWO"]);
+    }
+
+    // Requirement: 编译器 - 循环、数组、PRINT 格式和字符串函数
+    #[test]
+    fn test_loops_arrays_and_print() {
+        let source = "\
+10 DIM M(3,3)
+20 FOR I=0 TO 3: FOR J=3 TO 0 STEP -1
+30 M(I,J) = I*10+J
+40 NEXT J, I
+50 PRINT M(2,1), M(3,0); -M(1,1); 10/4
+60 FOR K=1 TO 5: Z(K) = K*K: NEXT
+70 PRINT Z(4); Z(10); LEN(\"ABC\"); ASC(\"A\"); CHR$(66)
+80 PRINT MID$(\"BASIC\",2,3); RIGHT$(\"BASIC\",2); STR$(-7); VAL(\" 12 \")
+90 PRINT TAB(5); \"X\"; SPC(3); \"Y\"; POS(0)
+100 PRINT 1=1; 1<>1; \"A\"<\"B\"; NOT 0; 6 AND 3; 4 OR 1; 2^10
+110 IF 0 THEN PRINT \"NO\": PRINT \"NEVER\"
+120 PRINT \"DONE\"
+";
+        assert_conforms("loops", source, &[]);
+    }
+
+    // Requirement: 编译器 - 运行时错误的报告与解释器相同
+    #[test]
+    fn test_runtime_error() {
+        let source = "10 PRINT \"START\"\n20 A = 0\n30 PRINT 1 / A\n40 PRINT \"NEVER\"\n";
+        let dir = work_dir("error");
+        let (expected, error) = interpret(&dir, source, &[]);
+        assert_eq!(error, Some(BasicError::DivisionByZero));
+        let (stdout, stderr) = compile_and_run(&dir, source, &[]);
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(stdout, expected);
+        assert_eq!(stderr, "?DIVISION BY ZERO IN 30\n");
+    }
+
+    // Requirement: 编译器 - 隐式数组、FRE 和 ?BAD SUBSCRIPT 与解释器相同
+    #[test]
+    fn test_implicit_arrays_and_fre() {
+        let source = "\
+10 PRINT FRE(0)
+20 A(3) = 7: PRINT A(3); A(10); FRE(0)
+30 PRINT B$(1,2) + \"-\"; FRE(0)
+40 PRINT A(1,1)
+";
+        let dir = work_dir("arrays");
+        let (expected, error) = interpret(&dir, source, &[]);
+        assert!(matches!(error, Some(BasicError::SubscriptOutOfRange(_))), "{:?}", error);
+        assert_eq!(expected, " 32767 \n 7  0  32705 \n- 32333 \n");
+        let (stdout, stderr) = compile_and_run(&dir, source, &[]);
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(stdout, expected);
+        assert_eq!(stderr, "?BAD SUBSCRIPT IN 40\n");
+    }
+
+    // Requirement: 编译器 - 依赖解释器环境的语句不能编译
+    #[test]
+    fn test_unsupported_statements() {
+        use crate::parser::Parser;
+        use crate::tokenizer::Tokenizer;
+
+        for (source, what) in [("10 POKE 1024, 1", "POKE"), ("20 GET K$", "GET"), ("30 LOAD \"X\"", "LOAD")] {
+            let tokens = Tokenizer::new(source).tokenize_line().unwrap();
+            let line = Parser::new(tokens).parse_line().unwrap().unwrap();
+            match compile_program(&[line], "test.bas") {
+                Err(BasicError::SyntaxError(message)) => assert!(message.starts_with(what), "{}", message),
+                other => panic!("expected an error, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+}
@@ -0,0 +1,867 @@
+//! 编译后程序的运行时支持
+//!
+//! `basic compile` 把这个文件原样嵌入生成的 Rust 源文件（作为 `mod rt`），
+//! 生成的状态机通过这里的 `Machine` 读写变量、输出、跳转和读取 DATA。
+//! 这里的语义逐条对应解释器（executor / variables / runtime），
+//! 包括数值格式、PRINT 分区、INPUT 解析和错误消息，保证编译前后输出一致。
+//!
+//! 生成的程序不依赖任何 crate：RND 使用内置的伪随机数发生器，
+//! CHR$/ASC 使用 ASCII 字符集，TI/TI$ 按真实时间计数。
+
+#![allow(dead_code)]
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// 调用栈深度上限（与解释器相同）
+const MAX_STACK_DEPTH: usize = 100;
+
+/// 每秒的 jiffy 数
+const JIFFIES_PER_SECOND: u64 = 60;
+
+/// 一天的 jiffy 数（TI 和 TI$ 在此回绕）
+const JIFFIES_PER_DAY: u64 = 24 * 60 * 60 * JIFFIES_PER_SECOND;
+
+/// 运行时错误，显示文本与解释器报告的错误相同
+#[derive(Debug, Clone, PartialEq)]
+pub enum BasicError {
+    SyntaxError(String),
+    DivisionByZero,
+    TypeMismatch(String),
+    UndefinedLine(u16),
+    SubscriptOutOfRange(String),
+    RedimensionedArray(String),
+    OutOfData,
+    StackOverflow,
+    IllegalQuantity(String),
+    ReturnWithoutGosub,
+    NextWithoutFor(String),
+}
+
+impl fmt::Display for BasicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BasicError::SyntaxError(_) => write!(f, "SYNTAX ERROR"),
+            BasicError::DivisionByZero => write!(f, "DIVISION BY ZERO"),
+            BasicError::TypeMismatch(_) => write!(f, "TYPE MISMATCH"),
+            BasicError::UndefinedLine(line) => write!(f, "UNDEFINED LINE {}", line),
+            BasicError::SubscriptOutOfRange(_) => write!(f, "BAD SUBSCRIPT"),
+            BasicError::RedimensionedArray(var) => write!(f, "?REDIM'D ARRAY ERROR: {}", var),
+            BasicError::OutOfData => write!(f, "OUT OF DATA"),
+            BasicError::StackOverflow => write!(f, "?STACK OVERFLOW ERROR"),
+            BasicError::IllegalQuantity(msg) => write!(f, "?ILLEGAL QUANTITY: {}", msg),
+            BasicError::ReturnWithoutGosub => write!(f, "RETURN WITHOUT GOSUB"),
+            BasicError::NextWithoutFor(_) => write!(f, "NEXT WITHOUT FOR"),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, BasicError>;
+
+/// 返回错误（用于只在运行到时才报告的错误）
+pub fn fail(error: BasicError) -> Result<Value> {
+    Err(error)
+}
+
+/// 变量值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    String(String),
+}
+
+impl Value {
+    pub fn as_number(&self) -> Result<f64> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            Value::String(_) => Err(BasicError::TypeMismatch("Expected number, got string".to_string())),
+        }
+    }
+
+    pub fn as_string(&self) -> Result<String> {
+        match self {
+            Value::String(s) => Ok(s.clone()),
+            Value::Number(_) => Err(BasicError::TypeMismatch("Expected string, got number".to_string())),
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Value::Number(_))
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, Value::String(_))
+    }
+
+    fn default_for(name: &str) -> Value {
+        if name.ends_with('$') {
+            Value::String(String::new())
+        } else {
+            Value::Number(0.0)
+        }
+    }
+}
+
+/// DATA 值
+#[derive(Debug, Clone, Copy)]
+pub enum Data {
+    Number(f64),
+    String(&'static str),
+}
+
+/// 编译器生成的程序描述
+pub struct Program {
+    /// 行号和该行第一个状态的编号（按行号排序）
+    pub lines: &'static [(u16, usize)],
+    /// 每个状态所在的行号
+    pub state_lines: &'static [u16],
+    /// 按程序顺序收集的 DATA 值
+    pub data: &'static [Data],
+    /// 含 DATA 的行：行号和该行第一个值在 data 中的下标
+    pub data_lines: &'static [(u16, usize)],
+    /// 简单变量名（大写），下标即变量槽位
+    pub variables: &'static [&'static str],
+    /// 数组名（大写）
+    pub arrays: &'static [&'static str],
+    /// DEF FN 函数名（大写）
+    pub functions: &'static [&'static str],
+}
+
+/// 执行一个状态
+pub type Step = fn(&mut Machine, usize) -> Result<()>;
+
+/// 运行程序，出错时按解释器的格式报告错误并以状态码 1 退出
+pub fn run(program: &'static Program, step: Step) {
+    let mut machine = Machine::new(program);
+    let result = machine.run(step);
+    machine.flush();
+    if let Err(error) = result {
+        eprintln!("?{} IN {}", error, machine.line);
+        std::process::exit(1);
+    }
+}
+
+/// 数组（每一维下标从 0 到上界）
+#[derive(Debug, Clone)]
+struct Array {
+    dimensions: Vec<usize>,
+    data: Vec<Value>,
+    is_string: bool,
+}
+
+impl Array {
+    fn new(dimensions: Vec<usize>, is_string: bool) -> Self {
+        let total_size: usize = dimensions.iter().map(|&d| d + 1).product();
+        let default_value = if is_string { Value::String(String::new()) } else { Value::Number(0.0) };
+        Array { dimensions, data: vec![default_value; total_size], is_string }
+    }
+
+    /// 在原版内存中占用的字节数（与解释器的 FRE 相同）
+    fn memory_size(&self) -> usize {
+        let element = if self.is_string { 3 } else { 5 };
+        5 + 2 * self.dimensions.len() + element * self.data.len()
+    }
+
+    fn index(&self, indices: &[usize]) -> Result<usize> {
+        if indices.len() != self.dimensions.len() {
+            return Err(BasicError::SubscriptOutOfRange(format!(
+                "Array has {} dimensions, but {} indices provided",
+                self.dimensions.len(),
+                indices.len()
+            )));
+        }
+        for (i, &idx) in indices.iter().enumerate() {
+            if idx > self.dimensions[i] {
+                return Err(BasicError::SubscriptOutOfRange(format!(
+                    "Index {} out of range (max {})",
+                    idx, self.dimensions[i]
+                )));
+            }
+        }
+        let mut index = 0;
+        let mut multiplier = 1;
+        for i in (0..indices.len()).rev() {
+            index += indices[i] * multiplier;
+            multiplier *= self.dimensions[i] + 1;
+        }
+        Ok(index)
+    }
+}
+
+/// 调用栈帧：GOSUB 和 FOR 共用一个栈
+#[derive(Debug, Clone)]
+enum Frame {
+    Gosub { resume: usize },
+    For { name: &'static str, slot: usize, end: f64, step: f64, body: usize },
+}
+
+/// 变量槽位的种类：TI 和 TI$ 读写时钟
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Number,
+    String,
+    Ti,
+    TiString,
+}
+
+/// 编译后程序的执行状态
+pub struct Machine {
+    program: &'static Program,
+    /// 下一个要执行的状态
+    pc: usize,
+    /// 当前行号（用于错误消息和 STOP）
+    line: u16,
+    running: bool,
+    slots: Vec<Slot>,
+    variables: Vec<Value>,
+    arrays: Vec<Option<Array>>,
+    /// 每个函数当前生效的 DEF FN 定义编号
+    functions: Vec<Option<usize>>,
+    stack: Vec<Frame>,
+    data_pointer: usize,
+    column: usize,
+    out: io::BufWriter<io::Stdout>,
+    rng: u64,
+    clock_base: u64,
+    clock_started: Instant,
+}
+
+impl Machine {
+    pub fn new(program: &'static Program) -> Self {
+        let slots = program
+            .variables
+            .iter()
+            .map(|&name| match name {
+                "TI" => Slot::Ti,
+                "TI$" => Slot::TiString,
+                _ if name.ends_with('$') => Slot::String,
+                _ => Slot::Number,
+            })
+            .collect();
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        Machine {
+            program,
+            pc: 0,
+            line: program.state_lines.first().copied().unwrap_or(0),
+            running: true,
+            slots,
+            variables: program.variables.iter().map(|name| Value::default_for(name)).collect(),
+            arrays: vec![None; program.arrays.len()],
+            functions: vec![None; program.functions.len()],
+            stack: Vec::new(),
+            data_pointer: 0,
+            column: 0,
+            out: io::BufWriter::new(io::stdout()),
+            rng: seed | 1,
+            clock_base: 0,
+            clock_started: Instant::now(),
+        }
+    }
+
+    /// 依次执行状态，直到程序结束、END/STOP 或出错
+    pub fn run(&mut self, step: Step) -> Result<()> {
+        while self.running && self.pc < self.program.state_lines.len() {
+            let pc = self.pc;
+            self.pc = pc + 1;
+            self.line = self.program.state_lines[pc];
+            step(self, pc)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) {
+        self.out.flush().ok();
+    }
+
+    // ---- 变量 ----
+
+    pub fn var(&self, slot: usize) -> Value {
+        match self.slots[slot] {
+            Slot::Ti => Value::Number(self.jiffies() as f64),
+            Slot::TiString => Value::String(self.time_string()),
+            _ => self.variables[slot].clone(),
+        }
+    }
+
+    pub fn set_var(&mut self, slot: usize, value: Value) -> Result<()> {
+        let name = self.program.variables[slot];
+        let is_string_var = matches!(self.slots[slot], Slot::String | Slot::TiString);
+        if is_string_var && !value.is_string() {
+            return Err(BasicError::TypeMismatch(format!("Cannot assign number to string variable {}", name)));
+        }
+        if !is_string_var && !value.is_number() {
+            return Err(BasicError::TypeMismatch(format!("Cannot assign string to numeric variable {}", name)));
+        }
+        match self.slots[slot] {
+            Slot::Ti => Err(BasicError::SyntaxError("TI is read-only".to_string())),
+            Slot::TiString => self.set_time_string(&value.as_string()?),
+            _ => {
+                self.variables[slot] = value;
+                Ok(())
+            }
+        }
+    }
+
+    /// 引用数组：未声明时按这次的下标个数创建，每维上界 10
+    fn array(&mut self, slot: usize, subscripts: usize) -> &mut Array {
+        let name = self.program.arrays[slot];
+        self.arrays[slot].get_or_insert_with(|| Array::new(vec![10; subscripts], name.ends_with('$')))
+    }
+
+    /// 数组元素
+    pub fn elem(&mut self, slot: usize, indices: &[usize]) -> Result<Value> {
+        let array = self.array(slot, indices.len());
+        Ok(array.data[array.index(indices)?].clone())
+    }
+
+    pub fn set_elem(&mut self, slot: usize, indices: &[usize], value: Value) -> Result<()> {
+        let array = self.array(slot, indices.len());
+        if array.is_string && !value.is_string() {
+            return Err(BasicError::TypeMismatch("Cannot assign number to string array".to_string()));
+        }
+        if !array.is_string && !value.is_number() {
+            return Err(BasicError::TypeMismatch("Cannot assign string to numeric array".to_string()));
+        }
+        let index = array.index(indices)?;
+        array.data[index] = value;
+        Ok(())
+    }
+
+    pub fn dim(&mut self, slot: usize, dimensions: Vec<usize>) -> Result<()> {
+        let name = self.program.arrays[slot];
+        if self.arrays[slot].is_some() {
+            return Err(BasicError::RedimensionedArray(name.to_string()));
+        }
+        self.arrays[slot] = Some(Array::new(dimensions, name.ends_with('$')));
+        Ok(())
+    }
+
+    /// CLEAR：清空变量、数组和 DEF FN
+    pub fn clear(&mut self) {
+        for (value, name) in self.variables.iter_mut().zip(self.program.variables) {
+            *value = Value::default_for(name);
+        }
+        self.arrays.iter_mut().for_each(|array| *array = None);
+        self.functions.iter_mut().for_each(|function| *function = None);
+    }
+
+    // ---- DEF FN ----
+
+    pub fn define(&mut self, function: usize, definition: usize) {
+        self.functions[function] = Some(definition);
+    }
+
+    /// 函数当前的定义编号，没有执行过 DEF FN 时报错
+    pub fn function(&self, function: usize) -> Result<usize> {
+        self.functions[function].ok_or_else(|| {
+            BasicError::SyntaxError(format!("Undefined function: FN {}", self.program.functions[function]))
+        })
+    }
+
+    // ---- 流程控制 ----
+
+    pub fn goto(&mut self, line: u16) -> Result<()> {
+        let index = self
+            .program
+            .lines
+            .binary_search_by_key(&line, |&(number, _)| number)
+            .map_err(|_| BasicError::UndefinedLine(line))?;
+        self.pc = self.program.lines[index].1;
+        self.line = line;
+        Ok(())
+    }
+
+    /// GOTO/GOSUB 的行号表达式
+    pub fn goto_value(&mut self, line: Value) -> Result<()> {
+        let line = line.as_number()? as u16;
+        self.goto(line)
+    }
+
+    pub fn push_gosub(&mut self, resume: usize) -> Result<()> {
+        self.push(Frame::Gosub { resume })
+    }
+
+    pub fn ret(&mut self) -> Result<()> {
+        while let Some(frame) = self.stack.pop() {
+            if let Frame::Gosub { resume } = frame {
+                self.resume(resume);
+                return Ok(());
+            }
+        }
+        Err(BasicError::ReturnWithoutGosub)
+    }
+
+    /// ON expr GOTO/GOSUB；超出范围时继续执行下一条语句
+    pub fn on(&mut self, index: Value, targets: &[u16], resume: Option<usize>) -> Result<()> {
+        let index = index.as_number()? as i32;
+        if index < 1 || index as usize > targets.len() {
+            return Ok(());
+        }
+        if let Some(resume) = resume {
+            self.push_gosub(resume)?;
+        }
+        self.goto(targets[(index - 1) as usize])
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn for_loop(
+        &mut self,
+        name: &'static str,
+        slot: usize,
+        start: Value,
+        end: Value,
+        step: Value,
+        body: usize,
+    ) -> Result<()> {
+        let start = start.as_number()?;
+        let end = end.as_number()?;
+        let step = step.as_number()?;
+        if step == 0.0 {
+            return Err(BasicError::IllegalQuantity("FOR loop step cannot be zero".to_string()));
+        }
+        self.set_var(slot, Value::Number(start))?;
+        self.push(Frame::For { name, slot, end, step, body })
+    }
+
+    /// NEXT [var]：没有变量名时匹配最近的 FOR
+    pub fn next(&mut self, name: Option<&str>) -> Result<()> {
+        let found = self.stack.iter().rposition(|frame| match frame {
+            Frame::For { name: var, .. } => name.is_none_or(|name| name == *var),
+            Frame::Gosub { .. } => false,
+        });
+        let index = found.ok_or_else(|| BasicError::NextWithoutFor(name.unwrap_or_default().to_string()))?;
+        let frame = self.stack.remove(index);
+        if let Frame::For { slot, end, step, body, .. } = frame {
+            let value = self.var(slot).as_number()? + step;
+            let should_continue = if step > 0.0 { value <= end } else { value >= end };
+            if should_continue {
+                self.set_var(slot, Value::Number(value))?;
+                self.push(frame)?;
+                self.resume(body);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn end(&mut self) {
+        self.running = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.flush();
+        println!("?BREAK IN {}", self.line);
+    }
+
+    fn push(&mut self, frame: Frame) -> Result<()> {
+        if self.stack.len() >= MAX_STACK_DEPTH {
+            return Err(BasicError::StackOverflow);
+        }
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn resume(&mut self, pc: usize) {
+        self.pc = pc;
+        if let Some(&line) = self.program.state_lines.get(pc) {
+            self.line = line;
+        }
+    }
+
+    // ---- DATA ----
+
+    pub fn read(&mut self, slot: usize) -> Result<()> {
+        let data = *self.program.data.get(self.data_pointer).ok_or(BasicError::OutOfData)?;
+        self.data_pointer += 1;
+        let value = if self.program.variables[slot].ends_with('$') {
+            match data {
+                Data::String(s) => Value::String(s.to_string()),
+                Data::Number(n) => Value::String(n.to_string()),
+            }
+        } else {
+            match data {
+                Data::Number(n) => Value::Number(n),
+                Data::String(s) => Value::Number(s.trim().parse::<f64>().unwrap_or(0.0)),
+            }
+        };
+        self.set_var(slot, value)
+    }
+
+    pub fn restore(&mut self) {
+        self.data_pointer = 0;
+    }
+
+    /// RESTORE n：指向第 n 行或之后的第一个 DATA 值
+    pub fn restore_to(&mut self, line: u16) {
+        self.data_pointer = self
+            .program
+            .data_lines
+            .iter()
+            .find(|&&(ln, _)| ln >= line)
+            .map_or(self.program.data.len(), |&(_, index)| index);
+    }
+
+    // ---- 输出 ----
+
+    pub fn output(&mut self, text: &str) {
+        self.out.write_all(text.as_bytes()).ok();
+        for ch in text.chars() {
+            if ch == '\n' || ch == '\r' {
+                self.column = 0;
+            } else if !ch.is_control() {
+                self.column += 1;
+            }
+        }
+    }
+
+    pub fn newline(&mut self) {
+        self.output("\n");
+    }
+
+    /// 打印值：正数前后各一个空格，负数前一个空格
+    pub fn print(&mut self, value: &Value) {
+        match value {
+            Value::Number(n) if *n >= 0.0 => self.output(&format!(" {} ", n)),
+            Value::Number(n) => self.output(&format!(" {}", n)),
+            Value::String(s) => self.output(s),
+        }
+    }
+
+    /// 逗号：对齐到下一个 14 列边界
+    pub fn comma(&mut self) {
+        let next = (self.column / 14 + 1) * 14;
+        self.output(&" ".repeat(next - self.column));
+    }
+
+    pub fn tab(&mut self, column: Value) -> Result<()> {
+        let target = column.as_number()? as usize;
+        if target > self.column {
+            self.output(&" ".repeat(target - self.column));
+        } else if target < self.column {
+            self.newline();
+            self.output(&" ".repeat(target));
+        }
+        Ok(())
+    }
+
+    pub fn spc(&mut self, count: Value) -> Result<()> {
+        let count = count.as_number()? as usize;
+        self.output(&" ".repeat(count));
+        Ok(())
+    }
+
+    // ---- INPUT ----
+
+    /// INPUT：显示提示符，读一行，按逗号拆分后依次赋值
+    pub fn input(&mut self, prompt: Option<&str>, slots: &[usize]) -> Result<()> {
+        if let Some(prompt) = prompt {
+            self.output(prompt);
+        }
+        self.output("? ");
+        self.flush();
+
+        let mut buffer = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut buffer)
+            .map_err(|e| BasicError::SyntaxError(format!("Failed to read input: {}", e)))?;
+        let values = parse_input_values(buffer.trim());
+
+        if values.len() != slots.len() {
+            self.output("?EXTRA IGNORED\n");
+        }
+        for (&slot, input) in slots.iter().zip(&values) {
+            if self.program.variables[slot].ends_with('$') {
+                let text = if input.starts_with('"') && input.ends_with('"') {
+                    input[1..input.len() - 1].to_string()
+                } else {
+                    input.clone()
+                };
+                self.set_var(slot, Value::String(text))?;
+            } else {
+                match input.parse::<f64>() {
+                    Ok(n) => self.set_var(slot, Value::Number(n))?,
+                    Err(_) => {
+                        self.output("?REDO FROM START\n");
+                        return Err(BasicError::TypeMismatch("Invalid number input".to_string()));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // ---- 需要机器状态的函数 ----
+
+    /// RND：[0, 1) 的伪随机数（xorshift64*），参数只求值不使用
+    pub fn rnd(&mut self) -> Result<Value> {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let bits = self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        Ok(Value::Number(bits as f64 / (1u64 << 53) as f64))
+    }
+
+    /// POS：当前打印列（从 1 开始）
+    pub fn pos(&self) -> Result<Value> {
+        Ok(Value::Number((self.column + 1) as f64))
+    }
+
+    /// 与解释器相同：32767 减去数组占用的字节数
+    pub fn fre(&self) -> Result<Value> {
+        let used: usize = self.arrays.iter().flatten().map(Array::memory_size).sum();
+        Ok(Value::Number(32767usize.saturating_sub(used) as f64))
+    }
+
+    // ---- 时钟 ----
+
+    fn jiffies(&self) -> u64 {
+        let elapsed = (self.clock_started.elapsed().as_millis() as u64) * JIFFIES_PER_SECOND / 1000;
+        (self.clock_base + elapsed) % JIFFIES_PER_DAY
+    }
+
+    fn time_string(&self) -> String {
+        let seconds = self.jiffies() / JIFFIES_PER_SECOND;
+        format!("{:02}{:02}{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    }
+
+    fn set_time_string(&mut self, text: &str) -> Result<()> {
+        let invalid = || BasicError::IllegalQuantity(format!("TI$ must be \"HHMMSS\": {:?}", text));
+        if text.len() != 6 || !text.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let field = |i: usize| text[i..i + 2].parse::<u64>().unwrap();
+        let (hours, minutes, seconds) = (field(0), field(2), field(4));
+        if hours >= 24 || minutes >= 60 || seconds >= 60 {
+            return Err(invalid());
+        }
+        self.clock_base = ((hours * 60 + minutes) * 60 + seconds) * JIFFIES_PER_SECOND;
+        self.clock_started = Instant::now();
+        Ok(())
+    }
+}
+
+/// 拆分 INPUT 的输入（引号内的逗号不拆分）
+fn parse_input_values(input: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for ch in input.chars() {
+        match ch {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(ch);
+            }
+            ',' if !in_quotes => {
+                values.push(current.trim().to_string());
+                current.clear();
+            }
+            _ => current.push(ch),
+        }
+    }
+    if !current.is_empty() || input.ends_with(',') {
+        values.push(current.trim().to_string());
+    }
+    values
+}
+
+/// 数组下标和 DIM 的上界：负数或大于 32767 报 ?ILLEGAL QUANTITY
+pub fn subscript(value: Value) -> Result<usize> {
+    let n = value.as_number()?;
+    if !(0.0..32768.0).contains(&n) {
+        return Err(BasicError::IllegalQuantity(format!("Array index {} out of range", n)));
+    }
+    Ok(n as usize)
+}
+
+pub fn truth(value: Value) -> Result<bool> {
+    Ok(value.as_number()? != 0.0)
+}
+
+// ---- 运算符 ----
+
+pub fn add(l: Value, r: Value) -> Result<Value> {
+    match (l, r) {
+        (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+        (Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
+        _ => Err(BasicError::TypeMismatch("Cannot add incompatible types".to_string())),
+    }
+}
+
+pub fn sub(l: Value, r: Value) -> Result<Value> {
+    Ok(Value::Number(l.as_number()? - r.as_number()?))
+}
+
+pub fn mul(l: Value, r: Value) -> Result<Value> {
+    Ok(Value::Number(l.as_number()? * r.as_number()?))
+}
+
+pub fn div(l: Value, r: Value) -> Result<Value> {
+    let (l, r) = (l.as_number()?, r.as_number()?);
+    if r == 0.0 {
+        return Err(BasicError::DivisionByZero);
+    }
+    Ok(Value::Number(l / r))
+}
+
+pub fn pow(l: Value, r: Value) -> Result<Value> {
+    Ok(Value::Number(l.as_number()?.powf(r.as_number()?)))
+}
+
+fn flag(condition: bool) -> Value {
+    Value::Number(if condition { -1.0 } else { 0.0 })
+}
+
+pub fn eq(l: Value, r: Value) -> Result<Value> {
+    Ok(flag(l == r))
+}
+
+pub fn ne(l: Value, r: Value) -> Result<Value> {
+    Ok(flag(l != r))
+}
+
+fn compare(l: Value, r: Value, number: fn(&f64, &f64) -> bool, string: fn(&String, &String) -> bool) -> Result<Value> {
+    match (l, r) {
+        (Value::Number(l), Value::Number(r)) => Ok(flag(number(&l, &r))),
+        (Value::String(l), Value::String(r)) => Ok(flag(string(&l, &r))),
+        _ => Err(BasicError::TypeMismatch("Cannot compare".to_string())),
+    }
+}
+
+pub fn lt(l: Value, r: Value) -> Result<Value> {
+    compare(l, r, f64::lt, String::lt)
+}
+
+pub fn gt(l: Value, r: Value) -> Result<Value> {
+    compare(l, r, f64::gt, String::gt)
+}
+
+pub fn le(l: Value, r: Value) -> Result<Value> {
+    compare(l, r, f64::le, String::le)
+}
+
+pub fn ge(l: Value, r: Value) -> Result<Value> {
+    compare(l, r, f64::ge, String::ge)
+}
+
+pub fn and(l: Value, r: Value) -> Result<Value> {
+    Ok(Value::Number((l.as_number()? as i32 & r.as_number()? as i32) as f64))
+}
+
+pub fn or(l: Value, r: Value) -> Result<Value> {
+    Ok(Value::Number((l.as_number()? as i32 | r.as_number()? as i32) as f64))
+}
+
+pub fn neg(v: Value) -> Result<Value> {
+    Ok(Value::Number(-v.as_number()?))
+}
+
+pub fn not(v: Value) -> Result<Value> {
+    Ok(Value::Number(!(v.as_number()? as i32) as f64))
+}
+
+// ---- 内置函数（参数已按解释器的顺序求值并转换类型） ----
+
+pub fn sgn(n: f64) -> Result<Value> {
+    Ok(Value::Number(if n > 0.0 { 1.0 } else if n < 0.0 { -1.0 } else { 0.0 }))
+}
+
+pub fn int(n: f64) -> Result<Value> {
+    Ok(Value::Number(n.floor()))
+}
+
+pub fn abs(n: f64) -> Result<Value> {
+    Ok(Value::Number(n.abs()))
+}
+
+pub fn sqr(n: f64) -> Result<Value> {
+    if n < 0.0 {
+        return Err(BasicError::IllegalQuantity("SQR of negative number".to_string()));
+    }
+    Ok(Value::Number(n.sqrt()))
+}
+
+pub fn sin(n: f64) -> Result<Value> {
+    Ok(Value::Number(n.sin()))
+}
+
+pub fn cos(n: f64) -> Result<Value> {
+    Ok(Value::Number(n.cos()))
+}
+
+pub fn tan(n: f64) -> Result<Value> {
+    Ok(Value::Number(n.tan()))
+}
+
+pub fn atn(n: f64) -> Result<Value> {
+    Ok(Value::Number(n.atan()))
+}
+
+pub fn log(n: f64) -> Result<Value> {
+    if n <= 0.0 {
+        return Err(BasicError::IllegalQuantity("LOG of non-positive number".to_string()));
+    }
+    Ok(Value::Number(n.ln()))
+}
+
+pub fn exp(n: f64) -> Result<Value> {
+    Ok(Value::Number(n.exp()))
+}
+
+pub fn len(s: String) -> Result<Value> {
+    Ok(Value::Number(s.len() as f64))
+}
+
+pub fn asc(s: String) -> Result<Value> {
+    let ch = s.chars().next().ok_or_else(|| BasicError::IllegalQuantity("ASC of empty string".to_string()))?;
+    if ch as u32 > 255 {
+        return Err(BasicError::IllegalQuantity(format!("ASC of character {:?} not in character set", ch)));
+    }
+    Ok(Value::Number(ch as u32 as f64))
+}
+
+pub fn chr(n: f64) -> Result<Value> {
+    if !(0.0..=255.0).contains(&n) {
+        return Err(BasicError::IllegalQuantity("CHR$ argument out of range".to_string()));
+    }
+    Ok(Value::String((n as u8 as char).to_string()))
+}
+
+pub fn str(n: f64) -> Result<Value> {
+    Ok(Value::String(if n >= 0.0 { format!(" {}", n) } else { n.to_string() }))
+}
+
+pub fn val(s: String) -> Result<Value> {
+    Ok(Value::Number(s.trim().parse::<f64>().unwrap_or(0.0)))
+}
+
+pub fn left(s: String, n: f64) -> Result<Value> {
+    Ok(Value::String(s.chars().take(n as usize).collect()))
+}
+
+pub fn right(s: String, n: f64) -> Result<Value> {
+    let skip = s.chars().count().saturating_sub(n as usize);
+    Ok(Value::String(s.chars().skip(skip).collect()))
+}
+
+pub fn mid(s: String, start: f64, len: Option<f64>) -> Result<Value> {
+    let start = (start as usize).saturating_sub(1);
+    let chars = s.chars().skip(start);
+    Ok(Value::String(match len {
+        Some(len) => chars.take(len as usize).collect(),
+        None => chars.collect(),
+    }))
+}
+
+pub fn instr(start: f64, s1: String, s2: String) -> Result<Value> {
+    let start = (start as usize).saturating_sub(1);
+    Ok(Value::Number(match s1[start..].find(&s2) {
+        Some(pos) => (start + pos + 1) as f64,
+        None => 0.0,
+    }))
+}
+
+pub fn space(n: f64) -> Result<Value> {
+    Ok(Value::String(" ".repeat(n as usize)))
+}
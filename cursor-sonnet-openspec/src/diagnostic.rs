@@ -0,0 +1,329 @@
+//! 错误诊断
+//!
+//! 给错误附上出错位置（BASIC 行号、语句序号、列号），并显示为两种格式：
+//!
+//! ```text
+//! ?SYNTAX ERROR: Expected Then, found Newline IN 120 (statement 2, column 17)
+//! 120 A = 1: IF A > 0
+//!                    ^
+//! ```
+//!
+//! 或兼容模式下经典的 `?SYNTAX ERROR IN 120`。
+//! 解析错误的位置来自 `SpannedToken` 和 `Parser::statement_spans`；
+//! 运行时错误的位置是执行器记录的出错节点（表达式或语句）的范围，
+//! 没有记录时按出错语句的序号重新解析该行的文本得到语句范围。
+
+use crate::ast::{ProgramLine, Span};
+use crate::error::BasicError;
+use crate::parser::Parser;
+use crate::token::Token;
+use crate::tokenizer::Tokenizer;
+
+/// 错误的显示格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiagnosticStyle {
+    /// 错误信息加位置，下面显示源码行和 ^ 标记（默认）
+    #[default]
+    Caret,
+    /// 经典格式：`?SYNTAX ERROR IN 120`
+    Classic,
+}
+
+/// 带位置信息的错误
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub error: BasicError,
+    /// BASIC 行号（直接模式为 None）
+    pub line_number: Option<u16>,
+    /// 出错语句在行内的序号（从 0 开始，冒号分隔的顶层语句）
+    pub statement: Option<usize>,
+    /// 出错位置在 `source` 中的字符范围
+    pub span: Option<Span>,
+    /// 出错行的文本
+    pub source: Option<String>,
+}
+
+impl From<BasicError> for Diagnostic {
+    fn from(error: BasicError) -> Self {
+        Diagnostic {
+            error,
+            line_number: None,
+            statement: None,
+            span: None,
+            source: None,
+        }
+    }
+}
+
+impl Diagnostic {
+    /// 解析一行输入，出错时给出出错位置
+    pub fn parse_line(source: &str) -> Result<Option<ProgramLine>, Diagnostic> {
+        let tokens = match Tokenizer::new(source).tokenize_line_spanned() {
+            Ok(tokens) => tokens,
+            Err(error) => return Err(Self::tokenizer_error(error, source)),
+        };
+        let line_number = match tokens.first().map(|spanned| &spanned.token) {
+            Some(Token::LineNumber(number)) => Some(*number),
+            _ => None,
+        };
+        let mut parser = Parser::new_spanned(tokens);
+        parser.parse_line().map_err(|error| Diagnostic {
+            error,
+            line_number,
+            statement: Some(parser.statement_spans().len()),
+            span: parser.current_span(),
+            source: Some(source.to_string()),
+        })
+    }
+
+    /// 运行时错误：`statement` 为出错语句的序号，`source` 为该行的文本，
+    /// `span` 为出错节点在 `source` 中的范围（见 `Executor::error_span`）
+    pub fn runtime(
+        error: BasicError,
+        line_number: u16,
+        statement: usize,
+        source: &str,
+        span: Option<Span>,
+    ) -> Diagnostic {
+        let span = span.or_else(|| {
+            let tokens = Tokenizer::new(source).tokenize_line_spanned().ok()?;
+            let line = Parser::new_spanned(tokens).parse_line().ok()??;
+            Some(line.statements.get(statement)?.span)
+        });
+        Diagnostic {
+            error,
+            line_number: Some(line_number),
+            statement: Some(statement),
+            span,
+            source: Some(source.to_string()),
+        }
+    }
+
+    /// 词法错误：出错字符之前的部分仍可解析，由此得到行号和语句序号
+    fn tokenizer_error(error: BasicError, source: &str) -> Diagnostic {
+        let span = tokenizer_error_span(&error, source.chars().count());
+        let prefix: String = span
+            .map(|span| source.chars().take(span.start).collect())
+            .unwrap_or_default();
+        let mut line_number = None;
+        let mut statement = None;
+        if let Ok(tokens) = Tokenizer::new(&prefix).tokenize_line_spanned() {
+            if let Some(Token::LineNumber(number)) = tokens.first().map(|spanned| &spanned.token) {
+                line_number = Some(*number);
+            }
+            let mut parser = Parser::new_spanned(tokens);
+            let complete = parser.parse_line().is_ok();
+            let count = parser.statement_spans().len();
+            // 前缀完整解析时，出错字符属于最后一条语句，除非前缀以冒号结束
+            statement = Some(if complete && !prefix.trim_end().ends_with(':') {
+                count.saturating_sub(1)
+            } else {
+                count
+            });
+        }
+        Diagnostic {
+            error,
+            line_number,
+            statement,
+            span,
+            source: Some(source.to_string()),
+        }
+    }
+
+    /// 出错位置的列号（从 1 开始）
+    pub fn column(&self) -> Option<usize> {
+        self.span.map(|span| span.start + 1)
+    }
+
+    /// 按指定格式显示（不带结尾换行）
+    pub fn render(&self, style: DiagnosticStyle) -> String {
+        let location = match self.line_number {
+            Some(line) => format!(" IN {}", line),
+            None => String::new(),
+        };
+        if style == DiagnosticStyle::Classic {
+            let message = if is_syntax_error(&self.error) {
+                "SYNTAX ERROR".to_string()
+            } else {
+                error_message(&self.error)
+            };
+            return format!("?{}{}", message, location);
+        }
+
+        let mut position = Vec::new();
+        if let Some(statement) = self.statement {
+            position.push(format!("statement {}", statement + 1));
+        }
+        if let Some(column) = self.column() {
+            position.push(format!("column {}", column));
+        }
+        let mut text = format!("?{}{}", headline(&self.error), location);
+        if !position.is_empty() {
+            text.push_str(&format!(" ({})", position.join(", ")));
+        }
+        if let (Some(source), Some(span)) = (&self.source, self.span) {
+            let width = span.end.saturating_sub(span.start).max(1);
+            text.push_str(&format!("\n{}\n{}{}", source, " ".repeat(span.start), "^".repeat(width)));
+        }
+        text
+    }
+}
+
+/// 检查程序文本（每行一个程序行）的语法，返回第一个错误
+pub fn check_program(source: &str) -> Result<(), Diagnostic> {
+    for line in source.lines() {
+        let line = line.trim();
+        if !line.is_empty() {
+            Diagnostic::parse_line(line)?;
+        }
+    }
+    Ok(())
+}
+
+/// 词法错误在输入行中的范围
+pub fn tokenizer_error_span(error: &BasicError, line_len: usize) -> Option<Span> {
+    match error {
+        BasicError::IllegalCharacter(_, pos, _) => Some(Span { start: *pos, end: pos + 1 }),
+        BasicError::UnterminatedString(pos) => Some(Span { start: *pos, end: line_len.max(pos + 1) }),
+        BasicError::InvalidNumber(text, pos) => Some(Span {
+            start: *pos,
+            end: pos + text.chars().count(),
+        }),
+        _ => None,
+    }
+}
+
+/// 运行时错误信息（经典格式，不带开头的 ?）
+pub fn error_message(error: &BasicError) -> String {
+    match error {
+        BasicError::SyntaxError(_) => "SYNTAX ERROR".to_string(),
+        BasicError::DivisionByZero => "DIVISION BY ZERO".to_string(),
+        BasicError::TypeMismatch(_) => "TYPE MISMATCH".to_string(),
+        BasicError::UndefinedLine(n) => format!("UNDEFINED LINE {}", n),
+        BasicError::UndefinedVariable(v) => format!("UNDEFINED VARIABLE {}", v),
+        BasicError::SubscriptOutOfRange(_) => "BAD SUBSCRIPT".to_string(),
+        BasicError::OutOfData => "OUT OF DATA".to_string(),
+        BasicError::ReturnWithoutGosub => "RETURN WITHOUT GOSUB".to_string(),
+        BasicError::NextWithoutFor(_) => "NEXT WITHOUT FOR".to_string(),
+        BasicError::CantContinue => "CAN'T CONTINUE".to_string(),
+        // Display 的文本以 ? 开头
+        _ => error.to_string().trim_start_matches('?').to_string(),
+    }
+}
+
+/// 带位置的格式里的错误信息：位置另行给出，语法错误保留说明
+fn headline(error: &BasicError) -> String {
+    match error {
+        BasicError::IllegalCharacter(ch, _, _) => format!("ILLEGAL CHARACTER '{}'", ch),
+        BasicError::UnterminatedString(_) => "UNTERMINATED STRING".to_string(),
+        BasicError::InvalidNumber(text, _) => format!("INVALID NUMBER '{}'", text),
+        BasicError::ExpectedExpression(_) => "EXPECTED EXPRESSION".to_string(),
+        BasicError::UnmatchedParenthesis(_) => "UNMATCHED PARENTHESIS".to_string(),
+        BasicError::InvalidStatement(_) => "SYNTAX ERROR".to_string(),
+        BasicError::SyntaxError(message) => format!("SYNTAX ERROR: {}", message),
+        _ => error_message(error),
+    }
+}
+
+/// 词法和语法错误（经典 BASIC 统一报告为 SYNTAX ERROR）
+fn is_syntax_error(error: &BasicError) -> bool {
+    matches!(
+        error,
+        BasicError::IllegalCharacter(..)
+            | BasicError::UnterminatedString(_)
+            | BasicError::InvalidNumber(..)
+            | BasicError::SyntaxError(_)
+            | BasicError::ExpectedExpression(_)
+            | BasicError::UnmatchedParenthesis(_)
+            | BasicError::InvalidStatement(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(source: &str) -> Diagnostic {
+        Diagnostic::parse_line(source).unwrap_err()
+    }
+
+    // Requirement: Diagnostics - 语法错误的位置和 ^ 标记
+    #[test]
+    fn test_parse_error_caret() {
+        let diagnostic = parse_error("120 A = 1: B = * 2");
+        assert_eq!(diagnostic.line_number, Some(120));
+        assert_eq!(diagnostic.statement, Some(1));
+        assert_eq!(diagnostic.column(), Some(16));
+        assert_eq!(
+            diagnostic.render(DiagnosticStyle::Caret),
+            "?EXPECTED EXPRESSION IN 120 (statement 2, column 16)\n120 A = 1: B = * 2\n               ^"
+        );
+        assert_eq!(diagnostic.render(DiagnosticStyle::Classic), "?SYNTAX ERROR IN 120");
+
+        // 缺少 THEN：指向行尾
+        let diagnostic = parse_error("30 IF A > 0");
+        assert_eq!(diagnostic.statement, Some(0));
+        assert_eq!(diagnostic.column(), Some(12));
+        assert!(diagnostic.render(DiagnosticStyle::Caret).starts_with("?SYNTAX ERROR: Expected Then"));
+
+        // 直接模式没有行号
+        let diagnostic = parse_error("PRINT (1");
+        assert_eq!(diagnostic.line_number, None);
+        assert_eq!(diagnostic.render(DiagnosticStyle::Classic), "?SYNTAX ERROR");
+    }
+
+    // Requirement: Diagnostics - 词法错误的位置
+    #[test]
+    fn test_tokenizer_error_position() {
+        let diagnostic = parse_error("40 PRINT 1: PRINT @");
+        assert_eq!(diagnostic.line_number, Some(40));
+        assert_eq!(diagnostic.statement, Some(1));
+        assert_eq!(
+            diagnostic.render(DiagnosticStyle::Caret),
+            "?ILLEGAL CHARACTER '@' IN 40 (statement 2, column 19)\n40 PRINT 1: PRINT @\n                  ^"
+        );
+
+        let diagnostic = parse_error("50 A$ = \"X\": PRINT \"ABC");
+        assert_eq!(diagnostic.statement, Some(1));
+        assert_eq!(diagnostic.span, Some(Span { start: 19, end: 23 }));
+        assert!(diagnostic.render(DiagnosticStyle::Caret).ends_with("\n                   ^^^^"));
+
+        let diagnostic = parse_error("60 PRINT 1:@");
+        assert_eq!(diagnostic.statement, Some(1));
+    }
+
+    // Requirement: Diagnostics - 运行时错误标出出错语句
+    #[test]
+    fn test_runtime_error() {
+        let diagnostic = Diagnostic::runtime(BasicError::DivisionByZero, 30, 1, "30 A = 1: PRINT A / 0: END", None);
+        assert_eq!(
+            diagnostic.render(DiagnosticStyle::Caret),
+            "?DIVISION BY ZERO IN 30 (statement 2, column 11)\n30 A = 1: PRINT A / 0: END\n          ^^^^^^^^^^^"
+        );
+        assert_eq!(diagnostic.render(DiagnosticStyle::Classic), "?DIVISION BY ZERO IN 30");
+
+        // 执行器给出的节点范围优先于整条语句
+        let span = Some(Span { start: 16, end: 21 });
+        let diagnostic = Diagnostic::runtime(BasicError::DivisionByZero, 30, 1, "30 A = 1: PRINT A / 0: END", span);
+        assert_eq!(
+            diagnostic.render(DiagnosticStyle::Caret),
+            "?DIVISION BY ZERO IN 30 (statement 2, column 17)\n30 A = 1: PRINT A / 0: END\n                ^^^^^"
+        );
+
+        // 没有位置信息的错误
+        let diagnostic = Diagnostic::from(BasicError::IllegalQuantity("X".to_string()));
+        assert_eq!(
+            diagnostic.render(DiagnosticStyle::Caret),
+            diagnostic.render(DiagnosticStyle::Classic)
+        );
+        assert!(!diagnostic.render(DiagnosticStyle::Classic).starts_with("??"));
+    }
+
+    // Requirement: Diagnostics - 检查整个程序
+    #[test]
+    fn test_check_program() {
+        assert!(check_program("10 PRINT 1\n\n20 END\n").is_ok());
+        let diagnostic = check_program("10 PRINT 1\n20 GOTO\n30 END").unwrap_err();
+        assert_eq!(diagnostic.line_number, Some(20));
+    }
+}
@@ -76,7 +76,7 @@ impl std::fmt::Display for BasicError {
                 write!(f, "?TYPE MISMATCH ERROR: {}", msg)
             }
             BasicError::SubscriptOutOfRange(var) => {
-                write!(f, "?SUBSCRIPT OUT OF RANGE: {}", var)
+                write!(f, "?BAD SUBSCRIPT: {}", var)
             }
             BasicError::RedimensionedArray(var) => {
                 write!(f, "?REDIM'D ARRAY ERROR: {}", var)
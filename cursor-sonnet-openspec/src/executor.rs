@@ -1,11 +1,18 @@
-/// 执行引擎
-///
-/// 求值表达式并执行语句
+//! 执行引擎
+//!
+//! 求值表达式并执行语句
 
 use crate::ast::*;
 use crate::error::{BasicError, Result};
+use crate::charset::Charset;
+use crate::clock::{ClockMode, JIFFIES_PER_DAY};
+use crate::functions::FunctionRegistry;
+use crate::keyboard::{self, Keyboard};
 use crate::runtime::Runtime;
-use crate::variables::{Value, Variables};
+use crate::screen::{Screen, ScreenConfig};
+use crate::variables::{subscript, Value, Variables};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 /// 输入回调函数类型
 pub type InputCallback = Box<dyn FnMut(&str) -> Option<String>>;
@@ -18,12 +25,46 @@ pub struct Executor {
     output_buffer: Vec<String>,
     /// 当前打印列位置
     print_column: usize,
-    /// DATA 数据存储
+    /// DATA 数据：RUN / RESTORE 时按行号顺序从已存储的程序收集
     data_values: Vec<DataValue>,
+    /// 含 DATA 的行：行号和该行第一个值在 data_values 中的下标
+    data_lines: Vec<(u16, usize)>,
     /// DATA 数据指针（当前读取位置）
     data_pointer: usize,
     /// 输入回调函数（用于测试）
     input_callback: Option<InputCallback>,
+    /// LIST / SAVE 输出程序文本的方式
+    listing_style: ListingStyle,
+    /// COMMON 声明的变量（CHAIN 时只保留这些；为空则保留全部变量）
+    common_variables: Vec<CommonVariable>,
+    /// POKE/PEEK 的 64K 地址空间（屏幕 RAM 和颜色 RAM 除外）
+    memory: Vec<u8>,
+    /// 虚拟字符屏幕（启用后 PRINT 输出写入屏幕）
+    screen: Option<Screen>,
+    /// 屏幕变化后是否用 ANSI 转义序列重画到终端
+    render_screen: bool,
+    /// 屏幕内容已变化、尚未重画
+    screen_dirty: bool,
+    /// 字符集（决定 CHR$/ASC、键盘输入和输出的转换）
+    charset: Charset,
+    /// GET 的键盘队列
+    keyboard: Keyboard,
+    /// Ctrl+C 中断标志（WAIT 等待时检查）
+    interrupt_flag: Option<Arc<AtomicBool>>,
+    /// 表达式中可调用的函数
+    functions: FunctionRegistry,
+    /// 上一个错误出在哪个最内层的表达式或语句（源码范围）
+    error_span: Option<Span>,
+}
+
+/// LIST / SAVE 输出程序文本的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListingStyle {
+    /// 原样输出用户输入的文本（默认）
+    #[default]
+    Source,
+    /// 由 AST 重新生成规范格式的文本
+    Canonical,
 }
 
 /// DATA 值类型
@@ -42,8 +83,202 @@ impl Executor {
             output_buffer: Vec::new(),
             print_column: 0,
             data_values: Vec::new(),
+            data_lines: Vec::new(),
             data_pointer: 0,
             input_callback: None,
+            listing_style: ListingStyle::default(),
+            common_variables: Vec::new(),
+            memory: vec![0; 65536],
+            screen: None,
+            render_screen: false,
+            screen_dirty: false,
+            charset: Charset::default(),
+            keyboard: Keyboard::new(),
+            interrupt_flag: None,
+            functions: FunctionRegistry::new(),
+            error_span: None,
+        }
+    }
+    
+    /// 设置字符集
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.charset = charset;
+        if let Some(screen) = self.screen.as_mut() {
+            screen.set_charset(charset);
+        }
+    }
+    
+    /// 当前字符集（程序可以用 CHR$(14) / CHR$(142) 切换 PETSCII 大小写）
+    pub fn charset(&self) -> Charset {
+        self.charset
+    }
+    
+    /// 启用虚拟字符屏幕
+    ///
+    /// `render` 为 true 时清空终端，之后屏幕有变化时在语句结束或等待输入前重画到终端；
+    /// 为 false 时只更新屏幕内容（用于测试，通过 `screen()` 读取）
+    pub fn enable_screen(&mut self, config: ScreenConfig, render: bool) {
+        let mut screen = Screen::new(config);
+        screen.set_charset(self.charset);
+        self.screen = Some(screen);
+        self.render_screen = render;
+        self.print_column = 0;
+        if render {
+            print!("\x1b[2J");
+            self.render();
+        }
+    }
+    
+    /// 获取虚拟字符屏幕
+    pub fn screen(&self) -> Option<&Screen> {
+        self.screen.as_ref()
+    }
+    
+    /// 把屏幕重画到终端
+    fn render(&self) {
+        if let (Some(screen), true) = (&self.screen, self.render_screen) {
+            use std::io::Write;
+            print!("{}", screen.render_ansi());
+            std::io::stdout().flush().ok();
+        }
+    }
+    
+    /// 屏幕有变化时重画一次：写屏幕只做标记，一条语句里多次 PRINT/POKE 只重画一次
+    fn flush_screen(&mut self) {
+        if self.screen_dirty {
+            self.screen_dirty = false;
+            self.render();
+        }
+    }
+    
+    /// 表达式中可调用的函数
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
+    
+    /// 表达式中可调用的函数（用于注册宿主函数）
+    pub fn functions_mut(&mut self) -> &mut FunctionRegistry {
+        &mut self.functions
+    }
+    
+    /// 当前打印列位置（0-based）
+    pub fn print_column(&self) -> usize {
+        self.print_column
+    }
+    
+    /// 设置 Ctrl+C 中断标志，WAIT 在标志置位时停止等待
+    pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt_flag = Some(flag);
+    }
+    
+    fn is_interrupted(&self) -> bool {
+        self.interrupt_flag.as_ref().is_some_and(|flag| flag.load(Ordering::SeqCst))
+    }
+    
+    /// 读取内存（屏幕 RAM 和颜色 RAM 映射到虚拟屏幕，160-162 映射到时钟）
+    pub fn peek(&self, address: u16) -> u8 {
+        if let Some(shift) = Self::clock_byte_shift(address) {
+            return (self.variables.clock().jiffies() >> shift) as u8;
+        }
+        self.screen.as_ref()
+            .and_then(|screen| screen.peek(address))
+            .unwrap_or(self.memory[address as usize])
+    }
+    
+    /// 写入内存（屏幕 RAM 和颜色 RAM 映射到虚拟屏幕，160-162 映射到时钟）
+    pub fn poke(&mut self, address: u16, value: u8) {
+        if let Some(shift) = Self::clock_byte_shift(address) {
+            let clock = self.variables.clock_mut();
+            let jiffies = (clock.jiffies() & !(0xFF << shift)) | ((value as u64) << shift);
+            clock.set_jiffies(jiffies);
+            return;
+        }
+        let on_screen = self.screen.as_mut()
+            .is_some_and(|screen| screen.poke(address, value));
+        if on_screen {
+            self.screen_dirty = true;
+        } else {
+            self.memory[address as usize] = value;
+        }
+    }
+    
+    /// 时钟的三个字节（160 最高，162 最低）在 jiffy 数中的位移
+    fn clock_byte_shift(address: u16) -> Option<u32> {
+        match address {
+            160 => Some(16),
+            161 => Some(8),
+            162 => Some(0),
+            _ => None,
+        }
+    }
+    
+    /// 执行 WAIT 语句：等到 (PEEK(address) XOR value) AND mask 不为零
+    ///
+    /// 每次检查之间时钟前进一个 jiffy（实时模式下睡眠 1/60 秒），所以等待时钟字节的
+    /// WAIT 在两种模式下都会结束；确定模式下一整天都没有满足条件则报错，避免测试卡死
+    fn execute_wait(&mut self, address: &Expr, mask: &Expr, value: Option<&Expr>) -> Result<()> {
+        let address = self.eval_address(address)?;
+        let mask = self.eval_byte(mask)?;
+        let value = match value {
+            Some(expr) => self.eval_byte(expr)?,
+            None => 0,
+        };
+        
+        // 等待期间屏幕不会变化，先把之前的输出画出来
+        self.flush_screen();
+        let mut waited = 0;
+        while (self.peek(address) ^ value) & mask == 0 {
+            if self.is_interrupted() {
+                return Ok(());
+            }
+            if let ClockMode::Deterministic { .. } = self.variables.clock().mode() {
+                if waited >= JIFFIES_PER_DAY {
+                    return Err(BasicError::IllegalQuantity(format!(
+                        "WAIT {} never satisfied", address
+                    )));
+                }
+            }
+            self.variables.clock_mut().wait(1);
+            waited += 1;
+        }
+        Ok(())
+    }
+    
+    /// 把表达式的值检查为字节（0-255）
+    fn eval_byte(&mut self, expr: &Expr) -> Result<u8> {
+        let value = self.eval_expr(expr)?.as_number()?;
+        if !(0.0..=255.0).contains(&value) {
+            return Err(BasicError::IllegalQuantity(format!("Byte value {} out of range", value)));
+        }
+        Ok(value as u8)
+    }
+    
+    /// 把表达式的值检查为内存地址（0-65535）
+    fn eval_address(&mut self, expr: &Expr) -> Result<u16> {
+        let address = self.eval_expr(expr)?.as_number()?;
+        if !(0.0..=65535.0).contains(&address) {
+            return Err(BasicError::IllegalQuantity(format!("Address {} out of range", address)));
+        }
+        Ok(address as u16)
+    }
+    
+    /// 设置 LIST / SAVE 的输出方式
+    pub fn set_listing_style(&mut self, style: ListingStyle) {
+        self.listing_style = style;
+    }
+    
+    /// 获取 LIST / SAVE 的输出方式
+    pub fn listing_style(&self) -> ListingStyle {
+        self.listing_style
+    }
+    
+    /// 获取程序行用于 LIST / SAVE 的文本
+    ///
+    /// 默认使用输入时的原始文本；规范模式或没有原始文本时由 AST 重新生成
+    pub fn line_text(&self, line: &ProgramLine) -> String {
+        match (self.listing_style, self.runtime.get_source(line.line_number)) {
+            (ListingStyle::Source, Some(source)) => source.to_string(),
+            _ => Self::serialize_program_line(line),
         }
     }
     
@@ -55,14 +290,37 @@ impl Executor {
         self.input_callback = Some(Box::new(callback));
     }
     
-    /// 添加 DATA 值
-    pub fn add_data_value(&mut self, value: DataValue) {
-        self.data_values.push(value);
+    /// 按程序行顺序重新收集 DATA 值，DATA 指针回到开头
+    pub fn restore_data(&mut self) {
+        self.restore_data_to(None);
     }
     
-    /// 重置 DATA 指针
-    pub fn restore_data(&mut self) {
-        self.data_pointer = 0;
+    /// 按程序行顺序重新收集 DATA 值，DATA 指针指向第 line 行或之后的第一个值
+    /// （之后没有 DATA 时，下一次 READ 报 ?OUT OF DATA）
+    pub fn restore_data_to(&mut self, line: Option<u16>) {
+        self.data_values.clear();
+        self.data_lines.clear();
+        for program_line in self.runtime.get_all_lines() {
+            for stmt in &program_line.statements {
+                if let StatementKind::Data { values } = &stmt.kind {
+                    if self.data_lines.last().map(|&(ln, _)| ln) != Some(program_line.line_number) {
+                        self.data_lines.push((program_line.line_number, self.data_values.len()));
+                    }
+                    // 转换 ast::DataValue 到 executor::DataValue
+                    self.data_values.extend(values.iter().map(|value| match value {
+                        crate::ast::DataValue::Number(n) => DataValue::Number(*n),
+                        crate::ast::DataValue::String(s) => DataValue::String(s.clone()),
+                    }));
+                }
+            }
+        }
+        self.data_pointer = match line {
+            Some(line) => self.data_lines
+                .iter()
+                .find(|&&(ln, _)| ln >= line)
+                .map_or(self.data_values.len(), |&(_, index)| index),
+            None => 0,
+        };
     }
     
     /// 读取下一个 DATA 值
@@ -89,19 +347,29 @@ impl Executor {
     
     /// 输出文本（添加到缓冲区并打印到终端）
     fn output(&mut self, text: &str) {
-        // 打印到终端
-        print!("{}", text);
+        // 启用屏幕时写入虚拟屏幕，列位置跟随屏幕光标
+        if let Some(screen) = self.screen.as_mut() {
+            screen.print(text);
+            self.print_column = screen.cursor().1;
+            self.charset = screen.charset();
+            self.output_buffer.push(text.to_string());
+            self.screen_dirty = true;
+            return;
+        }
+        
+        // 按字符集转换控制码后打印到终端
+        print!("{}", self.charset.render(text));
         use std::io::Write;
         std::io::stdout().flush().ok();
         
         // 同时添加到缓冲区（用于测试）
         self.output_buffer.push(text.to_string());
         
-        // 更新列位置
+        // 更新列位置（控制码不占列）
         for ch in text.chars() {
-            if ch == '\n' {
+            if ch == '\n' || ch == '\r' {
                 self.print_column = 0;
-            } else {
+            } else if !ch.is_control() {
                 self.print_column += 1;
             }
         }
@@ -132,48 +400,64 @@ impl Executor {
         &mut self.variables
     }
 
+    /// 上一条出错语句中出错位置的源码范围：最内层的出错表达式，
+    /// 没有出错的表达式时为出错的语句；节点没有源码位置时为 None
+    pub fn error_span(&self) -> Option<Span> {
+        self.error_span
+    }
+
+    /// 记录出错位置：错误从内向外传播，只保留最先记录的非空范围
+    fn note_error_span(&mut self, span: Span) {
+        if self.error_span.is_none() && !span.is_empty() {
+            self.error_span = Some(span);
+        }
+    }
+
     /// 求值表达式
     pub fn eval_expr(&mut self, expr: &Expr) -> Result<Value> {
-        match expr {
-            Expr::Number(n) => Ok(Value::Number(*n)),
+        let result = self.eval_expr_kind(expr);
+        if result.is_err() {
+            self.note_error_span(expr.span);
+        }
+        result
+    }
+
+    fn eval_expr_kind(&mut self, expr: &Expr) -> Result<Value> {
+        match &expr.kind {
+            ExprKind::Number(n) => Ok(Value::Number(*n)),
             
-            Expr::String(s) => Ok(Value::String(s.clone())),
+            ExprKind::String(s) => Ok(Value::String(s.clone())),
             
-            Expr::Variable(name) => {
+            ExprKind::Variable(name) => {
                 Ok(self.variables.get(name))
             }
             
-            Expr::ArrayAccess { name, indices } => {
+            // 没有 DIM 过的同名数组时，宿主函数的调用写法和数组访问相同
+            ExprKind::ArrayAccess { name, indices }
+                if !self.variables.has_array(name) && self.functions.get(name).is_some() =>
+            {
+                self.eval_function_call(name, indices)
+            }
+            
+            ExprKind::ArrayAccess { name, indices } => {
                 // 求值所有索引
                 let idx_values: Result<Vec<usize>> = indices.iter()
-                    .map(|idx_expr| {
-                        self.eval_expr(idx_expr)?
-                            .as_number()
-                            .and_then(|n| {
-                                if n < 0.0 {
-                                    Err(BasicError::SubscriptOutOfRange(
-                                        "Negative array index".to_string()
-                                    ))
-                                } else {
-                                    Ok(n as usize)
-                                }
-                            })
-                    })
+                    .map(|idx_expr| subscript(self.eval_expr(idx_expr)?.as_number()?))
                     .collect();
                 
                 let indices_usize = idx_values?;
                 self.variables.get_array_element(name, &indices_usize)
             }
             
-            Expr::FunctionCall { name, args } => {
+            ExprKind::FunctionCall { name, args } => {
                 self.eval_function_call(name, args)
             }
             
-            Expr::BinaryOp { left, op, right } => {
+            ExprKind::BinaryOp { left, op, right } => {
                 self.eval_binary_op(left, *op, right)
             }
             
-            Expr::UnaryOp { op, operand } => {
+            ExprKind::UnaryOp { op, operand } => {
                 self.eval_unary_op(*op, operand)
             }
         }
@@ -334,7 +618,7 @@ impl Executor {
         }
     }
 
-    /// 求值函数调用（内置函数）
+    /// 求值函数调用（用户自定义函数和注册表中的函数）
     fn eval_function_call(&mut self, name: &str, args: &[Expr]) -> Result<Value> {
         // 首先检查是否是用户自定义函数（FN name）
         if name.starts_with("FN") && name.len() > 2 {
@@ -366,8 +650,12 @@ impl Executor {
             // 设置参数值
             self.variables.set(&param_name, arg_value)?;
             
-            // 求值函数体
-            let result = self.eval_expr(&body)?;
+            // 求值函数体；函数体的范围属于 DEF 所在行，出错时改由调用处记录
+            let result = self.eval_expr(&body);
+            if result.is_err() {
+                self.error_span = None;
+            }
+            let result = result?;
             
             // 恢复原变量值
             let _ = self.variables.set(&param_name, old_value);
@@ -375,312 +663,33 @@ impl Executor {
             return Ok(result);
         }
         
-        match name.to_uppercase().as_str() {
-            // 数学函数
-            "SGN" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("SGN requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                let result = if n > 0.0 { 1.0 } else if n < 0.0 { -1.0 } else { 0.0 };
-                Ok(Value::Number(result))
-            }
-            
-            "INT" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("INT requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                Ok(Value::Number(n.floor()))
-            }
-            
-            "ABS" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("ABS requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                Ok(Value::Number(n.abs()))
-            }
-            
-            "SQR" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("SQR requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                if n < 0.0 {
-                    return Err(BasicError::IllegalQuantity("SQR of negative number".to_string()));
-                }
-                Ok(Value::Number(n.sqrt()))
-            }
-            
-            "SIN" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("SIN requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                Ok(Value::Number(n.sin()))
-            }
-            
-            "COS" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("COS requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                Ok(Value::Number(n.cos()))
-            }
-            
-            "TAN" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("TAN requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                Ok(Value::Number(n.tan()))
-            }
-            
-            "ATN" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("ATN requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                Ok(Value::Number(n.atan()))
-            }
-            
-            "LOG" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("LOG requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                if n <= 0.0 {
-                    return Err(BasicError::IllegalQuantity("LOG of non-positive number".to_string()));
-                }
-                Ok(Value::Number(n.ln()))
-            }
-            
-            "EXP" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("EXP requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                Ok(Value::Number(n.exp()))
-            }
-            
-            "RND" => {
-                use rand::Rng;
-                
-                // RND 函数的 BASIC 6502 语义：
-                // RND(0) - 返回最近生成的随机数（简化为生成新的）
-                // RND(正数) - 返回 [0, 1) 的随机浮点数
-                // RND(负数) - 使用负数作为种子（暂不实现种子功能）
-                let arg = if args.is_empty() {
-                    1.0  // 无参数默认为 RND(1)
-                } else {
-                    self.eval_expr(&args[0])?.as_number()?
-                };
-                
-                let mut rng = rand::thread_rng();
-                
-                // 简化实现：所有情况都返回 [0, 1) 的随机数
-                // 如果需要随机整数，用户可以写 INT(RND(1)*N)+1
-                let result = if arg < 0.0 {
-                    // 负数：暂时也返回随机数（标准BASIC会重新播种）
-                    rng.gen::<f64>()
-                } else {
-                    // 0或正数：返回 [0, 1) 的随机数
-                    rng.gen::<f64>()
-                };
-                
-                Ok(Value::Number(result))
-            }
-            
-            // 字符串函数
-            "LEN" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("LEN requires 1 argument".to_string()));
-                }
-                let s = self.eval_expr(&args[0])?.as_string()?;
-                Ok(Value::Number(s.len() as f64))
-            }
-            
-            "ASC" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("ASC requires 1 argument".to_string()));
-                }
-                let s = self.eval_expr(&args[0])?.as_string()?;
-                if s.is_empty() {
-                    return Err(BasicError::IllegalQuantity("ASC of empty string".to_string()));
-                }
-                Ok(Value::Number(s.chars().next().unwrap() as u8 as f64))
-            }
-            
-            "CHR$" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("CHR$ requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                if n < 0.0 || n > 255.0 {
-                    return Err(BasicError::IllegalQuantity("CHR$ argument out of range".to_string()));
-                }
-                let ch = n as u8 as char;
-                Ok(Value::String(ch.to_string()))
-            }
-            
-            "STR$" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("STR$ requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()?;
-                // BASIC 的 STR$ 在正数前加空格
-                let s = if n >= 0.0 {
-                    format!(" {}", n)
-                } else {
-                    n.to_string()
-                };
-                Ok(Value::String(s))
-            }
-            
-            "VAL" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("VAL requires 1 argument".to_string()));
-                }
-                let s = self.eval_expr(&args[0])?.as_string()?;
-                let n = s.trim().parse::<f64>().unwrap_or(0.0);
-                Ok(Value::Number(n))
-            }
-            
-            "LEFT$" => {
-                if args.len() != 2 {
-                    return Err(BasicError::SyntaxError("LEFT$ requires 2 arguments".to_string()));
-                }
-                let s = self.eval_expr(&args[0])?.as_string()?;
-                let n = self.eval_expr(&args[1])?.as_number()? as usize;
-                let result: String = s.chars().take(n).collect();
-                Ok(Value::String(result))
-            }
-            
-            "RIGHT$" => {
-                if args.len() != 2 {
-                    return Err(BasicError::SyntaxError("RIGHT$ requires 2 arguments".to_string()));
-                }
-                let s = self.eval_expr(&args[0])?.as_string()?;
-                let n = self.eval_expr(&args[1])?.as_number()? as usize;
-                let len = s.chars().count();
-                let skip = if n > len { 0 } else { len - n };
-                let result: String = s.chars().skip(skip).collect();
-                Ok(Value::String(result))
-            }
-            
-            "MID$" => {
-                if args.len() < 2 || args.len() > 3 {
-                    return Err(BasicError::SyntaxError("MID$ requires 2 or 3 arguments".to_string()));
-                }
-                let s = self.eval_expr(&args[0])?.as_string()?;
-                let start = self.eval_expr(&args[1])?.as_number()? as usize;
-                
-                // BASIC 的 MID$ 是 1-based
-                let start = if start > 0 { start - 1 } else { 0 };
-                
-                let chars: Vec<char> = s.chars().collect();
-                
-                if args.len() == 3 {
-                    let len = self.eval_expr(&args[2])?.as_number()? as usize;
-                    let result: String = chars.iter().skip(start).take(len).collect();
-                    Ok(Value::String(result))
-                } else {
-                    let result: String = chars.iter().skip(start).collect();
-                    Ok(Value::String(result))
-                }
-            }
-            
-            "INSTR" => {
-                // INSTR(start, string1, string2) 或 INSTR(string1, string2)
-                // 返回 string2 在 string1 中第一次出现的位置（1-based），如果没找到返回 0
-                if args.len() < 2 || args.len() > 3 {
-                    return Err(BasicError::SyntaxError("INSTR requires 2 or 3 arguments".to_string()));
-                }
-                
-                let (start_pos, str1, str2) = if args.len() == 3 {
-                    let start = self.eval_expr(&args[0])?.as_number()? as usize;
-                    let s1 = self.eval_expr(&args[1])?.as_string()?;
-                    let s2 = self.eval_expr(&args[2])?.as_string()?;
-                    (start, s1, s2)
-                } else {
-                    let s1 = self.eval_expr(&args[0])?.as_string()?;
-                    let s2 = self.eval_expr(&args[1])?.as_string()?;
-                    (1, s1, s2)
-                };
-                
-                // BASIC 的 INSTR 是 1-based
-                let start_pos = if start_pos > 0 { start_pos - 1 } else { 0 };
-                
-                // 从 start_pos 开始查找
-                if let Some(pos) = str1[start_pos..].find(&str2) {
-                    Ok(Value::Number((start_pos + pos + 1) as f64))
-                } else {
-                    Ok(Value::Number(0.0))
-                }
-            }
-            
-            "SPACE$" => {
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("SPACE$ requires 1 argument".to_string()));
-                }
-                let n = self.eval_expr(&args[0])?.as_number()? as usize;
-                Ok(Value::String(" ".repeat(n)))
-            }
-            
-            "POS" => {
-                // POS(x) - 返回当前打印列位置（1-based）
-                // 参数 x 被忽略，但必须提供（BASIC 6502 要求）
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("POS requires 1 argument".to_string()));
-                }
-                // 忽略参数，只返回当前列位置
-                let _ = self.eval_expr(&args[0])?;
-                // 返回 1-based 列位置（print_column 是 0-based）
-                Ok(Value::Number((self.print_column + 1) as f64))
-            }
-            
-            "FRE" => {
-                // FRE(x) - 返回剩余内存大小（简化实现）
-                // 参数 x 被忽略
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("FRE requires 1 argument".to_string()));
-                }
-                let _ = self.eval_expr(&args[0])?;
-                // 简化实现：返回一个固定的大数
-                Ok(Value::Number(32767.0))
-            }
-            
-            "PEEK" => {
-                // PEEK(addr) - 读取内存地址的值（简化实现）
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("PEEK requires 1 argument".to_string()));
-                }
-                let _ = self.eval_expr(&args[0])?;
-                // 简化实现：返回 0
-                Ok(Value::Number(0.0))
-            }
-            
-            "USR" => {
-                // USR(addr) - 调用机器语言程序（简化实现）
-                if args.len() != 1 {
-                    return Err(BasicError::SyntaxError("USR requires 1 argument".to_string()));
-                }
-                let _ = self.eval_expr(&args[0])?;
-                // 简化实现：返回 0
-                Ok(Value::Number(0.0))
-            }
-            
-            _ => Err(BasicError::SyntaxError(
-                format!("Unknown function: {}", name)
-            )),
-        }
+        // 内置函数和宿主函数：先取出函数（释放对注册表的借用），再求值参数
+        let function = self.functions.get(name).cloned().ok_or_else(|| {
+            BasicError::SyntaxError(format!("Unknown function: {}", name))
+        })?;
+        let values = args.iter()
+            .map(|arg| self.eval_expr(arg))
+            .collect::<Result<Vec<_>>>()?;
+        function.call(self, &values)
     }
 
     /// 执行语句
     pub fn execute_statement(&mut self, stmt: &Statement) -> Result<()> {
-        match stmt {
-            Statement::Let { target, value } => {
+        self.error_span = None;
+        let result = self.execute_statement_kind(stmt);
+        if result.is_err() {
+            self.note_error_span(stmt.span);
+        }
+        self.flush_screen();
+        result
+    }
+
+    fn execute_statement_kind(&mut self, stmt: &Statement) -> Result<()> {
+        // 确定模式的时钟按语句前进
+        self.variables.clock_mut().tick();
+        
+        match &stmt.kind {
+            StatementKind::Let { target, value } => {
                 let val = self.eval_expr(value)?;
                 
                 match target {
@@ -689,11 +698,7 @@ impl Executor {
                     }
                     AssignTarget::ArrayElement { name, indices } => {
                         let idx_values: Result<Vec<usize>> = indices.iter()
-                            .map(|idx_expr| {
-                                self.eval_expr(idx_expr)?
-                                    .as_number()
-                                    .map(|n| n as usize)
-                            })
+                            .map(|idx_expr| subscript(self.eval_expr(idx_expr)?.as_number()?))
                             .collect();
                         
                         let indices_usize = idx_values?;
@@ -704,40 +709,38 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::End => {
+            StatementKind::End => {
                 self.runtime.end_execution();
                 Ok(())
             }
             
-            Statement::Stop => {
+            StatementKind::Stop => {
                 self.runtime.pause_execution();
                 Ok(())
             }
             
-            Statement::New => {
+            StatementKind::New => {
                 self.runtime.clear_program();
                 self.variables.clear();
+                self.common_variables.clear();
                 Ok(())
             }
             
-            Statement::Clear => {
+            StatementKind::Clear => {
                 self.variables.clear();
+                self.common_variables.clear();
                 Ok(())
             }
             
-            Statement::Rem { comment: _ } => {
+            StatementKind::Rem { comment: _ } => {
                 // REM 注释语句：不执行任何操作
                 Ok(())
             }
             
-            Statement::Dim { arrays } => {
+            StatementKind::Dim { arrays } => {
                 for arr_dim in arrays {
                     let dimensions: Result<Vec<usize>> = arr_dim.dimensions.iter()
-                        .map(|dim_expr| {
-                            self.eval_expr(dim_expr)?
-                                .as_number()
-                                .map(|n| n as usize)
-                        })
+                        .map(|dim_expr| subscript(self.eval_expr(dim_expr)?.as_number()?))
                         .collect();
                     
                     let dims = dimensions?;
@@ -746,19 +749,19 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::Print { items } => {
+            StatementKind::Print { items } => {
                 self.execute_print(items)?;
                 Ok(())
             }
             
-            Statement::Goto { line_number } => {
+            StatementKind::Goto { line_number } => {
                 let line_val = self.eval_expr(line_number)?;
                 let line = line_val.as_number()? as u16;
                 self.runtime.set_execution_position(line, 0)?;
                 Ok(())
             }
             
-            Statement::If { condition, then_part } => {
+            StatementKind::If { condition, then_part } => {
                 let cond_val = self.eval_expr(condition)?;
                 let cond_num = cond_val.as_number()?;
                 
@@ -766,7 +769,7 @@ impl Executor {
                 if cond_num != 0.0 {
                     match then_part.as_ref() {
                         ThenPart::LineNumber(line) => {
-                            self.runtime.set_execution_position(*line as u16, 0)?;
+                            self.runtime.set_execution_position(*line, 0)?;
                         }
                         ThenPart::Statement(stmt) => {
                             self.execute_statement(stmt)?;
@@ -781,7 +784,7 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::Gosub { line_number } => {
+            StatementKind::Gosub { line_number } => {
                 // 保存返回地址（当前行号和语句索引）
                 let return_line = self.runtime.get_current_line().unwrap_or(0);
                 // 注意：get_current_stmt_index() 返回的是下一条语句的索引（因为 get_next_statement() 已经递增过了）
@@ -799,7 +802,7 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::Return => {
+            StatementKind::Return => {
                 // 从栈中弹出返回地址
                 let (return_line, return_stmt) = self.runtime.pop_gosub()?;
                 
@@ -811,7 +814,7 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::Input { prompt, variables } => {
+            StatementKind::Input { prompt, variables } => {
                 // 提取变量名
                 let var_names: Vec<String> = variables.iter()
                     .map(|target| match target {
@@ -828,13 +831,12 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::Data { values: _ } => {
-                // DATA 语句在程序加载时处理，执行时跳过
-                // 数据已经存储在 data_values 中
+            StatementKind::Data { values: _ } => {
+                // DATA 的值在 RUN / RESTORE 时收集，执行时跳过
                 Ok(())
             }
             
-            Statement::Read { variables } => {
+            StatementKind::Read { variables } => {
                 for target in variables {
                     let var_name = match target {
                         AssignTarget::Variable(name) => name.as_str(),
@@ -869,21 +871,15 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::Restore { line_number } => {
-                if line_number.is_some() {
-                    // RESTORE 到指定行（暂不支持，需要跟踪每行的 DATA 位置）
-                    return Err(BasicError::SyntaxError(
-                        "RESTORE to specific line not yet implemented".to_string()
-                    ));
-                }
-                self.restore_data();
+            StatementKind::Restore { line_number } => {
+                self.restore_data_to(*line_number);
                 Ok(())
             }
             
-            Statement::For { var, start, end, step } => {
+            StatementKind::For { var, start, end, step } => {
                 // 计算起始值、结束值和步长
-                let start_val = self.eval_expr(&start)?;
-                let end_val = self.eval_expr(&end)?;
+                let start_val = self.eval_expr(start)?;
+                let end_val = self.eval_expr(end)?;
                 let step_val = if let Some(ref s) = step {
                     self.eval_expr(s)?
                 } else {
@@ -924,7 +920,7 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::Next { var } => {
+            StatementKind::Next { var } => {
                 // 弹出 FOR 循环信息
                 let (loop_var, end_val, step_val, loop_line, loop_stmt) = 
                     self.runtime.pop_for_loop(var.clone())?;
@@ -963,9 +959,9 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::On { expr, targets, is_gosub } => {
+            StatementKind::On { expr, targets, is_gosub } => {
                 // 计算表达式的值
-                let index_val = self.eval_expr(&expr)?;
+                let index_val = self.eval_expr(expr)?;
                 let index = index_val.as_number()? as i32;
                 
                 // 索引从 1 开始
@@ -981,7 +977,8 @@ impl Executor {
                     // ON...GOSUB：保存返回地址并跳转
                     let return_line = self.runtime.get_current_line()
                         .ok_or_else(|| BasicError::SyntaxError("GOSUB without line number".to_string()))?;
-                    let return_stmt = self.runtime.get_current_stmt_index();
+                    // 与 GOSUB 相同，保存 ON 语句本身的索引，RETURN 回到它的下一条语句
+                    let return_stmt = self.runtime.get_current_stmt_index().saturating_sub(1);
                     
                     self.runtime.push_gosub(return_line, return_stmt)?;
                     self.runtime.set_execution_position(target_line, 0)?;
@@ -993,31 +990,61 @@ impl Executor {
                 Ok(())
             }
             
-            Statement::Load { filename } => {
+            StatementKind::Load { filename } => {
                 self.execute_load(filename)?;
                 Ok(())
             }
             
-            Statement::Save { filename } => {
+            StatementKind::Save { filename } => {
                 self.execute_save(filename)?;
                 Ok(())
             }
             
-            Statement::Get { variable } => {
+            StatementKind::Merge { filename } => {
+                self.execute_merge(filename)?;
+                Ok(())
+            }
+            
+            StatementKind::Chain { filename, line_number } => {
+                self.execute_chain(filename, *line_number)?;
+                Ok(())
+            }
+            
+            StatementKind::Common { variables } => {
+                for var in variables {
+                    if !self.common_variables.contains(var) {
+                        self.common_variables.push(var.clone());
+                    }
+                }
+                Ok(())
+            }
+            
+            StatementKind::Get { variable } => {
                 self.execute_get(variable)?;
                 Ok(())
             }
             
-            Statement::Null => {
+            StatementKind::Null => {
                 // NULL 语句：无操作，直接返回
                 Ok(())
             }
             
-            Statement::DefFn { name, param, body } => {
+            StatementKind::DefFn { name, param, body } => {
                 self.execute_def_fn(name, param, body)?;
                 Ok(())
             }
             
+            StatementKind::Poke { address, value } => {
+                let address = self.eval_address(address)?;
+                let value = self.eval_byte(value)?;
+                self.poke(address, value);
+                Ok(())
+            }
+            
+            StatementKind::Wait { address, mask, value } => {
+                self.execute_wait(address, mask, value.as_ref())
+            }
+            
             _ => {
                 // 其他语句暂未实现
                 Err(BasicError::SyntaxError(
@@ -1039,7 +1066,8 @@ impl Executor {
             self.output("? ");
         }
         
-        // 确保输出被刷新到终端
+        // 确保输出被刷新到终端（包括虚拟屏幕上的提示符）
+        self.flush_screen();
         io::stdout().flush().map_err(|e| {
             BasicError::SyntaxError(format!("Failed to flush stdout: {}", e))
         })?;
@@ -1051,13 +1079,17 @@ impl Executor {
                 BasicError::SyntaxError("No input provided".to_string())
             })?
         } else {
-            // 从 stdin 读取输入
+            // 从 stdin 读取输入（INPUT 需要终端的行编辑，先退出 GET 的 cbreak 模式）
+            keyboard::restore_terminal();
+            self.keyboard.clear();
             let mut buffer = String::new();
             io::stdin().read_line(&mut buffer).map_err(|e| {
                 BasicError::SyntaxError(format!("Failed to read input: {}", e))
             })?;
             buffer.trim().to_string()
         };
+        let charset = self.charset;
+        let input_line: String = input_line.chars().map(|ch| charset.translate_input(ch)).collect();
         
         // 解析输入值（考虑引号内的逗号）
         let values = Self::parse_input_values(&input_line);
@@ -1146,7 +1178,7 @@ impl Executor {
         })?;
         
         for (_, line) in program.iter() {
-            let line_text = Self::serialize_program_line(line);
+            let line_text = self.line_text(line);
             writeln!(file, "{}", line_text).map_err(|e| {
                 BasicError::SyntaxError(format!("Failed to write to file: {}", e))
             })?;
@@ -1157,25 +1189,18 @@ impl Executor {
     
     /// 执行 GET 命令 - 读取单字符输入（不等待回车）
     fn execute_get(&mut self, variable: &str) -> Result<()> {
-        use std::io::{self, Read};
-        
-        // GET 从 stdin 读取单个字符，不等待回车
-        // 注意：这在标准终端中可能不工作，因为终端通常是行缓冲的
+        // GET 不等待回车：没有按键时立即得到空字符串
         // 对于测试，我们可以使用输入回调
-        
         let ch = if let Some(ref mut callback) = self.input_callback {
             // 如果有回调，使用回调
             let input = callback("").unwrap_or_default();
-            input.chars().next().unwrap_or('\0')
+            let ch = input.chars().next().unwrap_or('\0');
+            self.charset.translate_input(ch)
         } else {
-            // 尝试从 stdin 读取单个字符（非阻塞）
-            // 由于标准库的限制，这里简化处理：读取一个字符，如果失败则返回空
-            let mut buffer = [0u8; 1];
-            if io::stdin().read_exact(&mut buffer).is_ok() {
-                buffer[0] as char
-            } else {
-                '\0'  // 无输入时返回空字符
-            }
+            // 从键盘队列取一个按键，方向键和功能键按字符集转换为控制码
+            self.keyboard.poll()
+                .and_then(|key| self.charset.translate_key(key))
+                .unwrap_or('\0')
         };
         
         // 根据变量类型赋值
@@ -1187,8 +1212,8 @@ impl Executor {
                 self.variables.set(variable, Value::String(ch.to_string()))?;
             }
         } else {
-            // 数值变量：存储 ASCII 码
-            let ascii = if ch == '\0' { 0.0 } else { ch as u8 as f64 };
+            // 数值变量：存储字符码
+            let ascii = if ch == '\0' { 0.0 } else { self.charset.from_char(ch).unwrap_or(0) as f64 };
             self.variables.set(variable, Value::Number(ascii))?;
         }
         
@@ -1207,7 +1232,7 @@ impl Executor {
         
         for (i, stmt) in line.statements.iter().enumerate() {
             if i > 0 {
-                result.push_str(":");
+                result.push(':');
             }
             result.push(' ');
             result.push_str(&Self::serialize_statement(stmt));
@@ -1218,11 +1243,11 @@ impl Executor {
     
     /// 将语句序列化为文本
     pub fn serialize_statement(stmt: &Statement) -> String {
-        match stmt {
-            Statement::Let { target, value } => {
+        match &stmt.kind {
+            StatementKind::Let { target, value } => {
                 format!("{} = {}", Self::serialize_assign_target(target), Self::serialize_expr(value))
             }
-            Statement::Print { items } => {
+            StatementKind::Print { items } => {
                 let mut result = "PRINT".to_string();
                 for item in items.iter() {
                     result.push(' ');
@@ -1230,31 +1255,31 @@ impl Executor {
                 }
                 result
             }
-            Statement::If { condition, then_part } => {
+            StatementKind::If { condition, then_part } => {
                 format!("IF {} THEN {}", Self::serialize_expr(condition), Self::serialize_then_part(then_part))
             }
-            Statement::Goto { line_number } => {
+            StatementKind::Goto { line_number } => {
                 format!("GOTO {}", Self::serialize_expr(line_number))
             }
-            Statement::Gosub { line_number } => {
+            StatementKind::Gosub { line_number } => {
                 format!("GOSUB {}", Self::serialize_expr(line_number))
             }
-            Statement::Return => "RETURN".to_string(),
-            Statement::For { var, start, end, step } => {
+            StatementKind::Return => "RETURN".to_string(),
+            StatementKind::For { var, start, end, step } => {
                 let mut result = format!("FOR {} = {} TO {}", var, Self::serialize_expr(start), Self::serialize_expr(end));
                 if let Some(step_expr) = step {
                     result.push_str(&format!(" STEP {}", Self::serialize_expr(step_expr)));
                 }
                 result
             }
-            Statement::Next { var } => {
+            StatementKind::Next { var } => {
                 if let Some(v) = var {
                     format!("NEXT {}", v)
                 } else {
                     "NEXT".to_string()
                 }
             }
-            Statement::On { expr, targets, is_gosub } => {
+            StatementKind::On { expr, targets, is_gosub } => {
                 let keyword = if *is_gosub { "GOSUB" } else { "GOTO" };
                 let target_str = targets.iter()
                     .map(|t| t.to_string())
@@ -1262,23 +1287,23 @@ impl Executor {
                     .join(",");
                 format!("ON {} {} {}", Self::serialize_expr(expr), keyword, target_str)
             }
-            Statement::Input { prompt, variables } => {
+            StatementKind::Input { prompt, variables } => {
                 let mut result = "INPUT ".to_string();
                 if let Some(p) = prompt {
-                    result.push_str(&format!("\"{}\" ", p));
+                    result.push_str(&format!("\"{}\"; ", p));
                 }
                 let var_str = variables.iter()
-                    .map(|v| Self::serialize_assign_target(v))
+                    .map(Self::serialize_assign_target)
                     .collect::<Vec<_>>()
                     .join(", ");
                 result.push_str(&var_str);
                 result
             }
-            Statement::Dim { arrays } => {
+            StatementKind::Dim { arrays } => {
                 let arr_str = arrays.iter()
                     .map(|arr| {
                         let dims = arr.dimensions.iter()
-                            .map(|d| Self::serialize_expr(d))
+                            .map(Self::serialize_expr)
                             .collect::<Vec<_>>()
                             .join(",");
                         format!("{}({})", arr.name, dims)
@@ -1287,7 +1312,7 @@ impl Executor {
                     .join(", ");
                 format!("DIM {}", arr_str)
             }
-            Statement::Data { values } => {
+            StatementKind::Data { values } => {
                 let val_str = values.iter()
                     .map(|v| match v {
                         crate::ast::DataValue::Number(n) => n.to_string(),
@@ -1297,56 +1322,103 @@ impl Executor {
                     .join(", ");
                 format!("DATA {}", val_str)
             }
-            Statement::Read { variables } => {
+            StatementKind::Read { variables } => {
                 let var_str = variables.iter()
-                    .map(|v| Self::serialize_assign_target(v))
+                    .map(Self::serialize_assign_target)
                     .collect::<Vec<_>>()
                     .join(", ");
                 format!("READ {}", var_str)
             }
-            Statement::Restore { line_number } => {
+            StatementKind::Restore { line_number } => {
                 if let Some(ln) = line_number {
                     format!("RESTORE {}", ln)
                 } else {
                     "RESTORE".to_string()
                 }
             }
-            Statement::Rem { comment } => {
+            StatementKind::Rem { comment } => {
                 if comment.is_empty() {
                     "REM".to_string()
                 } else {
                     format!("REM {}", comment)
                 }
             }
-            Statement::End => "END".to_string(),
-            Statement::Stop => "STOP".to_string(),
-            Statement::New => "NEW".to_string(),
-            Statement::Clear => "CLEAR".to_string(),
-            _ => "REM UNSUPPORTED STATEMENT".to_string(),
+            StatementKind::DefFn { name, param, body } => {
+                format!("DEF FN {}({}) = {}", name, param, Self::serialize_expr(body))
+            }
+            StatementKind::End => "END".to_string(),
+            StatementKind::Stop => "STOP".to_string(),
+            StatementKind::New => "NEW".to_string(),
+            StatementKind::Clear => "CLEAR".to_string(),
+            StatementKind::List { start, end } => {
+                match (start, end) {
+                    (None, None) => "LIST".to_string(),
+                    (Some(s), None) => format!("LIST {}-", s),
+                    (None, Some(e)) => format!("LIST -{}", e),
+                    (Some(s), Some(e)) => format!("LIST {}-{}", s, e),
+                }
+            }
+            StatementKind::Run { line_number } => {
+                if let Some(ln) = line_number {
+                    format!("RUN {}", ln)
+                } else {
+                    "RUN".to_string()
+                }
+            }
+            StatementKind::Cont => "CONT".to_string(),
+            StatementKind::Poke { address, value } => {
+                format!("POKE {}, {}", Self::serialize_expr(address), Self::serialize_expr(value))
+            }
+            StatementKind::Wait { address, mask, value } => {
+                let mut result = format!("WAIT {}, {}", Self::serialize_expr(address), Self::serialize_expr(mask));
+                if let Some(v) = value {
+                    result.push_str(&format!(", {}", Self::serialize_expr(v)));
+                }
+                result
+            }
+            StatementKind::Get { variable } => format!("GET {}", variable),
+            StatementKind::Null => "NULL".to_string(),
+            StatementKind::Load { filename } => format!("LOAD \"{}\"", filename),
+            StatementKind::Save { filename } => format!("SAVE \"{}\"", filename),
+            StatementKind::Merge { filename } => format!("MERGE \"{}\"", filename),
+            StatementKind::Chain { filename, line_number } => match line_number {
+                Some(n) => format!("CHAIN \"{}\",{}", filename, n),
+                None => format!("CHAIN \"{}\"", filename),
+            },
+            StatementKind::Common { variables } => {
+                let names: Vec<String> = variables.iter()
+                    .map(|var| if var.is_array { format!("{}()", var.name) } else { var.name.clone() })
+                    .collect();
+                format!("COMMON {}", names.join(", "))
+            }
         }
     }
     
     /// 将表达式序列化为文本
     pub fn serialize_expr(expr: &Expr) -> String {
-        match expr {
-            Expr::Number(n) => n.to_string(),
-            Expr::String(s) => format!("\"{}\"", s),
-            Expr::Variable(name) => name.clone(),
-            Expr::ArrayAccess { name, indices } => {
+        match &expr.kind {
+            ExprKind::Number(n) => n.to_string(),
+            ExprKind::String(s) => format!("\"{}\"", s),
+            ExprKind::Variable(name) => name.clone(),
+            ExprKind::ArrayAccess { name, indices } => {
                 let idx_str = indices.iter()
-                    .map(|i| Self::serialize_expr(i))
+                    .map(Self::serialize_expr)
                     .collect::<Vec<_>>()
                     .join(",");
                 format!("{}({})", name, idx_str)
             }
-            Expr::FunctionCall { name, args } => {
+            ExprKind::FunctionCall { name, args } => {
                 let arg_str = args.iter()
-                    .map(|a| Self::serialize_expr(a))
+                    .map(Self::serialize_expr)
                     .collect::<Vec<_>>()
                     .join(",");
-                format!("{}({})", name, arg_str)
+                // 用户函数以 "FNname" 存储，输出时还原为 "FN name" 以便重新解析
+                match name.strip_prefix("FN") {
+                    Some(func_name) if !func_name.is_empty() => format!("FN {}({})", func_name, arg_str),
+                    _ => format!("{}({})", name, arg_str),
+                }
             }
-            Expr::BinaryOp { left, op, right } => {
+            ExprKind::BinaryOp { left, op, right } => {
                 let op_str = match op {
                     BinaryOperator::Add => "+",
                     BinaryOperator::Subtract => "-",
@@ -1364,7 +1436,7 @@ impl Executor {
                 };
                 format!("({} {} {})", Self::serialize_expr(left), op_str, Self::serialize_expr(right))
             }
-            Expr::UnaryOp { op, operand } => {
+            ExprKind::UnaryOp { op, operand } => {
                 let op_str = match op {
                     UnaryOperator::Minus => "-",
                     UnaryOperator::Not => "NOT ",
@@ -1380,7 +1452,7 @@ impl Executor {
             AssignTarget::Variable(name) => name.clone(),
             AssignTarget::ArrayElement { name, indices } => {
                 let idx_str = indices.iter()
-                    .map(|i| Self::serialize_expr(i))
+                    .map(Self::serialize_expr)
                     .collect::<Vec<_>>()
                     .join(",");
                 format!("{}({})", name, idx_str)
@@ -1395,7 +1467,7 @@ impl Executor {
             ThenPart::Statement(stmt) => Self::serialize_statement(stmt),
             ThenPart::Statements(stmts) => {
                 stmts.iter()
-                    .map(|s| Self::serialize_statement(s))
+                    .map(Self::serialize_statement)
                     .collect::<Vec<_>>()
                     .join(":")
             }
@@ -1410,11 +1482,71 @@ impl Executor {
             PrintItem::Spc(expr) => format!("SPC({})", Self::serialize_expr(expr)),
             PrintItem::Comma => ",".to_string(),
             PrintItem::Semicolon => ";".to_string(),
+            PrintItem::Using { format, values } => {
+                let values: Vec<String> = values.iter().map(Self::serialize_expr).collect();
+                format!("USING {}; {}", Self::serialize_expr(format), values.join(", "))
+            }
         }
     }
     
     /// 执行 LOAD 命令 - 从文件加载程序
     fn execute_load(&mut self, filename: &str) -> Result<()> {
+        let lines = Self::read_program_file(filename)?;
+        
+        // 清空当前程序
+        self.runtime.clear_program();
+        self.variables.clear();
+        self.common_variables.clear();
+        
+        for (program_line, source) in lines {
+            self.runtime.add_line_with_source(program_line, source);
+        }
+        self.restore_data();
+        
+        Ok(())
+    }
+    
+    /// 执行 MERGE 命令 - 把文件中的行合并到当前程序
+    ///
+    /// 同号的行被文件中的行覆盖；变量、DEF FN 和 COMMON 声明保持不变，
+    /// DATA 按合并后的程序顺序重建，DATA 指针回到开头
+    fn execute_merge(&mut self, filename: &str) -> Result<()> {
+        let lines = Self::read_program_file(filename)?;
+        
+        for (program_line, source) in lines {
+            self.runtime.add_line_with_source(program_line, source);
+        }
+        self.restore_data();
+        
+        Ok(())
+    }
+    
+    /// 执行 CHAIN 命令 - 加载另一个程序并从指定行（默认第一行）开始运行
+    ///
+    /// 变量保留：执行过 COMMON 时只保留 COMMON 声明的变量和数组，否则保留全部。
+    /// DEF FN 定义和 COMMON 声明被清除，DATA 由新程序重建，DATA 指针回到开头
+    fn execute_chain(&mut self, filename: &str, line_number: Option<u16>) -> Result<()> {
+        let lines = Self::read_program_file(filename)?;
+        
+        self.runtime.clear_program();
+        for (program_line, source) in lines {
+            self.runtime.add_line_with_source(program_line, source);
+        }
+        self.restore_data();
+        
+        if !self.common_variables.is_empty() {
+            self.variables.retain_common(&self.common_variables);
+        }
+        self.variables.clear_functions();
+        self.common_variables.clear();
+        
+        self.runtime.start_execution(line_number)
+    }
+    
+    /// 读取并解析程序文件，返回程序行及其原始文本
+    ///
+    /// 整个文件解析成功后才返回，解析出错时当前程序不受影响
+    pub fn read_program_file(filename: &str) -> Result<Vec<(ProgramLine, String)>> {
         use std::fs;
         use crate::tokenizer::Tokenizer;
         use crate::parser::Parser;
@@ -1424,45 +1556,27 @@ impl Executor {
             BasicError::SyntaxError(format!("Failed to read file: {}", e))
         })?;
         
-        // 清空当前程序
-        self.runtime.clear_program();
-        self.variables.clear();
-        self.data_values.clear();
-        self.data_pointer = 0;
-        
-        // 逐行解析并添加到程序
-        for line in content.lines() {
-            let line = line.trim();
+        // 逐行解析
+        let mut lines = Vec::new();
+        for raw_line in content.lines() {
+            let line = raw_line.trim();
             if line.is_empty() {
                 continue;
             }
             
-            // 使用tokenizer和parser解析每一行
+            // 使用tokenizer和parser解析每一行（带源码范围，供运行时错误定位）
             let mut tokenizer = Tokenizer::new(line);
-            let tokens = tokenizer.tokenize_line()?;
+            let tokens = tokenizer.tokenize_line_spanned()?;
             
-            let mut parser = Parser::new(tokens);
+            let mut parser = Parser::new_spanned(tokens);
             if let Some(program_line) = parser.parse_line()? {
                 if program_line.line_number > 0 {
-                    // 在添加之前，收集所有 DATA 语句的值
-                    for stmt in &program_line.statements {
-                        if let Statement::Data { values } = stmt {
-                            for value in values {
-                                // 转换 ast::DataValue 到 executor::DataValue
-                                let exec_value = match value {
-                                    crate::ast::DataValue::Number(n) => DataValue::Number(*n),
-                                    crate::ast::DataValue::String(s) => DataValue::String(s.clone()),
-                                };
-                                self.add_data_value(exec_value);
-                            }
-                        }
-                    }
-                    self.runtime.add_line(program_line);
+                    lines.push((program_line, raw_line.trim_start().to_string()));
                 }
             }
         }
         
-        Ok(())
+        Ok(lines)
     }
     
     /// 执行 PRINT 语句
@@ -1503,6 +1617,14 @@ impl Executor {
                 PrintItem::Semicolon => {
                     // 分号：不添加空格（紧密连接）
                 }
+                PrintItem::Using { format, values } => {
+                    let format = self.eval_expr(format)?.as_string()?;
+                    let values = values.iter()
+                        .map(|expr| self.eval_expr(expr))
+                        .collect::<Result<Vec<_>>>()?;
+                    let text = crate::print_using::format_using(&format, &values)?;
+                    self.output(&text);
+                }
             }
         }
         
@@ -1555,9 +1677,9 @@ mod tests {
     fn test_addition() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::Number(5.0),
+            Expr::number(5.0),
             BinaryOperator::Add,
-            Expr::Number(3.0)
+            Expr::number(3.0)
         );
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(8.0));
@@ -1568,9 +1690,9 @@ mod tests {
     fn test_subtraction() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::Number(10.0),
+            Expr::number(10.0),
             BinaryOperator::Subtract,
-            Expr::Number(7.0)
+            Expr::number(7.0)
         );
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(3.0));
@@ -1581,9 +1703,9 @@ mod tests {
     fn test_multiplication() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::Number(4.0),
+            Expr::number(4.0),
             BinaryOperator::Multiply,
-            Expr::Number(5.0)
+            Expr::number(5.0)
         );
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(20.0));
@@ -1594,9 +1716,9 @@ mod tests {
     fn test_division() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::Number(15.0),
+            Expr::number(15.0),
             BinaryOperator::Divide,
-            Expr::Number(3.0)
+            Expr::number(3.0)
         );
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(5.0));
@@ -1607,9 +1729,9 @@ mod tests {
     fn test_float_division() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::Number(10.0),
+            Expr::number(10.0),
             BinaryOperator::Divide,
-            Expr::Number(4.0)
+            Expr::number(4.0)
         );
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(2.5));
@@ -1620,9 +1742,9 @@ mod tests {
     fn test_division_by_zero() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::Number(5.0),
+            Expr::number(5.0),
             BinaryOperator::Divide,
-            Expr::Number(0.0)
+            Expr::number(0.0)
         );
         let result = exec.eval_expr(&expr);
         assert!(result.is_err());
@@ -1634,9 +1756,9 @@ mod tests {
     fn test_power() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::Number(2.0),
+            Expr::number(2.0),
             BinaryOperator::Power,
-            Expr::Number(3.0)
+            Expr::number(3.0)
         );
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(8.0));
@@ -1646,7 +1768,7 @@ mod tests {
     #[test]
     fn test_unary_minus() {
         let mut exec = Executor::new();
-        let expr = Expr::unary(UnaryOperator::Minus, Expr::Number(5.0));
+        let expr = Expr::unary(UnaryOperator::Minus, Expr::number(5.0));
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(-5.0));
     }
@@ -1656,9 +1778,9 @@ mod tests {
     fn test_equal() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::Number(5.0),
+            Expr::number(5.0),
             BinaryOperator::Equal,
-            Expr::Number(5.0)
+            Expr::number(5.0)
         );
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(-1.0)); // BASIC true = -1
@@ -1669,9 +1791,9 @@ mod tests {
     fn test_not_equal() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::Number(5.0),
+            Expr::number(5.0),
             BinaryOperator::NotEqual,
-            Expr::Number(4.0)
+            Expr::number(4.0)
         );
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(-1.0));
@@ -1682,9 +1804,9 @@ mod tests {
     fn test_string_concatenation() {
         let mut exec = Executor::new();
         let expr = Expr::binary(
-            Expr::String("HELLO".to_string()),
+            Expr::string("HELLO".to_string()),
             BinaryOperator::Add,
-            Expr::String(" WORLD".to_string())
+            Expr::string(" WORLD".to_string())
         );
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::String("HELLO WORLD".to_string()));
@@ -1696,7 +1818,7 @@ mod tests {
         let mut exec = Executor::new();
         exec.variables.set("A", Value::Number(42.0)).unwrap();
         
-        let expr = Expr::Variable("A".to_string());
+        let expr = Expr::variable("A".to_string());
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(42.0));
     }
@@ -1706,10 +1828,10 @@ mod tests {
     fn test_let_statement() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Let {
+        let stmt = StatementKind::Let {
             target: AssignTarget::Variable("X".to_string()),
-            value: Expr::Number(100.0),
-        };
+            value: Expr::number(100.0),
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert_eq!(exec.variables.get("X"), Value::Number(100.0));
@@ -1720,14 +1842,14 @@ mod tests {
     fn test_dim_statement() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Dim {
+        let stmt = StatementKind::Dim {
             arrays: vec![
                 ArrayDim {
                     name: "A".to_string(),
-                    dimensions: vec![Expr::Number(10.0)],
+                    dimensions: vec![Expr::number(10.0)],
                 }
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert!(exec.variables.has_array("A"));
@@ -1739,18 +1861,18 @@ mod tests {
         let mut exec = Executor::new();
         
         // ABS
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "ABS".to_string(),
-            args: vec![Expr::Number(-42.0)],
-        };
+            args: vec![Expr::number(-42.0)],
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(42.0));
         
         // INT
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "INT".to_string(),
-            args: vec![Expr::Number(3.7)],
-        };
+            args: vec![Expr::number(3.7)],
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(3.0));
     }
@@ -1761,39 +1883,39 @@ mod tests {
         let mut exec = Executor::new();
         
         // RND(1) - 返回 [0, 1) 的随机数
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "RND".to_string(),
-            args: vec![Expr::Number(1.0)],
-        };
+            args: vec![Expr::number(1.0)],
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         let value = result.as_number().unwrap();
-        assert!(value >= 0.0 && value < 1.0, "RND(1) should return [0, 1), got {}", value);
+        assert!((0.0..1.0).contains(&value), "RND(1) should return [0, 1), got {}", value);
         
         // RND(0) - 也返回 [0, 1) 的随机数
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "RND".to_string(),
-            args: vec![Expr::Number(0.0)],
-        };
+            args: vec![Expr::number(0.0)],
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         let value = result.as_number().unwrap();
-        assert!(value >= 0.0 && value < 1.0, "RND(0) should return [0, 1), got {}", value);
+        assert!((0.0..1.0).contains(&value), "RND(0) should return [0, 1), got {}", value);
         
         // RND(-1) - 负数参数也返回随机数
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "RND".to_string(),
-            args: vec![Expr::Number(-1.0)],
-        };
+            args: vec![Expr::number(-1.0)],
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         let value = result.as_number().unwrap();
-        assert!(value >= 0.0 && value < 1.0, "RND(-1) should return [0, 1), got {}", value);
+        assert!((0.0..1.0).contains(&value), "RND(-1) should return [0, 1), got {}", value);
         
         // 测试随机性：生成多个值，应该不全相同
         let mut values = Vec::new();
         for _ in 0..10 {
-            let expr = Expr::FunctionCall {
+            let expr = ExprKind::FunctionCall {
                 name: "RND".to_string(),
-                args: vec![Expr::Number(1.0)],
-            };
+                args: vec![Expr::number(1.0)],
+            }.into();
             let result = exec.eval_expr(&expr).unwrap();
             values.push(result.as_number().unwrap());
         }
@@ -1813,33 +1935,33 @@ mod tests {
         let mut dice_values = Vec::new();
         for _ in 0..20 {
             // RND(1)*6
-            let rnd_expr = Expr::FunctionCall {
+            let rnd_expr = ExprKind::FunctionCall {
                 name: "RND".to_string(),
-                args: vec![Expr::Number(1.0)],
-            };
-            let multiply_expr = Expr::BinaryOp {
+                args: vec![Expr::number(1.0)],
+            }.into();
+            let multiply_expr = ExprKind::BinaryOp {
                 left: Box::new(rnd_expr),
                 op: BinaryOperator::Multiply,
-                right: Box::new(Expr::Number(6.0)),
-            };
+                right: Box::new(Expr::number(6.0)),
+            }.into();
             // INT(RND(1)*6)
-            let int_expr = Expr::FunctionCall {
+            let int_expr = ExprKind::FunctionCall {
                 name: "INT".to_string(),
                 args: vec![multiply_expr],
-            };
+            }.into();
             // INT(RND(1)*6)+1
-            let dice_expr = Expr::BinaryOp {
+            let dice_expr = ExprKind::BinaryOp {
                 left: Box::new(int_expr),
                 op: BinaryOperator::Add,
-                right: Box::new(Expr::Number(1.0)),
-            };
+                right: Box::new(Expr::number(1.0)),
+            }.into();
             
             let result = exec.eval_expr(&dice_expr).unwrap();
             let value = result.as_number().unwrap() as i32;
             dice_values.push(value);
             
             // 验证范围
-            assert!(value >= 1 && value <= 6, "Dice value should be 1-6, got {}", value);
+            assert!((1..=6).contains(&value), "Dice value should be 1-6, got {}", value);
         }
         
         // 验证分布（至少应该有3个不同的值）
@@ -1855,83 +1977,83 @@ mod tests {
         let mut exec = Executor::new();
         
         // LEN
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "LEN".to_string(),
-            args: vec![Expr::String("HELLO".to_string())],
-        };
+            args: vec![Expr::string("HELLO".to_string())],
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(5.0));
         
         // LEFT$
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "LEFT$".to_string(),
             args: vec![
-                Expr::String("HELLO".to_string()),
-                Expr::Number(3.0),
+                Expr::string("HELLO".to_string()),
+                Expr::number(3.0),
             ],
-        };
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::String("HEL".to_string()));
         
         // INSTR - 两个参数形式
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "INSTR".to_string(),
             args: vec![
-                Expr::String("HELLO".to_string()),
-                Expr::String("L".to_string()),
+                Expr::string("HELLO".to_string()),
+                Expr::string("L".to_string()),
             ],
-        };
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(3.0)); // "L" 在 "HELLO" 中的位置是 3
         
         // INSTR - 三个参数形式（从指定位置开始）
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "INSTR".to_string(),
             args: vec![
-                Expr::Number(1.0),
-                Expr::String("HELLO".to_string()),
-                Expr::String("L".to_string()),
+                Expr::number(1.0),
+                Expr::string("HELLO".to_string()),
+                Expr::string("L".to_string()),
             ],
-        };
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(3.0));
         
         // INSTR - 从位置 4 开始查找
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "INSTR".to_string(),
             args: vec![
-                Expr::Number(4.0),
-                Expr::String("HELLO".to_string()),
-                Expr::String("L".to_string()),
+                Expr::number(4.0),
+                Expr::string("HELLO".to_string()),
+                Expr::string("L".to_string()),
             ],
-        };
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(4.0)); // 从位置 4 开始，"L" 在位置 4
         
         // INSTR - 找不到的情况
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "INSTR".to_string(),
             args: vec![
-                Expr::String("HELLO".to_string()),
-                Expr::String("X".to_string()),
+                Expr::string("HELLO".to_string()),
+                Expr::string("X".to_string()),
             ],
-        };
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::Number(0.0)); // 没找到返回 0
         
         // SPACE$
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "SPACE$".to_string(),
-            args: vec![Expr::Number(5.0)],
-        };
+            args: vec![Expr::number(5.0)],
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::String("     ".to_string())); // 5 个空格
         
         // SPACE$(0) - 空字符串
-        let expr = Expr::FunctionCall {
+        let expr = ExprKind::FunctionCall {
             name: "SPACE$".to_string(),
-            args: vec![Expr::Number(0.0)],
-        };
+            args: vec![Expr::number(0.0)],
+        }.into();
         let result = exec.eval_expr(&expr).unwrap();
         assert_eq!(result, Value::String("".to_string()));
     }
@@ -1943,12 +2065,12 @@ mod tests {
         
         // 2 + 3 * 4 = 14
         let expr = Expr::binary(
-            Expr::Number(2.0),
+            Expr::number(2.0),
             BinaryOperator::Add,
             Expr::binary(
-                Expr::Number(3.0),
+                Expr::number(3.0),
                 BinaryOperator::Multiply,
-                Expr::Number(4.0)
+                Expr::number(4.0)
             )
         );
         
@@ -1961,11 +2083,11 @@ mod tests {
     fn test_print_number() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::Number(42.0)),
+                PrintItem::Expr(Expr::number(42.0)),
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert_eq!(exec.get_output(), " 42 \n");
@@ -1976,11 +2098,11 @@ mod tests {
     fn test_print_string() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::String("HELLO".to_string())),
+                PrintItem::Expr(Expr::string("HELLO".to_string())),
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert_eq!(exec.get_output(), "HELLO\n");
@@ -1992,11 +2114,11 @@ mod tests {
         let mut exec = Executor::new();
         exec.variables.set("A", Value::Number(10.0)).unwrap();
         
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::Variable("A".to_string())),
+                PrintItem::Expr(Expr::variable("A".to_string())),
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert_eq!(exec.get_output(), " 10 \n");
@@ -2007,15 +2129,15 @@ mod tests {
     fn test_print_semicolon() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::Number(1.0)),
+                PrintItem::Expr(Expr::number(1.0)),
                 PrintItem::Semicolon,
-                PrintItem::Expr(Expr::Number(2.0)),
+                PrintItem::Expr(Expr::number(2.0)),
                 PrintItem::Semicolon,
-                PrintItem::Expr(Expr::Number(3.0)),
+                PrintItem::Expr(Expr::number(3.0)),
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert_eq!(exec.get_output(), " 1  2  3 \n");
@@ -2026,12 +2148,12 @@ mod tests {
     fn test_print_no_newline() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::Number(42.0)),
+                PrintItem::Expr(Expr::number(42.0)),
                 PrintItem::Semicolon,
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert_eq!(exec.get_output(), " 42 ");
@@ -2042,9 +2164,9 @@ mod tests {
     fn test_print_empty() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert_eq!(exec.get_output(), "\n");
@@ -2055,13 +2177,13 @@ mod tests {
     fn test_print_comma_alignment() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::Number(1.0)),
+                PrintItem::Expr(Expr::number(1.0)),
                 PrintItem::Comma,
-                PrintItem::Expr(Expr::Number(2.0)),
+                PrintItem::Expr(Expr::number(2.0)),
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         let output = exec.get_output();
@@ -2078,22 +2200,22 @@ mod tests {
         // 添加程序行
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Let {
+            statements: vec![StatementKind::Let {
                 target: AssignTarget::Variable("A".to_string()),
-                value: Expr::Number(1.0),
-            }]
+                value: Expr::number(1.0),
+            }.into()]
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 100,
-            statements: vec![Statement::Let {
+            statements: vec![StatementKind::Let {
                 target: AssignTarget::Variable("B".to_string()),
-                value: Expr::Number(99.0),
-            }]
+                value: Expr::number(99.0),
+            }.into()]
         });
         
-        let stmt = Statement::Goto {
-            line_number: Expr::Number(100.0),
-        };
+        let stmt = StatementKind::Goto {
+            line_number: Expr::number(100.0),
+        }.into();
         exec.execute_statement(&stmt).unwrap();
         
         // 验证跳转成功（下一行应该是 100）
@@ -2108,24 +2230,24 @@ mod tests {
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 100,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         
         // 启动执行来设置初始状态
         exec.runtime_mut().start_execution(Some(10)).unwrap();
         
-        let stmt = Statement::If {
+        let stmt = StatementKind::If {
             condition: Expr::binary(
-                Expr::Variable("A".to_string()),
+                Expr::variable("A".to_string()),
                 BinaryOperator::Greater,
-                Expr::Number(10.0),
+                Expr::number(10.0),
             ),
             then_part: Box::new(ThenPart::LineNumber(100)),
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert_eq!(exec.runtime().get_current_line(), Some(100));
@@ -2144,14 +2266,14 @@ mod tests {
         
         let current_line = exec.runtime().get_current_line();
         
-        let stmt = Statement::If {
+        let stmt = StatementKind::If {
             condition: Expr::binary(
-                Expr::Variable("A".to_string()),
+                Expr::variable("A".to_string()),
                 BinaryOperator::Greater,
-                Expr::Number(10.0),
+                Expr::number(10.0),
             ),
             then_part: Box::new(ThenPart::LineNumber(100)),
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         // 条件为假，不应该跳转
@@ -2164,20 +2286,20 @@ mod tests {
         let mut exec = Executor::new();
         exec.variables.set("A", Value::Number(15.0)).unwrap();
         
-        let stmt = Statement::If {
+        let stmt = StatementKind::If {
             condition: Expr::binary(
-                Expr::Variable("A".to_string()),
+                Expr::variable("A".to_string()),
                 BinaryOperator::Greater,
-                Expr::Number(10.0),
+                Expr::number(10.0),
             ),
-            then_part: Box::new(ThenPart::Statement(
-                Statement::Print {
+            then_part: Box::new(ThenPart::Statement(Box::new(
+                StatementKind::Print {
                     items: vec![
-                        PrintItem::Expr(Expr::String("TRUE".to_string())),
+                        PrintItem::Expr(Expr::string("TRUE".to_string())),
                     ],
-                }
-            )),
-        };
+                }.into()
+            ))),
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         assert_eq!(exec.get_output(), "TRUE\n");
@@ -2188,15 +2310,15 @@ mod tests {
     fn test_tab_function() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::String("A".to_string())),
+                PrintItem::Expr(Expr::string("A".to_string())),
                 PrintItem::Semicolon,
-                PrintItem::Tab(Expr::Number(10.0)),
+                PrintItem::Tab(Expr::number(10.0)),
                 PrintItem::Semicolon,
-                PrintItem::Expr(Expr::String("B".to_string())),
+                PrintItem::Expr(Expr::string("B".to_string())),
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         let output = exec.get_output();
@@ -2210,15 +2332,15 @@ mod tests {
     fn test_spc_function() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::Print {
+        let stmt = StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::String("A".to_string())),
+                PrintItem::Expr(Expr::string("A".to_string())),
                 PrintItem::Semicolon,
-                PrintItem::Spc(Expr::Number(5.0)),
+                PrintItem::Spc(Expr::number(5.0)),
                 PrintItem::Semicolon,
-                PrintItem::Expr(Expr::String("B".to_string())),
+                PrintItem::Expr(Expr::string("B".to_string())),
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         let output = exec.get_output();
@@ -2233,19 +2355,19 @@ mod tests {
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 500,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         
         // 启动执行
         exec.runtime_mut().start_execution(Some(10)).unwrap();
         
-        let stmt = Statement::Gosub {
-            line_number: Expr::Number(500.0),
-        };
+        let stmt = StatementKind::Gosub {
+            line_number: Expr::number(500.0),
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -2262,15 +2384,15 @@ mod tests {
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 20,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 500,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         
         // 启动执行并设置调用栈
@@ -2278,7 +2400,7 @@ mod tests {
         exec.runtime_mut().push_gosub(20, 0).unwrap();
         exec.runtime_mut().set_execution_position(500, 0).unwrap();
         
-        let stmt = Statement::Return;
+        let stmt = StatementKind::Return.into();
         exec.execute_statement(&stmt).unwrap();
         
         // 验证返回到调用点
@@ -2294,40 +2416,40 @@ mod tests {
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 100,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 200,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         
         // 启动执行
         exec.runtime_mut().start_execution(Some(10)).unwrap();
         
         // 第一次 GOSUB
-        exec.execute_statement(&Statement::Gosub {
-            line_number: Expr::Number(100.0),
-        }).unwrap();
+        exec.execute_statement(&StatementKind::Gosub {
+            line_number: Expr::number(100.0),
+        }.into()).unwrap();
         assert_eq!(exec.runtime().stack_depth(), 1);
         
         // 第二次 GOSUB（嵌套）
-        exec.execute_statement(&Statement::Gosub {
-            line_number: Expr::Number(200.0),
-        }).unwrap();
+        exec.execute_statement(&StatementKind::Gosub {
+            line_number: Expr::number(200.0),
+        }.into()).unwrap();
         assert_eq!(exec.runtime().stack_depth(), 2);
         assert_eq!(exec.runtime().get_current_line(), Some(200));
         
         // 第一次 RETURN
-        exec.execute_statement(&Statement::Return).unwrap();
+        exec.execute_statement(&StatementKind::Return.into()).unwrap();
         assert_eq!(exec.runtime().stack_depth(), 1);
         assert_eq!(exec.runtime().get_current_line(), Some(100));
         
         // 第二次 RETURN
-        exec.execute_statement(&Statement::Return).unwrap();
+        exec.execute_statement(&StatementKind::Return.into()).unwrap();
         assert_eq!(exec.runtime().stack_depth(), 0);
         assert_eq!(exec.runtime().get_current_line(), Some(10));
     }
@@ -2340,10 +2462,10 @@ mod tests {
         // 设置输入回调
         exec.set_input_callback(|_| Some("42".to_string()));
         
-        let stmt = Statement::Input {
+        let stmt = StatementKind::Input {
             prompt: None,
             variables: vec![AssignTarget::Variable("A".to_string())],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -2361,10 +2483,10 @@ mod tests {
         
         exec.set_input_callback(|_| Some("100".to_string()));
         
-        let stmt = Statement::Input {
+        let stmt = StatementKind::Input {
             prompt: Some("ENTER VALUE".to_string()),
             variables: vec![AssignTarget::Variable("X".to_string())],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -2380,14 +2502,14 @@ mod tests {
         
         exec.set_input_callback(|_| Some("10, 20, 30".to_string()));
         
-        let stmt = Statement::Input {
+        let stmt = StatementKind::Input {
             prompt: None,
             variables: vec![
                 AssignTarget::Variable("A".to_string()),
                 AssignTarget::Variable("B".to_string()),
                 AssignTarget::Variable("C".to_string())
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -2403,10 +2525,10 @@ mod tests {
         
         exec.set_input_callback(|_| Some("HELLO".to_string()));
         
-        let stmt = Statement::Input {
+        let stmt = StatementKind::Input {
             prompt: None,
             variables: vec![AssignTarget::Variable("A$".to_string())],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -2420,33 +2542,40 @@ mod tests {
         
         exec.set_input_callback(|_| Some("\"HELLO, WORLD\"".to_string()));
         
-        let stmt = Statement::Input {
+        let stmt = StatementKind::Input {
             prompt: None,
             variables: vec![AssignTarget::Variable("A$".to_string())],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
         assert_eq!(exec.variables.get("A$"), Value::String("HELLO, WORLD".to_string()));
     }
 
+    /// 存储一行 DATA 并重新收集 DATA（测试用）
+    fn add_data_line(exec: &mut Executor, line_number: u16, values: Vec<crate::ast::DataValue>) {
+        exec.runtime_mut().add_line(ProgramLine {
+            line_number,
+            statements: vec![StatementKind::Data { values }.into()],
+        });
+        exec.restore_data();
+    }
+    
     // Requirement: DATA/READ 机制 - DATA 存储和 READ 读取
     #[test]
     fn test_data_read() {
+        use crate::ast::DataValue;
         let mut exec = Executor::new();
         
-        // 添加 DATA 值
-        exec.add_data_value(DataValue::Number(1.0));
-        exec.add_data_value(DataValue::Number(2.0));
-        exec.add_data_value(DataValue::Number(3.0));
+        add_data_line(&mut exec, 10, vec![DataValue::Number(1.0), DataValue::Number(2.0), DataValue::Number(3.0)]);
         
-        let stmt = Statement::Read {
+        let stmt = StatementKind::Read {
             variables: vec![
                 AssignTarget::Variable("A".to_string()),
                 AssignTarget::Variable("B".to_string()),
                 AssignTarget::Variable("C".to_string())
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -2458,17 +2587,17 @@ mod tests {
     // Requirement: DATA/READ 机制 - 混合数据类型
     #[test]
     fn test_data_read_mixed_types() {
+        use crate::ast::DataValue;
         let mut exec = Executor::new();
         
-        exec.add_data_value(DataValue::Number(42.0));
-        exec.add_data_value(DataValue::String("HELLO".to_string()));
+        add_data_line(&mut exec, 10, vec![DataValue::Number(42.0), DataValue::String("HELLO".to_string())]);
         
-        let stmt = Statement::Read {
+        let stmt = StatementKind::Read {
             variables: vec![
                 AssignTarget::Variable("A".to_string()),
                 AssignTarget::Variable("B$".to_string())
             ],
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -2481,14 +2610,14 @@ mod tests {
     fn test_out_of_data_error() {
         let mut exec = Executor::new();
         
-        exec.add_data_value(DataValue::Number(1.0));
+        add_data_line(&mut exec, 10, vec![crate::ast::DataValue::Number(1.0)]);
         
-        let stmt = Statement::Read {
+        let stmt = StatementKind::Read {
             variables: vec![
                 AssignTarget::Variable("A".to_string()),
                 AssignTarget::Variable("B".to_string())
             ],
-        };
+        }.into();
         
         let result = exec.execute_statement(&stmt);
         assert!(result.is_err());
@@ -2498,28 +2627,77 @@ mod tests {
     // Requirement: RESTORE 数据指针 - RESTORE 重置到开头
     #[test]
     fn test_restore() {
+        use crate::ast::DataValue;
         let mut exec = Executor::new();
         
-        exec.add_data_value(DataValue::Number(1.0));
-        exec.add_data_value(DataValue::Number(2.0));
+        add_data_line(&mut exec, 10, vec![DataValue::Number(1.0), DataValue::Number(2.0)]);
         
         // 第一次 READ
-        exec.execute_statement(&Statement::Read {
+        exec.execute_statement(&StatementKind::Read {
             variables: vec![AssignTarget::Variable("A".to_string())],
-        }).unwrap();
+        }.into()).unwrap();
         assert_eq!(exec.variables.get("A"), Value::Number(1.0));
         
         // RESTORE
-        exec.execute_statement(&Statement::Restore {
+        exec.execute_statement(&StatementKind::Restore {
             line_number: None,
-        }).unwrap();
+        }.into()).unwrap();
         
         // 第二次 READ（应该重新从头开始）
-        exec.execute_statement(&Statement::Read {
+        exec.execute_statement(&StatementKind::Read {
             variables: vec![AssignTarget::Variable("B".to_string())],
-        }).unwrap();
+        }.into()).unwrap();
         assert_eq!(exec.variables.get("B"), Value::Number(1.0));
     }
+    
+    // Requirement: DATA/READ 机制 - 按行号顺序，而不是输入顺序
+    #[test]
+    fn test_data_in_line_order() {
+        use crate::parser::Parser;
+        use crate::tokenizer::Tokenizer;
+        
+        let mut exec = Executor::new();
+        // 30 先输入，20 输入两次（后一次替换前一次），40 输入后删除
+        for source in ["30 DATA 3", "20 DATA 99", "10 READ A,B,C: PRINT A;B;C", "20 DATA 1,2", "40 DATA 4"] {
+            let tokens = Tokenizer::new(source).tokenize_line().unwrap();
+            exec.runtime_mut().add_line(Parser::new(tokens).parse_line().unwrap().unwrap());
+        }
+        exec.runtime_mut().delete_line(40);
+        exec.restore_data();
+        exec.runtime_mut().start_execution(None).unwrap();
+        run_to_end(&mut exec);
+        assert_eq!(exec.get_output(), " 1  2  3 \n");
+    }
+    
+    // Requirement: RESTORE 数据指针 - RESTORE n 指向第 n 行或之后的第一个 DATA
+    #[test]
+    fn test_restore_to_line() {
+        use crate::parser::Parser;
+        use crate::tokenizer::Tokenizer;
+        
+        let source = "10 DATA 1,2\n20 DATA 3: DATA 4\n40 DATA 5\n50 RESTORE 20: READ A,B\n60 RESTORE 30: READ C\n70 RESTORE 41: READ D";
+        let mut exec = Executor::new();
+        for line in source.lines() {
+            let tokens = Tokenizer::new(line).tokenize_line().unwrap();
+            exec.runtime_mut().add_line(Parser::new(tokens).parse_line().unwrap().unwrap());
+        }
+        exec.restore_data();
+        exec.runtime_mut().start_execution(None).unwrap();
+        
+        let mut result = Ok(());
+        while let Some(stmt) = exec.runtime_mut().get_next_statement() {
+            result = exec.execute_statement(&stmt);
+            if result.is_err() {
+                break;
+            }
+        }
+        assert_eq!(exec.variables.get("A"), Value::Number(3.0));
+        assert_eq!(exec.variables.get("B"), Value::Number(4.0));
+        assert_eq!(exec.variables.get("C"), Value::Number(5.0));
+        // 41 之后没有 DATA：?OUT OF DATA，报告 READ 所在的行
+        assert_eq!(result, Err(BasicError::OutOfData));
+        assert_eq!(exec.runtime().get_current_line(), Some(70));
+    }
 
     // Requirement: FOR...NEXT 循环 - 正向循环
     #[test]
@@ -2530,44 +2708,44 @@ mod tests {
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
             statements: vec![
-                Statement::For {
+                StatementKind::For {
                     var: "I".to_string(),
-                    start: Expr::Number(1.0),
-                    end: Expr::Number(3.0),
+                    start: Expr::number(1.0),
+                    end: Expr::number(3.0),
                     step: None,
-                },
+                }.into(),
             ],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 20,
-            statements: vec![Statement::Next { var: Some("I".to_string()) }],
+            statements: vec![StatementKind::Next { var: Some("I".to_string()) }.into()],
         });
         
         // 启动执行
         exec.runtime_mut().start_execution(Some(10)).unwrap();
         
         // 第一次循环：I=1
-        exec.execute_statement(&Statement::For {
+        exec.execute_statement(&StatementKind::For {
             var: "I".to_string(),
-            start: Expr::Number(1.0),
-            end: Expr::Number(3.0),
+            start: Expr::number(1.0),
+            end: Expr::number(3.0),
             step: None,
-        }).unwrap();
+        }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(1.0));
         
         // NEXT：I=2
         exec.runtime_mut().set_execution_position(20, 0).unwrap();
-        exec.execute_statement(&Statement::Next { var: Some("I".to_string()) }).unwrap();
+        exec.execute_statement(&StatementKind::Next { var: Some("I".to_string()) }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(2.0));
         
         // NEXT：I=3
         exec.runtime_mut().set_execution_position(20, 0).unwrap();
-        exec.execute_statement(&Statement::Next { var: Some("I".to_string()) }).unwrap();
+        exec.execute_statement(&StatementKind::Next { var: Some("I".to_string()) }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(3.0));
         
         // NEXT：循环结束 (I递增到4但不再循环)
         exec.runtime_mut().set_execution_position(20, 0).unwrap();
-        exec.execute_statement(&Statement::Next { var: Some("I".to_string()) }).unwrap();
+        exec.execute_statement(&StatementKind::Next { var: Some("I".to_string()) }.into()).unwrap();
         // 循环已结束，变量值应该为循环后的值 4
         assert_eq!(exec.variables.get("I"), Value::Number(3.0));
     }
@@ -2580,36 +2758,36 @@ mod tests {
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
             statements: vec![
-                Statement::For {
+                StatementKind::For {
                     var: "I".to_string(),
-                    start: Expr::Number(0.0),
-                    end: Expr::Number(4.0),
-                    step: Some(Expr::Number(2.0)),
-                },
+                    start: Expr::number(0.0),
+                    end: Expr::number(4.0),
+                    step: Some(Expr::number(2.0)),
+                }.into(),
             ],
         });
         
         exec.runtime_mut().start_execution(Some(10)).unwrap();
         
         // FOR I=0 TO 4 STEP 2
-        exec.execute_statement(&Statement::For {
+        exec.execute_statement(&StatementKind::For {
             var: "I".to_string(),
-            start: Expr::Number(0.0),
-            end: Expr::Number(4.0),
-            step: Some(Expr::Number(2.0)),
-        }).unwrap();
+            start: Expr::number(0.0),
+            end: Expr::number(4.0),
+            step: Some(Expr::number(2.0)),
+        }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(0.0));
         
         // NEXT：I=2
-        exec.execute_statement(&Statement::Next { var: Some("I".to_string()) }).unwrap();
+        exec.execute_statement(&StatementKind::Next { var: Some("I".to_string()) }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(2.0));
         
         // NEXT：I=4
-        exec.execute_statement(&Statement::Next { var: Some("I".to_string()) }).unwrap();
+        exec.execute_statement(&StatementKind::Next { var: Some("I".to_string()) }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(4.0));
         
         // NEXT：循环结束
-        exec.execute_statement(&Statement::Next { var: Some("I".to_string()) }).unwrap();
+        exec.execute_statement(&StatementKind::Next { var: Some("I".to_string()) }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(4.0));
     }
 
@@ -2621,36 +2799,36 @@ mod tests {
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
             statements: vec![
-                Statement::For {
+                StatementKind::For {
                     var: "I".to_string(),
-                    start: Expr::Number(3.0),
-                    end: Expr::Number(1.0),
-                    step: Some(Expr::Number(-1.0)),
-                },
+                    start: Expr::number(3.0),
+                    end: Expr::number(1.0),
+                    step: Some(Expr::number(-1.0)),
+                }.into(),
             ],
         });
         
         exec.runtime_mut().start_execution(Some(10)).unwrap();
         
         // FOR I=3 TO 1 STEP -1
-        exec.execute_statement(&Statement::For {
+        exec.execute_statement(&StatementKind::For {
             var: "I".to_string(),
-            start: Expr::Number(3.0),
-            end: Expr::Number(1.0),
-            step: Some(Expr::Number(-1.0)),
-        }).unwrap();
+            start: Expr::number(3.0),
+            end: Expr::number(1.0),
+            step: Some(Expr::number(-1.0)),
+        }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(3.0));
         
         // NEXT：I=2
-        exec.execute_statement(&Statement::Next { var: Some("I".to_string()) }).unwrap();
+        exec.execute_statement(&StatementKind::Next { var: Some("I".to_string()) }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(2.0));
         
         // NEXT：I=1
-        exec.execute_statement(&Statement::Next { var: Some("I".to_string()) }).unwrap();
+        exec.execute_statement(&StatementKind::Next { var: Some("I".to_string()) }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(1.0));
         
         // NEXT：循环结束
-        exec.execute_statement(&Statement::Next { var: Some("I".to_string()) }).unwrap();
+        exec.execute_statement(&StatementKind::Next { var: Some("I".to_string()) }.into()).unwrap();
         assert_eq!(exec.variables.get("I"), Value::Number(1.0));
     }
 
@@ -2661,25 +2839,25 @@ mod tests {
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 100,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 200,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         
         exec.runtime_mut().start_execution(Some(10)).unwrap();
         
         // ON 2 GOTO 100,200,300
-        exec.execute_statement(&Statement::On {
-            expr: Expr::Number(2.0),
+        exec.execute_statement(&StatementKind::On {
+            expr: Expr::number(2.0),
             targets: vec![100, 200, 300],
             is_gosub: false,
-        }).unwrap();
+        }.into()).unwrap();
         
         // 应该跳转到 200
         assert_eq!(exec.runtime().get_current_line(), Some(200));
@@ -2692,21 +2870,21 @@ mod tests {
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 100,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         
         exec.runtime_mut().start_execution(Some(10)).unwrap();
         
         // ON 1 GOSUB 100,200
-        exec.execute_statement(&Statement::On {
-            expr: Expr::Number(1.0),
+        exec.execute_statement(&StatementKind::On {
+            expr: Expr::number(1.0),
             targets: vec![100, 200],
             is_gosub: true,
-        }).unwrap();
+        }.into()).unwrap();
         
         // 应该跳转到 100
         assert_eq!(exec.runtime().get_current_line(), Some(100));
@@ -2721,18 +2899,18 @@ mod tests {
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Rem { comment: String::new() }],
+            statements: vec![StatementKind::Rem { comment: String::new() }.into()],
         });
         
         exec.runtime_mut().start_execution(Some(10)).unwrap();
         let current_line = exec.runtime().get_current_line();
         
         // ON 5 GOTO 100,200  (5 超出范围)
-        exec.execute_statement(&Statement::On {
-            expr: Expr::Number(5.0),
+        exec.execute_statement(&StatementKind::On {
+            expr: Expr::number(5.0),
             targets: vec![100, 200],
             is_gosub: false,
-        }).unwrap();
+        }.into()).unwrap();
         
         // 应该继续在当前行
         assert_eq!(exec.runtime().get_current_line(), current_line);
@@ -2747,29 +2925,29 @@ mod tests {
         // 添加一些程序行
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Print {
-                items: vec![PrintItem::Expr(Expr::String("HELLO".to_string()))],
-            }],
+            statements: vec![StatementKind::Print {
+                items: vec![PrintItem::Expr(Expr::string("HELLO".to_string()))],
+            }.into()],
         });
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 20,
-            statements: vec![Statement::Let {
+            statements: vec![StatementKind::Let {
                 target: AssignTarget::Variable("A".to_string()),
-                value: Expr::Number(42.0),
-            }],
+                value: Expr::number(42.0),
+            }.into()],
         });
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 30,
-            statements: vec![Statement::End],
+            statements: vec![StatementKind::End.into()],
         });
         
         // 保存程序到文件
         let filename = "test_program.bas";
-        exec.execute_statement(&Statement::Save {
+        exec.execute_statement(&StatementKind::Save {
             filename: filename.to_string(),
-        }).unwrap();
+        }.into()).unwrap();
         
         // 验证文件存在
         assert!(fs::metadata(filename).is_ok());
@@ -2779,9 +2957,9 @@ mod tests {
         assert_eq!(exec.runtime().line_count(), 0);
         
         // 加载程序
-        exec.execute_statement(&Statement::Load {
+        exec.execute_statement(&StatementKind::Load {
             filename: filename.to_string(),
-        }).unwrap();
+        }.into()).unwrap();
         
         // 验证程序已加载
         assert_eq!(exec.runtime().line_count(), 3);
@@ -2798,9 +2976,9 @@ mod tests {
         let mut exec = Executor::new();
         
         // 尝试保存空程序应该失败
-        let result = exec.execute_statement(&Statement::Save {
+        let result = exec.execute_statement(&StatementKind::Save {
             filename: "empty.bas".to_string(),
-        });
+        }.into());
         
         assert!(result.is_err());
     }
@@ -2810,9 +2988,9 @@ mod tests {
         let mut exec = Executor::new();
         
         // 尝试加载不存在的文件应该失败
-        let result = exec.execute_statement(&Statement::Load {
+        let result = exec.execute_statement(&StatementKind::Load {
             filename: "nonexistent.bas".to_string(),
-        });
+        }.into());
         
         assert!(result.is_err());
     }
@@ -2827,40 +3005,40 @@ mod tests {
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 10,
             statements: vec![
-                Statement::For {
+                StatementKind::For {
                     var: "I".to_string(),
-                    start: Expr::Number(1.0),
-                    end: Expr::Number(10.0),
-                    step: Some(Expr::Number(1.0)),
-                },
+                    start: Expr::number(1.0),
+                    end: Expr::number(10.0),
+                    step: Some(Expr::number(1.0)),
+                }.into(),
             ],
         });
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 20,
             statements: vec![
-                Statement::Print {
-                    items: vec![PrintItem::Expr(Expr::Variable("I".to_string()))],
-                },
+                StatementKind::Print {
+                    items: vec![PrintItem::Expr(Expr::variable("I".to_string()))],
+                }.into(),
             ],
         });
         
         exec.runtime_mut().add_line(ProgramLine {
             line_number: 30,
-            statements: vec![Statement::Next { var: Some("I".to_string()) }],
+            statements: vec![StatementKind::Next { var: Some("I".to_string()) }.into()],
         });
         
         // 保存并重新加载
         let filename = "test_complex.bas";
-        exec.execute_statement(&Statement::Save {
+        exec.execute_statement(&StatementKind::Save {
             filename: filename.to_string(),
-        }).unwrap();
+        }.into()).unwrap();
         
         exec.runtime_mut().clear_program();
         
-        exec.execute_statement(&Statement::Load {
+        exec.execute_statement(&StatementKind::Load {
             filename: filename.to_string(),
-        }).unwrap();
+        }.into()).unwrap();
         
         // 验证程序结构
         assert_eq!(exec.runtime().line_count(), 3);
@@ -2869,6 +3047,284 @@ mod tests {
         fs::remove_file(filename).ok();
     }
     
+    #[test]
+    fn test_save_load_preserves_source_text() {
+        use std::fs;
+        
+        let mut exec = Executor::new();
+        
+        // 非规范的空格、括号和数字写法
+        let original = "10 PRINT  1E3;.5\n20 A=(1+2)*3 : REM  KEEP   SPACES\n30 DEF FN SQ(X)=X*X\n40 POKE 1024,  1\n";
+        let input_file = "test_source_in.bas";
+        let output_file = "test_source_out.bas";
+        fs::write(input_file, original).unwrap();
+        
+        exec.execute_statement(&StatementKind::Load {
+            filename: input_file.to_string(),
+        }.into()).unwrap();
+        let line = exec.runtime().get_line(20).unwrap().clone();
+        assert_eq!(exec.line_text(&line), "20 A=(1+2)*3 : REM  KEEP   SPACES");
+        
+        exec.execute_statement(&StatementKind::Save {
+            filename: output_file.to_string(),
+        }.into()).unwrap();
+        let saved = fs::read_to_string(output_file).unwrap();
+        
+        fs::remove_file(input_file).ok();
+        fs::remove_file(output_file).ok();
+        
+        assert_eq!(saved, original);
+    }
+    
+    #[test]
+    fn test_canonical_listing_style() {
+        use crate::tokenizer::Tokenizer;
+        use crate::parser::Parser;
+        
+        let mut exec = Executor::new();
+        let source = "10 PRINT  1E3;.5: DEF FN SQ(X)=X*X: POKE 1024,FN SQ(2)";
+        let tokens = Tokenizer::new(source).tokenize_line().unwrap();
+        let line = Parser::new(tokens).parse_line().unwrap().unwrap();
+        exec.runtime_mut().add_line_with_source(line.clone(), source.to_string());
+        
+        assert_eq!(exec.line_text(&line), source);
+        
+        exec.set_listing_style(ListingStyle::Canonical);
+        let canonical = exec.line_text(&line);
+        assert_eq!(canonical, "10 PRINT 1000 ; 0.5: DEF FN SQ(X) = (X * X): POKE 1024, FN SQ(2)");
+        
+        // 规范格式必须能重新解析为相同的 AST
+        let tokens = Tokenizer::new(&canonical).tokenize_line().unwrap();
+        let reparsed = Parser::new(tokens).parse_line().unwrap().unwrap();
+        assert_eq!(reparsed, line);
+    }
+    
+    /// 运行已加载的程序直到结束（测试用）
+    fn run_to_end(exec: &mut Executor) {
+        while let Some(stmt) = exec.runtime_mut().get_next_statement() {
+            exec.execute_statement(&stmt).unwrap();
+            if exec.runtime().is_stopped() {
+                break;
+            }
+        }
+    }
+    
+    #[test]
+    fn test_host_functions() {
+        use crate::functions::ArgType;
+        use crate::parser::Parser;
+        use crate::tokenizer::Tokenizer;
+        use std::collections::HashMap;
+        
+        let mut exec = Executor::new();
+        let db: HashMap<String, String> = [("NAME".to_string(), "ADA".to_string())].into();
+        exec.functions_mut().register("DB$", &[ArgType::String], ArgType::String, move |args| {
+            Ok(Value::String(db.get(&args[0].as_string()?).cloned().unwrap_or_default()))
+        });
+        // 本地桩代替真正的 HTTP 请求
+        exec.functions_mut().register("HTTPGET$", &[ArgType::String], ArgType::String, |args| {
+            Ok(Value::String(format!("200 {}", args[0].as_string()?)))
+        });
+        
+        let mut run = |source: &str| {
+            let tokens = Tokenizer::new(source).tokenize_line().unwrap();
+            let line = Parser::new(tokens).parse_line().unwrap().unwrap();
+            exec.execute_statement(&line.statements[0])
+        };
+        run("PRINT DB$(\"NAME\");LEN(HTTPGET$(\"/\"))").unwrap();
+        // 宿主函数和内置函数一样检查参数
+        assert!(matches!(run("PRINT DB$(1)"), Err(BasicError::TypeMismatch(_))));
+        match run("PRINT DB$(\"A\",\"B\")") {
+            Err(BasicError::SyntaxError(msg)) => assert_eq!(msg, "DB$ requires 1 argument"),
+            other => panic!("Expected syntax error, got {:?}", other),
+        }
+        // DIM 过的同名数组优先
+        run("DIM DB$(3)").unwrap();
+        run("PRINT \"[\";DB$(1);\"]\"").unwrap();
+        
+        assert_eq!(exec.get_output(), "ADA 5 \n[]\n");
+    }
+    
+    #[test]
+    fn test_deterministic_clock_and_wait() {
+        use crate::parser::Parser;
+        use crate::tokenizer::Tokenizer;
+        
+        let source = "10 T=TI\n20 FOR I=1 TO 10: NEXT I\n30 PRINT TI-T\n40 WAIT 162,128\n50 PRINT TI";
+        let mut exec = Executor::new();
+        exec.variables_mut().clock_mut().set_mode(ClockMode::Deterministic { jiffies_per_statement: 1 });
+        for line in source.lines() {
+            let tokens = Tokenizer::new(line).tokenize_line().unwrap();
+            exec.runtime_mut().add_line(Parser::new(tokens).parse_line().unwrap().unwrap());
+        }
+        exec.runtime_mut().start_execution(None).unwrap();
+        run_to_end(&mut exec);
+        
+        // FOR + 10 次 NEXT + PRINT 各一条语句；WAIT 等到时钟低字节的最高位置位，
+        // 之后的 PRINT 本身再前进一个 jiffy
+        assert_eq!(exec.get_output(), " 12 \n 129 \n");
+        assert_eq!(exec.peek(162), 129);
+        
+        // 确定模式下永远不会满足的 WAIT 报错而不是卡死
+        let wait = StatementKind::Wait { address: Expr::number(4096.0), mask: Expr::number(1.0), value: None }.into();
+        assert!(exec.execute_statement(&wait).is_err());
+    }
+    
+    #[test]
+    fn test_merge_overlays_lines() {
+        use std::fs;
+        
+        let mut exec = Executor::new();
+        let base = "test_merge_base.bas";
+        let part = "test_merge_part.bas";
+        fs::write(base, "10 A=1\n20 DATA 1\n30 PRINT A\n").unwrap();
+        fs::write(part, "15 DATA 2\n30 PRINT A+1\n").unwrap();
+        
+        exec.execute_statement(&StatementKind::Load { filename: base.to_string() }.into()).unwrap();
+        exec.variables_mut().set("X", Value::Number(5.0)).unwrap();
+        exec.execute_statement(&StatementKind::Merge { filename: part.to_string() }.into()).unwrap();
+        
+        fs::remove_file(base).ok();
+        fs::remove_file(part).ok();
+        
+        let numbers: Vec<u16> = exec.runtime().get_all_lines().iter().map(|l| l.line_number).collect();
+        assert_eq!(numbers, vec![10, 15, 20, 30]);
+        assert_eq!(exec.runtime().get_source(30), Some("30 PRINT A+1"));
+        // 变量保留，DATA 按程序顺序重建
+        assert_eq!(exec.variables().get("X"), Value::Number(5.0));
+        assert_eq!(exec.read_data_value().unwrap(), DataValue::Number(2.0));
+        assert_eq!(exec.read_data_value().unwrap(), DataValue::Number(1.0));
+    }
+    
+    #[test]
+    fn test_chain_keeps_common_variables() {
+        use std::fs;
+        
+        let mut exec = Executor::new();
+        let first = "test_chain_first.bas";
+        let second = "test_chain_second.bas";
+        fs::write(first, "10 A=1: B=2: DIM C(3): C(1)=7\n20 DEF FN F(X)=X\n30 COMMON A, C()\n40 CHAIN \"test_chain_second.bas\",20\n").unwrap();
+        fs::write(second, "10 PRINT \"SKIPPED\"\n20 READ D: PRINT A; B; C(1); D\n30 DATA 9\n").unwrap();
+        
+        exec.execute_statement(&StatementKind::Load { filename: first.to_string() }.into()).unwrap();
+        exec.runtime_mut().start_execution(None).unwrap();
+        run_to_end(&mut exec);
+        
+        fs::remove_file(first).ok();
+        fs::remove_file(second).ok();
+        
+        assert_eq!(exec.get_output(), " 1  0  7  9 \n");
+        assert!(!exec.variables().has_function("F"));
+        assert_eq!(exec.runtime().get_source(10), Some("10 PRINT \"SKIPPED\""));
+    }
+    
+    #[test]
+    fn test_chain_without_common_keeps_all_variables() {
+        use std::fs;
+        
+        let mut exec = Executor::new();
+        let next = "test_chain_all.bas";
+        fs::write(next, "10 PRINT A; B$\n").unwrap();
+        
+        exec.variables_mut().set("A", Value::Number(3.0)).unwrap();
+        exec.variables_mut().set("B$", Value::String("X".to_string())).unwrap();
+        exec.execute_statement(&StatementKind::Chain { filename: next.to_string(), line_number: None }.into()).unwrap();
+        run_to_end(&mut exec);
+        
+        fs::remove_file(next).ok();
+        
+        assert_eq!(exec.get_output(), " 3 X\n");
+    }
+    
+    #[test]
+    fn test_print_using() {
+        use crate::tokenizer::Tokenizer;
+        use crate::parser::Parser;
+        
+        let mut exec = Executor::new();
+        exec.variables_mut().set("A$", Value::String("WIDGET".to_string())).unwrap();
+        for source in ["PRINT USING \"\\    \\ $$#,###.##\"; A$, 1234.5", "PRINT USING \"##\"; 1, 2;"] {
+            let tokens = Tokenizer::new(source).tokenize_line().unwrap();
+            let line = Parser::new(tokens).parse_line().unwrap().unwrap();
+            exec.execute_statement(&line.statements[0]).unwrap();
+            
+            // 序列化后重新解析得到相同的语句
+            let text = Executor::serialize_statement(&line.statements[0]);
+            let tokens = Tokenizer::new(&text).tokenize_line().unwrap();
+            let reparsed = Parser::new(tokens).parse_line().unwrap().unwrap();
+            assert_eq!(reparsed.statements, line.statements);
+        }
+        
+        assert_eq!(exec.get_output(), "WIDGET  $1,234.50\n 1 2");
+    }
+    
+    #[test]
+    fn test_poke_peek_screen() {
+        let mut exec = Executor::new();
+        
+        // 没有屏幕时 POKE/PEEK 读写普通内存
+        exec.execute_statement(&StatementKind::Poke {
+            address: Expr::number(1024.0),
+            value: Expr::number(42.0),
+        }.into()).unwrap();
+        assert_eq!(exec.peek(1024), 42);
+        
+        exec.enable_screen(ScreenConfig::C64, false);
+        
+        // POKE 1024+X+40*Y,C 画到屏幕上
+        for (x, code) in [(2.0, 8.0), (3.0, 9.0)] {
+            exec.execute_statement(&StatementKind::Poke {
+                address: Expr::number(1024.0 + x + 40.0),
+                value: Expr::number(code),
+            }.into()).unwrap();
+        }
+        // PRINT 的控制码移动屏幕光标：HOME，下移两行，右移一列
+        exec.execute_statement(&StatementKind::Print {
+            items: vec![PrintItem::Expr(Expr::string("\u{13}\u{11}\u{11}\u{1d}OK".to_string()))],
+        }.into()).unwrap();
+        
+        let screen = exec.screen().unwrap();
+        assert_eq!(screen.to_text().lines().take(4).collect::<Vec<_>>(), vec!["", "  HI", " OK", ""]);
+        assert_eq!(exec.peek(1024 + 40), 32);
+        
+        let peek = ExprKind::FunctionCall { name: "PEEK".to_string(), args: vec![Expr::number(1024.0 + 42.0)] }.into();
+        assert_eq!(exec.eval_expr(&peek).unwrap(), Value::Number(8.0));
+        
+        assert!(exec.execute_statement(&StatementKind::Poke {
+            address: Expr::number(70000.0),
+            value: Expr::number(1.0),
+        }.into()).is_err());
+    }
+    
+    // Requirement: 屏幕 - 写屏幕只做标记，每条语句结束时重画一次
+    #[test]
+    fn test_screen_redrawn_once_per_statement() {
+        let mut exec = Executor::new();
+        exec.enable_screen(ScreenConfig::C64, false);
+        
+        exec.poke(1024, 1);
+        exec.poke(1025, 2);
+        assert!(exec.screen_dirty);
+        // 不写屏幕的语句也会把之前的变化画出来
+        exec.execute_statement(&StatementKind::Rem { comment: String::new() }.into()).unwrap();
+        assert!(!exec.screen_dirty);
+        
+        // 屏幕外的内存不影响屏幕
+        exec.poke(4096, 1);
+        assert!(!exec.screen_dirty);
+        
+        // 出错的语句在出错前写的内容同样会画出来
+        let stmt = StatementKind::Print {
+            items: vec![
+                PrintItem::Expr(Expr::string("A".to_string())),
+                PrintItem::Expr(Expr::binary(Expr::number(1.0), BinaryOperator::Divide, Expr::number(0.0))),
+            ],
+        }.into();
+        assert!(exec.execute_statement(&stmt).is_err());
+        assert!(!exec.screen_dirty);
+        assert!(exec.screen().unwrap().to_text().starts_with('A'));
+    }
+    
     // ========== 高级功能测试 ==========
     
     // Test: POS 函数 - 基本功能
@@ -2877,17 +3333,17 @@ mod tests {
         let mut exec = Executor::new();
         
         // 初始位置应该是 1（1-based）
-        let pos_expr = Expr::FunctionCall {
+        let pos_expr = ExprKind::FunctionCall {
             name: "POS".to_string(),
-            args: vec![Expr::Number(0.0)],
-        };
+            args: vec![Expr::number(0.0)],
+        }.into();
         let pos = exec.eval_expr(&pos_expr).unwrap();
         assert_eq!(pos, Value::Number(1.0));
         
         // 打印一些内容后，位置应该更新
-        exec.execute_statement(&Statement::Print {
-            items: vec![PrintItem::Expr(Expr::String("ABC".to_string()))],
-        }).unwrap();
+        exec.execute_statement(&StatementKind::Print {
+            items: vec![PrintItem::Expr(Expr::string("ABC".to_string()))],
+        }.into()).unwrap();
         
         let pos = exec.eval_expr(&pos_expr).unwrap();
         // 输出 "ABC" + 换行，所以新行开始应该是 1
@@ -2900,16 +3356,16 @@ mod tests {
         let mut exec = Executor::new();
         
         // 使用 TAB 后检查位置
-        exec.execute_statement(&Statement::Print {
+        exec.execute_statement(&StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::String("START".to_string())),
-                PrintItem::Tab(Expr::Number(15.0)),
-                PrintItem::Expr(Expr::FunctionCall {
+                PrintItem::Expr(Expr::string("START".to_string())),
+                PrintItem::Tab(Expr::number(15.0)),
+                PrintItem::Expr(ExprKind::FunctionCall {
                     name: "POS".to_string(),
-                    args: vec![Expr::Number(0.0)],
-                }),
+                    args: vec![Expr::number(0.0)],
+                }.into()),
             ],
-        }).unwrap();
+        }.into()).unwrap();
         
         let output = exec.get_output();
         assert!(output.contains("START"));
@@ -2920,16 +3376,16 @@ mod tests {
     fn test_pos_with_spc() {
         let mut exec = Executor::new();
         
-        exec.execute_statement(&Statement::Print {
+        exec.execute_statement(&StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::String("A".to_string())),
-                PrintItem::Spc(Expr::Number(5.0)),
-                PrintItem::Expr(Expr::FunctionCall {
+                PrintItem::Expr(Expr::string("A".to_string())),
+                PrintItem::Spc(Expr::number(5.0)),
+                PrintItem::Expr(ExprKind::FunctionCall {
                     name: "POS".to_string(),
-                    args: vec![Expr::Number(0.0)],
-                }),
+                    args: vec![Expr::number(0.0)],
+                }.into()),
             ],
-        }).unwrap();
+        }.into()).unwrap();
         
         let output = exec.get_output();
         assert!(output.contains("A"));
@@ -2940,15 +3396,15 @@ mod tests {
     fn test_def_fn_statement() {
         let mut exec = Executor::new();
         
-        let stmt = Statement::DefFn {
+        let stmt = StatementKind::DefFn {
             name: "SQUARE".to_string(),
             param: "X".to_string(),
             body: Expr::binary(
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
                 BinaryOperator::Multiply,
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
             ),
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -2962,21 +3418,21 @@ mod tests {
         let mut exec = Executor::new();
         
         // 定义函数
-        exec.execute_statement(&Statement::DefFn {
+        exec.execute_statement(&StatementKind::DefFn {
             name: "SQUARE".to_string(),
             param: "X".to_string(),
             body: Expr::binary(
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
                 BinaryOperator::Multiply,
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
             ),
-        }).unwrap();
+        }.into()).unwrap();
         
         // 调用函数
-        let fn_call = Expr::FunctionCall {
+        let fn_call = ExprKind::FunctionCall {
             name: "FNSQUARE".to_string(),
-            args: vec![Expr::Number(5.0)],
-        };
+            args: vec![Expr::number(5.0)],
+        }.into();
         
         let result = exec.eval_expr(&fn_call).unwrap();
         assert_eq!(result, Value::Number(25.0));
@@ -2991,21 +3447,21 @@ mod tests {
         exec.variables.set("GVAL", Value::Number(10.0)).unwrap();
         
         // 定义使用全局变量的函数
-        exec.execute_statement(&Statement::DefFn {
+        exec.execute_statement(&StatementKind::DefFn {
             name: "ADDG".to_string(),
             param: "X".to_string(),
             body: Expr::binary(
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
                 BinaryOperator::Add,
-                Expr::Variable("GVAL".to_string()),
+                Expr::variable("GVAL".to_string()),
             ),
-        }).unwrap();
+        }.into()).unwrap();
         
         // 调用函数
-        let fn_call = Expr::FunctionCall {
+        let fn_call = ExprKind::FunctionCall {
             name: "FNADDG".to_string(),
-            args: vec![Expr::Number(5.0)],
-        };
+            args: vec![Expr::number(5.0)],
+        }.into();
         
         let result = exec.eval_expr(&fn_call).unwrap();
         assert_eq!(result, Value::Number(15.0));
@@ -3020,34 +3476,34 @@ mod tests {
         let mut exec = Executor::new();
         
         // 定义两个函数
-        exec.execute_statement(&Statement::DefFn {
+        exec.execute_statement(&StatementKind::DefFn {
             name: "DOUBLE".to_string(),
             param: "Y".to_string(),
             body: Expr::binary(
-                Expr::Variable("Y".to_string()),
+                Expr::variable("Y".to_string()),
                 BinaryOperator::Add,
-                Expr::Variable("Y".to_string()),
+                Expr::variable("Y".to_string()),
             ),
-        }).unwrap();
+        }.into()).unwrap();
         
-        exec.execute_statement(&Statement::DefFn {
+        exec.execute_statement(&StatementKind::DefFn {
             name: "SQUARE".to_string(),
             param: "X".to_string(),
             body: Expr::binary(
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
                 BinaryOperator::Multiply,
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
             ),
-        }).unwrap();
+        }.into()).unwrap();
         
         // 嵌套调用：FN SQUARE(FN DOUBLE(2))
-        let nested = Expr::FunctionCall {
+        let nested = ExprKind::FunctionCall {
             name: "FNSQUARE".to_string(),
-            args: vec![Expr::FunctionCall {
+            args: vec![ExprKind::FunctionCall {
                 name: "FNDOUBLE".to_string(),
-                args: vec![Expr::Number(2.0)],
-            }],
-        };
+                args: vec![Expr::number(2.0)],
+            }.into()],
+        }.into();
         
         let result = exec.eval_expr(&nested).unwrap();
         // FN DOUBLE(2) = 4, FN SQUARE(4) = 16
@@ -3063,21 +3519,21 @@ mod tests {
         exec.variables.set("X", Value::Number(100.0)).unwrap();
         
         // 定义函数，参数名也是 X
-        exec.execute_statement(&Statement::DefFn {
+        exec.execute_statement(&StatementKind::DefFn {
             name: "TEST".to_string(),
             param: "X".to_string(),
             body: Expr::binary(
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
                 BinaryOperator::Add,
-                Expr::Number(1.0),
+                Expr::number(1.0),
             ),
-        }).unwrap();
+        }.into()).unwrap();
         
         // 调用函数，参数值应该是传入的值，不是全局变量
-        let fn_call = Expr::FunctionCall {
+        let fn_call = ExprKind::FunctionCall {
             name: "FNTEST".to_string(),
-            args: vec![Expr::Number(5.0)],
-        };
+            args: vec![Expr::number(5.0)],
+        }.into();
         
         let result = exec.eval_expr(&fn_call).unwrap();
         assert_eq!(result, Value::Number(6.0));
@@ -3094,9 +3550,9 @@ mod tests {
         // 设置输入回调
         exec.set_input_callback(|_| Some("A".to_string()));
         
-        let stmt = Statement::Get {
+        let stmt = StatementKind::Get {
             variable: "CH$".to_string(),
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -3113,9 +3569,9 @@ mod tests {
         // 设置输入回调
         exec.set_input_callback(|_| Some("A".to_string()));
         
-        let stmt = Statement::Get {
+        let stmt = StatementKind::Get {
             variable: "CH".to_string(),
-        };
+        }.into();
         
         exec.execute_statement(&stmt).unwrap();
         
@@ -3132,9 +3588,9 @@ mod tests {
         // 设置回调返回 None（无输入）
         exec.set_input_callback(|_| None);
         
-        let stmt = Statement::Get {
+        let stmt = StatementKind::Get {
             variable: "CH$".to_string(),
-        };
+        }.into();
         
         // 由于没有输入，应该返回空字符串
         exec.execute_statement(&stmt).unwrap();
@@ -3143,6 +3599,33 @@ mod tests {
         assert_eq!(ch, Value::String(String::new()));
     }
     
+    // Test: PETSCII 字符集下的 CHR$/ASC 和 GET
+    #[test]
+    fn test_petscii_charset() {
+        let mut exec = Executor::new();
+        exec.set_charset(Charset::Petscii);
+        
+        let chr = |n: f64| ExprKind::FunctionCall { name: "CHR$".to_string(), args: vec![Expr::number(n)] }.into();
+        assert_eq!(exec.eval_expr(&chr(115.0)).unwrap(), Value::String("♥".to_string()));
+        assert_eq!(exec.eval_expr(&chr(65.0)).unwrap(), Value::String("A".to_string()));
+        
+        let asc = ExprKind::FunctionCall { name: "ASC".to_string(), args: vec![chr(176.0)] }.into();
+        assert_eq!(exec.eval_expr(&asc).unwrap(), Value::Number(176.0));
+        
+        // 键盘上的小写字母在大写/图形字符集下按大写处理
+        exec.set_input_callback(|_| Some("q".to_string()));
+        exec.execute_statement(&StatementKind::Get { variable: "K$".to_string() }.into()).unwrap();
+        assert_eq!(exec.variables.get("K$"), Value::String("Q".to_string()));
+        
+        // CHR$(14) 切换到小写字符集，控制码不占打印列
+        exec.execute_statement(&StatementKind::Print {
+            items: vec![PrintItem::Expr(chr(14.0)), PrintItem::Semicolon],
+        }.into()).unwrap();
+        assert_eq!(exec.charset(), Charset::PetsciiLower);
+        assert_eq!(exec.print_column, 0);
+        assert_eq!(exec.eval_expr(&chr(65.0)).unwrap(), Value::String("a".to_string()));
+    }
+    
     // Test: NULL 语句 - 无操作
     #[test]
     fn test_null_statement() {
@@ -3152,7 +3635,7 @@ mod tests {
         exec.variables.set("X", Value::Number(10.0)).unwrap();
         
         // 执行 NULL 语句
-        exec.execute_statement(&Statement::Null).unwrap();
+        exec.execute_statement(&StatementKind::Null.into()).unwrap();
         
         // 验证变量未被修改
         assert_eq!(exec.variables.get("X"), Value::Number(10.0));
@@ -3167,9 +3650,9 @@ mod tests {
         let mut exec = Executor::new();
         
         // 执行多个 NULL 语句
-        exec.execute_statement(&Statement::Null).unwrap();
-        exec.execute_statement(&Statement::Null).unwrap();
-        exec.execute_statement(&Statement::Null).unwrap();
+        exec.execute_statement(&StatementKind::Null.into()).unwrap();
+        exec.execute_statement(&StatementKind::Null.into()).unwrap();
+        exec.execute_statement(&StatementKind::Null.into()).unwrap();
         
         // 验证没有错误，没有输出
         assert_eq!(exec.get_output(), "");
@@ -3181,58 +3664,58 @@ mod tests {
         let mut exec = Executor::new();
         
         // 测试 POS 函数
-        exec.execute_statement(&Statement::Print {
+        exec.execute_statement(&StatementKind::Print {
             items: vec![
-                PrintItem::Expr(Expr::String("POS TEST:".to_string())),
+                PrintItem::Expr(Expr::string("POS TEST:".to_string())),
             ],
-        }).unwrap();
+        }.into()).unwrap();
         
-        let pos_expr = Expr::FunctionCall {
+        let pos_expr = ExprKind::FunctionCall {
             name: "POS".to_string(),
-            args: vec![Expr::Number(0.0)],
-        };
+            args: vec![Expr::number(0.0)],
+        }.into();
         let pos = exec.eval_expr(&pos_expr).unwrap();
         assert!(pos.as_number().unwrap() >= 1.0);
         
         // 测试 DEF FN 和 FN 调用
-        exec.execute_statement(&Statement::DefFn {
+        exec.execute_statement(&StatementKind::DefFn {
             name: "SQUARE".to_string(),
             param: "X".to_string(),
             body: Expr::binary(
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
                 BinaryOperator::Multiply,
-                Expr::Variable("X".to_string()),
+                Expr::variable("X".to_string()),
             ),
-        }).unwrap();
+        }.into()).unwrap();
         
-        exec.execute_statement(&Statement::DefFn {
+        exec.execute_statement(&StatementKind::DefFn {
             name: "DOUBLE".to_string(),
             param: "Y".to_string(),
             body: Expr::binary(
-                Expr::Variable("Y".to_string()),
+                Expr::variable("Y".to_string()),
                 BinaryOperator::Add,
-                Expr::Variable("Y".to_string()),
+                Expr::variable("Y".to_string()),
             ),
-        }).unwrap();
+        }.into()).unwrap();
         
         // 测试函数调用
-        let result1 = exec.eval_expr(&Expr::FunctionCall {
+        let result1 = exec.eval_expr(&ExprKind::FunctionCall {
             name: "FNSQUARE".to_string(),
-            args: vec![Expr::Number(5.0)],
-        }).unwrap();
+            args: vec![Expr::number(5.0)],
+        }.into()).unwrap();
         assert_eq!(result1, Value::Number(25.0));
         
-        let result2 = exec.eval_expr(&Expr::FunctionCall {
+        let result2 = exec.eval_expr(&ExprKind::FunctionCall {
             name: "FNDOUBLE".to_string(),
-            args: vec![Expr::Number(7.0)],
-        }).unwrap();
+            args: vec![Expr::number(7.0)],
+        }.into()).unwrap();
         assert_eq!(result2, Value::Number(14.0));
         
         // 测试 NULL 语句
-        exec.execute_statement(&Statement::Null).unwrap();
-        exec.execute_statement(&Statement::Print {
-            items: vec![PrintItem::Expr(Expr::String("AFTER NULL".to_string()))],
-        }).unwrap();
+        exec.execute_statement(&StatementKind::Null.into()).unwrap();
+        exec.execute_statement(&StatementKind::Print {
+            items: vec![PrintItem::Expr(Expr::string("AFTER NULL".to_string()))],
+        }.into()).unwrap();
         
         let output = exec.get_output();
         assert!(output.contains("AFTER NULL"));
@@ -3262,7 +3745,7 @@ mod tests {
         
         // 读取 test.bas 文件
         let test_file = "test.bas";
-        if !fs::metadata(test_file).is_ok() {
+        if fs::metadata(test_file).is_err() {
             // 如果文件不存在，跳过测试
             eprintln!("Warning: test.bas not found, skipping integration test");
             return;
@@ -3283,18 +3766,6 @@ mod tests {
             let mut parser = Parser::new(tokens);
             if let Some(program_line) = parser.parse_line().unwrap() {
                 if program_line.line_number > 0 {
-                    // 收集 DATA 语句
-                    for stmt in &program_line.statements {
-                        if let Statement::Data { values } = stmt {
-                            for value in values {
-                                let exec_value = match value {
-                                    crate::ast::DataValue::Number(n) => DataValue::Number(*n),
-                                    crate::ast::DataValue::String(s) => DataValue::String(s.clone()),
-                                };
-                                exec.add_data_value(exec_value);
-                            }
-                        }
-                    }
                     exec.runtime_mut().add_line(program_line);
                 }
             }
@@ -3319,12 +3790,12 @@ mod tests {
             };
             
             // 跳过 STOP 语句（需要交互）
-            if matches!(stmt, Statement::Stop) {
+            if matches!(stmt.kind, StatementKind::Stop) {
                 break;
             }
             
             // 跳过 INPUT 和 GET（已处理）
-            if matches!(stmt, Statement::Input { .. }) || matches!(stmt, Statement::Get { .. }) {
+            if matches!(stmt.kind, StatementKind::Input { .. }) || matches!(stmt.kind, StatementKind::Get { .. }) {
                 exec.execute_statement(&stmt).ok();
                 continue;
             }
@@ -3353,5 +3824,44 @@ mod tests {
         assert!(exec.variables.has_function("SQUARE"), "Function SQUARE should be defined");
         assert!(exec.variables.has_function("DOUBLE"), "Function DOUBLE should be defined");
     }
-}
 
+    // Requirement: Source Spans - 运行时错误指向最内层的出错节点
+    #[test]
+    fn test_error_span() {
+        use crate::parser::Parser;
+        use crate::tokenizer::Tokenizer;
+
+        fn spanned(source: &str) -> Statement {
+            let tokens = Tokenizer::new(source).tokenize_line_spanned().unwrap();
+            let mut line = Parser::new_spanned(tokens).parse_line().unwrap().unwrap();
+            line.statements.remove(0)
+        }
+        fn text(source: &str, span: Span) -> String {
+            source.chars().skip(span.start).take(span.end - span.start).collect()
+        }
+
+        let mut exec = Executor::new();
+
+        // 括号内的除零：标出括号所在的子表达式
+        let source = "PRINT 1 + 2 / (3 - 3)";
+        assert!(exec.execute_statement(&spanned(source)).is_err());
+        assert_eq!(text(source, exec.error_span().unwrap()), "2 / (3 - 3)");
+
+        // 没有出错表达式时标出整条语句
+        let source = "RETURN";
+        assert!(exec.execute_statement(&spanned(source)).is_err());
+        assert_eq!(text(source, exec.error_span().unwrap()), "RETURN");
+
+        // 函数体内出错时标出调用处
+        exec.execute_statement(&spanned("DEF FN F(X) = 1 / X")).unwrap();
+        let source = "PRINT 2 + FN F(0)";
+        assert!(exec.execute_statement(&spanned(source)).is_err());
+        assert_eq!(text(source, exec.error_span().unwrap()), "FN F(0)");
+
+        // 成功的语句清除上一次的记录；没有位置信息的节点不记录
+        exec.execute_statement(&spanned("PRINT 1")).unwrap();
+        assert_eq!(exec.error_span(), None);
+        assert!(exec.execute_statement(&StatementKind::Return.into()).is_err());
+        assert_eq!(exec.error_span(), None);
+    }
+}
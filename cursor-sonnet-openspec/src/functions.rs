@@ -0,0 +1,386 @@
+//! 函数注册表
+//!
+//! 表达式中可以调用的函数都登记在注册表里：名称、参数个数、参数类型、
+//! 返回类型和实现。执行器创建时注册全部标准函数；嵌入解释器的程序
+//! 可以注册自己的 Rust 闭包，BASIC 程序像调用内置函数一样调用它们，
+//! 参数个数和类型按同样的规则检查、报错。
+
+use crate::error::{BasicError, Result};
+use crate::executor::Executor;
+use crate::variables::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// 参数或返回值的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgType {
+    /// 数值
+    Number,
+    /// 字符串
+    String,
+    /// 任意类型（由实现自行检查）
+    Any,
+}
+
+impl ArgType {
+    /// 检查值的类型，不符合时返回 TYPE MISMATCH
+    pub fn check(self, value: &Value) -> Result<()> {
+        match self {
+            ArgType::Number => value.as_number().map(|_| ()),
+            ArgType::String => value.as_string().map(|_| ()),
+            ArgType::Any => Ok(()),
+        }
+    }
+}
+
+/// 函数实现：参数已经求值并通过类型检查
+pub type NativeFunction = Rc<dyn Fn(&mut Executor, &[Value]) -> Result<Value>>;
+
+/// 可以从 BASIC 调用的函数
+#[derive(Clone)]
+pub struct Function {
+    name: String,
+    params: Vec<ArgType>,
+    /// 必需参数个数，其余参数可以省略
+    required: usize,
+    returns: ArgType,
+    implementation: NativeFunction,
+}
+
+impl Function {
+    /// 函数名（大写，如 "LEFT$"）
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 参数类型
+    pub fn params(&self) -> &[ArgType] {
+        &self.params
+    }
+
+    /// 必需参数个数
+    pub fn required(&self) -> usize {
+        self.required
+    }
+
+    /// 返回值类型
+    pub fn returns(&self) -> ArgType {
+        self.returns
+    }
+
+    /// 检查参数、调用实现并检查返回值
+    ///
+    /// 参数个数不对是语法错误，参数或返回值类型不对是 TYPE MISMATCH
+    pub fn call(&self, executor: &mut Executor, args: &[Value]) -> Result<Value> {
+        if args.len() < self.required || args.len() > self.params.len() {
+            return Err(BasicError::SyntaxError(format!(
+                "{} requires {}", self.name, self.arity_text()
+            )));
+        }
+        for (param, arg) in self.params.iter().zip(args) {
+            param.check(arg)?;
+        }
+
+        let result = (self.implementation)(executor, args)?;
+        self.returns.check(&result)?;
+        Ok(result)
+    }
+
+    /// 参数个数的描述，用于错误信息
+    fn arity_text(&self) -> String {
+        let max = self.params.len();
+        let plural = if max == 1 { "argument" } else { "arguments" };
+        if self.required == max {
+            format!("{} {}", max, plural)
+        } else {
+            format!("{} or {} {}", self.required, max, plural)
+        }
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("required", &self.required)
+            .field("returns", &self.returns)
+            .finish()
+    }
+}
+
+/// 函数注册表（按名称索引）
+#[derive(Debug, Clone)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Function>,
+}
+
+impl FunctionRegistry {
+    /// 创建空的注册表
+    pub fn empty() -> Self {
+        FunctionRegistry { functions: HashMap::new() }
+    }
+
+    /// 创建包含全部标准函数的注册表
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        for &(name, params, required, returns, implementation) in STANDARD_FUNCTIONS {
+            registry.insert(Function {
+                name: name.to_string(),
+                params: params.to_vec(),
+                required,
+                returns,
+                implementation: Rc::new(implementation),
+            });
+        }
+        registry
+    }
+
+    /// 注册宿主函数（同名函数被替换）
+    ///
+    /// 名称不区分大小写，返回字符串的函数按 BASIC 的约定以 `$` 结尾。
+    /// 不是关键字的名称按数组的写法调用，如 `DB$(K$)`，因此至少要有
+    /// 一个参数；已经 DIM 过的同名数组优先。
+    pub fn register<F>(&mut self, name: &str, params: &[ArgType], returns: ArgType, implementation: F)
+    where
+        F: Fn(&[Value]) -> Result<Value> + 'static,
+    {
+        self.insert(Function {
+            name: name.to_uppercase(),
+            params: params.to_vec(),
+            required: params.len(),
+            returns,
+            implementation: Rc::new(move |_, args| implementation(args)),
+        });
+    }
+
+    fn insert(&mut self, function: Function) {
+        self.functions.insert(function.name.clone(), function);
+    }
+
+    /// 注销函数，返回函数是否存在
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.functions.remove(&name.to_uppercase()).is_some()
+    }
+
+    /// 按名称查找函数
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.functions.get(&name.to_uppercase())
+    }
+
+    /// 全部函数名（已排序）
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Standard = (&'static str, &'static [ArgType], usize, ArgType, fn(&mut Executor, &[Value]) -> Result<Value>);
+
+const N: ArgType = ArgType::Number;
+const S: ArgType = ArgType::String;
+const A: ArgType = ArgType::Any;
+
+/// FRE 在没有数组时报告的可用字节数
+pub const FREE_MEMORY: usize = 32767;
+
+/// 标准函数：名称、参数类型、必需参数个数、返回值类型、实现
+static STANDARD_FUNCTIONS: &[Standard] = &[
+    // 数学函数
+    ("SGN", &[N], 1, N, |_, args| {
+        let n = number(&args[0]);
+        Ok(Value::Number(if n > 0.0 { 1.0 } else if n < 0.0 { -1.0 } else { 0.0 }))
+    }),
+    ("INT", &[N], 1, N, |_, args| Ok(Value::Number(number(&args[0]).floor()))),
+    ("ABS", &[N], 1, N, |_, args| Ok(Value::Number(number(&args[0]).abs()))),
+    ("SQR", &[N], 1, N, |_, args| {
+        let n = number(&args[0]);
+        if n < 0.0 {
+            return Err(BasicError::IllegalQuantity("SQR of negative number".to_string()));
+        }
+        Ok(Value::Number(n.sqrt()))
+    }),
+    ("SIN", &[N], 1, N, |_, args| Ok(Value::Number(number(&args[0]).sin()))),
+    ("COS", &[N], 1, N, |_, args| Ok(Value::Number(number(&args[0]).cos()))),
+    ("TAN", &[N], 1, N, |_, args| Ok(Value::Number(number(&args[0]).tan()))),
+    ("ATN", &[N], 1, N, |_, args| Ok(Value::Number(number(&args[0]).atan()))),
+    ("LOG", &[N], 1, N, |_, args| {
+        let n = number(&args[0]);
+        if n <= 0.0 {
+            return Err(BasicError::IllegalQuantity("LOG of non-positive number".to_string()));
+        }
+        Ok(Value::Number(n.ln()))
+    }),
+    ("EXP", &[N], 1, N, |_, args| Ok(Value::Number(number(&args[0]).exp()))),
+    // RND 的参数可以省略（等于 RND(1)）
+    ("RND", &[N], 0, N, rnd),
+    // 字符串函数
+    ("LEN", &[S], 1, N, |_, args| Ok(Value::Number(string(&args[0]).len() as f64))),
+    ("ASC", &[S], 1, N, |executor, args| {
+        let ch = string(&args[0]).chars().next().ok_or_else(|| {
+            BasicError::IllegalQuantity("ASC of empty string".to_string())
+        })?;
+        let code = executor.charset().from_char(ch).ok_or_else(|| {
+            BasicError::IllegalQuantity(format!("ASC of character {:?} not in character set", ch))
+        })?;
+        Ok(Value::Number(code as f64))
+    }),
+    ("CHR$", &[N], 1, S, |executor, args| {
+        let n = number(&args[0]);
+        if !(0.0..=255.0).contains(&n) {
+            return Err(BasicError::IllegalQuantity("CHR$ argument out of range".to_string()));
+        }
+        Ok(Value::String(executor.charset().to_char(n as u8).to_string()))
+    }),
+    ("STR$", &[N], 1, S, |_, args| {
+        let n = number(&args[0]);
+        // BASIC 的 STR$ 在正数前加空格
+        let s = if n >= 0.0 { format!(" {}", n) } else { n.to_string() };
+        Ok(Value::String(s))
+    }),
+    ("VAL", &[S], 1, N, |_, args| {
+        Ok(Value::Number(string(&args[0]).trim().parse::<f64>().unwrap_or(0.0)))
+    }),
+    ("LEFT$", &[S, N], 2, S, |_, args| {
+        let n = number(&args[1]) as usize;
+        Ok(Value::String(string(&args[0]).chars().take(n).collect()))
+    }),
+    ("RIGHT$", &[S, N], 2, S, |_, args| {
+        let s = string(&args[0]);
+        let skip = s.chars().count().saturating_sub(number(&args[1]) as usize);
+        Ok(Value::String(s.chars().skip(skip).collect()))
+    }),
+    ("MID$", &[S, N, N], 2, S, |_, args| {
+        // BASIC 的 MID$ 是 1-based
+        let start = (number(&args[1]) as usize).saturating_sub(1);
+        let chars = string(&args[0]).chars().skip(start);
+        let result = match args.get(2) {
+            Some(len) => chars.take(number(len) as usize).collect(),
+            None => chars.collect(),
+        };
+        Ok(Value::String(result))
+    }),
+    // INSTR(start, string1, string2) 或 INSTR(string1, string2)：
+    // 可省略的是第一个参数，所以类型由实现检查
+    ("INSTR", &[A, A, A], 2, N, instr),
+    ("SPACE$", &[N], 1, S, |_, args| Ok(Value::String(" ".repeat(number(&args[0]) as usize)))),
+    // 系统函数
+    ("POS", &[A], 1, N, |executor, _| {
+        // 参数被忽略；返回 1-based 列位置
+        Ok(Value::Number((executor.print_column() + 1) as f64))
+    }),
+    ("FRE", &[A], 1, N, |executor, _| {
+        // 参数被忽略；固定的可用空间减去数组占用的字节数
+        let used = executor.variables().array_memory();
+        Ok(Value::Number(FREE_MEMORY.saturating_sub(used) as f64))
+    }),
+    ("PEEK", &[N], 1, N, |executor, args| {
+        // 屏幕 RAM / 颜色 RAM 映射到虚拟屏幕
+        let address = number(&args[0]);
+        if !(0.0..=65535.0).contains(&address) {
+            return Err(BasicError::IllegalQuantity(format!("Address {} out of range", address)));
+        }
+        Ok(Value::Number(executor.peek(address as u16) as f64))
+    }),
+    ("USR", &[A], 1, N, |_, _| {
+        // 调用机器语言程序（简化实现：返回 0）
+        Ok(Value::Number(0.0))
+    }),
+];
+
+/// 数值参数（类型已检查）
+fn number(value: &Value) -> f64 {
+    value.as_number().unwrap_or(0.0)
+}
+
+/// 字符串参数（类型已检查）
+fn string(value: &Value) -> &str {
+    match value {
+        Value::String(s) => s,
+        Value::Number(_) => "",
+    }
+}
+
+fn rnd(_: &mut Executor, _: &[Value]) -> Result<Value> {
+    use rand::Rng;
+
+    // RND 函数的 BASIC 6502 语义：
+    // RND(0) - 返回最近生成的随机数（简化为生成新的）
+    // RND(正数) - 返回 [0, 1) 的随机浮点数
+    // RND(负数) - 使用负数作为种子（暂不实现种子功能）
+    // 简化实现：所有情况都返回 [0, 1) 的随机数
+    Ok(Value::Number(rand::thread_rng().gen::<f64>()))
+}
+
+fn instr(_: &mut Executor, args: &[Value]) -> Result<Value> {
+    // 返回 string2 在 string1 中第一次出现的位置（1-based），如果没找到返回 0
+    let (start_pos, str1, str2) = match args {
+        [start, s1, s2] => (start.as_number()? as usize, s1.as_string()?, s2.as_string()?),
+        [s1, s2] => (1, s1.as_string()?, s2.as_string()?),
+        _ => unreachable!(),
+    };
+
+    // BASIC 的 INSTR 是 1-based
+    let start_pos = start_pos.saturating_sub(1);
+
+    // 从 start_pos 开始查找
+    match str1.get(start_pos..).and_then(|rest| rest.find(&str2)) {
+        Some(pos) => Ok(Value::Number((start_pos + pos + 1) as f64)),
+        None => Ok(Value::Number(0.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_functions() {
+        let registry = FunctionRegistry::new();
+        let mut executor = Executor::new();
+        let left = registry.get("left$").unwrap();
+        assert_eq!(left.params(), &[ArgType::String, ArgType::Number]);
+        assert_eq!(
+            left.call(&mut executor, &[Value::String("HELLO".to_string()), Value::Number(2.0)]).unwrap(),
+            Value::String("HE".to_string())
+        );
+        assert!(registry.names().contains(&"MID$"));
+    }
+
+    #[test]
+    fn test_argument_checking() {
+        let registry = FunctionRegistry::new();
+        let mut executor = Executor::new();
+        let mid = registry.get("MID$").unwrap();
+        match mid.call(&mut executor, &[Value::String("A".to_string())]) {
+            Err(BasicError::SyntaxError(msg)) => assert_eq!(msg, "MID$ requires 2 or 3 arguments"),
+            other => panic!("Expected syntax error, got {:?}", other),
+        }
+        let len = registry.get("LEN").unwrap();
+        assert!(matches!(len.call(&mut executor, &[Value::Number(1.0)]), Err(BasicError::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn test_register_host_function() {
+        let mut registry = FunctionRegistry::empty();
+        registry.register("twice", &[ArgType::Number], ArgType::Number, |args| {
+            Ok(Value::Number(args[0].as_number()? * 2.0))
+        });
+        registry.register("BAD$", &[], ArgType::String, |_| Ok(Value::Number(0.0)));
+        let mut executor = Executor::new();
+
+        assert_eq!(registry.get("TWICE").unwrap().call(&mut executor, &[Value::Number(21.0)]).unwrap(), Value::Number(42.0));
+        // 返回值类型也要检查
+        assert!(matches!(registry.get("BAD$").unwrap().call(&mut executor, &[]), Err(BasicError::TypeMismatch(_))));
+        assert!(registry.unregister("TWICE"));
+        assert!(registry.get("TWICE").is_none());
+    }
+}
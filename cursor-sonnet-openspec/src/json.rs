@@ -0,0 +1,191 @@
+//! 程序 AST 的 JSON 导入导出
+//!
+//! 外部工具（可视化、网页编辑器、重构脚本）通过 JSON 读写 BASIC 程序，
+//! 不需要自己实现词法和语法分析。JSON 外面包一层带格式名和版本号的文档：
+//!
+//! ```json
+//! { "format": "basic-m6502-ast", "version": 1, "lines": [ ... ] }
+//! { "format": "basic-m6502-ast", "version": 1, "line": { ... } }
+//! ```
+//!
+//! 节点直接由 `ast` 的类型序列化：枚举用 snake_case 的变体名作为键
+//! （`{"number": 10.0}`、`{"binary_op": {"left": ..., "op": "add", "right": ...}}`），
+//! 没有数据的变体是字符串（`"return"`、`"comma"`）。
+//! 格式有不兼容的变化时增加 `VERSION`，读取时拒绝更高的版本。
+
+use crate::ast::ProgramLine;
+use crate::error::{BasicError, Result};
+use crate::executor::Executor;
+use crate::parser::Parser;
+use crate::tokenizer::Tokenizer;
+use serde::{Deserialize, Serialize};
+
+/// 文档格式名
+pub const FORMAT: &str = "basic-m6502-ast";
+
+/// 当前格式版本
+pub const VERSION: u32 = 1;
+
+/// 整个程序
+#[derive(Debug, Serialize, Deserialize)]
+struct ProgramDocument {
+    format: String,
+    version: u32,
+    lines: Vec<ProgramLine>,
+}
+
+/// 单独一行
+#[derive(Debug, Serialize, Deserialize)]
+struct LineDocument {
+    format: String,
+    version: u32,
+    line: ProgramLine,
+}
+
+/// 程序导出为 JSON（带缩进）
+pub fn program_to_json(lines: &[ProgramLine]) -> String {
+    let document = ProgramDocument {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        lines: lines.to_vec(),
+    };
+    serde_json::to_string_pretty(&document).expect("AST is always serializable")
+}
+
+/// 从 JSON 导入程序
+pub fn program_from_json(text: &str) -> Result<Vec<ProgramLine>> {
+    let document: ProgramDocument = serde_json::from_str(text).map_err(json_error)?;
+    check_header(&document.format, document.version)?;
+    Ok(document.lines)
+}
+
+/// 一行导出为 JSON（不带缩进）
+pub fn line_to_json(line: &ProgramLine) -> String {
+    let document = LineDocument {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        line: line.clone(),
+    };
+    serde_json::to_string(&document).expect("AST is always serializable")
+}
+
+/// 从 JSON 导入一行
+pub fn line_from_json(text: &str) -> Result<ProgramLine> {
+    let document: LineDocument = serde_json::from_str(text).map_err(json_error)?;
+    check_header(&document.format, document.version)?;
+    Ok(document.line)
+}
+
+/// 解析程序文本（每行一个带行号的程序行），同号的行以后出现的为准
+pub fn parse_program(source: &str) -> Result<Vec<ProgramLine>> {
+    let mut lines = std::collections::BTreeMap::new();
+    for text in source.lines() {
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let tokens = Tokenizer::new(text).tokenize_line()?;
+        match Parser::new(tokens).parse_line()? {
+            Some(line) if line.line_number > 0 => {
+                lines.insert(line.line_number, line);
+            }
+            _ => {
+                return Err(BasicError::SyntaxError(format!("Program line without line number: {}", text)));
+            }
+        }
+    }
+    Ok(lines.into_values().collect())
+}
+
+/// 程序行还原为 BASIC 文本（规范格式，每行一个换行）
+pub fn unparse_program(lines: &[ProgramLine]) -> String {
+    lines
+        .iter()
+        .map(|line| Executor::serialize_program_line(line) + "\n")
+        .collect()
+}
+
+fn check_header(format: &str, version: u32) -> Result<()> {
+    if format != FORMAT {
+        return Err(BasicError::SyntaxError(format!("Not a {} document: format {:?}", FORMAT, format)));
+    }
+    if version > VERSION {
+        return Err(BasicError::SyntaxError(format!(
+            "Unsupported {} version {} (this build reads up to {})",
+            FORMAT, version, VERSION
+        )));
+    }
+    Ok(())
+}
+
+fn json_error(error: serde_json::Error) -> BasicError {
+    BasicError::SyntaxError(format!("Invalid program JSON: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "\
+10 REM ROUND TRIP
+20 DIM A(10), B$(2, 3): DEF FN SQ(X) = X * X
+30 FOR I = 1 TO 10 STEP 2: A(I) = FN SQ(I): NEXT I
+40 IF A(3) > 5 AND NOT Q THEN PRINT \"BIG\"; TAB(10); -A(3), : GOTO 60
+50 ON I GOSUB 100, 110: INPUT \"NAME\"; N$
+60 READ X, Y$: DATA 1.5, \"HI, THERE\": RESTORE
+70 PRINT USING \"##.#\"; X: POKE 1024, 1: GET K$
+80 END
+100 RETURN
+110 PRINT MID$(N$, 2, 3): RETURN
+";
+
+    // Requirement: JSON - 程序往返后 AST 不变
+    #[test]
+    fn test_program_round_trip() {
+        let lines = parse_program(PROGRAM).unwrap();
+        let json = program_to_json(&lines);
+        assert_eq!(program_from_json(&json).unwrap(), lines);
+
+        // 还原的文本重新解析得到相同的 AST
+        let text = unparse_program(&lines);
+        assert_eq!(parse_program(&text).unwrap(), lines);
+    }
+
+    // Requirement: JSON - 单行格式固定
+    #[test]
+    fn test_line_format() {
+        let line = &parse_program("10 LET A = -2 + B(1): RETURN").unwrap()[0];
+        let json = line_to_json(line);
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"format":"basic-m6502-ast","version":1,"line":{"line_number":10,"statements":["#,
+                r#"{"let":{"target":{"variable":"A"},"value":{"binary_op":{"left":{"unary_op":{"op":"minus","operand":{"number":2.0}}},"#,
+                r#""op":"add","right":{"array_access":{"name":"B","indices":[{"number":1.0}]}}}}}},"#,
+                r#""return"]}}"#
+            )
+        );
+        assert_eq!(&line_from_json(&json).unwrap(), line);
+    }
+
+    // Requirement: JSON - 源码范围不写入 JSON
+    #[test]
+    fn test_spans_not_serialized() {
+        let source = "10 LET A = -2 + B(1): RETURN";
+        let tokens = Tokenizer::new(source).tokenize_line_spanned().unwrap();
+        let spanned = Parser::new_spanned(tokens).parse_line().unwrap().unwrap();
+        assert!(!spanned.statements[0].span.is_empty());
+        let line = &parse_program(source).unwrap()[0];
+        assert_eq!(line_to_json(&spanned), line_to_json(line));
+    }
+
+    // Requirement: JSON - 拒绝其他格式和更高版本
+    #[test]
+    fn test_header_checks() {
+        let json = program_to_json(&parse_program("10 END").unwrap());
+        assert!(program_from_json(&json.replace("\"version\": 1", "\"version\": 2")).is_err());
+        assert!(program_from_json(&json.replace(FORMAT, "other")).is_err());
+        assert!(program_from_json("{\"lines\": []}").is_err());
+        assert!(program_from_json("not json").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! 键盘输入
+//!
+//! GET 不等待回车：第一次 GET 时终端切换到 cbreak 模式（关闭行缓冲和回显，
+//! 保留 Ctrl+C 信号和输出换行处理），之后每次 GET 只读取已经到达的字节。
+//! 方向键和功能键的转义序列解析为 `Key`，再由字符集转换为对应的控制码。
+//! 按键先进入一个小的键盘队列，和原版一样，队列满时多出的按键被丢弃。
+
+use std::collections::VecDeque;
+
+/// 键盘队列容量（原版的键盘缓冲区是 10 个字符）
+pub const BUFFER_SIZE: usize = 10;
+
+/// 一次按键
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// 普通字符
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    /// 功能键 F1-F12
+    Function(u8),
+}
+
+/// 键盘队列
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    queue: VecDeque<Key>,
+}
+
+impl Keyboard {
+    /// 创建空的键盘队列
+    pub fn new() -> Self {
+        Keyboard { queue: VecDeque::with_capacity(BUFFER_SIZE) }
+    }
+
+    /// 按键放入队列，队列满时丢弃
+    pub fn push(&mut self, key: Key) {
+        if self.queue.len() < BUFFER_SIZE {
+            self.queue.push_back(key);
+        }
+    }
+
+    /// 读取终端上已经到达的按键，返回队列中的第一个按键；没有按键时立即返回 None
+    pub fn poll(&mut self) -> Option<Key> {
+        for key in parse_keys(&terminal::read_available()) {
+            self.push(key);
+        }
+        self.queue.pop_front()
+    }
+
+    /// 清空队列
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+/// 恢复终端原来的模式（程序结束、INPUT 读取整行和 Ctrl+C 时调用）
+pub fn restore_terminal() {
+    terminal::restore();
+}
+
+/// 把终端发送的字节解析为按键
+///
+/// 识别 VT100/xterm 的方向键、Home、Delete 和 F1-F12 转义序列，
+/// 无法识别的转义序列被丢弃
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+    let mut keys = Vec::new();
+
+    while let Some(ch) = chars.next() {
+        let key = match ch {
+            '\r' | '\n' => Key::Enter,
+            '\x08' | '\x7f' => Key::Backspace,
+            '\x1b' => {
+                // ESC 后面跟 '[' 或 'O' 才是转义序列，否则是单独的 ESC 键
+                let introducer = match chars.peek() {
+                    Some(&c @ ('[' | 'O')) => c,
+                    _ => {
+                        keys.push(Key::Escape);
+                        continue;
+                    }
+                };
+                chars.next();
+                let mut parameter = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_digit() && c != ';' {
+                        break;
+                    }
+                    parameter.push(c);
+                    chars.next();
+                }
+                match (introducer, parameter.as_str(), chars.next()) {
+                    (_, _, Some('A')) => Key::Up,
+                    (_, _, Some('B')) => Key::Down,
+                    (_, _, Some('C')) => Key::Right,
+                    (_, _, Some('D')) => Key::Left,
+                    (_, _, Some('H')) | ('[', "1" | "7", Some('~')) => Key::Home,
+                    ('[', "3", Some('~')) => Key::Delete,
+                    ('O', _, Some(c @ 'P'..='S')) => Key::Function(c as u8 - b'P' + 1),
+                    ('[', number, Some('~')) => match number {
+                        "15" => Key::Function(5),
+                        "17" => Key::Function(6),
+                        "18" => Key::Function(7),
+                        "19" => Key::Function(8),
+                        "20" => Key::Function(9),
+                        "21" => Key::Function(10),
+                        "23" => Key::Function(11),
+                        "24" => Key::Function(12),
+                        _ => continue,
+                    },
+                    _ => continue,
+                }
+            }
+            ch => Key::Char(ch),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::sync::Mutex;
+
+    /// 切换到 cbreak 模式之前的终端设置
+    static SAVED: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+    /// 标准输入是终端时切换到 cbreak 模式（已经切换过则什么也不做）
+    fn enable() -> bool {
+        let mut saved = SAVED.lock().unwrap_or_else(|e| e.into_inner());
+        if saved.is_some() {
+            return true;
+        }
+        // SAFETY: 只对标准输入调用 termios 函数，termios 结构由 tcgetattr 填充
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) != 1 {
+                return false;
+            }
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return false;
+            }
+            let original = termios;
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            termios.c_cc[libc::VMIN] = 0;
+            termios.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                return false;
+            }
+            *saved = Some(original);
+        }
+        true
+    }
+
+    /// 恢复切换之前的终端设置
+    pub fn restore() {
+        let mut saved = SAVED.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(original) = saved.take() {
+            // SAFETY: original 是 tcgetattr 读到的设置
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original);
+            }
+        }
+    }
+
+    /// 读取已经到达的字节，不阻塞
+    ///
+    /// 标准输入不是终端（管道或文件）时每次最多读一个字节，避免吃掉后面的输入行
+    pub fn read_available() -> Vec<u8> {
+        let limit = if enable() { 64 } else { 1 };
+        let mut bytes = Vec::new();
+        while bytes.len() < limit {
+            let mut poll = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+            let mut byte = 0u8;
+            // SAFETY: poll 和 read 只访问栈上的局部变量
+            let read = unsafe {
+                if libc::poll(&mut poll, 1, 0) <= 0 || poll.revents & libc::POLLIN == 0 {
+                    break;
+                }
+                libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1)
+            };
+            if read != 1 {
+                break;
+            }
+            bytes.push(byte);
+        }
+        bytes
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    /// 其他平台不支持非阻塞读取，GET 总是得到空字符串
+    pub fn read_available() -> Vec<u8> {
+        Vec::new()
+    }
+
+    pub fn restore() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Requirement: 键盘 - 转义序列解析
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(
+            parse_keys(b"a\x1b[A\x1b[B\x1b[C\x1b[D\r"),
+            vec![Key::Char('a'), Key::Up, Key::Down, Key::Right, Key::Left, Key::Enter]
+        );
+        assert_eq!(
+            parse_keys(b"\x1bOP\x1b[15~\x1b[24~\x1b[3~\x1b[H\x7f"),
+            vec![Key::Function(1), Key::Function(5), Key::Function(12), Key::Delete, Key::Home, Key::Backspace]
+        );
+        // 单独的 ESC 和无法识别的序列
+        assert_eq!(parse_keys(b"\x1b\x1b[99~x"), vec![Key::Escape, Key::Char('x')]);
+        assert_eq!(parse_keys("é".as_bytes()), vec![Key::Char('é')]);
+    }
+
+    // Requirement: 键盘 - 队列满时丢弃按键
+    #[test]
+    fn test_queue_capacity() {
+        let mut keyboard = Keyboard::new();
+        for ch in "ABCDEFGHIJKL".chars() {
+            keyboard.push(Key::Char(ch));
+        }
+        let mut keys = Vec::new();
+        while let Some(key) = keyboard.queue.pop_front() {
+            keys.push(key);
+        }
+        assert_eq!(keys.len(), BUFFER_SIZE);
+        assert_eq!(keys.last(), Some(&Key::Char('J')));
+    }
+}
@@ -2,17 +2,28 @@ pub mod error;
 pub mod token;
 pub mod tokenizer;
 pub mod ast;
+pub mod charset;
+pub mod clock;
 pub mod parser;
 pub mod runtime;
 pub mod variables;
 pub mod executor;
+pub mod functions;
+pub mod keyboard;
+pub mod print_using;
+pub mod compiler;
+pub mod json;
+pub mod diagnostic;
+pub mod screen;
+pub mod lsp;
 
 pub use error::{BasicError, Result};
 pub use token::Token;
-pub use tokenizer::Tokenizer;
+pub use tokenizer::{SpannedToken, Tokenizer};
 pub use ast::*;
 pub use parser::Parser;
 pub use runtime::Runtime;
 pub use variables::{Variables, Value, Array};
-pub use executor::{Executor, DataValue};
+pub use executor::{Executor, DataValue, ListingStyle};
+pub use functions::{ArgType, FunctionRegistry};
 
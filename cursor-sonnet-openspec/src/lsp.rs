@@ -0,0 +1,1026 @@
+//! 语言服务器（LSP）
+//!
+//! 基于 Tokenizer / Parser / ast 为 .bas 文件提供诊断、跳转定义、查找引用、
+//! 悬停提示、变量重命名和 GOSUB 入口符号，通过 stdio 上的 JSON-RPC 与编辑器通信
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value as Json};
+
+use crate::ast::*;
+use crate::diagnostic;
+use crate::error::BasicError;
+use crate::executor::Executor;
+use crate::parser::Parser;
+use crate::token::Token;
+use crate::tokenizer::{SpannedToken, Tokenizer};
+
+/// JSON-RPC 错误码
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// 诊断严重级别（LSP DiagnosticSeverity）
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+
+/// 文档符号种类（LSP SymbolKind::Function）
+const SYMBOL_KIND_FUNCTION: u8 = 12;
+
+/// 内置函数说明（名称、签名、描述）
+pub struct BuiltinFunction {
+    pub name: &'static str,
+    pub signature: &'static str,
+    pub description: &'static str,
+}
+
+/// 内置函数表（用于悬停提示）
+pub const BUILTIN_FUNCTIONS: &[BuiltinFunction] = &[
+    BuiltinFunction { name: "SGN", signature: "SGN(X)", description: "Sign of X: -1, 0 or 1." },
+    BuiltinFunction { name: "INT", signature: "INT(X)", description: "Largest integer not greater than X." },
+    BuiltinFunction { name: "ABS", signature: "ABS(X)", description: "Absolute value of X." },
+    BuiltinFunction { name: "USR", signature: "USR(X)", description: "Call the user machine-language routine with argument X." },
+    BuiltinFunction { name: "FRE", signature: "FRE(X)", description: "Number of free bytes of memory. X is ignored." },
+    BuiltinFunction { name: "POS", signature: "POS(X)", description: "Current print column (1-based). X is ignored." },
+    BuiltinFunction { name: "SQR", signature: "SQR(X)", description: "Square root of X. X must not be negative." },
+    BuiltinFunction { name: "RND", signature: "RND(X)", description: "Random number in [0, 1)." },
+    BuiltinFunction { name: "LOG", signature: "LOG(X)", description: "Natural logarithm of X. X must be positive." },
+    BuiltinFunction { name: "EXP", signature: "EXP(X)", description: "e raised to the power X." },
+    BuiltinFunction { name: "COS", signature: "COS(X)", description: "Cosine of X (radians)." },
+    BuiltinFunction { name: "SIN", signature: "SIN(X)", description: "Sine of X (radians)." },
+    BuiltinFunction { name: "TAN", signature: "TAN(X)", description: "Tangent of X (radians)." },
+    BuiltinFunction { name: "ATN", signature: "ATN(X)", description: "Arctangent of X, in radians." },
+    BuiltinFunction { name: "PEEK", signature: "PEEK(ADDR)", description: "Byte stored at memory address ADDR." },
+    BuiltinFunction { name: "LEN", signature: "LEN(S$)", description: "Number of characters in S$." },
+    BuiltinFunction { name: "STR$", signature: "STR$(X)", description: "String form of X, with a leading space for non-negative numbers." },
+    BuiltinFunction { name: "VAL", signature: "VAL(S$)", description: "Numeric value of S$, or 0 if it is not a number." },
+    BuiltinFunction { name: "ASC", signature: "ASC(S$)", description: "Character code of the first character of S$." },
+    BuiltinFunction { name: "CHR$", signature: "CHR$(N)", description: "One-character string with code N (0-255)." },
+    BuiltinFunction { name: "LEFT$", signature: "LEFT$(S$, N)", description: "Leftmost N characters of S$." },
+    BuiltinFunction { name: "RIGHT$", signature: "RIGHT$(S$, N)", description: "Rightmost N characters of S$." },
+    BuiltinFunction { name: "MID$", signature: "MID$(S$, START[, N])", description: "N characters of S$ starting at position START (1-based)." },
+    BuiltinFunction { name: "INSTR", signature: "INSTR([START,] S$, T$)", description: "Position of T$ in S$ (1-based), or 0 if not found." },
+    BuiltinFunction { name: "SPACE$", signature: "SPACE$(N)", description: "String of N spaces." },
+    BuiltinFunction { name: "TAB", signature: "TAB(N)", description: "PRINT only: move to column N." },
+    BuiltinFunction { name: "SPC", signature: "SPC(N)", description: "PRINT only: output N spaces." },
+];
+
+/// 按名称查找内置函数
+pub fn find_builtin(name: &str) -> Option<&'static BuiltinFunction> {
+    let upper = name.to_uppercase();
+    BUILTIN_FUNCTIONS.iter().find(|f| f.name == upper)
+}
+
+/// 文档中的源码范围（0-based 行号，行内字符偏移左闭右开）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceRange {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SourceRange {
+    fn contains(&self, line: usize, column: usize) -> bool {
+        self.line == line && self.start <= column && column <= self.end
+    }
+}
+
+/// 可以被跳转、引用或重命名的符号
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SymbolKey {
+    /// BASIC 行号
+    Line(u16),
+    /// 简单变量（大写名称）
+    Variable(String),
+    /// 数组（与同名简单变量相互独立）
+    Array(String),
+    /// DEF FN 用户函数
+    UserFunction(String),
+    /// 内置函数
+    Builtin(&'static str),
+}
+
+/// 符号在文档中的一次出现
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    pub key: SymbolKey,
+    pub range: SourceRange,
+    /// 是否为定义处（行首行号或 DEF FN）
+    pub is_definition: bool,
+}
+
+/// 诊断信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub range: SourceRange,
+    pub severity: u8,
+    pub message: String,
+}
+
+/// 一个文档的分析结果
+#[derive(Debug, Clone, Default)]
+pub struct DocumentAnalysis {
+    /// BASIC 行号 -> 行号 token 所在范围
+    pub line_definitions: BTreeMap<u16, SourceRange>,
+    /// 所有符号出现位置
+    pub occurrences: Vec<Occurrence>,
+    /// 诊断信息
+    pub diagnostics: Vec<Diagnostic>,
+    /// GOSUB / ON...GOSUB 的目标行号
+    pub gosub_targets: BTreeSet<u16>,
+    /// 用户函数名 -> 定义文本（用于悬停提示）
+    pub user_functions: HashMap<String, String>,
+    /// 各 BASIC 行的 REM 注释（行首语句为 REM 时）
+    pub line_comments: HashMap<u16, String>,
+}
+
+impl DocumentAnalysis {
+    /// 查找指定位置上的符号
+    pub fn occurrence_at(&self, line: usize, column: usize) -> Option<&Occurrence> {
+        self.occurrences.iter().find(|occ| occ.range.contains(line, column))
+    }
+
+    /// 查找某个符号的所有出现位置
+    pub fn occurrences_of<'a>(&'a self, key: &'a SymbolKey) -> impl Iterator<Item = &'a Occurrence> + 'a {
+        self.occurrences.iter().filter(move |occ| &occ.key == key)
+    }
+}
+
+/// 分析整个 BASIC 源文件
+pub fn analyze(text: &str) -> DocumentAnalysis {
+    let mut analysis = DocumentAnalysis::default();
+    let mut line_references = Vec::new();
+
+    for (index, raw_line) in text.lines().enumerate() {
+        if raw_line.trim().is_empty() {
+            continue;
+        }
+        analyze_line(index, raw_line, &mut analysis, &mut line_references);
+    }
+
+    // 引用了不存在的行号
+    for (line_number, range) in line_references {
+        if !analysis.line_definitions.contains_key(&line_number) {
+            analysis.diagnostics.push(Diagnostic {
+                range,
+                severity: SEVERITY_ERROR,
+                message: format!("?UNDEF'D STATEMENT: line {} does not exist", line_number),
+            });
+        }
+    }
+
+    analysis
+}
+
+/// 分析一行源码
+fn analyze_line(
+    index: usize,
+    raw_line: &str,
+    analysis: &mut DocumentAnalysis,
+    line_references: &mut Vec<(u16, SourceRange)>,
+) {
+    let line_len = raw_line.chars().count();
+    let whole_line = SourceRange { line: index, start: 0, end: line_len };
+
+    let mut tokenizer = Tokenizer::new(raw_line);
+    let tokens = match tokenizer.tokenize_line_spanned() {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            analysis.diagnostics.push(Diagnostic {
+                range: tokenizer_error_range(index, &e, line_len).unwrap_or(whole_line),
+                severity: SEVERITY_ERROR,
+                message: diagnostic_message(&e),
+            });
+            return;
+        }
+    };
+
+    let line_number = match tokens.first().map(|t| &t.token) {
+        Some(Token::LineNumber(n)) => {
+            let range = span_range(index, &tokens[0]);
+            if analysis.line_definitions.contains_key(n) {
+                analysis.diagnostics.push(Diagnostic {
+                    range,
+                    severity: SEVERITY_WARNING,
+                    message: format!("Duplicate line number {}; this line replaces the earlier one", n),
+                });
+            }
+            analysis.line_definitions.insert(*n, range);
+            Some(*n)
+        }
+        _ => {
+            analysis.diagnostics.push(Diagnostic {
+                range: whole_line,
+                severity: SEVERITY_ERROR,
+                message: "?MISSING LINE NUMBER".to_string(),
+            });
+            None
+        }
+    };
+
+    collect_occurrences(index, &tokens, analysis, line_references);
+
+    let plain_tokens: Vec<Token> = tokens.iter().map(|t| t.token.clone()).collect();
+    let mut parser = Parser::new(plain_tokens);
+    match parser.parse_line() {
+        Ok(Some(program_line)) => {
+            if let Some(number) = line_number {
+                collect_from_ast(number, &program_line.statements, analysis);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            let range = tokens
+                .get(parser.position())
+                .map(|t| span_range(index, t))
+                .unwrap_or(whole_line);
+            analysis.diagnostics.push(Diagnostic {
+                range,
+                severity: SEVERITY_ERROR,
+                message: diagnostic_message(&e),
+            });
+        }
+    }
+}
+
+/// 从 token 流中收集行号引用、变量、数组和函数的出现位置
+fn collect_occurrences(
+    index: usize,
+    tokens: &[SpannedToken],
+    analysis: &mut DocumentAnalysis,
+    line_references: &mut Vec<(u16, SourceRange)>,
+) {
+    // 上一个 token 之后是否应出现跳转目标行号
+    let mut expect_target = false;
+    // 当前是否在 ON ... GOTO/GOSUB 的目标列表中
+    let mut in_on_statement = false;
+    let mut in_target_list = false;
+
+    for (i, spanned) in tokens.iter().enumerate() {
+        let range = span_range(index, spanned);
+        let previous = if i > 0 { Some(&tokens[i - 1].token) } else { None };
+
+        match &spanned.token {
+            Token::LineNumber(n) => {
+                analysis.occurrences.push(Occurrence {
+                    key: SymbolKey::Line(*n),
+                    range,
+                    is_definition: true,
+                });
+            }
+            Token::Number(n) if expect_target => match line_number_target(*n) {
+                Some(target) => {
+                    analysis.occurrences.push(Occurrence {
+                        key: SymbolKey::Line(target),
+                        range,
+                        is_definition: false,
+                    });
+                    line_references.push((target, range));
+                }
+                None => analysis.diagnostics.push(Diagnostic {
+                    range,
+                    severity: SEVERITY_ERROR,
+                    message: diagnostic_message(&BasicError::SyntaxError(format!("Invalid line number {}", n))),
+                }),
+            },
+            Token::Identifier(name) => {
+                let upper = name.to_uppercase();
+                let next = tokens.get(i + 1).map(|t| &t.token);
+                let (key, is_definition) = if previous == Some(&Token::Fn) {
+                    let is_def = i >= 2 && tokens[i - 2].token == Token::Def;
+                    (SymbolKey::UserFunction(upper), is_def)
+                } else if next == Some(&Token::LeftParen) {
+                    (SymbolKey::Array(upper), false)
+                } else {
+                    (SymbolKey::Variable(upper), false)
+                };
+                analysis.occurrences.push(Occurrence { key, range, is_definition });
+            }
+            _ => {
+                if let Some(builtin) = builtin_for_token(&spanned.token) {
+                    analysis.occurrences.push(Occurrence {
+                        key: SymbolKey::Builtin(builtin.name),
+                        range,
+                        is_definition: false,
+                    });
+                }
+            }
+        }
+
+        // 更新跳转目标上下文
+        match &spanned.token {
+            Token::On => in_on_statement = true,
+            Token::Colon => {
+                in_on_statement = false;
+                in_target_list = false;
+            }
+            _ => {}
+        }
+        expect_target = match &spanned.token {
+            Token::Goto | Token::Gosub => {
+                in_target_list = in_on_statement;
+                true
+            }
+            Token::Then | Token::Run | Token::Restore => true,
+            Token::Comma => in_target_list,
+            Token::Number(_) if in_target_list => false,
+            _ => {
+                in_target_list = false;
+                false
+            }
+        };
+    }
+}
+
+/// 从 AST 中收集 GOSUB 目标、用户函数定义和行注释
+fn collect_from_ast(line_number: u16, statements: &[Statement], analysis: &mut DocumentAnalysis) {
+    if let Some(StatementKind::Rem { comment }) = statements.first().map(|stmt| &stmt.kind) {
+        if !comment.is_empty() {
+            analysis.line_comments.insert(line_number, comment.clone());
+        }
+    }
+
+    for stmt in statements {
+        collect_from_statement(stmt, analysis);
+    }
+}
+
+fn collect_from_statement(stmt: &Statement, analysis: &mut DocumentAnalysis) {
+    match &stmt.kind {
+        StatementKind::Gosub { line_number } => {
+            if let ExprKind::Number(n) = line_number.kind {
+                // 越界的目标在 collect_occurrences 中报告
+                if let Some(target) = line_number_target(n) {
+                    analysis.gosub_targets.insert(target);
+                }
+            }
+        }
+        StatementKind::On { targets, is_gosub: true, .. } => {
+            analysis.gosub_targets.extend(targets.iter().copied());
+        }
+        StatementKind::DefFn { name, param, body } => {
+            analysis.user_functions.insert(
+                name.to_uppercase(),
+                format!("FN {}({}) = {}", name.to_uppercase(), param, Executor::serialize_expr(body)),
+            );
+        }
+        StatementKind::If { then_part, .. } => match then_part.as_ref() {
+            ThenPart::LineNumber(_) => {}
+            ThenPart::Statement(inner) => collect_from_statement(inner, analysis),
+            ThenPart::Statements(inner) => {
+                for s in inner {
+                    collect_from_statement(s, analysis);
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+/// 内置函数 token 对应的说明
+fn builtin_for_token(token: &Token) -> Option<&'static BuiltinFunction> {
+    let name = match token {
+        Token::Sgn => "SGN",
+        Token::Int => "INT",
+        Token::Abs => "ABS",
+        Token::Usr => "USR",
+        Token::Fre => "FRE",
+        Token::Pos => "POS",
+        Token::Sqr => "SQR",
+        Token::Rnd => "RND",
+        Token::Log => "LOG",
+        Token::Exp => "EXP",
+        Token::Cos => "COS",
+        Token::Sin => "SIN",
+        Token::Tan => "TAN",
+        Token::Atn => "ATN",
+        Token::Peek => "PEEK",
+        Token::Len => "LEN",
+        Token::StrFunc => "STR$",
+        Token::Val => "VAL",
+        Token::Asc => "ASC",
+        Token::ChrFunc => "CHR$",
+        Token::LeftFunc => "LEFT$",
+        Token::RightFunc => "RIGHT$",
+        Token::MidFunc => "MID$",
+        Token::Instr => "INSTR",
+        Token::SpaceFunc => "SPACE$",
+        Token::Tab => "TAB",
+        Token::Spc => "SPC",
+        _ => return None,
+    };
+    find_builtin(name)
+}
+
+/// 跳转目标的行号；不是 0-65535 之间的整数时为 None
+fn line_number_target(n: f64) -> Option<u16> {
+    (n.fract() == 0.0 && (0.0..=u16::MAX as f64).contains(&n)).then_some(n as u16)
+}
+
+fn span_range(line: usize, token: &SpannedToken) -> SourceRange {
+    SourceRange { line, start: token.start, end: token.end }
+}
+
+/// 词法错误的位置
+fn tokenizer_error_range(line: usize, error: &BasicError, line_len: usize) -> Option<SourceRange> {
+    diagnostic::tokenizer_error_span(error, line_len).map(|span| SourceRange {
+        line,
+        start: span.start,
+        end: span.end,
+    })
+}
+
+/// 诊断消息文本（非法字符错误的 Display 含多行上下文，这里只保留首行）
+fn diagnostic_message(error: &BasicError) -> String {
+    match error {
+        BasicError::IllegalCharacter(ch, _, _) => format!("?ILLEGAL CHARACTER '{}'", ch),
+        _ => error.to_string(),
+    }
+}
+
+/// 判断是否为合法的 BASIC 变量名
+fn is_valid_identifier(name: &str) -> bool {
+    let body = name.strip_suffix('$').unwrap_or(name);
+    let mut chars = body.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric()) && Token::from_keyword(name).is_none()
+}
+
+/// 已打开的文档
+struct Document {
+    lines: Vec<String>,
+    analysis: DocumentAnalysis,
+}
+
+impl Document {
+    fn new(text: &str) -> Self {
+        Document {
+            lines: text.lines().map(|l| l.to_string()).collect(),
+            analysis: analyze(text),
+        }
+    }
+
+    /// LSP 位置（UTF-16 列）转换为字符偏移
+    fn char_column(&self, line: usize, utf16_column: usize) -> usize {
+        let Some(text) = self.lines.get(line) else {
+            return utf16_column;
+        };
+        let mut units = 0;
+        for (i, ch) in text.chars().enumerate() {
+            if units >= utf16_column {
+                return i;
+            }
+            units += ch.len_utf16();
+        }
+        text.chars().count()
+    }
+
+    /// 字符偏移转换为 LSP 位置（UTF-16 列）
+    fn utf16_column(&self, line: usize, char_column: usize) -> usize {
+        match self.lines.get(line) {
+            Some(text) => text.chars().take(char_column).map(|c| c.len_utf16()).sum(),
+            None => char_column,
+        }
+    }
+
+    fn range_json(&self, range: &SourceRange) -> Json {
+        json!({
+            "start": { "line": range.line, "character": self.utf16_column(range.line, range.start) },
+            "end": { "line": range.line, "character": self.utf16_column(range.line, range.end) },
+        })
+    }
+}
+
+/// BASIC 语言服务器
+pub struct LanguageServer {
+    documents: HashMap<String, Document>,
+    shutdown_requested: bool,
+    exit_code: Option<i32>,
+}
+
+impl LanguageServer {
+    /// 创建新的语言服务器
+    pub fn new() -> Self {
+        LanguageServer {
+            documents: HashMap::new(),
+            shutdown_requested: false,
+            exit_code: None,
+        }
+    }
+
+    /// 收到 exit 通知后的退出码
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// 处理一条 JSON-RPC 消息，返回需要发送给客户端的消息（响应和通知）
+    pub fn handle_message(&mut self, message: &Json) -> Vec<Json> {
+        let Some(method) = message.get("method").and_then(Json::as_str) else {
+            // 客户端发来的响应（本服务器不发请求），忽略
+            if message.get("id").is_some() && message.get("result").is_some() {
+                return Vec::new();
+            }
+            return vec![error_response(message.get("id").cloned().unwrap_or(Json::Null), INVALID_REQUEST, "Invalid request")];
+        };
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+
+        match message.get("id").cloned() {
+            Some(id) => {
+                let result = self.handle_request(method, &params);
+                vec![match result {
+                    Ok(value) => json!({ "jsonrpc": "2.0", "id": id, "result": value }),
+                    Err((code, msg)) => error_response(id, code, &msg),
+                }]
+            }
+            None => self.handle_notification(method, &params),
+        }
+    }
+
+    fn handle_request(&mut self, method: &str, params: &Json) -> std::result::Result<Json, (i64, String)> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "renameProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "basic-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/rename" => self.rename(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        match method {
+            "textDocument/didOpen" => {
+                let uri = str_at(params, &["textDocument", "uri"]);
+                let text = str_at(params, &["textDocument", "text"]);
+                match (uri, text) {
+                    (Some(uri), Some(text)) => self.update_document(uri, text),
+                    _ => Vec::new(),
+                }
+            }
+            "textDocument/didChange" => {
+                // 只支持全量同步：取最后一次变更的完整文本
+                let uri = str_at(params, &["textDocument", "uri"]);
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                match (uri, text) {
+                    (Some(uri), Some(text)) => self.update_document(uri, text),
+                    _ => Vec::new(),
+                }
+            }
+            "textDocument/didClose" => match str_at(params, &["textDocument", "uri"]) {
+                Some(uri) => {
+                    self.documents.remove(uri);
+                    vec![publish_diagnostics(uri, Vec::new())]
+                }
+                None => Vec::new(),
+            },
+            "exit" => {
+                self.exit_code = Some(if self.shutdown_requested { 0 } else { 1 });
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn update_document(&mut self, uri: &str, text: &str) -> Vec<Json> {
+        let document = Document::new(text);
+        let diagnostics = document
+            .analysis
+            .diagnostics
+            .iter()
+            .map(|d| {
+                json!({
+                    "range": document.range_json(&d.range),
+                    "severity": d.severity,
+                    "source": "basic",
+                    "message": d.message,
+                })
+            })
+            .collect();
+        self.documents.insert(uri.to_string(), document);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    /// 解析请求中的文档和位置，返回 (uri, 文档, 行, 字符列)
+    fn locate<'a>(&'a self, params: &Json) -> std::result::Result<(&'a str, &'a Document, usize, usize), (i64, String)> {
+        let uri = str_at(params, &["textDocument", "uri"])
+            .ok_or((INVALID_PARAMS, "Missing textDocument.uri".to_string()))?;
+        let (uri, document) = self
+            .documents
+            .get_key_value(uri)
+            .ok_or((INVALID_PARAMS, format!("Unknown document: {}", uri)))?;
+        let line = params.pointer("/position/line").and_then(Json::as_u64);
+        let character = params.pointer("/position/character").and_then(Json::as_u64);
+        match (line, character) {
+            (Some(line), Some(character)) => {
+                let line = line as usize;
+                Ok((uri.as_str(), document, line, document.char_column(line, character as usize)))
+            }
+            _ => Err((INVALID_PARAMS, "Missing position".to_string())),
+        }
+    }
+
+    fn definition(&self, params: &Json) -> std::result::Result<Json, (i64, String)> {
+        let (uri, document, line, column) = self.locate(params)?;
+        let analysis = &document.analysis;
+        let target = analysis.occurrence_at(line, column).and_then(|occ| match &occ.key {
+            SymbolKey::Line(n) => analysis.line_definitions.get(n).copied(),
+            SymbolKey::UserFunction(_) => analysis
+                .occurrences_of(&occ.key)
+                .find(|o| o.is_definition)
+                .map(|o| o.range),
+            _ => None,
+        });
+        Ok(match target {
+            Some(range) => json!({ "uri": uri, "range": document.range_json(&range) }),
+            None => Json::Null,
+        })
+    }
+
+    fn references(&self, params: &Json) -> std::result::Result<Json, (i64, String)> {
+        let (uri, document, line, column) = self.locate(params)?;
+        let analysis = &document.analysis;
+        let include_declaration = params
+            .pointer("/context/includeDeclaration")
+            .and_then(Json::as_bool)
+            .unwrap_or(true);
+        let Some(occurrence) = analysis.occurrence_at(line, column) else {
+            return Ok(Json::Null);
+        };
+
+        Ok(Json::Array(
+            analysis
+                .occurrences_of(&occurrence.key)
+                .filter(|o| include_declaration || !o.is_definition)
+                .map(|o| json!({ "uri": uri, "range": document.range_json(&o.range) }))
+                .collect(),
+        ))
+    }
+
+    fn hover(&self, params: &Json) -> std::result::Result<Json, (i64, String)> {
+        let (_, document, line, column) = self.locate(params)?;
+        let analysis = &document.analysis;
+        let Some(occurrence) = analysis.occurrence_at(line, column) else {
+            return Ok(Json::Null);
+        };
+        let text = match &occurrence.key {
+            SymbolKey::Builtin(name) => find_builtin(name)
+                .map(|f| format!("```basic\n{}\n```\n{}", f.signature, f.description)),
+            SymbolKey::UserFunction(name) => analysis
+                .user_functions
+                .get(name)
+                .map(|def| format!("```basic\nDEF {}\n```", def)),
+            _ => None,
+        };
+        Ok(match text {
+            Some(value) => json!({
+                "contents": { "kind": "markdown", "value": value },
+                "range": document.range_json(&occurrence.range),
+            }),
+            None => Json::Null,
+        })
+    }
+
+    fn rename(&self, params: &Json) -> std::result::Result<Json, (i64, String)> {
+        let (uri, document, line, column) = self.locate(params)?;
+        let analysis = &document.analysis;
+        let new_name = params
+            .get("newName")
+            .and_then(Json::as_str)
+            .ok_or((INVALID_PARAMS, "Missing newName".to_string()))?
+            .to_uppercase();
+
+        let occurrence = analysis
+            .occurrence_at(line, column)
+            .ok_or((INVALID_PARAMS, "No variable at this position".to_string()))?;
+        let old_name = match &occurrence.key {
+            SymbolKey::Variable(name) | SymbolKey::Array(name) | SymbolKey::UserFunction(name) => name,
+            _ => return Err((INVALID_PARAMS, "Only variables, arrays and FN functions can be renamed".to_string())),
+        };
+        if !is_valid_identifier(&new_name) {
+            return Err((INVALID_PARAMS, format!("'{}' is not a valid variable name", new_name)));
+        }
+        if old_name.ends_with('$') != new_name.ends_with('$') {
+            return Err((INVALID_PARAMS, "Renaming cannot change the variable type ($ suffix)".to_string()));
+        }
+
+        let edits: Vec<Json> = analysis
+            .occurrences_of(&occurrence.key)
+            .map(|o| json!({ "range": document.range_json(&o.range), "newText": new_name }))
+            .collect();
+        Ok(json!({ "changes": { uri: edits } }))
+    }
+
+    fn document_symbols(&self, params: &Json) -> std::result::Result<Json, (i64, String)> {
+        let uri = str_at(params, &["textDocument", "uri"])
+            .ok_or((INVALID_PARAMS, "Missing textDocument.uri".to_string()))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or((INVALID_PARAMS, format!("Unknown document: {}", uri)))?;
+        let analysis = &document.analysis;
+
+        let symbols = analysis
+            .gosub_targets
+            .iter()
+            .filter_map(|n| analysis.line_definitions.get(n).map(|range| (n, range)))
+            .map(|(n, range)| {
+                let line_len = document.lines.get(range.line).map_or(0, |l| l.chars().count());
+                let full = SourceRange { line: range.line, start: 0, end: line_len };
+                let mut symbol = json!({
+                    "name": format!("GOSUB {}", n),
+                    "kind": SYMBOL_KIND_FUNCTION,
+                    "range": document.range_json(&full),
+                    "selectionRange": document.range_json(range),
+                });
+                if let Some(comment) = analysis.line_comments.get(n) {
+                    symbol["detail"] = Json::String(comment.clone());
+                }
+                symbol
+            })
+            .collect();
+        Ok(Json::Array(symbols))
+    }
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn str_at<'a>(value: &'a Json, path: &[&str]) -> Option<&'a str> {
+    path.iter().try_fold(value, |v, key| v.get(key))?.as_str()
+}
+
+fn error_response(id: Json, code: i64, message: &str) -> Json {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// 读取一条 `Content-Length` 分帧的消息；输入结束时返回 `Ok(None)`
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0u8; content_length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// 写出一条 `Content-Length` 分帧的消息
+pub fn write_message<W: Write>(writer: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// 在给定的输入输出流上运行语言服务器，返回进程退出码
+pub fn run<R: BufRead, W: Write>(mut reader: R, mut writer: W) -> io::Result<i32> {
+    let mut server = LanguageServer::new();
+
+    while let Some(body) = read_message(&mut reader)? {
+        let replies = match serde_json::from_str::<Json>(&body) {
+            Ok(message) => server.handle_message(&message),
+            Err(e) => vec![error_response(Json::Null, PARSE_ERROR, &e.to_string())],
+        };
+        for reply in &replies {
+            write_message(&mut writer, reply)?;
+        }
+        if let Some(code) = server.exit_code() {
+            return Ok(code);
+        }
+    }
+
+    // 输入流关闭但未收到 exit
+    Ok(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "10 REM MAIN\n\
+                           20 A = 1: B$ = \"X\"\n\
+                           30 GOSUB 100\n\
+                           40 IF A > 0 THEN 60\n\
+                           50 GOTO 999\n\
+                           60 PRINT LEN(B$); A\n\
+                           70 END\n\
+                           100 REM DRAW BOARD\n\
+                           110 A = A + 1\n\
+                           120 RETURN\n";
+
+    fn open(server: &mut LanguageServer, text: &str) -> Vec<Json> {
+        server.handle_message(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///p.bas", "languageId": "basic", "version": 1, "text": text } },
+        }))
+    }
+
+    fn request(server: &mut LanguageServer, method: &str, params: Json) -> Json {
+        let mut replies = server.handle_message(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }));
+        assert_eq!(replies.len(), 1);
+        replies.remove(0)
+    }
+
+    fn at(line: usize, character: usize) -> Json {
+        json!({ "textDocument": { "uri": "file:///p.bas" }, "position": { "line": line, "character": character } })
+    }
+
+    #[test]
+    fn test_undefined_goto_target_diagnostic() {
+        let analysis = analyze(PROGRAM);
+        assert_eq!(analysis.diagnostics.len(), 1);
+        let diag = &analysis.diagnostics[0];
+        assert!(diag.message.contains("999"));
+        assert_eq!(diag.range, SourceRange { line: 4, start: 8, end: 11 });
+    }
+
+    #[test]
+    fn test_parse_error_diagnostic_points_at_token() {
+        let analysis = analyze("10 PRINT (1 + 2\n20 A = \"OPEN\n");
+        assert_eq!(analysis.diagnostics.len(), 2);
+        assert_eq!(analysis.diagnostics[0].range.line, 0);
+        assert_eq!(analysis.diagnostics[0].range.start, 15);
+        assert_eq!(analysis.diagnostics[1].range, SourceRange { line: 1, start: 7, end: 12 });
+    }
+
+    #[test]
+    fn test_missing_line_number_and_duplicates() {
+        let analysis = analyze("PRINT 1\n10 END\n10 STOP\n");
+        let messages: Vec<_> = analysis.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("MISSING LINE NUMBER"));
+        assert!(messages[1].contains("Duplicate line number 10"));
+    }
+
+    #[test]
+    fn test_on_gosub_targets_are_references() {
+        let analysis = analyze("10 ON X GOSUB 100, 200: GOTO 10\n100 RETURN\n");
+        assert_eq!(analysis.gosub_targets, BTreeSet::from([100, 200]));
+        let undefined: Vec<_> = analysis.diagnostics.iter().map(|d| d.message.clone()).collect();
+        assert_eq!(undefined.len(), 1);
+        assert!(undefined[0].contains("200"));
+    }
+
+    #[test]
+    fn test_out_of_range_targets_are_diagnosed() {
+        let analysis = analyze("10 GOTO 70000\n20 GOSUB 1.5\n30 IF X THEN 65535\n4464 END\n");
+        let messages: Vec<_> = analysis.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].contains("Invalid line number 70000"));
+        assert_eq!(analysis.diagnostics[0].range, SourceRange { line: 0, start: 8, end: 13 });
+        assert!(messages[1].contains("Invalid line number 1.5"));
+        assert!(messages[2].contains("line 65535 does not exist"));
+        // 越界的目标不会被当作其他行的引用
+        assert!(analysis.gosub_targets.is_empty());
+        assert!(!analysis.occurrences.iter().any(|o| o.key == SymbolKey::Line(4464) && !o.is_definition));
+    }
+
+    #[test]
+    fn test_publish_diagnostics_on_open() {
+        let mut server = LanguageServer::new();
+        let replies = open(&mut server, PROGRAM);
+        assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(replies[0]["params"]["diagnostics"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_goto_definition_of_line_reference() {
+        let mut server = LanguageServer::new();
+        open(&mut server, PROGRAM);
+        // 第 3 行 "30 GOSUB 100" 中的 100
+        let reply = request(&mut server, "textDocument/definition", at(2, 10));
+        assert_eq!(reply["result"]["range"]["start"], json!({ "line": 7, "character": 0 }));
+        assert_eq!(reply["result"]["range"]["end"], json!({ "line": 7, "character": 3 }));
+    }
+
+    #[test]
+    fn test_find_references_for_variable_and_line() {
+        let mut server = LanguageServer::new();
+        open(&mut server, PROGRAM);
+        let reply = request(&mut server, "textDocument/references", at(1, 3));
+        assert_eq!(reply["result"].as_array().unwrap().len(), 5);
+
+        let mut params = at(7, 1);
+        params["context"] = json!({ "includeDeclaration": false });
+        let reply = request(&mut server, "textDocument/references", params);
+        let refs = reply["result"].as_array().unwrap();
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0]["range"]["start"]["line"], 2);
+    }
+
+    #[test]
+    fn test_hover_builtin_function() {
+        let mut server = LanguageServer::new();
+        open(&mut server, PROGRAM);
+        let reply = request(&mut server, "textDocument/hover", at(5, 10));
+        let value = reply["result"]["contents"]["value"].as_str().unwrap();
+        assert!(value.contains("LEN(S$)"));
+    }
+
+    #[test]
+    fn test_rename_variable() {
+        let mut server = LanguageServer::new();
+        open(&mut server, PROGRAM);
+        let mut params = at(8, 4);
+        params["newName"] = json!("count");
+        let reply = request(&mut server, "textDocument/rename", params);
+        let edits = reply["result"]["changes"]["file:///p.bas"].as_array().unwrap();
+        assert_eq!(edits.len(), 5);
+        assert!(edits.iter().all(|e| e["newText"] == "COUNT"));
+
+        let mut params = at(8, 4);
+        params["newName"] = json!("C$");
+        let reply = request(&mut server, "textDocument/rename", params);
+        assert_eq!(reply["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_document_symbols_for_gosub_entries() {
+        let mut server = LanguageServer::new();
+        open(&mut server, PROGRAM);
+        let reply = request(&mut server, "textDocument/documentSymbol", json!({ "textDocument": { "uri": "file:///p.bas" } }));
+        let symbols = reply["result"].as_array().unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0]["name"], "GOSUB 100");
+        assert_eq!(symbols[0]["detail"], "DRAW BOARD");
+    }
+
+    #[test]
+    fn test_stdio_session_with_canned_requests() {
+        let messages = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ];
+        let mut input = Vec::new();
+        for m in &messages {
+            write_message(&mut input, m).unwrap();
+        }
+
+        let mut output = Vec::new();
+        let code = run(io::Cursor::new(input), &mut output).unwrap();
+        assert_eq!(code, 0);
+
+        let mut reader = io::Cursor::new(output);
+        let init: Json = serde_json::from_str(&read_message(&mut reader).unwrap().unwrap()).unwrap();
+        assert_eq!(init["result"]["capabilities"]["renameProvider"], true);
+        let shutdown: Json = serde_json::from_str(&read_message(&mut reader).unwrap().unwrap()).unwrap();
+        assert_eq!(shutdown["id"], 2);
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+}
@@ -1,15 +1,67 @@
 use basic_m6502::{
-    ast::DataValue, BasicError, Executor, Parser, Result, Statement, Tokenizer,
+    charset::Charset,
+    clock::ClockMode,
+    compiler,
+    diagnostic::{self, Diagnostic, DiagnosticStyle},
+    json, keyboard,
+    screen::ScreenConfig,
+    BasicError, Executor, ListingStyle, Parser, Result, StatementKind, Token, Tokenizer,
 };
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
+use std::io::IsTerminal;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 /// REPL 提示符
 const PROMPT: &str = "BASIC.rs.";
 
+/// LIST 分页时每页显示的行数
+const PAGE_SIZE: usize = 20;
+
+/// AUTO 的默认起始行号和步长
+const AUTO_DEFAULT: u16 = 10;
+
+/// REPL 的输入模式
+enum InputMode {
+    /// 普通输入
+    Normal,
+    /// AUTO：自动生成行号，空行结束
+    Auto { next: u16, step: u16 },
+    /// EDIT：输入缓冲区预填为该行的文本（只作用于下一次输入）
+    Edit(String),
+}
+
+/// 只在 REPL 中可用的行编辑命令（不是 BASIC 语句，不能写进程序）
+#[derive(Debug, PartialEq)]
+enum EditCommand {
+    /// AUTO [start[,step]]
+    Auto { start: u16, step: u16 },
+    /// DELETE n / n-m / -m / n-
+    Delete { start: Option<u16>, end: Option<u16> },
+    /// EDIT n
+    Edit(u16),
+}
+
 fn main() -> Result<()> {
+    // --classic-errors：错误只显示经典的 ?SYNTAX ERROR IN 120，不显示出错位置
+    let style = if std::env::args().skip(1).any(|arg| arg == "--classic-errors") {
+        DiagnosticStyle::Classic
+    } else {
+        DiagnosticStyle::Caret
+    };
+    
+    // 子命令（compile / parse / unparse）处理文件后直接退出，不进入 REPL
+    let command_args: Vec<String> = std::env::args().skip(1).filter(|arg| arg != "--classic-errors").collect();
+    if let Some(result) = run_subcommand(&command_args) {
+        if let Err(diagnostic) = result {
+            // 显示完整的错误信息（出错位置、哪条语句不能编译、用法等）
+            eprintln!("{}", diagnostic.render(style));
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    
     println!("Microsoft BASIC 6502 Interpreter (Rust Edition)");
     println!();
 
@@ -19,10 +71,54 @@ fn main() -> Result<()> {
     
     ctrlc::set_handler(move || {
         interrupted_clone.store(true, Ordering::SeqCst);
+        // GET 可能把终端切换到了 cbreak 模式
+        keyboard::restore_terminal();
     }).expect("Error setting Ctrl-C handler");
 
     // 创建执行器
     let mut executor = Executor::new();
+    executor.set_interrupt_flag(interrupted.clone());
+    
+    // --canonical：LIST/SAVE 输出由 AST 重新生成的规范格式，而不是原始输入文本
+    if std::env::args().skip(1).any(|arg| arg == "--canonical") {
+        executor.set_listing_style(ListingStyle::Canonical);
+    }
+    
+    // --charset ascii|petscii|petscii-lower|apple2：CHR$/ASC、键盘输入和输出使用的字符集
+    // 没有指定时，启用 Commodore 风格的屏幕则使用 PETSCII，否则使用 ASCII
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let option = |name: &str| {
+        args.iter().position(|arg| arg == name).map(|position| args.get(position + 1).map(String::as_str))
+    };
+    match option("--charset") {
+        Some(name) => {
+            let name = name.unwrap_or("");
+            let charset = Charset::from_name(name).ok_or_else(|| {
+                BasicError::SyntaxError(format!("Unknown character set: {}", name))
+            })?;
+            executor.set_charset(charset);
+        }
+        None if option("--screen").is_some() => executor.set_charset(Charset::Petscii),
+        None => {}
+    }
+    
+    // --screen 40x25|22x23|80x24：启用虚拟字符屏幕，POKE 屏幕 RAM 直接显示
+    if let Some(size) = option("--screen") {
+        let size = size.unwrap_or("40x25");
+        let config = ScreenConfig::from_size(size).ok_or_else(|| {
+            BasicError::SyntaxError(format!("Unknown screen size: {}", size))
+        })?;
+        executor.enable_screen(config, true);
+    }
+    
+    // --clock real|deterministic[:N]：TI/TI$ 按真实时间计数，或每条语句前进 N 个 jiffy
+    if let Some(name) = option("--clock") {
+        let name = name.unwrap_or("");
+        let mode = ClockMode::from_name(name).ok_or_else(|| {
+            BasicError::SyntaxError(format!("Unknown clock mode: {}", name))
+        })?;
+        executor.variables_mut().clock_mut().set_mode(mode);
+    }
 
     // 创建 rustyline 编辑器（带历史记录）
     let mut rl = DefaultEditor::new().map_err(|e| {
@@ -36,6 +132,7 @@ fn main() -> Result<()> {
     // REPL 主循环
     // 提示符类型：None(无提示), Some(PROMPT)(命令执行后)
     let mut prompt_text: Option<&str> = Some(PROMPT);
+    let mut mode = InputMode::Normal;
     
     loop {
         // 使用println打印提示符（如果需要）
@@ -46,16 +143,57 @@ fn main() -> Result<()> {
         // 清除中断标志（准备接收新命令）
         interrupted.store(false, Ordering::SeqCst);
         
-        // 读取一行（提示符设为空）
-        let readline = rl.readline("");
+        // 读取一行（提示符设为空）；AUTO/EDIT 模式下预填输入缓冲区
+        let readline = match &mode {
+            InputMode::Normal => rl.readline(""),
+            InputMode::Auto { next, .. } => {
+                rl.readline_with_initial("", (&format!("{} ", next), ""))
+            }
+            InputMode::Edit(text) => rl.readline_with_initial("", (text.as_str(), "")),
+        };
         
         match readline {
             Ok(line) => {
+                // EDIT 的预填只作用于一次输入
+                if let InputMode::Edit(_) = mode {
+                    mode = InputMode::Normal;
+                }
+                
+                // AUTO 模式：空行（或只有行号）结束自动编号
+                if let InputMode::Auto { next, step } = mode {
+                    let trimmed = line.trim();
+                    if trimmed.is_empty() || trimmed == next.to_string() {
+                        mode = InputMode::Normal;
+                        prompt_text = Some(PROMPT);
+                        continue;
+                    }
+                    // 下一个行号跟随实际输入的行号；不是程序行则退出 AUTO
+                    mode = match leading_line_number(trimmed) {
+                        Some(number) => match number.checked_add(step) {
+                            Some(next) => InputMode::Auto { next, step },
+                            None => InputMode::Normal,
+                        },
+                        None => InputMode::Normal,
+                    };
+                }
+                
                 // 添加到历史记录
                 rl.add_history_entry(line.as_str()).ok();
                 
+                // 行编辑命令（AUTO/DELETE/EDIT）
+                let result = match parse_edit_command(&line) {
+                    Some(command) => command.and_then(|command| {
+                        mode = execute_edit_command(&mut executor, command)?;
+                        // 进入 AUTO/EDIT 后直接开始输入，不打印提示符
+                        Ok(matches!(mode, InputMode::Normal))
+                    }),
+                    None => process_line(&mut executor, &line, &interrupted, &mut rl, style),
+                };
+                // 回到 REPL 前恢复 GET 切换的终端模式
+                keyboard::restore_terminal();
+                
                 // 处理输入行
-                match process_line(&mut executor, &line, &interrupted) {
+                match result {
                     Ok(should_print_ready) => {
                         // 执行成功后，根据返回值决定是否显示提示符
                         if should_print_ready {
@@ -72,7 +210,7 @@ fn main() -> Result<()> {
                                 prompt_text = None;
                             }
                             _ => {
-                                eprintln!("?{}", format_error(&e));
+                                eprintln!("{}", Diagnostic::from(e).render(style));
                                 prompt_text = Some(PROMPT);
                             }
                         }
@@ -80,7 +218,8 @@ fn main() -> Result<()> {
                 }
             }
             Err(ReadlineError::Interrupted) => {
-                // Ctrl+C 中断
+                // Ctrl+C 中断（同时退出 AUTO/EDIT）
+                mode = InputMode::Normal;
                 if let Some(line) = executor.runtime().get_current_line() {
                     println!("?BREAK IN {}", line);
                 } else {
@@ -109,7 +248,15 @@ fn main() -> Result<()> {
 
 /// 处理一行输入
 /// 返回值：Ok(bool) - true 表示应该打印提示符, false 表示不打印
-fn process_line(executor: &mut Executor, line: &str, interrupted: &Arc<AtomicBool>) -> Result<bool> {
+fn process_line(
+    executor: &mut Executor,
+    line: &str,
+    interrupted: &Arc<AtomicBool>,
+    rl: &mut DefaultEditor,
+    style: DiagnosticStyle,
+) -> Result<bool> {
+    // 程序行的原始文本（保留用户的空格和写法）
+    let source = line.trim_start();
     let line = line.trim();
     
     // 空行
@@ -117,15 +264,17 @@ fn process_line(executor: &mut Executor, line: &str, interrupted: &Arc<AtomicBoo
         return Ok(false);
     }
     
-    // 词法分析
-    let mut tokenizer = Tokenizer::new(line);
-    let tokens = tokenizer.tokenize_line()?;
-    
-    // 语法分析
-    let mut parser = Parser::new(tokens);
+    // 词法和语法分析；出错时显示出错位置
+    let parsed = match Diagnostic::parse_line(line) {
+        Ok(parsed) => parsed,
+        Err(diagnostic) => {
+            eprintln!("{}", diagnostic.render(style));
+            return Ok(true);
+        }
+    };
     
     // 解析程序行
-    if let Some(program_line) = parser.parse_line()? {
+    if let Some(program_line) = parsed {
         // 检查是否是带行号的程序行
         if program_line.line_number > 0 {
             // 添加或删除程序行
@@ -134,21 +283,10 @@ fn process_line(executor: &mut Executor, line: &str, interrupted: &Arc<AtomicBoo
                 executor.runtime_mut().delete_line(program_line.line_number);
             } else {
                 // 非空行：添加到程序
-                // 在添加之前，收集所有 DATA 语句的值
-                for stmt in &program_line.statements {
-                    if let Statement::Data { values } = stmt {
-                        for value in values {
-                            // 转换 ast::DataValue 到 executor::DataValue
-                            let exec_value = match value {
-                                DataValue::Number(n) => basic_m6502::DataValue::Number(*n),
-                                DataValue::String(s) => basic_m6502::DataValue::String(s.clone()),
-                            };
-                            executor.add_data_value(exec_value);
-                        }
-                    }
-                }
-                executor.runtime_mut().add_line(program_line);
+                executor.runtime_mut().add_line_with_source(program_line, source.to_string());
             }
+            // DATA 按行号顺序从程序重新收集；和原版一样，编辑程序后 DATA 指针回到开头
+            executor.restore_data();
             // 程序行输入后不打印 Ready
             return Ok(false);
         }
@@ -160,33 +298,39 @@ fn process_line(executor: &mut Executor, line: &str, interrupted: &Arc<AtomicBoo
         let statement = program_line.statements.into_iter().next().unwrap();
         
         // 特殊命令处理
-        match &statement {
-            Statement::List { start, end } => {
-                list_program(executor, *start, *end);
+        match &statement.kind {
+            StatementKind::List { start, end } => {
+                list_program(executor, *start, *end, rl);
                 Ok(true)
             }
-            Statement::Run { line_number } => {
-                run_program(executor, *line_number, interrupted)?;
+            StatementKind::Run { line_number } => {
+                run_program(executor, *line_number, interrupted, style)?;
                 Ok(true)
             }
-            Statement::New => {
+            StatementKind::New => {
                 executor.execute_statement(&statement)?;
                 println!("New program started.");
                 Ok(true)
             }
-            Statement::End => {
+            StatementKind::End => {
                 executor.execute_statement(&statement)?;
                 Ok(true)
             }
-            Statement::Stop => {
+            StatementKind::Stop => {
                 executor.execute_statement(&statement)?;
                 if let Some(line) = executor.runtime().get_current_line() {
                     println!("?BREAK IN {}", line);
                 }
                 Ok(true)
             }
-            Statement::Cont => {
-                continue_program(executor, interrupted)?;
+            StatementKind::Cont => {
+                continue_program(executor, interrupted, style)?;
+                Ok(true)
+            }
+            StatementKind::Chain { .. } => {
+                // CHAIN 加载新程序并设置好起始位置，然后像 RUN 一样执行
+                executor.execute_statement(&statement)?;
+                run_program(executor, None, interrupted, style)?;
                 Ok(true)
             }
             _ => {
@@ -200,8 +344,89 @@ fn process_line(executor: &mut Executor, line: &str, interrupted: &Arc<AtomicBoo
     }
 }
 
+/// 解析行编辑命令
+/// 返回 None 表示不是行编辑命令，交给 BASIC 解析器处理
+fn parse_edit_command(line: &str) -> Option<Result<EditCommand>> {
+    let mut tokenizer = Tokenizer::new(line.trim());
+    let tokens = tokenizer.tokenize_line().ok()?;
+    let name = match tokens.first() {
+        Some(Token::Identifier(name)) => name.to_uppercase(),
+        _ => return None,
+    };
+    let args = tokens[1..].to_vec();
+    
+    let command = match name.as_str() {
+        "AUTO" => parse_auto_arguments(&args),
+        "DELETE" => match Parser::parse_range_argument(args) {
+            Ok((None, None)) => Err(BasicError::SyntaxError("DELETE requires a line range".to_string())),
+            Ok((start, end)) => Ok(EditCommand::Delete { start, end }),
+            Err(e) => Err(e),
+        },
+        "EDIT" => match Parser::parse_range_argument(args) {
+            Ok((Some(start), Some(end))) if start == end => Ok(EditCommand::Edit(start)),
+            Ok(_) => Err(BasicError::SyntaxError("EDIT requires a single line number".to_string())),
+            Err(e) => Err(e),
+        },
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// 解析 AUTO 的参数：[start[,step]]
+fn parse_auto_arguments(args: &[Token]) -> Result<EditCommand> {
+    let mut numbers = Vec::new();
+    let mut expect_number = true;
+    for token in args {
+        match token {
+            Token::Number(n) if expect_number && *n >= 0.0 && *n <= u16::MAX as f64 => {
+                numbers.push(*n as u16);
+                expect_number = false;
+            }
+            Token::Comma if !expect_number => expect_number = true,
+            Token::Newline | Token::Eof => break,
+            _ => return Err(BasicError::SyntaxError(format!("Unexpected {:?} in AUTO", token))),
+        }
+    }
+    
+    match numbers.as_slice() {
+        [] if expect_number => Ok(EditCommand::Auto { start: AUTO_DEFAULT, step: AUTO_DEFAULT }),
+        [start] if !expect_number => Ok(EditCommand::Auto { start: *start, step: AUTO_DEFAULT }),
+        [start, step] if !expect_number && *step > 0 => Ok(EditCommand::Auto { start: *start, step: *step }),
+        _ => Err(BasicError::SyntaxError("Invalid AUTO arguments".to_string())),
+    }
+}
+
+/// 执行行编辑命令，返回之后的输入模式
+fn execute_edit_command(executor: &mut Executor, command: EditCommand) -> Result<InputMode> {
+    match command {
+        EditCommand::Auto { start, step } => Ok(InputMode::Auto { next: start, step }),
+        EditCommand::Delete { start, end } => {
+            // 删除单行时该行必须存在
+            if let (Some(start), Some(end)) = (start, end) {
+                if start == end && executor.runtime().get_line(start).is_none() {
+                    return Err(BasicError::UndefinedLine(start));
+                }
+            }
+            executor.runtime_mut().delete_lines_range(start, end);
+            Ok(InputMode::Normal)
+        }
+        EditCommand::Edit(number) => {
+            let line = executor.runtime().get_line(number)
+                .ok_or(BasicError::UndefinedLine(number))?;
+            Ok(InputMode::Edit(executor.line_text(line)))
+        }
+    }
+}
+
+/// 取出输入行开头的行号（AUTO 模式用来决定下一个行号）
+fn leading_line_number(line: &str) -> Option<u16> {
+    let digits: String = line.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
 /// 列出程序
-fn list_program(executor: &Executor, start: Option<u16>, end: Option<u16>) {
+/// 输出到终端时每 PAGE_SIZE 行暂停一次，输入 Q 结束列表
+fn list_program(executor: &Executor, start: Option<u16>, end: Option<u16>, rl: &mut DefaultEditor) {
     let lines = executor.runtime().get_program_lines(start, end);
     
     if lines.is_empty() {
@@ -213,18 +438,29 @@ fn list_program(executor: &Executor, start: Option<u16>, end: Option<u16>) {
         return;
     }
     
-    for line in lines {
-        // 使用完整的序列化函数
-        println!("{}", Executor::serialize_program_line(line));
+    let paging = std::io::stdout().is_terminal();
+    for (index, line) in lines.iter().enumerate() {
+        if paging && index > 0 && index % PAGE_SIZE == 0 {
+            match rl.readline("-- MORE -- ") {
+                Ok(answer) if !answer.trim().eq_ignore_ascii_case("Q") => {}
+                _ => return,
+            }
+        }
+        println!("{}", executor.line_text(line));
     }
 }
 
 /// 运行程序
-fn run_program(executor: &mut Executor, line_number: Option<u16>, interrupted: &Arc<AtomicBool>) -> Result<()> {
+fn run_program(
+    executor: &mut Executor,
+    line_number: Option<u16>,
+    interrupted: &Arc<AtomicBool>,
+    style: DiagnosticStyle,
+) -> Result<()> {
     // 只有在未运行时才启动执行
     if !executor.runtime().is_running() && !executor.runtime().is_paused() {
         // 在启动新执行前，清空所有变量和数组（经典 BASIC 行为）
-        executor.variables_mut().clear();
+        executor.execute_statement(&StatementKind::Clear.into())?;
         // 重置 DATA 指针，使其可以从头读取 DATA 语句
         executor.restore_data();
         executor.runtime_mut().start_execution(line_number)?;
@@ -250,12 +486,26 @@ fn run_program(executor: &mut Executor, line_number: Option<u16>, interrupted: &
         
         // 执行语句
         if let Err(e) = executor.execute_statement(&stmt) {
-            // 输出错误和行号
-            if let Some(line) = executor.runtime().get_current_line() {
-                eprintln!("?{} IN {}", format_error(&e), line);
-            } else {
-                eprintln!("?{}", format_error(&e));
-            }
+            // 输出错误、行号和出错语句（get_next_statement 已指向下一条语句）
+            let runtime = executor.runtime();
+            let diagnostic = match runtime.get_current_line().and_then(|line| runtime.get_line(line)) {
+                Some(line) => {
+                    // 出错节点的范围是相对输入时的原始文本的
+                    let (source, span) = match (runtime.get_source(line.line_number), executor.error_span()) {
+                        (Some(source), Some(span)) => (source.to_string(), Some(span)),
+                        _ => (executor.line_text(line), None),
+                    };
+                    Diagnostic::runtime(
+                        e,
+                        line.line_number,
+                        runtime.get_current_stmt_index().saturating_sub(1),
+                        &source,
+                        span,
+                    )
+                }
+                None => Diagnostic::from(e),
+            };
+            eprintln!("{}", diagnostic.render(style));
             // 错误已经打印，不再向上传播，直接返回 Ok
             return Ok(());
         }
@@ -276,7 +526,7 @@ fn run_program(executor: &mut Executor, line_number: Option<u16>, interrupted: &
 }
 
 /// 继续执行程序
-fn continue_program(executor: &mut Executor, interrupted: &Arc<AtomicBool>) -> Result<()> {
+fn continue_program(executor: &mut Executor, interrupted: &Arc<AtomicBool>, style: DiagnosticStyle) -> Result<()> {
     if !executor.runtime().can_continue() {
         println!("?CAN'T CONTINUE");
         // 错误已经打印，返回 Ok 以便显示 Ready
@@ -285,27 +535,94 @@ fn continue_program(executor: &mut Executor, interrupted: &Arc<AtomicBool>) -> R
     
     // 从暂停点恢复执行
     executor.runtime_mut().continue_execution()?;
-    run_program(executor, None, interrupted)
+    run_program(executor, None, interrupted, style)
+}
+
+/// 子命令的文件参数：输入文件、-o 输出文件和 --json
+struct FileArguments {
+    input: String,
+    output: Option<String>,
+    json: bool,
 }
 
-/// 格式化错误信息
-fn format_error(error: &BasicError) -> String {
-    match error {
-        BasicError::IllegalCharacter(_, _, _) => {
-            // 使用 Display 格式显示完整错误信息（包含上下文）
-            format!("{}", error)
+impl FileArguments {
+    fn parse(args: &[String], usage: &str) -> Result<Self> {
+        let usage = || BasicError::SyntaxError(format!("usage: {}", usage));
+        let mut parsed = FileArguments { input: String::new(), output: None, json: false };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" => parsed.output = Some(args.next().ok_or_else(usage)?.clone()),
+                "--json" => parsed.json = true,
+                _ if parsed.input.is_empty() && !arg.starts_with('-') => parsed.input = arg.clone(),
+                _ => return Err(usage()),
+            }
+        }
+        if parsed.input.is_empty() {
+            return Err(usage());
+        }
+        Ok(parsed)
+    }
+    
+    /// 写入 -o 指定的文件，没有指定时写到标准输出
+    fn write_output(&self, text: &str) -> Result<()> {
+        match &self.output {
+            Some(output) => std::fs::write(output, text).map_err(|e| {
+                BasicError::IoError(format!("Failed to write {}: {}", output, e))
+            }),
+            None => {
+                print!("{}", text);
+                Ok(())
+            }
         }
-        BasicError::SyntaxError(_) => "SYNTAX ERROR".to_string(),
-        BasicError::DivisionByZero => "DIVISION BY ZERO".to_string(),
-        BasicError::TypeMismatch(_) => "TYPE MISMATCH".to_string(),
-        BasicError::UndefinedLine(n) => format!("UNDEFINED LINE {}", n),
-        BasicError::UndefinedVariable(v) => format!("UNDEFINED VARIABLE {}", v),
-        BasicError::SubscriptOutOfRange(_) => "SUBSCRIPT OUT OF RANGE".to_string(),
-        BasicError::OutOfData => "OUT OF DATA".to_string(),
-        BasicError::ReturnWithoutGosub => "RETURN WITHOUT GOSUB".to_string(),
-        BasicError::NextWithoutFor(_) => "NEXT WITHOUT FOR".to_string(),
-        BasicError::CantContinue => "CAN'T CONTINUE".to_string(),
-        _ => format!("{}", error),  // 使用 Display 格式而不是 Debug
     }
 }
 
+fn read_file(filename: &str) -> Result<String> {
+    std::fs::read_to_string(filename).map_err(|e| {
+        BasicError::IoError(format!("Failed to read {}: {}", filename, e))
+    })
+}
+
+/// 执行子命令；不是子命令时返回 None
+///
+/// - basic compile prog.bas [-o prog.rs]：编译为独立的 Rust 源文件（默认输出到同名 .rs）
+/// - basic parse prog.bas --json [-o prog.json]：输出程序的 AST（JSON）
+/// - basic unparse prog.json [-o prog.bas]：由 AST（JSON）还原 BASIC 程序
+fn run_subcommand(args: &[String]) -> Option<std::result::Result<(), Diagnostic>> {
+    let (command, rest) = args.split_first()?;
+    let result = match command.as_str() {
+        "compile" => FileArguments::parse(rest, "basic compile prog.bas [-o prog.rs]")
+            .map_err(Diagnostic::from)
+            .and_then(|args| {
+                // 先检查语法，出错时显示出错位置
+                diagnostic::check_program(&read_file(&args.input)?)?;
+                let output = args.output.clone().unwrap_or_else(|| {
+                    std::path::Path::new(&args.input).with_extension("rs").to_string_lossy().into_owned()
+                });
+                let source = compiler::compile_file(&args.input)?;
+                std::fs::write(&output, source).map_err(|e| {
+                    BasicError::IoError(format!("Failed to write {}: {}", output, e))
+                })?;
+                println!("Compiled {} to {} (build it with: rustc -O {})", args.input, output, output);
+                Ok(())
+            }),
+        "parse" => FileArguments::parse(rest, "basic parse prog.bas --json [-o prog.json]")
+            .map_err(Diagnostic::from)
+            .and_then(|args| {
+                if !args.json {
+                    return Err(BasicError::SyntaxError("parse: only --json output is supported".to_string()).into());
+                }
+                let source = read_file(&args.input)?;
+                diagnostic::check_program(&source)?;
+                let lines = json::parse_program(&source)?;
+                Ok(args.write_output(&(json::program_to_json(&lines) + "\n"))?)
+            }),
+        "unparse" => FileArguments::parse(rest, "basic unparse prog.json [-o prog.bas]").and_then(|args| {
+            let lines = json::program_from_json(&read_file(&args.input)?)?;
+            args.write_output(&json::unparse_program(&lines))
+        }).map_err(Diagnostic::from),
+        _ => return None,
+    };
+    Some(result)
+}
@@ -1,14 +1,19 @@
-/// 语法解析器
-///
-/// 将 Token 流解析为 AST
+//! 语法解析器
+//!
+//! 将 Token 流解析为 AST
 
 use crate::ast::*;
 use crate::error::{BasicError, Result};
 use crate::token::Token;
+use crate::tokenizer::SpannedToken;
 
 pub struct Parser {
     tokens: Vec<Token>,
     position: usize,
+    /// 每个 token 的源码范围（由 `new_spanned` 创建时才有）
+    token_spans: Vec<Span>,
+    /// 已解析的顶层语句的源码范围
+    statement_spans: Vec<Span>,
 }
 
 impl Parser {
@@ -17,9 +22,46 @@ impl Parser {
         Parser {
             tokens,
             position: 0,
+            token_spans: Vec::new(),
+            statement_spans: Vec::new(),
         }
     }
 
+    /// 由带位置信息的 tokens 创建解析器，解析时记录语句和出错位置的源码范围
+    pub fn new_spanned(tokens: Vec<SpannedToken>) -> Self {
+        let token_spans = tokens
+            .iter()
+            .map(|spanned| Span { start: spanned.start, end: spanned.end })
+            .collect();
+        Parser {
+            tokens: tokens.into_iter().map(|spanned| spanned.token).collect(),
+            position: 0,
+            token_spans,
+            statement_spans: Vec::new(),
+        }
+    }
+
+    /// 当前 token 索引（解析出错时即为出错位置）
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// 顶层语句（冒号分隔）的源码范围，与 `ProgramLine::statements` 一一对应
+    ///
+    /// 解析出错时只包含出错语句之前的语句，出错语句的序号即为其长度。
+    /// 不是由 `new_spanned` 创建的解析器返回空列表
+    pub fn statement_spans(&self) -> &[Span] {
+        &self.statement_spans
+    }
+
+    /// 当前 token 的源码范围（解析出错时即为出错位置）
+    pub fn current_span(&self) -> Option<Span> {
+        self.token_spans
+            .get(self.position)
+            .or_else(|| self.token_spans.last())
+            .copied()
+    }
+
     /// 解析程序行（可能包含多条语句，用冒号分隔）
     pub fn parse_line(&mut self) -> Result<Option<ProgramLine>> {
         // 跳过空行
@@ -71,8 +113,10 @@ impl Parser {
         let mut statements = Vec::new();
 
         while !self.is_at_end() && self.current() != &Token::Newline {
+            let first = self.position;
             let stmt = self.parse_statement()?;
             statements.push(stmt);
+            self.record_statement_span(first);
 
             // 检查是否有冒号分隔符
             if self.current() == &Token::Colon {
@@ -88,6 +132,13 @@ impl Parser {
 
     /// 解析单条语句
     fn parse_statement(&mut self) -> Result<Statement> {
+        let first = self.position;
+        let kind = self.parse_statement_kind()?;
+        Ok(Statement::new(kind, self.span_from(first)))
+    }
+
+    /// 解析单条语句的内容
+    fn parse_statement_kind(&mut self) -> Result<StatementKind> {
         let token = self.current().clone();
 
         match token {
@@ -98,7 +149,7 @@ impl Parser {
             Token::Gosub => self.parse_gosub(),
             Token::Return => {
                 self.advance();
-                Ok(Statement::Return)
+                Ok(StatementKind::Return)
             }
             Token::For => self.parse_for(),
             Token::Next => self.parse_next(),
@@ -120,39 +171,42 @@ impl Parser {
                 } else {
                     String::new()
                 };
-                Ok(Statement::Rem { comment })
+                Ok(StatementKind::Rem { comment })
             }
             Token::End => {
                 self.advance();
-                Ok(Statement::End)
+                Ok(StatementKind::End)
             }
             Token::Stop => {
                 self.advance();
-                Ok(Statement::Stop)
+                Ok(StatementKind::Stop)
             }
             Token::New => {
                 self.advance();
-                Ok(Statement::New)
+                Ok(StatementKind::New)
             }
             Token::Clear => {
                 self.advance();
-                Ok(Statement::Clear)
+                Ok(StatementKind::Clear)
             }
             Token::List => self.parse_list(),
             Token::Run => self.parse_run(),
             Token::Cont => {
                 self.advance();
-                Ok(Statement::Cont)
+                Ok(StatementKind::Cont)
             }
             Token::Poke => self.parse_poke(),
             Token::Wait => self.parse_wait(),
             Token::Get => self.parse_get(),
             Token::Null => {
                 self.advance();
-                Ok(Statement::Null)
+                Ok(StatementKind::Null)
             }
             Token::Load => self.parse_load(),
             Token::Save => self.parse_save(),
+            Token::Merge => self.parse_merge(),
+            Token::Chain => self.parse_chain(),
+            Token::Common => self.parse_common(),
             // 隐式 LET（赋值语句没有 LET 关键字）
             Token::Identifier(_) => {
                 // 检查后面是否是 =, ( 或 , 
@@ -163,14 +217,18 @@ impl Parser {
     }
 
     /// 解析 PRINT 语句
-    fn parse_print(&mut self) -> Result<Statement> {
+    fn parse_print(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Print)?;
 
         let mut items = Vec::new();
 
         // PRINT 后面可以为空（输出换行）
         if self.current() == &Token::Newline || self.current() == &Token::Colon {
-            return Ok(Statement::Print { items });
+            return Ok(StatementKind::Print { items });
+        }
+
+        if self.current() == &Token::Using {
+            return self.parse_print_using();
         }
 
         loop {
@@ -224,27 +282,49 @@ impl Parser {
             }
         }
 
-        Ok(Statement::Print { items })
+        Ok(StatementKind::Print { items })
+    }
+
+    /// 解析 PRINT USING 格式串; 表达式 [, 表达式...] [;]
+    fn parse_print_using(&mut self) -> Result<StatementKind> {
+        self.expect(&Token::Using)?;
+        let format = self.parse_expression()?;
+        self.expect(&Token::Semicolon)?;
+
+        let mut values = vec![self.parse_expression()?];
+        let mut items = Vec::new();
+        while self.current() == &Token::Comma || self.current() == &Token::Semicolon {
+            self.advance();
+            // 末尾的分隔符表示不换行
+            if self.current() == &Token::Newline || self.current() == &Token::Colon || self.is_at_end() {
+                items.push(PrintItem::Semicolon);
+                break;
+            }
+            values.push(self.parse_expression()?);
+        }
+
+        items.insert(0, PrintItem::Using { format, values });
+        Ok(StatementKind::Print { items })
     }
 
     /// 解析 LET 语句
-    fn parse_let(&mut self) -> Result<Statement> {
+    fn parse_let(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Let)?;
         self.parse_assignment()
     }
 
     /// 解析隐式 LET（没有 LET 关键字的赋值）
-    fn parse_implicit_let(&mut self) -> Result<Statement> {
+    fn parse_implicit_let(&mut self) -> Result<StatementKind> {
         self.parse_assignment()
     }
 
     /// 解析赋值（LET A = 10 或 A = 10）
-    fn parse_assignment(&mut self) -> Result<Statement> {
+    fn parse_assignment(&mut self) -> Result<StatementKind> {
         let target = self.parse_assign_target()?;
         self.expect(&Token::Equal)?;
         let value = self.parse_expression()?;
 
-        Ok(Statement::Let { target, value })
+        Ok(StatementKind::Let { target, value })
     }
 
     /// 解析赋值目标（变量或数组元素）
@@ -263,7 +343,7 @@ impl Parser {
     }
 
     /// 解析 IF 语句
-    fn parse_if(&mut self) -> Result<Statement> {
+    fn parse_if(&mut self) -> Result<StatementKind> {
         self.expect(&Token::If)?;
         
         let condition = self.parse_expression()?;
@@ -290,34 +370,34 @@ impl Parser {
             }
             
             if statements.len() == 1 {
-                ThenPart::Statement(statements.into_iter().next().unwrap())
+                ThenPart::Statement(Box::new(statements.into_iter().next().unwrap()))
             } else {
                 ThenPart::Statements(statements)
             }
         };
         
-        Ok(Statement::If {
+        Ok(StatementKind::If {
             condition,
             then_part: Box::new(then_part),
         })
     }
 
     /// 解析 GOTO 语句
-    fn parse_goto(&mut self) -> Result<Statement> {
+    fn parse_goto(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Goto)?;
         let line_number = self.parse_expression()?;
-        Ok(Statement::Goto { line_number })
+        Ok(StatementKind::Goto { line_number })
     }
 
     /// 解析 GOSUB 语句
-    fn parse_gosub(&mut self) -> Result<Statement> {
+    fn parse_gosub(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Gosub)?;
         let line_number = self.parse_expression()?;
-        Ok(Statement::Gosub { line_number })
+        Ok(StatementKind::Gosub { line_number })
     }
 
     /// 解析 FOR 语句
-    fn parse_for(&mut self) -> Result<Statement> {
+    fn parse_for(&mut self) -> Result<StatementKind> {
         self.expect(&Token::For)?;
         
         let var = self.expect_identifier()?;
@@ -333,11 +413,11 @@ impl Parser {
             None
         };
         
-        Ok(Statement::For { var, start, end, step })
+        Ok(StatementKind::For { var, start, end, step })
     }
 
     /// 解析 NEXT 语句
-    fn parse_next(&mut self) -> Result<Statement> {
+    fn parse_next(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Next)?;
         
         let var = if let Token::Identifier(name) = self.current() {
@@ -348,11 +428,11 @@ impl Parser {
             None
         };
         
-        Ok(Statement::Next { var })
+        Ok(StatementKind::Next { var })
     }
 
     /// 解析 ON...GOTO 或 ON...GOSUB
-    fn parse_on(&mut self) -> Result<Statement> {
+    fn parse_on(&mut self) -> Result<StatementKind> {
         self.expect(&Token::On)?;
         
         let expr = self.parse_expression()?;
@@ -388,11 +468,11 @@ impl Parser {
             }
         }
         
-        Ok(Statement::On { expr, targets, is_gosub })
+        Ok(StatementKind::On { expr, targets, is_gosub })
     }
 
     /// 解析 INPUT 语句
-    fn parse_input(&mut self) -> Result<Statement> {
+    fn parse_input(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Input)?;
         
         // 检查是否有提示符
@@ -422,11 +502,11 @@ impl Parser {
             }
         }
         
-        Ok(Statement::Input { prompt, variables })
+        Ok(StatementKind::Input { prompt, variables })
     }
 
     /// 解析 DIM 语句
-    fn parse_dim(&mut self) -> Result<Statement> {
+    fn parse_dim(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Dim)?;
         
         let mut arrays = Vec::new();
@@ -446,11 +526,11 @@ impl Parser {
             }
         }
         
-        Ok(Statement::Dim { arrays })
+        Ok(StatementKind::Dim { arrays })
     }
 
     /// 解析 DATA 语句
-    fn parse_data(&mut self) -> Result<Statement> {
+    fn parse_data(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Data)?;
         
         let mut values = Vec::new();
@@ -486,11 +566,11 @@ impl Parser {
             }
         }
         
-        Ok(Statement::Data { values })
+        Ok(StatementKind::Data { values })
     }
 
     /// 解析 READ 语句
-    fn parse_read(&mut self) -> Result<Statement> {
+    fn parse_read(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Read)?;
         
         let mut variables = Vec::new();
@@ -504,11 +584,11 @@ impl Parser {
             }
         }
         
-        Ok(Statement::Read { variables })
+        Ok(StatementKind::Read { variables })
     }
 
     /// 解析 RESTORE 语句
-    fn parse_restore(&mut self) -> Result<Statement> {
+    fn parse_restore(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Restore)?;
         
         let line_number = if let Token::Number(num) = self.current() {
@@ -519,11 +599,11 @@ impl Parser {
             None
         };
         
-        Ok(Statement::Restore { line_number })
+        Ok(StatementKind::Restore { line_number })
     }
 
     /// 解析 DEF FN 语句
-    fn parse_def_fn(&mut self) -> Result<Statement> {
+    fn parse_def_fn(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Def)?;
         
         // DEF FN 语句格式：DEF FN name(param) = expr
@@ -539,23 +619,143 @@ impl Parser {
         
         let body = self.parse_expression()?;
         
-        Ok(Statement::DefFn { name, param, body })
+        Ok(StatementKind::DefFn { name, param, body })
     }
 
     /// 解析 LIST 语句
-    fn parse_list(&mut self) -> Result<Statement> {
+    fn parse_list(&mut self) -> Result<StatementKind> {
         self.expect(&Token::List)?;
         
-        // TODO: 解析行号范围 (LIST 10-50)
-        // 目前简化处理
-        Ok(Statement::List {
-            start: None,
-            end: None,
-        })
+        let (start, end) = self.parse_line_range()?;
+        Ok(StatementKind::List { start, end })
+    }
+
+    /// 解析行号范围：空、`n`、`n-`、`-n`、`n-m` 或 `n,m`
+    ///
+    /// 返回 (起始行, 结束行)，`None` 表示该端不限
+    fn parse_line_range(&mut self) -> Result<(Option<u16>, Option<u16>)> {
+        let start = self.parse_optional_line_number()?;
+        
+        if self.current() == &Token::Minus || self.current() == &Token::Comma {
+            self.advance();
+            let end = self.parse_optional_line_number()?;
+            if start.is_none() && end.is_none() {
+                return Err(BasicError::SyntaxError(
+                    "Expected line number in range".to_string()
+                ));
+            }
+            Ok((start, end))
+        } else {
+            // 单个行号：只包含该行
+            Ok((start, start))
+        }
+    }
+
+    /// 解析可选的行号
+    fn parse_optional_line_number(&mut self) -> Result<Option<u16>> {
+        if let Token::Number(num) = self.current() {
+            let num = *num;
+            if num < 0.0 || num > u16::MAX as f64 || num.fract() != 0.0 {
+                return Err(BasicError::SyntaxError(format!("Invalid line number {}", num)));
+            }
+            self.advance();
+            Ok(Some(num as u16))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// 解析 REPL 命令（如 DELETE）的行号范围参数
+    ///
+    /// `tokens` 为命令名之后的 token，必须恰好构成一个行号范围
+    pub fn parse_range_argument(tokens: Vec<Token>) -> Result<(Option<u16>, Option<u16>)> {
+        let mut parser = Parser::new(tokens);
+        let range = parser.parse_line_range()?;
+        if !parser.is_at_end() && parser.current() != &Token::Newline {
+            return Err(BasicError::SyntaxError(format!(
+                "Unexpected {:?} after line range",
+                parser.current()
+            )));
+        }
+        Ok(range)
+    }
+
+    /// 解析 MERGE 语句
+    fn parse_merge(&mut self) -> Result<StatementKind> {
+        self.expect(&Token::Merge)?;
+        
+        if let Token::String(filename) = self.current() {
+            let filename = filename.clone();
+            self.advance();
+            Ok(StatementKind::Merge { filename })
+        } else {
+            Err(BasicError::SyntaxError(
+                "Expected filename string in MERGE".to_string()
+            ))
+        }
+    }
+
+    /// 解析 CHAIN 语句：CHAIN "file"[,line]
+    fn parse_chain(&mut self) -> Result<StatementKind> {
+        self.expect(&Token::Chain)?;
+        
+        let filename = if let Token::String(filename) = self.current() {
+            let filename = filename.clone();
+            self.advance();
+            filename
+        } else {
+            return Err(BasicError::SyntaxError(
+                "Expected filename string in CHAIN".to_string()
+            ));
+        };
+        
+        let line_number = if self.current() == &Token::Comma {
+            self.advance();
+            match self.parse_optional_line_number()? {
+                Some(num) => Some(num),
+                None => {
+                    return Err(BasicError::SyntaxError(
+                        "Expected line number after comma in CHAIN".to_string()
+                    ));
+                }
+            }
+        } else {
+            None
+        };
+        
+        Ok(StatementKind::Chain { filename, line_number })
+    }
+
+    /// 解析 COMMON 语句：COMMON A, B$, C()
+    fn parse_common(&mut self) -> Result<StatementKind> {
+        self.expect(&Token::Common)?;
+        
+        let mut variables = Vec::new();
+        
+        loop {
+            let name = self.expect_identifier()?;
+            let is_array = if self.current() == &Token::LeftParen {
+                self.advance();
+                self.expect(&Token::RightParen)?;
+                true
+            } else {
+                false
+            };
+            
+            variables.push(CommonVariable { name, is_array });
+            
+            if self.current() == &Token::Comma {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        
+        Ok(StatementKind::Common { variables })
     }
 
     /// 解析 RUN 语句
-    fn parse_run(&mut self) -> Result<Statement> {
+    fn parse_run(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Run)?;
         
         let line_number = if let Token::Number(num) = self.current() {
@@ -566,22 +766,22 @@ impl Parser {
             None
         };
         
-        Ok(Statement::Run { line_number })
+        Ok(StatementKind::Run { line_number })
     }
 
     /// 解析 POKE 语句
-    fn parse_poke(&mut self) -> Result<Statement> {
+    fn parse_poke(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Poke)?;
         
         let address = self.parse_expression()?;
         self.expect(&Token::Comma)?;
         let value = self.parse_expression()?;
         
-        Ok(Statement::Poke { address, value })
+        Ok(StatementKind::Poke { address, value })
     }
 
     /// 解析 WAIT 语句
-    fn parse_wait(&mut self) -> Result<Statement> {
+    fn parse_wait(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Wait)?;
         
         let address = self.parse_expression()?;
@@ -595,26 +795,26 @@ impl Parser {
             None
         };
         
-        Ok(Statement::Wait { address, mask, value })
+        Ok(StatementKind::Wait { address, mask, value })
     }
 
     /// 解析 GET 语句
-    fn parse_get(&mut self) -> Result<Statement> {
+    fn parse_get(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Get)?;
         
         let variable = self.expect_identifier()?;
         
-        Ok(Statement::Get { variable })
+        Ok(StatementKind::Get { variable })
     }
 
     /// 解析 LOAD 语句
-    fn parse_load(&mut self) -> Result<Statement> {
+    fn parse_load(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Load)?;
         
         if let Token::String(filename) = self.current() {
             let filename = filename.clone();
             self.advance();
-            Ok(Statement::Load { filename })
+            Ok(StatementKind::Load { filename })
         } else {
             Err(BasicError::SyntaxError(
                 "Expected filename string in LOAD".to_string()
@@ -623,13 +823,13 @@ impl Parser {
     }
 
     /// 解析 SAVE 语句
-    fn parse_save(&mut self) -> Result<Statement> {
+    fn parse_save(&mut self) -> Result<StatementKind> {
         self.expect(&Token::Save)?;
         
         if let Token::String(filename) = self.current() {
             let filename = filename.clone();
             self.advance();
-            Ok(Statement::Save { filename })
+            Ok(StatementKind::Save { filename })
         } else {
             Err(BasicError::SyntaxError(
                 "Expected filename string in SAVE".to_string()
@@ -671,9 +871,10 @@ impl Parser {
     /// 解析 NOT 表达式
     fn parse_not_expression(&mut self) -> Result<Expr> {
         if self.current() == &Token::Not {
+            let first = self.position;
             self.advance();
             let operand = self.parse_not_expression()?;
-            Ok(Expr::unary(UnaryOperator::Not, operand))
+            Ok(self.unary(first, UnaryOperator::Not, operand))
         } else {
             self.parse_relational_expression()
         }
@@ -752,9 +953,10 @@ impl Parser {
     /// 解析一元表达式（负号）
     fn parse_unary_expression(&mut self) -> Result<Expr> {
         if self.current() == &Token::Minus {
+            let first = self.position;
             self.advance();
             let operand = self.parse_unary_expression()?;
-            Ok(Expr::unary(UnaryOperator::Minus, operand))
+            Ok(self.unary(first, UnaryOperator::Minus, operand))
         } else if self.current() == &Token::Plus {
             // 一元加号直接跳过
             self.advance();
@@ -766,16 +968,17 @@ impl Parser {
 
     /// 解析基本表达式（字面量、变量、函数调用、括号）
     fn parse_primary_expression(&mut self) -> Result<Expr> {
-        match self.current() {
+        let first = self.position;
+        let kind = match self.current() {
             Token::Number(num) => {
                 let num = *num;
                 self.advance();
-                Ok(Expr::Number(num))
+                ExprKind::Number(num)
             }
             Token::String(s) => {
                 let s = s.clone();
                 self.advance();
-                Ok(Expr::String(s))
+                ExprKind::String(s)
             }
             Token::Identifier(name) => {
                 let name = name.clone();
@@ -790,12 +993,12 @@ impl Parser {
                     // 判断是数组访问还是函数调用
                     // 简化处理：如果名称以$结尾，且是字符串函数，视为函数
                     // 否则根据上下文判断（这里默认为数组）
-                    Ok(Expr::ArrayAccess {
+                    ExprKind::ArrayAccess {
                         name,
                         indices: args,
-                    })
+                    }
                 } else {
-                    Ok(Expr::Variable(name))
+                    ExprKind::Variable(name)
                 }
             }
             // FN 用户自定义函数调用
@@ -806,23 +1009,26 @@ impl Parser {
                 let args = self.parse_expression_list()?;
                 self.expect(&Token::RightParen)?;
                 // 函数名格式为 "FNname"
-                Ok(Expr::FunctionCall {
+                ExprKind::FunctionCall {
                     name: format!("FN{}", func_name),
                     args,
-                })
+                }
             }
             // 内置函数
             _ if self.is_function_token(self.current()) => {
-                self.parse_function_call()
+                self.parse_function_call()?
             }
             Token::LeftParen => {
                 self.advance();
-                let expr = self.parse_expression()?;
+                let mut expr = self.parse_expression()?;
                 self.expect(&Token::RightParen)?;
-                Ok(expr)
+                // 括号算在表达式的范围内，外层运算的范围才能覆盖到右括号
+                expr.span = self.span_from(first);
+                return Ok(expr);
             }
-            _ => Err(BasicError::ExpectedExpression(self.position)),
-        }
+            _ => return Err(BasicError::ExpectedExpression(self.position)),
+        };
+        Ok(Expr::new(kind, self.span_from(first)))
     }
 
     /// 检查是否为函数 token
@@ -839,7 +1045,7 @@ impl Parser {
     }
 
     /// 解析函数调用
-    fn parse_function_call(&mut self) -> Result<Expr> {
+    fn parse_function_call(&mut self) -> Result<ExprKind> {
         let func_token = self.current().clone();
         self.advance();
         
@@ -877,7 +1083,7 @@ impl Parser {
         let args = self.parse_expression_list()?;
         self.expect(&Token::RightParen)?;
         
-        Ok(Expr::FunctionCall { name, args })
+        Ok(ExprKind::FunctionCall { name, args })
     }
 
     /// 解析表达式列表（逗号分隔）
@@ -921,6 +1127,29 @@ impl Parser {
         self.position >= self.tokens.len() || self.current() == &Token::Eof
     }
 
+    /// 从 token `first` 到上一个 token 的源码范围（没有位置信息时为空范围）
+    fn span_from(&self, first: usize) -> Span {
+        let last = self.position.max(first + 1) - 1;
+        match (self.token_spans.get(first), self.token_spans.get(last)) {
+            (Some(start), Some(end)) => start.to(*end),
+            _ => Span::default(),
+        }
+    }
+
+    /// 创建从 token `first` 开始的一元运算
+    fn unary(&self, first: usize, op: UnaryOperator, operand: Expr) -> Expr {
+        let mut expr = Expr::unary(op, operand);
+        expr.span = self.span_from(first);
+        expr
+    }
+
+    /// 记录从 token `first` 到上一个 token 的语句范围
+    fn record_statement_span(&mut self, first: usize) {
+        if !self.token_spans.is_empty() {
+            self.statement_spans.push(self.span_from(first));
+        }
+    }
+
     /// 期望特定 token
     fn expect(&mut self, expected: &Token) -> Result<()> {
         if self.current() == expected {
@@ -968,10 +1197,10 @@ mod tests {
         let line = parse_line_helper("PRINT 2 + 3").unwrap().unwrap();
         assert_eq!(line.statements.len(), 1);
         
-        match &line.statements[0] {
-            Statement::Print { items } => {
+        match &line.statements[0].kind {
+            StatementKind::Print { items } => {
                 assert_eq!(items.len(), 1);
-                if let PrintItem::Expr(Expr::BinaryOp { op, .. }) = &items[0] {
+                if let PrintItem::Expr(Expr { kind: ExprKind::BinaryOp { op, .. }, .. }) = &items[0] {
                     assert_eq!(*op, BinaryOperator::Add);
                 }
             }
@@ -984,16 +1213,16 @@ mod tests {
     fn test_parse_operator_precedence() {
         let line = parse_line_helper("PRINT 2 + 3 * 4").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Print { items } => {
-                if let PrintItem::Expr(Expr::BinaryOp { left, op, right }) = &items[0] {
+        match &line.statements[0].kind {
+            StatementKind::Print { items } => {
+                if let PrintItem::Expr(Expr { kind: ExprKind::BinaryOp { left, op, right }, .. }) = &items[0] {
                     assert_eq!(*op, BinaryOperator::Add);
-                    assert_eq!(**left, Expr::Number(2.0));
+                    assert_eq!(**left, Expr::number(2.0));
                     // right 应该是 3 * 4
-                    if let Expr::BinaryOp { left: l2, op: op2, right: r2 } = &**right {
+                    if let ExprKind::BinaryOp { left: l2, op: op2, right: r2 } = &right.kind {
                         assert_eq!(*op2, BinaryOperator::Multiply);
-                        assert_eq!(**l2, Expr::Number(3.0));
-                        assert_eq!(**r2, Expr::Number(4.0));
+                        assert_eq!(**l2, Expr::number(3.0));
+                        assert_eq!(**r2, Expr::number(4.0));
                     } else {
                         panic!("Expected multiplication on right side");
                     }
@@ -1008,15 +1237,15 @@ mod tests {
     fn test_parse_parentheses() {
         let line = parse_line_helper("PRINT (2 + 3) * 4").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Print { items } => {
-                if let PrintItem::Expr(Expr::BinaryOp { left, op, right }) = &items[0] {
+        match &line.statements[0].kind {
+            StatementKind::Print { items } => {
+                if let PrintItem::Expr(Expr { kind: ExprKind::BinaryOp { left, op, right }, .. }) = &items[0] {
                     assert_eq!(*op, BinaryOperator::Multiply);
                     // left 应该是 (2 + 3)
-                    if let Expr::BinaryOp { op: op_inner, .. } = &**left {
+                    if let ExprKind::BinaryOp { op: op_inner, .. } = &left.kind {
                         assert_eq!(*op_inner, BinaryOperator::Add);
                     }
-                    assert_eq!(**right, Expr::Number(4.0));
+                    assert_eq!(**right, Expr::number(4.0));
                 }
             }
             _ => panic!("Expected Print statement"),
@@ -1029,10 +1258,10 @@ mod tests {
         let line = parse_line_helper("10 LET A = 10").unwrap().unwrap();
         assert_eq!(line.line_number, 10);
         
-        match &line.statements[0] {
-            Statement::Let { target, value } => {
+        match &line.statements[0].kind {
+            StatementKind::Let { target, value } => {
                 assert_eq!(*target, AssignTarget::Variable("A".to_string()));
-                assert_eq!(*value, Expr::Number(10.0));
+                assert_eq!(*value, Expr::number(10.0));
             }
             _ => panic!("Expected Let statement"),
         }
@@ -1043,10 +1272,10 @@ mod tests {
     fn test_parse_implicit_let() {
         let line = parse_line_helper("10 A = 10").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Let { target, value } => {
+        match &line.statements[0].kind {
+            StatementKind::Let { target, value } => {
                 assert_eq!(*target, AssignTarget::Variable("A".to_string()));
-                assert_eq!(*value, Expr::Number(10.0));
+                assert_eq!(*value, Expr::number(10.0));
             }
             _ => panic!("Expected Let statement"),
         }
@@ -1057,10 +1286,10 @@ mod tests {
     fn test_parse_print_single() {
         let line = parse_line_helper("10 PRINT 42").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Print { items } => {
+        match &line.statements[0].kind {
+            StatementKind::Print { items } => {
                 assert_eq!(items.len(), 1);
-                assert!(matches!(&items[0], PrintItem::Expr(Expr::Number(42.0))));
+                assert!(matches!(&items[0], PrintItem::Expr(Expr { kind: ExprKind::Number(42.0), .. })));
             }
             _ => panic!("Expected Print statement"),
         }
@@ -1071,8 +1300,8 @@ mod tests {
     fn test_parse_print_comma() {
         let line = parse_line_helper("10 PRINT A, B, C").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Print { items } => {
+        match &line.statements[0].kind {
+            StatementKind::Print { items } => {
                 assert_eq!(items.len(), 5); // A, Comma, B, Comma, C
             }
             _ => panic!("Expected Print statement"),
@@ -1084,9 +1313,9 @@ mod tests {
     fn test_parse_goto() {
         let line = parse_line_helper("10 GOTO 100").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Goto { line_number } => {
-                assert_eq!(*line_number, Expr::Number(100.0));
+        match &line.statements[0].kind {
+            StatementKind::Goto { line_number } => {
+                assert_eq!(*line_number, Expr::number(100.0));
             }
             _ => panic!("Expected Goto statement"),
         }
@@ -1097,8 +1326,8 @@ mod tests {
     fn test_parse_if_then_line_number() {
         let line = parse_line_helper("10 IF A > 10 THEN 200").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::If { condition: _, then_part } => {
+        match &line.statements[0].kind {
+            StatementKind::If { condition: _, then_part } => {
                 assert_eq!(**then_part, ThenPart::LineNumber(200));
             }
             _ => panic!("Expected If statement"),
@@ -1110,10 +1339,10 @@ mod tests {
     fn test_parse_if_then_statement() {
         let line = parse_line_helper("10 IF A > 10 THEN PRINT A").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::If { condition: _, then_part } => {
+        match &line.statements[0].kind {
+            StatementKind::If { condition: _, then_part } => {
                 match &**then_part {
-                    ThenPart::Statement(Statement::Print { .. }) => (),
+                    ThenPart::Statement(stmt) if matches!(stmt.kind, StatementKind::Print { .. }) => (),
                     _ => panic!("Expected Print in THEN clause"),
                 }
             }
@@ -1126,11 +1355,11 @@ mod tests {
     fn test_parse_for() {
         let line = parse_line_helper("10 FOR I = 1 TO 10").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::For { var, start, end, step } => {
+        match &line.statements[0].kind {
+            StatementKind::For { var, start, end, step } => {
                 assert_eq!(*var, "I");
-                assert_eq!(*start, Expr::Number(1.0));
-                assert_eq!(*end, Expr::Number(10.0));
+                assert_eq!(*start, Expr::number(1.0));
+                assert_eq!(*end, Expr::number(10.0));
                 assert_eq!(*step, None);
             }
             _ => panic!("Expected For statement"),
@@ -1142,10 +1371,10 @@ mod tests {
     fn test_parse_for_with_step() {
         let line = parse_line_helper("10 FOR I = 10 TO 1 STEP -1").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::For { var, start: _, end: _, step } => {
+        match &line.statements[0].kind {
+            StatementKind::For { var, start: _, end: _, step } => {
                 assert_eq!(*var, "I");
-                assert_eq!(step.is_some(), true);
+                assert!(step.is_some());
             }
             _ => panic!("Expected For statement"),
         }
@@ -1156,8 +1385,8 @@ mod tests {
     fn test_parse_next() {
         let line = parse_line_helper("20 NEXT I").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Next { var } => {
+        match &line.statements[0].kind {
+            StatementKind::Next { var } => {
                 assert_eq!(*var, Some("I".to_string()));
             }
             _ => panic!("Expected Next statement"),
@@ -1177,9 +1406,9 @@ mod tests {
         let line = parse_line_helper("10 FOR I=1 TO 10: PRINT I: NEXT I").unwrap().unwrap();
         assert_eq!(line.statements.len(), 3);
         
-        assert!(matches!(line.statements[0], Statement::For { .. }));
-        assert!(matches!(line.statements[1], Statement::Print { .. }));
-        assert!(matches!(line.statements[2], Statement::Next { .. }));
+        assert!(matches!(line.statements[0].kind, StatementKind::For { .. }));
+        assert!(matches!(line.statements[1].kind, StatementKind::Print { .. }));
+        assert!(matches!(line.statements[2].kind, StatementKind::Next { .. }));
     }
 
     // Requirement: INPUT 语句解析 - 基本 INPUT
@@ -1187,8 +1416,8 @@ mod tests {
     fn test_parse_input() {
         let line = parse_line_helper("10 INPUT A").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Input { prompt, variables } => {
+        match &line.statements[0].kind {
+            StatementKind::Input { prompt, variables } => {
                 assert_eq!(*prompt, None);
                 assert_eq!(variables.len(), 1);
             }
@@ -1201,8 +1430,8 @@ mod tests {
     fn test_parse_input_with_prompt() {
         let line = parse_line_helper("10 INPUT \"ENTER VALUE\"; A").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Input { prompt, variables } => {
+        match &line.statements[0].kind {
+            StatementKind::Input { prompt, variables } => {
                 assert_eq!(*prompt, Some("ENTER VALUE".to_string()));
                 assert_eq!(variables.len(), 1);
             }
@@ -1215,8 +1444,8 @@ mod tests {
     fn test_parse_dim() {
         let line = parse_line_helper("10 DIM A(10)").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Dim { arrays } => {
+        match &line.statements[0].kind {
+            StatementKind::Dim { arrays } => {
                 assert_eq!(arrays.len(), 1);
                 assert_eq!(arrays[0].name, "A");
                 assert_eq!(arrays[0].dimensions.len(), 1);
@@ -1230,9 +1459,9 @@ mod tests {
     fn test_parse_function_call() {
         let line = parse_line_helper("PRINT SIN(X)").unwrap().unwrap();
         
-        match &line.statements[0] {
-            Statement::Print { items } => {
-                if let PrintItem::Expr(Expr::FunctionCall { name, args }) = &items[0] {
+        match &line.statements[0].kind {
+            StatementKind::Print { items } => {
+                if let PrintItem::Expr(Expr { kind: ExprKind::FunctionCall { name, args }, .. }) = &items[0] {
                     assert_eq!(name, "SIN");
                     assert_eq!(args.len(), 1);
                 }
@@ -1240,5 +1469,150 @@ mod tests {
             _ => panic!("Expected Print statement"),
         }
     }
-}
 
+    // Requirement: LIST 行号范围
+    #[test]
+    fn test_parse_list_ranges() {
+        let cases = vec![
+            ("LIST", None, None),
+            ("LIST 100", Some(100), Some(100)),
+            ("LIST -100", None, Some(100)),
+            ("LIST 100-", Some(100), None),
+            ("LIST 100-200", Some(100), Some(200)),
+            ("LIST 100,200", Some(100), Some(200)),
+        ];
+        for (input, start, end) in cases {
+            let line = parse_line_helper(input).unwrap().unwrap();
+            assert_eq!(line.statements[0], StatementKind::List { start, end }.into(), "Failed for input: {}", input);
+        }
+        
+        assert!(parse_line_helper("LIST -").is_err());
+    }
+
+    // Requirement: REPL 命令的行号范围参数
+    #[test]
+    fn test_parse_range_argument() {
+        let tokens = Tokenizer::new("DELETE 100-200").tokenize_line().unwrap();
+        let range = Parser::parse_range_argument(tokens[1..].to_vec()).unwrap();
+        assert_eq!(range, (Some(100), Some(200)));
+        
+        let tokens = Tokenizer::new("DELETE 100 X").tokenize_line().unwrap();
+        assert!(Parser::parse_range_argument(tokens[1..].to_vec()).is_err());
+    }
+
+    // Requirement: MERGE / CHAIN / COMMON
+    #[test]
+    fn test_parse_merge_chain_common() {
+        let line = parse_line_helper("MERGE \"PART2.BAS\"").unwrap().unwrap();
+        assert_eq!(line.statements[0], StatementKind::Merge { filename: "PART2.BAS".to_string() }.into());
+        
+        let line = parse_line_helper("CHAIN \"NEXT.BAS\"").unwrap().unwrap();
+        assert_eq!(line.statements[0], StatementKind::Chain { filename: "NEXT.BAS".to_string(), line_number: None }.into());
+        
+        let line = parse_line_helper("CHAIN \"NEXT.BAS\",100").unwrap().unwrap();
+        assert_eq!(line.statements[0], StatementKind::Chain { filename: "NEXT.BAS".to_string(), line_number: Some(100) }.into());
+        
+        let line = parse_line_helper("10 COMMON A, B$, C()").unwrap().unwrap();
+        assert_eq!(line.statements[0], StatementKind::Common {
+            variables: vec![
+                CommonVariable { name: "A".to_string(), is_array: false },
+                CommonVariable { name: "B$".to_string(), is_array: false },
+                CommonVariable { name: "C".to_string(), is_array: true },
+            ],
+        }.into());
+        
+        assert!(parse_line_helper("CHAIN").is_err());
+        assert!(parse_line_helper("CHAIN \"NEXT.BAS\",").is_err());
+        assert!(parse_line_helper("COMMON C(1)").is_err());
+
+        // 行号参数的检查与 LIST 等命令相同
+        for bad in ["70000", "1.5"] {
+            let chain = parse_line_helper(&format!("CHAIN \"NEXT.BAS\",{}", bad)).unwrap_err();
+            let list = parse_line_helper(&format!("LIST {}", bad)).unwrap_err();
+            assert_eq!(chain, BasicError::SyntaxError(format!("Invalid line number {}", bad)));
+            assert_eq!(chain, list);
+        }
+    }
+
+    // Requirement: PRINT USING
+    #[test]
+    fn test_parse_print_using() {
+        let line = parse_line_helper("PRINT USING \"###.##\"; A, B;").unwrap().unwrap();
+        assert_eq!(line.statements[0], StatementKind::Print {
+            items: vec![
+                PrintItem::Using {
+                    format: Expr::string("###.##".to_string()),
+                    values: vec![Expr::variable("A".to_string()), Expr::variable("B".to_string())],
+                },
+                PrintItem::Semicolon,
+            ],
+        }.into());
+
+        assert!(parse_line_helper("PRINT USING \"##\"").is_err());
+    }
+
+    // Requirement: Source Spans - 语句范围与出错位置
+    #[test]
+    fn test_statement_spans() {
+        let source = "10 PRINT \"A\": X = 1 + 2 :IF X THEN PRINT X: END";
+        let tokens = Tokenizer::new(source).tokenize_line_spanned().unwrap();
+        let mut parser = Parser::new_spanned(tokens);
+        let line = parser.parse_line().unwrap().unwrap();
+        assert_eq!(line.statements.len(), 3);
+        let texts: Vec<String> = parser
+            .statement_spans()
+            .iter()
+            .map(|span| source.chars().skip(span.start).take(span.end - span.start).collect())
+            .collect();
+        assert_eq!(texts, vec!["PRINT \"A\"", "X = 1 + 2", "IF X THEN PRINT X: END"]);
+
+        // 出错时：之前的语句已有范围，当前 token 指向出错位置
+        let source = "20 A = 1: B = * 2";
+        let tokens = Tokenizer::new(source).tokenize_line_spanned().unwrap();
+        let mut parser = Parser::new_spanned(tokens);
+        assert!(parser.parse_line().is_err());
+        assert_eq!(parser.statement_spans().len(), 1);
+        assert_eq!(parser.current_span(), Some(Span { start: 14, end: 15 }));
+
+        // 不带位置信息的解析器
+        let mut parser = Parser::new(Tokenizer::new(source).tokenize_line().unwrap());
+        assert!(parser.parse_line().is_err());
+        assert!(parser.statement_spans().is_empty());
+        assert_eq!(parser.current_span(), None);
+    }
+
+    // Requirement: Source Spans - 表达式和语句节点带源码范围
+    #[test]
+    fn test_node_spans() {
+        let source = "10 PRINT A + B(2) * SQR(X): IF X THEN Y = -(Z - 1)";
+        let tokens = Tokenizer::new(source).tokenize_line_spanned().unwrap();
+        let line = Parser::new_spanned(tokens).parse_line().unwrap().unwrap();
+        let text = |span: Span| -> String { source.chars().skip(span.start).take(span.end - span.start).collect() };
+
+        assert_eq!(text(line.statements[0].span), "PRINT A + B(2) * SQR(X)");
+        let StatementKind::Print { items } = &line.statements[0].kind else { panic!("Expected Print") };
+        let PrintItem::Expr(sum) = &items[0] else { panic!("Expected expression") };
+        assert_eq!(text(sum.span), "A + B(2) * SQR(X)");
+        let ExprKind::BinaryOp { left, right, .. } = &sum.kind else { panic!("Expected BinaryOp") };
+        assert_eq!(text(left.span), "A");
+        assert_eq!(text(right.span), "B(2) * SQR(X)");
+        let ExprKind::BinaryOp { left, right, .. } = &right.kind else { panic!("Expected BinaryOp") };
+        assert_eq!(text(left.span), "B(2)");
+        assert_eq!(text(right.span), "SQR(X)");
+
+        // THEN 后的语句、一元运算和括号
+        assert_eq!(text(line.statements[1].span), "IF X THEN Y = -(Z - 1)");
+        let StatementKind::If { then_part, .. } = &line.statements[1].kind else { panic!("Expected If") };
+        let ThenPart::Statement(assign) = then_part.as_ref() else { panic!("Expected statement") };
+        assert_eq!(text(assign.span), "Y = -(Z - 1)");
+        let StatementKind::Let { value, .. } = &assign.kind else { panic!("Expected Let") };
+        assert_eq!(text(value.span), "-(Z - 1)");
+        let ExprKind::UnaryOp { operand, .. } = &value.kind else { panic!("Expected UnaryOp") };
+        assert_eq!(text(operand.span), "(Z - 1)");
+
+        // 不带位置信息的解析器得到空范围；范围不影响相等比较
+        let plain = Parser::new(Tokenizer::new(source).tokenize_line().unwrap()).parse_line().unwrap().unwrap();
+        assert!(plain.statements.iter().all(|stmt| stmt.span.is_empty()));
+        assert_eq!(plain, line);
+    }
+}
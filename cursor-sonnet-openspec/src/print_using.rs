@@ -0,0 +1,417 @@
+//! PRINT USING 格式化
+//!
+//! 按格式字符串输出数值和字符串，支持：
+//! - 数值字段：`#`、`.`、`,`（千位分隔）、前导 `+`、尾随 `+`/`-`、
+//!   前导 `**`（星号填充）、`$$`（浮动美元符）、`**$`、指数 `^^^^`
+//! - 字符串字段：`!`（第一个字符）、`&`（整个字符串）、`\  \`（固定宽度）
+//! - `_` 转义下一个字符，其余字符原样输出
+//!
+//! 数值放不下时在前面加 `%` 并完整输出
+
+use crate::error::{BasicError, Result};
+use crate::variables::Value;
+
+/// 格式字符串中的一段
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    /// 原样输出的文本
+    Literal(String),
+    /// 数值字段
+    Number(NumberField),
+    /// `!`：字符串的第一个字符
+    FirstChar,
+    /// `&`：整个字符串
+    WholeString,
+    /// `\  \`：固定宽度的字符串
+    FixedString(usize),
+}
+
+/// 数值字段的描述
+#[derive(Debug, Clone, PartialEq, Default)]
+struct NumberField {
+    /// 小数点左边的位置数（包括 `**`、`$$`、`,` 和前导 `+` 占的位置）
+    integer_width: usize,
+    /// 小数点左边 `#` 的个数
+    digits: usize,
+    /// 小数位数（`None` 表示没有小数点）
+    decimals: Option<usize>,
+    /// 整数部分使用千位分隔符
+    commas: bool,
+    /// 前导 `+`：总是输出符号
+    leading_plus: bool,
+    /// 尾随的 `+` 或 `-`
+    trailing_sign: Option<char>,
+    /// `**`：用星号填充前导空白
+    fill_asterisk: bool,
+    /// `$$`：数字前紧跟美元符
+    dollar: bool,
+    /// `^^^^`：指数格式
+    exponent: bool,
+}
+
+/// 按格式字符串格式化一组值
+///
+/// 值多于字段时格式字符串从头重复使用；值用完后输出停在下一个字段之前
+pub fn format_using(format: &str, values: &[Value]) -> Result<String> {
+    let pieces = parse_format(format);
+    if !pieces.iter().any(|piece| !matches!(piece, Piece::Literal(_))) {
+        return Err(BasicError::IllegalQuantity(
+            "PRINT USING format has no fields".to_string()
+        ));
+    }
+
+    let mut output = String::new();
+    let mut values = values.iter().peekable();
+    loop {
+        for piece in &pieces {
+            if let Piece::Literal(text) = piece {
+                output.push_str(text);
+                continue;
+            }
+            let Some(value) = values.next() else {
+                return Ok(output);
+            };
+            output.push_str(&format_field(piece, value)?);
+        }
+        if values.peek().is_none() {
+            return Ok(output);
+        }
+    }
+}
+
+/// 格式化单个字段
+fn format_field(piece: &Piece, value: &Value) -> Result<String> {
+    match (piece, value) {
+        (Piece::Number(field), Value::Number(n)) => Ok(format_number(field, *n)),
+        (Piece::FirstChar, Value::String(s)) => Ok(pad_string(s, 1)),
+        (Piece::WholeString, Value::String(s)) => Ok(s.clone()),
+        (Piece::FixedString(width), Value::String(s)) => Ok(pad_string(s, *width)),
+        (Piece::Number(_), Value::String(_)) => Err(BasicError::TypeMismatch(
+            "PRINT USING numeric field needs a number".to_string()
+        )),
+        _ => Err(BasicError::TypeMismatch(
+            "PRINT USING string field needs a string".to_string()
+        )),
+    }
+}
+
+/// 截断或用空格补齐到指定宽度
+fn pad_string(s: &str, width: usize) -> String {
+    let mut result: String = s.chars().take(width).collect();
+    let len = result.chars().count();
+    result.push_str(&" ".repeat(width - len));
+    result
+}
+
+/// 把格式字符串拆成文本和字段
+fn parse_format(format: &str) -> Vec<Piece> {
+    let chars: Vec<char> = format.chars().collect();
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+
+        let field = match ch {
+            '_' => {
+                // 转义：下一个字符原样输出
+                literal.push(chars.get(i + 1).copied().unwrap_or('_'));
+                i += 2;
+                continue;
+            }
+            '!' => {
+                i += 1;
+                Some(Piece::FirstChar)
+            }
+            '&' => {
+                i += 1;
+                Some(Piece::WholeString)
+            }
+            '\\' => {
+                // \ 与下一个 \ 之间只能是空格
+                let spaces = chars[i + 1..].iter().take_while(|&&c| c == ' ').count();
+                if chars.get(i + 1 + spaces) == Some(&'\\') {
+                    i += spaces + 2;
+                    Some(Piece::FixedString(spaces + 2))
+                } else {
+                    None
+                }
+            }
+            _ => parse_number_field(&chars, &mut i).map(Piece::Number),
+        };
+
+        match field {
+            Some(field) => {
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                }
+                pieces.push(field);
+            }
+            None => {
+                literal.push(ch);
+                i += 1;
+            }
+        }
+    }
+
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+    pieces
+}
+
+/// 从位置 `*i` 解析数值字段，不是数值字段时返回 None 且不移动位置
+fn parse_number_field(chars: &[char], i: &mut usize) -> Option<NumberField> {
+    let at = |pos: usize| chars.get(pos).copied();
+    let starts_number = |pos: usize| match at(pos) {
+        Some('#') => true,
+        Some('.') => at(pos + 1) == Some('#'),
+        Some('*') => at(pos + 1) == Some('*'),
+        Some('$') => at(pos + 1) == Some('$'),
+        _ => false,
+    };
+
+    let mut pos = *i;
+    let mut field = NumberField::default();
+
+    if at(pos) == Some('+') && starts_number(pos + 1) {
+        field.leading_plus = true;
+        field.integer_width += 1;
+        pos += 1;
+    }
+    if !starts_number(pos) {
+        return None;
+    }
+
+    if at(pos) == Some('*') {
+        field.fill_asterisk = true;
+        field.integer_width += 2;
+        pos += 2;
+        if at(pos) == Some('$') {
+            field.dollar = true;
+            field.integer_width += 1;
+            pos += 1;
+        }
+    } else if at(pos) == Some('$') {
+        field.dollar = true;
+        field.integer_width += 2;
+        pos += 2;
+    }
+
+    // 整数部分：# 和千位分隔符 ,
+    while let Some(c) = at(pos) {
+        match c {
+            '#' => field.digits += 1,
+            ',' => field.commas = true,
+            _ => break,
+        }
+        field.integer_width += 1;
+        pos += 1;
+    }
+
+    // 小数部分
+    if at(pos) == Some('.') {
+        pos += 1;
+        let mut decimals = 0;
+        while at(pos) == Some('#') {
+            decimals += 1;
+            pos += 1;
+        }
+        field.decimals = Some(decimals);
+    }
+
+    // 指数 ^^^^
+    if (0..4).all(|k| at(pos + k) == Some('^')) {
+        field.exponent = true;
+        pos += 4;
+    }
+
+    // 尾随符号
+    if !field.leading_plus {
+        if let Some(sign @ ('+' | '-')) = at(pos) {
+            field.trailing_sign = Some(sign);
+            pos += 1;
+        }
+    }
+
+    *i = pos;
+    Some(field)
+}
+
+/// 按数值字段格式化数值
+fn format_number(field: &NumberField, n: f64) -> String {
+    let negative = n < 0.0;
+    let decimals = field.decimals.unwrap_or(0);
+
+    // 符号：前导 + 总是输出；尾随符号放在最后；否则负数前加 -
+    let (lead_sign, trail_sign) = match field.trailing_sign {
+        Some('+') => ("", if negative { "-" } else { "+" }),
+        Some(_) => ("", if negative { "-" } else { " " }),
+        None if field.leading_plus => (if negative { "-" } else { "+" }, ""),
+        None => (if negative { "-" } else { "" }, ""),
+    };
+
+    let (mut int_part, frac_part, exponent) = if field.exponent {
+        let (mantissa, exponent) = scale_for_exponent(field, n.abs());
+        let (int_part, frac_part) = split_fixed(mantissa, decimals);
+        (int_part, frac_part, Some(exponent))
+    } else {
+        let (int_part, frac_part) = split_fixed(n.abs(), decimals);
+        (int_part, frac_part, None)
+    };
+
+    // 没有整数位时省略前导 0（如 ".##"）
+    if int_part == "0" && field.digits == 0 && field.decimals.is_some() {
+        int_part.clear();
+    }
+    if field.commas {
+        int_part = group_thousands(&int_part);
+    }
+
+    let mut left = String::from(lead_sign);
+    if field.dollar {
+        left.push('$');
+    }
+    left.push_str(&int_part);
+
+    let width = left.chars().count();
+    let mut result = if width > field.integer_width {
+        // 溢出：加 % 并完整输出
+        format!("%{}", left)
+    } else {
+        let fill = if field.fill_asterisk { "*" } else { " " };
+        format!("{}{}", fill.repeat(field.integer_width - width), left)
+    };
+
+    if field.decimals.is_some() {
+        result.push('.');
+        result.push_str(&frac_part);
+    }
+    if let Some(exponent) = exponent {
+        result.push_str(&format!("E{}{:02}", if exponent < 0 { '-' } else { '+' }, exponent.abs()));
+    }
+    result.push_str(trail_sign);
+    result
+}
+
+/// 按小数位数四舍五入，返回整数部分和小数部分
+fn split_fixed(value: f64, decimals: usize) -> (String, String) {
+    let text = format!("{:.*}", decimals, value);
+    match text.split_once('.') {
+        Some((int_part, frac_part)) => (int_part.to_string(), frac_part.to_string()),
+        None => (text, String::new()),
+    }
+}
+
+/// 计算指数格式的尾数和指数，使尾数的整数部分正好占满 `#` 位置
+fn scale_for_exponent(field: &NumberField, value: f64) -> (f64, i32) {
+    if value == 0.0 {
+        return (0.0, 0);
+    }
+    // 没有指定符号时，一个 # 位置留给符号
+    let reserve_sign = field.trailing_sign.is_none() && !field.leading_plus;
+    let int_digits = if reserve_sign {
+        field.digits.saturating_sub(1)
+    } else {
+        field.digits
+    } as i32;
+    let decimals = field.decimals.unwrap_or(0);
+
+    let mut exponent = value.log10().floor() as i32 - (int_digits - 1);
+    let mut mantissa = value / 10f64.powi(exponent);
+    // 四舍五入后可能进位（如 9.99 -> 10.0），需要再调整一次
+    let rounded: f64 = format!("{:.*}", decimals, mantissa).parse().unwrap_or(mantissa);
+    if rounded >= 10f64.powi(int_digits.max(0)) {
+        exponent += 1;
+        mantissa = value / 10f64.powi(exponent);
+    }
+    (mantissa, exponent)
+}
+
+/// 整数部分每三位加一个逗号
+fn group_thousands(digits: &str) -> String {
+    let mut result = String::new();
+    for (index, ch) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(ch);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(format: &str, n: f64) -> String {
+        format_using(format, &[Value::Number(n)]).unwrap()
+    }
+
+    fn text(format: &str, s: &str) -> String {
+        format_using(format, &[Value::String(s.to_string())]).unwrap()
+    }
+
+    // Requirement: PRINT USING 数值字段
+    #[test]
+    fn test_numeric_fields() {
+        assert_eq!(num("##.##", 0.5), " 0.50");
+        assert_eq!(num("###.##", 12.345), " 12.35");
+        assert_eq!(num("##.##", -1.5), "-1.50");
+        assert_eq!(num(".##", 0.25), ".25");
+        assert_eq!(num("###", 7.0), "  7");
+        assert_eq!(num("#,###,###.##", 1234567.891), "1,234,567.89");
+        assert_eq!(num("###,###.##", 1234567.891), "%1,234,567.89");
+        assert_eq!(num("#,###", 1234.0), "1,234");
+    }
+
+    // Requirement: PRINT USING 符号、$$ 和 **
+    #[test]
+    fn test_sign_dollar_and_asterisk() {
+        assert_eq!(num("+##.#", 2.0), " +2.0");
+        assert_eq!(num("+##.#", -2.0), " -2.0");
+        assert_eq!(num("##.#-", -2.0), " 2.0-");
+        assert_eq!(num("##.#-", 2.0), " 2.0 ");
+        assert_eq!(num("##.#+", 2.0), " 2.0+");
+        assert_eq!(num("$$###.##", 45.5), "  $45.50");
+        assert_eq!(num("**###.##", 45.5), "***45.50");
+        assert_eq!(num("**$##.##", 45.5), "**$45.50");
+    }
+
+    // Requirement: PRINT USING 溢出和指数
+    #[test]
+    fn test_overflow_and_exponent() {
+        assert_eq!(num("##.##", 123.456), "%123.46");
+        assert_eq!(num("##", -10.0), "%-10");
+        assert_eq!(num("##.##^^^^", 234.56), " 2.35E+02");
+        assert_eq!(num("##.##^^^^", 0.000123), " 1.23E-04");
+        assert_eq!(num("##.##^^^^", 9.999), " 1.00E+01");
+        assert_eq!(num("+#.##^^^^", -1500.0), "-1.50E+03");
+    }
+
+    // Requirement: PRINT USING 字符串字段和转义
+    #[test]
+    fn test_string_fields_and_literals() {
+        assert_eq!(text("!", "HELLO"), "H");
+        assert_eq!(text("&!", "HI"), "HI");
+        assert_eq!(text("\\  \\", "HELLO"), "HELL");
+        assert_eq!(text("[\\  \\]", "AB"), "[AB  ]");
+        assert_eq!(num("_#_#: ##", 5.0), "##:  5");
+        assert_eq!(num("TOTAL ##%", 42.0), "TOTAL 42%");
+    }
+
+    // Requirement: PRINT USING 格式重复使用
+    #[test]
+    fn test_format_reuse_and_errors() {
+        let values = [Value::Number(1.0), Value::Number(2.0), Value::Number(3.0)];
+        assert_eq!(format_using("[##]", &values).unwrap(), "[ 1][ 2][ 3]");
+        assert_eq!(format_using("## AND ## ", &values[..1]).unwrap(), " 1 AND ");
+
+        assert!(matches!(format_using("NO FIELDS", &values), Err(BasicError::IllegalQuantity(_))));
+        assert!(matches!(
+            format_using("&", &[Value::Number(1.0)]),
+            Err(BasicError::TypeMismatch(_))
+        ));
+    }
+}
@@ -1,6 +1,6 @@
-/// 运行时环境
-///
-/// 管理 BASIC 程序的执行状态，包括程序存储、变量、调用栈等
+//! 运行时环境
+//!
+//! 管理 BASIC 程序的执行状态，包括程序存储、变量、调用栈等
 
 use std::collections::BTreeMap;
 use crate::ast::*;
@@ -45,6 +45,9 @@ pub struct Runtime {
     /// 程序存储（行号 -> 程序行）
     program: BTreeMap<u16, ProgramLine>,
     
+    /// 程序行的原始输入文本（行号 -> 文本），用于 LIST/SAVE 原样输出
+    sources: BTreeMap<u16, String>,
+    
     /// 调用栈（GOSUB 和 FOR 循环）
     call_stack: Vec<CallFrame>,
     
@@ -66,6 +69,7 @@ impl Runtime {
     pub fn new() -> Self {
         Runtime {
             program: BTreeMap::new(),
+            sources: BTreeMap::new(),
             call_stack: Vec::new(),
             state: ExecutionState::NotRunning,
             current_line: None,
@@ -75,7 +79,10 @@ impl Runtime {
     }
 
     /// 添加或替换程序行
+    ///
+    /// 不附带原始文本，LIST/SAVE 时由 AST 重新生成该行
     pub fn add_line(&mut self, line: ProgramLine) {
+        self.sources.remove(&line.line_number);
         if line.statements.is_empty() {
             // 空语句列表表示删除该行
             self.program.remove(&line.line_number);
@@ -84,9 +91,37 @@ impl Runtime {
         }
     }
 
+    /// 添加或替换程序行，同时保存用户输入的原始文本（含行号）
+    pub fn add_line_with_source(&mut self, line: ProgramLine, source: String) {
+        let line_number = line.line_number;
+        let is_delete = line.statements.is_empty();
+        self.add_line(line);
+        if !is_delete {
+            self.sources.insert(line_number, source);
+        }
+    }
+
+    /// 获取程序行的原始输入文本
+    pub fn get_source(&self, line_number: u16) -> Option<&str> {
+        self.sources.get(&line_number).map(|s| s.as_str())
+    }
+
     /// 删除程序行
     pub fn delete_line(&mut self, line_number: u16) {
         self.program.remove(&line_number);
+        self.sources.remove(&line_number);
+    }
+
+    /// 删除指定范围内的程序行（`None` 表示该端不限），返回删除的行数
+    pub fn delete_lines_range(&mut self, start: Option<u16>, end: Option<u16>) -> usize {
+        let numbers: Vec<u16> = self.get_lines_range(start, end)
+            .iter()
+            .map(|line| line.line_number)
+            .collect();
+        for number in &numbers {
+            self.delete_line(*number);
+        }
+        numbers.len()
     }
 
     /// 获取程序行
@@ -123,6 +158,7 @@ impl Runtime {
     /// 清空程序
     pub fn clear_program(&mut self) {
         self.program.clear();
+        self.sources.clear();
         self.reset_execution_state();
     }
     
@@ -272,7 +308,7 @@ impl Runtime {
             }
         } else {
             Err(BasicError::NextWithoutFor(
-                expected_var.unwrap_or_else(|| "".to_string())
+                expected_var.unwrap_or_default()
             ))
         }
     }
@@ -424,9 +460,9 @@ mod tests {
         
         let line = ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Print {
-                items: vec![PrintItem::Expr(Expr::String("HELLO".to_string()))],
-            }],
+            statements: vec![StatementKind::Print {
+                items: vec![PrintItem::Expr(Expr::string("HELLO".to_string()))],
+            }.into()],
         };
         
         runtime.add_line(line);
@@ -442,27 +478,27 @@ mod tests {
         // 添加第一个版本
         let line1 = ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Print {
-                items: vec![PrintItem::Expr(Expr::String("HELLO".to_string()))],
-            }],
+            statements: vec![StatementKind::Print {
+                items: vec![PrintItem::Expr(Expr::string("HELLO".to_string()))],
+            }.into()],
         };
         runtime.add_line(line1);
         
         // 替换
         let line2 = ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Print {
-                items: vec![PrintItem::Expr(Expr::String("WORLD".to_string()))],
-            }],
+            statements: vec![StatementKind::Print {
+                items: vec![PrintItem::Expr(Expr::string("WORLD".to_string()))],
+            }.into()],
         };
         runtime.add_line(line2);
         
         assert_eq!(runtime.line_count(), 1);
         let line = runtime.get_line(10).unwrap();
-        match &line.statements[0] {
-            Statement::Print { items } => {
+        match &line.statements[0].kind {
+            StatementKind::Print { items } => {
                 match &items[0] {
-                    PrintItem::Expr(Expr::String(s)) => assert_eq!(s, "WORLD"),
+                    PrintItem::Expr(Expr { kind: ExprKind::String(s), .. }) => assert_eq!(s, "WORLD"),
                     _ => panic!("Expected string"),
                 }
             }
@@ -477,7 +513,7 @@ mod tests {
         
         let line = ProgramLine {
             line_number: 10,
-            statements: vec![Statement::End],
+            statements: vec![StatementKind::End.into()],
         };
         runtime.add_line(line);
         assert_eq!(runtime.line_count(), 1);
@@ -487,15 +523,55 @@ mod tests {
         assert!(runtime.get_line(10).is_none());
     }
 
+    // Requirement: 程序存储和管理 - 保存原始文本
+    #[test]
+    fn test_line_source_text() {
+        let mut runtime = Runtime::new();
+        
+        runtime.add_line_with_source(
+            ProgramLine { line_number: 10, statements: vec![StatementKind::End.into()] },
+            "10 END  ".to_string(),
+        );
+        assert_eq!(runtime.get_source(10), Some("10 END  "));
+        
+        // 不带原始文本替换该行，旧文本失效
+        runtime.add_line(ProgramLine { line_number: 10, statements: vec![StatementKind::Stop.into()] });
+        assert_eq!(runtime.get_source(10), None);
+        
+        runtime.add_line_with_source(
+            ProgramLine { line_number: 20, statements: vec![StatementKind::End.into()] },
+            "20END".to_string(),
+        );
+        runtime.delete_line(20);
+        assert_eq!(runtime.get_source(20), None);
+    }
+
+    // Requirement: 程序存储和管理 - 删除行号范围
+    #[test]
+    fn test_delete_lines_range() {
+        let mut runtime = Runtime::new();
+        for n in [10, 100, 150, 200, 300] {
+            runtime.add_line(ProgramLine { line_number: n, statements: vec![StatementKind::End.into()] });
+        }
+        
+        assert_eq!(runtime.delete_lines_range(Some(100), Some(200)), 3);
+        let remaining: Vec<u16> = runtime.get_all_lines().iter().map(|l| l.line_number).collect();
+        assert_eq!(remaining, vec![10, 300]);
+        
+        assert_eq!(runtime.delete_lines_range(None, Some(50)), 1);
+        assert_eq!(runtime.delete_lines_range(Some(400), None), 0);
+        assert_eq!(runtime.line_count(), 1);
+    }
+
     // Requirement: 程序存储和管理 - 行号排序
     #[test]
     fn test_line_sorting() {
         let mut runtime = Runtime::new();
         
         // 乱序添加
-        runtime.add_line(ProgramLine { line_number: 30, statements: vec![Statement::End] });
-        runtime.add_line(ProgramLine { line_number: 10, statements: vec![Statement::End] });
-        runtime.add_line(ProgramLine { line_number: 20, statements: vec![Statement::End] });
+        runtime.add_line(ProgramLine { line_number: 30, statements: vec![StatementKind::End.into()] });
+        runtime.add_line(ProgramLine { line_number: 10, statements: vec![StatementKind::End.into()] });
+        runtime.add_line(ProgramLine { line_number: 20, statements: vec![StatementKind::End.into()] });
         
         let lines = runtime.get_all_lines();
         assert_eq!(lines[0].line_number, 10);
@@ -508,8 +584,8 @@ mod tests {
     fn test_start_execution_from_first_line() {
         let mut runtime = Runtime::new();
         
-        runtime.add_line(ProgramLine { line_number: 10, statements: vec![Statement::End] });
-        runtime.add_line(ProgramLine { line_number: 20, statements: vec![Statement::End] });
+        runtime.add_line(ProgramLine { line_number: 10, statements: vec![StatementKind::End.into()] });
+        runtime.add_line(ProgramLine { line_number: 20, statements: vec![StatementKind::End.into()] });
         
         runtime.start_execution(None).unwrap();
         assert_eq!(runtime.get_current_line(), Some(10));
@@ -520,8 +596,8 @@ mod tests {
     fn test_start_execution_from_specific_line() {
         let mut runtime = Runtime::new();
         
-        runtime.add_line(ProgramLine { line_number: 10, statements: vec![Statement::End] });
-        runtime.add_line(ProgramLine { line_number: 100, statements: vec![Statement::End] });
+        runtime.add_line(ProgramLine { line_number: 10, statements: vec![StatementKind::End.into()] });
+        runtime.add_line(ProgramLine { line_number: 100, statements: vec![StatementKind::End.into()] });
         
         runtime.start_execution(Some(100)).unwrap();
         assert_eq!(runtime.get_current_line(), Some(100));
@@ -532,8 +608,8 @@ mod tests {
     fn test_goto_jump() {
         let mut runtime = Runtime::new();
         
-        runtime.add_line(ProgramLine { line_number: 10, statements: vec![Statement::End] });
-        runtime.add_line(ProgramLine { line_number: 100, statements: vec![Statement::End] });
+        runtime.add_line(ProgramLine { line_number: 10, statements: vec![StatementKind::End.into()] });
+        runtime.add_line(ProgramLine { line_number: 100, statements: vec![StatementKind::End.into()] });
         
         runtime.start_execution(Some(10)).unwrap();
         runtime.set_execution_position(100, 0).unwrap();
@@ -546,7 +622,7 @@ mod tests {
     fn test_goto_undefined_line() {
         let mut runtime = Runtime::new();
         
-        runtime.add_line(ProgramLine { line_number: 10, statements: vec![Statement::End] });
+        runtime.add_line(ProgramLine { line_number: 10, statements: vec![StatementKind::End.into()] });
         
         let result = runtime.set_execution_position(999, 0);
         assert!(result.is_err());
@@ -680,8 +756,8 @@ mod tests {
     fn test_new_command() {
         let mut runtime = Runtime::new();
         
-        runtime.add_line(ProgramLine { line_number: 10, statements: vec![Statement::End] });
-        runtime.add_line(ProgramLine { line_number: 20, statements: vec![Statement::End] });
+        runtime.add_line(ProgramLine { line_number: 10, statements: vec![StatementKind::End.into()] });
+        runtime.add_line(ProgramLine { line_number: 20, statements: vec![StatementKind::End.into()] });
         assert_eq!(runtime.line_count(), 2);
         
         runtime.clear_program();
@@ -694,7 +770,7 @@ mod tests {
     fn test_stop_pause() {
         let mut runtime = Runtime::new();
         
-        runtime.add_line(ProgramLine { line_number: 10, statements: vec![Statement::End] });
+        runtime.add_line(ProgramLine { line_number: 10, statements: vec![StatementKind::End.into()] });
         runtime.start_execution(None).unwrap();
         
         runtime.pause_execution();
@@ -706,7 +782,7 @@ mod tests {
     fn test_cont_resume() {
         let mut runtime = Runtime::new();
         
-        runtime.add_line(ProgramLine { line_number: 10, statements: vec![Statement::End] });
+        runtime.add_line(ProgramLine { line_number: 10, statements: vec![StatementKind::End.into()] });
         runtime.start_execution(None).unwrap();
         
         runtime.pause_execution();
@@ -734,13 +810,13 @@ mod tests {
         runtime.add_line(ProgramLine {
             line_number: 10,
             statements: vec![
-                Statement::Let {
+                StatementKind::Let {
                     target: AssignTarget::Variable("A".to_string()),
-                    value: Expr::Number(5.0),
-                },
-                Statement::Print {
-                    items: vec![PrintItem::Expr(Expr::Variable("A".to_string()))],
-                },
+                    value: Expr::number(5.0),
+                }.into(),
+                StatementKind::Print {
+                    items: vec![PrintItem::Expr(Expr::variable("A".to_string()))],
+                }.into(),
             ],
         });
         
@@ -749,12 +825,12 @@ mod tests {
         // 获取第一条语句
         let stmt1 = runtime.get_next_statement();
         assert!(stmt1.is_some());
-        assert!(matches!(stmt1.unwrap(), Statement::Let { .. }));
+        assert!(matches!(stmt1.unwrap().kind, StatementKind::Let { .. }));
         
         // 获取第二条语句
         let stmt2 = runtime.get_next_statement();
         assert!(stmt2.is_some());
-        assert!(matches!(stmt2.unwrap(), Statement::Print { .. }));
+        assert!(matches!(stmt2.unwrap().kind, StatementKind::Print { .. }));
         
         // 没有更多语句了
         let stmt3 = runtime.get_next_statement();
@@ -768,18 +844,18 @@ mod tests {
         
         runtime.add_line(ProgramLine {
             line_number: 10,
-            statements: vec![Statement::Let {
+            statements: vec![StatementKind::Let {
                 target: AssignTarget::Variable("A".to_string()),
-                value: Expr::Number(1.0),
-            }],
+                value: Expr::number(1.0),
+            }.into()],
         });
         
         runtime.add_line(ProgramLine {
             line_number: 20,
-            statements: vec![Statement::Let {
+            statements: vec![StatementKind::Let {
                 target: AssignTarget::Variable("B".to_string()),
-                value: Expr::Number(2.0),
-            }],
+                value: Expr::number(2.0),
+            }.into()],
         });
         
         runtime.start_execution(None).unwrap();
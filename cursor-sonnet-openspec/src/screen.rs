@@ -0,0 +1,427 @@
+//! 虚拟字符屏幕
+//!
+//! 模拟 Commodore 风格的内存映射文本屏幕：屏幕 RAM 存放屏幕码，颜色 RAM 存放颜色，
+//! 两者都映射到 POKE/PEEK 的地址空间。PRINT 的文本和 PETSCII 控制码写入屏幕，
+//! 屏幕可以渲染为 ANSI 转义序列输出到终端，也可以导出为纯文本（用于测试）
+//!
+//! 字符和字符码之间的转换由当前字符集负责（见 `charset` 模块）
+
+use crate::charset::{screen_code_to_petscii, Charset};
+
+/// 屏幕布局：尺寸以及屏幕 RAM / 颜色 RAM 的起始地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenConfig {
+    /// 列数
+    pub columns: usize,
+    /// 行数
+    pub rows: usize,
+    /// 屏幕 RAM 起始地址
+    pub screen_base: u16,
+    /// 颜色 RAM 起始地址
+    pub color_base: u16,
+}
+
+impl ScreenConfig {
+    /// Commodore 64：40x25，屏幕 RAM 在 1024，颜色 RAM 在 55296
+    pub const C64: ScreenConfig = ScreenConfig {
+        columns: 40,
+        rows: 25,
+        screen_base: 1024,
+        color_base: 55296,
+    };
+
+    /// VIC-20：22x23，屏幕 RAM 在 7680，颜色 RAM 在 38400
+    pub const VIC20: ScreenConfig = ScreenConfig {
+        columns: 22,
+        rows: 23,
+        screen_base: 7680,
+        color_base: 38400,
+    };
+
+    /// 80 列终端：80x24，地址与 C64 相同
+    pub const WIDE: ScreenConfig = ScreenConfig {
+        columns: 80,
+        rows: 24,
+        screen_base: 1024,
+        color_base: 55296,
+    };
+
+    /// 按 "40x25" / "22x23" / "80x24" 选择布局
+    pub fn from_size(size: &str) -> Option<ScreenConfig> {
+        match size {
+            "40x25" => Some(Self::C64),
+            "22x23" => Some(Self::VIC20),
+            "80x24" => Some(Self::WIDE),
+            _ => None,
+        }
+    }
+
+    /// 屏幕单元数
+    pub fn cells(&self) -> usize {
+        self.columns * self.rows
+    }
+}
+
+/// 默认前景色（C64 开机时的浅蓝色）
+const DEFAULT_COLOR: u8 = 14;
+
+/// 空格的屏幕码
+const SPACE: u8 = 32;
+
+/// C64 调色板（RGB），用于 ANSI 24 位颜色
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00), // 0 黑
+    (0xFF, 0xFF, 0xFF), // 1 白
+    (0x88, 0x39, 0x32), // 2 红
+    (0x67, 0xB6, 0xBD), // 3 青
+    (0x8B, 0x3F, 0x96), // 4 紫
+    (0x55, 0xA0, 0x49), // 5 绿
+    (0x40, 0x31, 0x8D), // 6 蓝
+    (0xBF, 0xCE, 0x72), // 7 黄
+    (0x8B, 0x54, 0x29), // 8 橙
+    (0x57, 0x42, 0x00), // 9 棕
+    (0xB8, 0x69, 0x62), // 10 浅红
+    (0x50, 0x50, 0x50), // 11 深灰
+    (0x78, 0x78, 0x78), // 12 灰
+    (0x94, 0xE0, 0x89), // 13 浅绿
+    (0x78, 0x69, 0xC4), // 14 浅蓝
+    (0x9F, 0x9F, 0x9F), // 15 浅灰
+];
+
+/// 虚拟字符屏幕
+#[derive(Debug, Clone)]
+pub struct Screen {
+    config: ScreenConfig,
+    /// 屏幕 RAM（屏幕码，+128 为反色）
+    chars: Vec<u8>,
+    /// 颜色 RAM（0-15）
+    colors: Vec<u8>,
+    /// 光标行
+    row: usize,
+    /// 光标列
+    column: usize,
+    /// 当前前景色
+    color: u8,
+    /// 反色模式（CHR$(18) 打开，CHR$(146) 关闭）
+    reverse: bool,
+    /// 字符集（CHR$(14) / CHR$(142) 切换 PETSCII 大小写）
+    charset: Charset,
+}
+
+impl Screen {
+    /// 创建空白屏幕
+    pub fn new(config: ScreenConfig) -> Self {
+        Screen {
+            config,
+            chars: vec![SPACE; config.cells()],
+            colors: vec![DEFAULT_COLOR; config.cells()],
+            row: 0,
+            column: 0,
+            color: DEFAULT_COLOR,
+            reverse: false,
+            charset: Charset::Petscii,
+        }
+    }
+
+    /// 当前字符集
+    pub fn charset(&self) -> Charset {
+        self.charset
+    }
+
+    /// 设置字符集（屏幕内容不变，按新字符集显示）
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.charset = charset;
+    }
+
+    /// 屏幕布局
+    pub fn config(&self) -> ScreenConfig {
+        self.config
+    }
+
+    /// 光标位置（行, 列）
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.column)
+    }
+
+    /// 地址是否落在屏幕 RAM 或颜色 RAM 中
+    pub fn contains(&self, address: u16) -> bool {
+        self.screen_offset(address).is_some() || self.color_offset(address).is_some()
+    }
+
+    fn screen_offset(&self, address: u16) -> Option<usize> {
+        let offset = address.checked_sub(self.config.screen_base)? as usize;
+        (offset < self.config.cells()).then_some(offset)
+    }
+
+    fn color_offset(&self, address: u16) -> Option<usize> {
+        let offset = address.checked_sub(self.config.color_base)? as usize;
+        (offset < self.config.cells()).then_some(offset)
+    }
+
+    /// 读取屏幕 RAM / 颜色 RAM，不在屏幕范围内返回 None
+    pub fn peek(&self, address: u16) -> Option<u8> {
+        if let Some(offset) = self.screen_offset(address) {
+            return Some(self.chars[offset]);
+        }
+        self.color_offset(address).map(|offset| self.colors[offset])
+    }
+
+    /// 写入屏幕 RAM / 颜色 RAM，不在屏幕范围内返回 false
+    pub fn poke(&mut self, address: u16, value: u8) -> bool {
+        if let Some(offset) = self.screen_offset(address) {
+            self.chars[offset] = value;
+            true
+        } else if let Some(offset) = self.color_offset(address) {
+            self.colors[offset] = value & 0x0F;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 清屏并把光标移到左上角
+    pub fn clear(&mut self) {
+        self.chars.fill(SPACE);
+        self.colors.fill(self.color);
+        self.row = 0;
+        self.column = 0;
+    }
+
+    /// 输出文本：普通字符写到光标处，PETSCII 控制码移动光标或改变颜色
+    pub fn print(&mut self, text: &str) {
+        for ch in text.chars() {
+            let code = self.charset.from_char(ch).unwrap_or(b'?');
+            match code {
+                b'\n' | b'\r' => self.newline(),
+                147 => self.clear(),
+                19 => {
+                    self.row = 0;
+                    self.column = 0;
+                }
+                17 => self.cursor_down(),
+                145 => self.row = self.row.saturating_sub(1),
+                29 => self.cursor_right(),
+                157 => self.cursor_left(),
+                18 => self.reverse = true,
+                146 => self.reverse = false,
+                14 if self.charset.is_petscii() => self.charset = Charset::PetsciiLower,
+                142 if self.charset.is_petscii() => self.charset = Charset::Petscii,
+                code => match color_code(code) {
+                    Some(color) => self.color = color,
+                    None => {
+                        if let Some(screen_code) = petscii_to_screen_code(code) {
+                            self.put(screen_code);
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    /// 在光标处写入一个屏幕码并右移光标
+    fn put(&mut self, screen_code: u8) {
+        let offset = self.row * self.config.columns + self.column;
+        self.chars[offset] = if self.reverse { screen_code | 0x80 } else { screen_code };
+        self.colors[offset] = self.color;
+        self.cursor_right();
+    }
+
+    fn newline(&mut self) {
+        self.column = 0;
+        self.reverse = false;
+        self.cursor_down();
+    }
+
+    fn cursor_down(&mut self) {
+        if self.row + 1 < self.config.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    fn cursor_right(&mut self) {
+        self.column += 1;
+        if self.column >= self.config.columns {
+            self.column = 0;
+            self.cursor_down();
+        }
+    }
+
+    fn cursor_left(&mut self) {
+        if self.column > 0 {
+            self.column -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.column = self.config.columns - 1;
+        }
+    }
+
+    /// 整屏上移一行，最后一行清空
+    fn scroll(&mut self) {
+        let columns = self.config.columns;
+        self.chars.drain(..columns);
+        self.chars.extend(std::iter::repeat_n(SPACE, columns));
+        self.colors.drain(..columns);
+        self.colors.extend(std::iter::repeat_n(self.color, columns));
+    }
+
+    /// 导出为纯文本：每行去掉行尾空格，反色字符按普通字符输出
+    pub fn to_text(&self) -> String {
+        self.chars
+            .chunks(self.config.columns)
+            .map(|row| {
+                let line: String = row.iter().map(|&code| self.screen_code_to_char(code)).collect();
+                line.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// 渲染为 ANSI 转义序列：逐行重画整个屏幕，最后把终端光标放到屏幕光标处
+    pub fn render_ansi(&self) -> String {
+        let mut out = String::from("\x1b[H");
+        for (row, cells) in self.chars.chunks(self.config.columns).enumerate() {
+            out.push_str(&format!("\x1b[{};1H", row + 1));
+            let mut current: Option<(u8, bool)> = None;
+            for (column, &code) in cells.iter().enumerate() {
+                let color = self.colors[row * self.config.columns + column];
+                let reverse = code & 0x80 != 0;
+                if current != Some((color, reverse)) {
+                    let (r, g, b) = PALETTE[color as usize];
+                    out.push_str(&format!("\x1b[0;38;2;{};{};{}m", r, g, b));
+                    if reverse {
+                        out.push_str("\x1b[7m");
+                    }
+                    current = Some((color, reverse));
+                }
+                out.push(self.screen_code_to_char(code));
+            }
+        }
+        out.push_str(&format!("\x1b[0m\x1b[{};{}H", self.row + 1, self.column + 1));
+        out
+    }
+
+    /// 屏幕码按当前字符集转换为显示字符（忽略反色位）
+    fn screen_code_to_char(&self, code: u8) -> char {
+        match self.charset.to_char(screen_code_to_petscii(code)) {
+            '\u{a0}' => ' ',
+            ch => ch,
+        }
+    }
+}
+
+/// PETSCII 颜色控制码对应的颜色
+fn color_code(code: u8) -> Option<u8> {
+    let color = match code {
+        144 => 0,
+        5 => 1,
+        28 => 2,
+        159 => 3,
+        156 => 4,
+        30 => 5,
+        31 => 6,
+        158 => 7,
+        129 => 8,
+        149 => 9,
+        150 => 10,
+        151 => 11,
+        152 => 12,
+        153 => 13,
+        154 => 14,
+        155 => 15,
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// PETSCII 可打印字符转换为屏幕码，控制码返回 None
+pub fn petscii_to_screen_code(code: u8) -> Option<u8> {
+    match code {
+        32..=63 => Some(code),
+        64..=95 => Some(code - 64),
+        96..=127 => Some(code - 32),
+        160..=191 => Some(code - 64),
+        192..=254 => Some(code - 128),
+        255 => Some(94),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Requirement: 屏幕 - PRINT 写入屏幕并换行
+    #[test]
+    fn test_print_and_text_capture() {
+        let mut screen = Screen::new(ScreenConfig::VIC20);
+        screen.print("HELLO\nWORLD");
+
+        let text = screen.to_text();
+        assert!(text.starts_with("HELLO\nWORLD\n"));
+        assert_eq!(screen.cursor(), (1, 5));
+        assert_eq!(screen.peek(7680), Some(8)); // H 的屏幕码
+    }
+
+    // Requirement: 屏幕 - PETSCII 控制码
+    #[test]
+    fn test_control_codes() {
+        let mut screen = Screen::new(ScreenConfig::C64);
+        screen.print("JUNK");
+        screen.print("\u{93}"); // CHR$(147) 清屏
+        assert_eq!(screen.cursor(), (0, 0));
+        assert_eq!(screen.to_text().trim(), "");
+
+        // 下移两行、右移三列，红色输出
+        screen.print("\u{11}\u{11}\u{1d}\u{1d}\u{1d}\u{1c}X");
+        assert_eq!(screen.peek(1024 + 2 * 40 + 3), Some(24));
+        assert_eq!(screen.peek(55296 + 2 * 40 + 3), Some(2));
+
+        // HOME 后左移到上一行行尾
+        screen.print("\u{13}\u{11}\u{9d}");
+        assert_eq!(screen.cursor(), (0, 39));
+    }
+
+    // Requirement: 屏幕 - 内存映射
+    #[test]
+    fn test_poke_and_peek() {
+        let mut screen = Screen::new(ScreenConfig::C64);
+        assert!(screen.poke(1024 + 5 + 40 * 3, 1));
+        assert!(screen.poke(55296 + 5 + 40 * 3, 0xF7));
+        assert!(!screen.poke(2024, 1));
+
+        assert_eq!(screen.peek(1024 + 5 + 40 * 3), Some(1));
+        assert_eq!(screen.peek(55296 + 5 + 40 * 3), Some(7));
+        assert_eq!(screen.peek(1023), None);
+        assert_eq!(screen.to_text().lines().nth(3), Some("     A"));
+    }
+
+    // Requirement: 屏幕 - 滚动和自动换行
+    #[test]
+    fn test_wrap_and_scroll() {
+        let mut screen = Screen::new(ScreenConfig::VIC20);
+        screen.print(&"A".repeat(23));
+        assert_eq!(screen.cursor(), (1, 1));
+
+        for _ in 0..30 {
+            screen.print("\n");
+        }
+        screen.print("LAST");
+        let text = screen.to_text();
+        assert_eq!(text.lines().count(), 23);
+        assert_eq!(text.lines().last(), Some("LAST"));
+        assert!(text.lines().rev().skip(1).all(|line| line.is_empty()));
+    }
+
+    // Requirement: 屏幕 - ANSI 渲染
+    #[test]
+    fn test_render_ansi() {
+        let mut screen = Screen::new(ScreenConfig::VIC20);
+        screen.print("\u{12}HI");
+        let ansi = screen.render_ansi();
+        assert!(ansi.starts_with("\x1b[H\x1b[1;1H"));
+        assert!(ansi.contains("\x1b[7mHI"));
+        assert!(ansi.ends_with("\x1b[0m\x1b[1;3H"));
+    }
+}
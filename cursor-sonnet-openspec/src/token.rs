@@ -41,12 +41,16 @@ pub enum Token {
     Clear,
     Get,
     New,
+    Merge,
+    Chain,
+    Common,
     
     // 控制流关键字
     Then,
     To,
     Step,
     Fn,  // FN 用于用户自定义函数调用
+    Using,  // PRINT USING
     
     // 内置函数（22个）
     // 数学函数
@@ -144,12 +148,16 @@ impl Token {
             "CLEAR" => Some(Token::Clear),
             "GET" => Some(Token::Get),
             "NEW" => Some(Token::New),
+            "MERGE" => Some(Token::Merge),
+            "CHAIN" => Some(Token::Chain),
+            "COMMON" => Some(Token::Common),
             
             // 控制流关键字
             "THEN" => Some(Token::Then),
             "TO" => Some(Token::To),
             "STEP" => Some(Token::Step),
             "FN" => Some(Token::Fn),
+            "USING" => Some(Token::Using),
             
             // 数学函数
             "SGN" => Some(Token::Sgn),
@@ -202,7 +210,8 @@ impl Token {
             Token::If | Token::Restore | Token::Gosub | Token::Return | Token::Rem |
             Token::Stop | Token::On | Token::Null | Token::Wait | Token::Load |
             Token::Save | Token::Def | Token::Poke | Token::Print | Token::Cont |
-            Token::List | Token::Clear | Token::Get | Token::New
+            Token::List | Token::Clear | Token::Get | Token::New |
+            Token::Merge | Token::Chain | Token::Common
         )
     }
 }
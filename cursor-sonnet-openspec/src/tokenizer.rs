@@ -1,6 +1,6 @@
-/// 词法分析器
-///
-/// 将 BASIC 源代码文本转换为 Token 流
+//! 词法分析器
+//!
+//! 将 BASIC 源代码文本转换为 Token 流
 
 use crate::error::{BasicError, Result};
 use crate::token::Token;
@@ -10,6 +10,17 @@ pub struct Tokenizer {
     position: usize,
     is_line_start: bool,
     rem_comment: Option<String>,  // 存储 REM 注释内容
+    rem_start: usize,             // REM 注释内容的起始位置
+}
+
+/// 带源码位置的 token
+///
+/// `start`/`end` 为输入行中的字符偏移（左闭右开），供编辑器工具定位使用
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub start: usize,
+    pub end: usize,
 }
 
 impl Tokenizer {
@@ -20,11 +31,23 @@ impl Tokenizer {
             position: 0,
             is_line_start: true,
             rem_comment: None,
+            rem_start: 0,
         }
     }
 
     /// 解析整行并返回所有 tokens
     pub fn tokenize_line(&mut self) -> Result<Vec<Token>> {
+        Ok(self
+            .tokenize_line_spanned()?
+            .into_iter()
+            .map(|spanned| spanned.token)
+            .collect())
+    }
+
+    /// 解析整行并返回带位置信息的 tokens
+    ///
+    /// 与 `tokenize_line` 产生相同的 token 序列，额外记录每个 token 在输入中的字符范围
+    pub fn tokenize_line_spanned(&mut self) -> Result<Vec<SpannedToken>> {
         let mut tokens = Vec::new();
         self.is_line_start = true;
         self.rem_comment = None;  // 重置 REM 注释
@@ -36,23 +59,31 @@ impl Tokenizer {
                 break;
             }
 
+            let start = self.position;
             let token = self.next_token()?;
+            let end = self.position;
             
             match &token {
                 Token::Eof => break,
                 Token::Newline => break,
                 Token::Rem => {
-                    tokens.push(token);
+                    let rem_end = start + 3;
+                    tokens.push(SpannedToken { token, start, end: rem_end });
                     // 如果有 REM 注释内容，将其作为 String token 添加
                     if let Some(ref comment) = self.rem_comment {
-                        tokens.push(Token::String(comment.clone()));
+                        tokens.push(SpannedToken {
+                            token: Token::String(comment.clone()),
+                            start: self.rem_start,
+                            end: self.rem_start + comment.chars().count(),
+                        });
                     }
                 }
-                _ => tokens.push(token),
+                _ => tokens.push(SpannedToken { token, start, end }),
             }
         }
 
-        tokens.push(Token::Newline);
+        let len = self.input.len();
+        tokens.push(SpannedToken { token: Token::Newline, start: len, end: len });
         Ok(tokens)
     }
 
@@ -80,7 +111,7 @@ impl Tokenizer {
         }
 
         // 数字
-        if ch.is_ascii_digit() || (ch == '.' && self.peek_next().map_or(false, |c| c.is_ascii_digit())) {
+        if ch.is_ascii_digit() || (ch == '.' && self.peek_next().is_some_and(|c| c.is_ascii_digit())) {
             return self.read_number();
         }
 
@@ -301,6 +332,7 @@ impl Tokenizer {
             if keyword_token == Token::Rem {
                 // 跳过空白字符
                 self.skip_whitespace();
+                self.rem_start = self.position;
                 // 读取到行尾的所有内容作为注释
                 let mut comment = String::new();
                 while self.position < self.input.len() {
@@ -434,6 +466,7 @@ mod tests {
 
     // Requirement: 数字常量解析 - 浮点数
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_parse_float() {
         // 在表达式中的数字（不在行首）
         let mut tokenizer = Tokenizer::new("PRINT 3.14159");
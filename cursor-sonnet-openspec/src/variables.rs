@@ -1,10 +1,12 @@
-/// 变量系统
-///
-/// 管理 BASIC 程序的变量存储，包括简单变量和数组
+//! 变量系统
+//!
+//! 管理 BASIC 程序的变量存储，包括简单变量和数组。
+//! TI 和 TI$ 是保留变量，读写 60Hz 时钟
 
 use std::collections::HashMap;
 use crate::error::{BasicError, Result};
-use crate::ast::Expr;
+use crate::ast::{CommonVariable, Expr};
+use crate::clock::JiffyClock;
 
 /// 用户自定义函数定义
 #[derive(Debug, Clone)]
@@ -58,6 +60,17 @@ impl Value {
     }
 }
 
+/// 未 DIM 的数组在第一次引用时自动创建，每一维上界为 10
+pub const DEFAULT_ARRAY_BOUND: usize = 10;
+
+/// 数组下标和 DIM 上界（原版 INTIDX）：负数或大于 32767 报 ?ILLEGAL QUANTITY
+pub fn subscript(n: f64) -> Result<usize> {
+    if !(0.0..32768.0).contains(&n) {
+        return Err(BasicError::IllegalQuantity(format!("Array index {} out of range", n)));
+    }
+    Ok(n as usize)
+}
+
 /// 数组结构
 #[derive(Debug, Clone)]
 pub struct Array {
@@ -92,10 +105,17 @@ impl Array {
         &self.dimensions
     }
 
+    /// 在原版内存中占用的字节数：名字 2、长度 2、维数 1、每维上界 2，
+    /// 每个元素数值 5 字节、字符串描述符 3 字节
+    pub fn memory_size(&self) -> usize {
+        let element = if self.is_string { 3 } else { 5 };
+        5 + 2 * self.dimensions.len() + element * self.data.len()
+    }
+
     /// 计算多维索引到一维索引的转换
     fn calculate_index(&self, indices: &[usize]) -> Result<usize> {
         if indices.len() != self.dimensions.len() {
-            return Err(BasicError::SyntaxError(
+            return Err(BasicError::SubscriptOutOfRange(
                 format!("Array has {} dimensions, but {} indices provided",
                     self.dimensions.len(), indices.len())
             ));
@@ -156,6 +176,8 @@ pub struct Variables {
     arrays: HashMap<String, Array>,
     /// 用户自定义函数
     functions: HashMap<String, UserFunction>,
+    /// TI / TI$ 读写的时钟（CLEAR 不影响）
+    clock: JiffyClock,
 }
 
 impl Variables {
@@ -165,9 +187,20 @@ impl Variables {
             simple: HashMap::new(),
             arrays: HashMap::new(),
             functions: HashMap::new(),
+            clock: JiffyClock::default(),
         }
     }
 
+    /// 获取时钟
+    pub fn clock(&self) -> &JiffyClock {
+        &self.clock
+    }
+
+    /// 获取可变时钟
+    pub fn clock_mut(&mut self) -> &mut JiffyClock {
+        &mut self.clock
+    }
+
     /// 标准化变量名（转大写）
     fn normalize_name(name: &str) -> String {
         name.to_uppercase()
@@ -177,6 +210,13 @@ impl Variables {
     pub fn get(&self, name: &str) -> Value {
         let key = Self::normalize_name(name);
         
+        // 保留变量：时钟
+        match key.as_str() {
+            "TI" => return Value::Number(self.clock.jiffies() as f64),
+            "TI$" => return Value::String(self.clock.time_string()),
+            _ => {}
+        }
+        
         // 判断默认值类型
         let default_value = if key.ends_with('$') {
             Value::String(String::new())
@@ -204,6 +244,13 @@ impl Variables {
             ));
         }
         
+        // 保留变量：TI 只读，TI$ 设置时钟
+        match key.as_str() {
+            "TI" => return Err(BasicError::SyntaxError("TI is read-only".to_string())),
+            "TI$" => return self.clock.set_time_string(&value.as_string()?),
+            _ => {}
+        }
+        
         self.simple.insert(key, value);
         Ok(())
     }
@@ -224,49 +271,29 @@ impl Variables {
         Ok(())
     }
 
-    /// 获取数组元素
-    pub fn get_array_element(&self, name: &str, indices: &[usize]) -> Result<Value> {
+    /// 引用数组：不存在时按这次引用的下标个数自动创建，每维 0..10
+    /// （之后再 DIM 报 ?REDIM'D ARRAY，下标个数不同报 ?BAD SUBSCRIPT）
+    fn array_mut(&mut self, name: &str, subscripts: usize) -> &mut Array {
         let key = Self::normalize_name(name);
-        
-        // 如果数组不存在，自动创建默认大小（10）
-        if !self.arrays.contains_key(&key) {
-            // 对于未声明的数组，检查索引是否在默认范围内
-            for &idx in indices {
-                if idx > 10 {
-                    return Err(BasicError::SubscriptOutOfRange(
-                        format!("Index {} out of range (default array size is 10)", idx)
-                    ));
-                }
-            }
-            
-            // 返回默认值
-            let default_value = if key.ends_with('$') {
-                Value::String(String::new())
-            } else {
-                Value::Number(0.0)
-            };
-            return Ok(default_value);
-        }
-        
-        let array = self.arrays.get(&key).unwrap();
-        array.get(indices)
+        let is_string = key.ends_with('$');
+        self.arrays
+            .entry(key)
+            .or_insert_with(|| Array::new(vec![DEFAULT_ARRAY_BOUND; subscripts], is_string))
+    }
+
+    /// 获取数组元素
+    pub fn get_array_element(&mut self, name: &str, indices: &[usize]) -> Result<Value> {
+        self.array_mut(name, indices.len()).get(indices)
     }
 
     /// 设置数组元素
     pub fn set_array_element(&mut self, name: &str, indices: &[usize], value: Value) -> Result<()> {
-        let key = Self::normalize_name(name);
-        
-        // 如果数组不存在，自动创建默认大小
-        if !self.arrays.contains_key(&key) {
-            // 创建默认数组（根据索引维度）
-            let dimensions = vec![10; indices.len()];
-            let is_string = key.ends_with('$');
-            let array = Array::new(dimensions, is_string);
-            self.arrays.insert(key.clone(), array);
-        }
-        
-        let array = self.arrays.get_mut(&key).unwrap();
-        array.set(indices, value)
+        self.array_mut(name, indices.len()).set(indices, value)
+    }
+
+    /// 所有数组占用的字节数（FRE 用）
+    pub fn array_memory(&self) -> usize {
+        self.arrays.values().map(Array::memory_size).sum()
     }
 
     /// 检查数组是否存在
@@ -282,6 +309,22 @@ impl Variables {
         self.functions.clear();
     }
 
+    /// 只保留 COMMON 声明的变量和数组（用于 CHAIN）
+    pub fn retain_common(&mut self, common: &[CommonVariable]) {
+        let keep = |is_array: bool, key: &String| {
+            common.iter().any(|var| {
+                var.is_array == is_array && &Self::normalize_name(&var.name) == key
+            })
+        };
+        self.simple.retain(|key, _| keep(false, key));
+        self.arrays.retain(|key, _| keep(true, key));
+    }
+
+    /// 清除所有用户自定义函数
+    pub fn clear_functions(&mut self) {
+        self.functions.clear();
+    }
+
     /// 获取所有变量名（用于调试）
     pub fn list_variables(&self) -> Vec<(String, Value)> {
         self.simple.iter()
@@ -505,9 +548,55 @@ mod tests {
     // Requirement: 隐式数组声明 - 隐式数组大小限制
     #[test]
     fn test_implicit_array_size_limit() {
-        let vars = Variables::new();
+        let mut vars = Variables::new();
         let result = vars.get_array_element("A", &[11]);
-        assert!(result.is_err());
+        assert!(matches!(result.unwrap_err(), BasicError::SubscriptOutOfRange(_)));
+    }
+
+    // Requirement: 隐式数组声明 - 读取也会创建数组，之后 DIM 报错
+    #[test]
+    fn test_implicit_array_created_by_read() {
+        let mut vars = Variables::new();
+        assert_eq!(vars.get_array_element("B$", &[2, 10]).unwrap(), Value::String(String::new()));
+        assert!(vars.has_array("B$"));
+
+        let result = vars.dim_array("B$", vec![20]);
+        assert!(matches!(result.unwrap_err(), BasicError::RedimensionedArray(_)));
+    }
+
+    // Requirement: 数组元素访问 - 下标个数与维数不同
+    #[test]
+    fn test_array_subscript_count_mismatch() {
+        let mut vars = Variables::new();
+        vars.set_array_element("A", &[1], Value::Number(1.0)).unwrap();
+        let result = vars.get_array_element("A", &[1, 1]);
+        assert!(matches!(result.unwrap_err(), BasicError::SubscriptOutOfRange(_)));
+
+        vars.dim_array("M", vec![2, 2]).unwrap();
+        let result = vars.set_array_element("M", &[1], Value::Number(1.0));
+        assert!(matches!(result.unwrap_err(), BasicError::SubscriptOutOfRange(_)));
+    }
+
+    // Requirement: 数组下标 - 负数和过大的值
+    #[test]
+    fn test_subscript_range() {
+        assert_eq!(subscript(3.7).unwrap(), 3);
+        assert_eq!(subscript(32767.0).unwrap(), 32767);
+        assert!(matches!(subscript(-1.0).unwrap_err(), BasicError::IllegalQuantity(_)));
+        assert!(matches!(subscript(32768.0).unwrap_err(), BasicError::IllegalQuantity(_)));
+    }
+
+    // Requirement: FRE - 数组占用的内存
+    #[test]
+    fn test_array_memory() {
+        let mut vars = Variables::new();
+        assert_eq!(vars.array_memory(), 0);
+        // 头部 5 + 2，11 个 5 字节的数值
+        vars.set_array_element("A", &[0], Value::Number(1.0)).unwrap();
+        assert_eq!(vars.array_memory(), 62);
+        // 头部 5 + 4，3 * 4 个 3 字节的字符串描述符
+        vars.dim_array("S$", vec![2, 3]).unwrap();
+        assert_eq!(vars.array_memory(), 62 + 45);
     }
 
     // Requirement: 数组重新声明 - 重复 DIM 错误
@@ -534,6 +623,27 @@ mod tests {
         assert_eq!(vars.get("B$"), Value::String(String::new()));
     }
 
+    // Requirement: 保留变量 - TI 只读，TI$ 设置时钟
+    #[test]
+    fn test_clock_variables() {
+        use crate::clock::ClockMode;
+        
+        let mut vars = Variables::new();
+        vars.clock_mut().set_mode(ClockMode::Deterministic { jiffies_per_statement: 1 });
+        
+        vars.set("TI$", Value::String("012345".to_string())).unwrap();
+        assert_eq!(vars.get("ti$"), Value::String("012345".to_string()));
+        assert_eq!(vars.get("TI"), Value::Number(((60.0 + 23.0) * 60.0 + 45.0) * 60.0));
+        
+        assert!(vars.set("TI", Value::Number(0.0)).is_err());
+        assert!(vars.set("TI$", Value::String("99".to_string())).is_err());
+        assert!(vars.set("TI$", Value::Number(1.0)).is_err());
+        
+        // CLEAR 不影响时钟
+        vars.clear();
+        assert_eq!(vars.get("TI$"), Value::String("012345".to_string()));
+    }
+
     // Requirement: 变量清空 - CLEAR 清空数组
     #[test]
     fn test_clear_arrays() {
@@ -579,6 +689,25 @@ mod tests {
         assert_eq!(list.len(), 2);
     }
 
+    // Test: COMMON 变量保留
+    #[test]
+    fn test_retain_common() {
+        let mut vars = Variables::new();
+        vars.set("A", Value::Number(1.0)).unwrap();
+        vars.set("B$", Value::String("KEEP".to_string())).unwrap();
+        vars.set("C", Value::Number(3.0)).unwrap();
+        vars.dim_array("A", vec![5]).unwrap();
+        vars.dim_array("D", vec![5]).unwrap();
+        
+        vars.retain_common(&[
+            CommonVariable { name: "b$".to_string(), is_array: false },
+            CommonVariable { name: "D".to_string(), is_array: true },
+        ]);
+        
+        assert_eq!(vars.list_variables(), vec![("B$".to_string(), Value::String("KEEP".to_string()))]);
+        assert_eq!(vars.list_arrays(), vec!["D".to_string()]);
+    }
+
     // Test: Value 类型方法
     #[test]
     fn test_value_as_number() {